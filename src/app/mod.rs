@@ -1,228 +1,691 @@
 mod sdl_events;
 
-use log::debug;
-use sdl2::{image::InitFlag, AudioSubsystem, Sdl, VideoSubsystem};
-use std::{sync::atomic::Ordering, time::Duration};
+use log::{debug, error, info, warn};
+use sdl2::{image::InitFlag, AudioSubsystem, EventSubsystem, Sdl, VideoSubsystem};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Instant,
+};
 
 use crate::{
+    config::PlayerOptions,
     entity::EventMessage,
     global::{
-        AUDIO_SUMMARY, EVENT_CHANNEL, FR_STEP, GLOBAL_PTS_MILLIS, MAX_VOLUME, VIDEO_SUMMARY,
-        VOLUME, VOLUME_STEP,
+        AUDIO_BUFFER, AUDIO_DISABLED, AUDIO_SUMMARY, DECODER_OPTIONS, DSP_SETTINGS, EVENT_CHANNEL,
+        FR_STEP, MAX_VOLUME, MUTED, NETWORK_OPTIONS, PRESENT_INTERVAL, RENDERER_OPTIONS,
+        THREAD_JOIN_TIMEOUT, TONE_MAP_OPERATOR, VIDEO_FILTER_SETTINGS, VIDEO_SUMMARY, VOLUME,
+        VOLUME_STEP,
     },
     media::{
-        decoder::MediaDecoder,
-        player::{traits::Player, MediaPlayer},
+        decoder::{MediaDecoder, SeekMode},
+        player::{state::PlaybackState, traits::Player, MediaPlayer},
+        playlist::Playlist,
+        source::MediaSource,
+    },
+    sound::{playback_devices, Sounder},
+    ui::{
+        components::dialog::show_error, renderer::RendererOverrides, start_window::StartWindow,
+        video_window::VideoWindow,
+    },
+    util::{
+        error::{handle_result, safe_send, PlayerError},
+        worker::join_timeout,
     },
-    sound::Sounder,
-    ui::{components::dialog::show_error, start_window::StartWindow, video_window::VideoWindow},
-    util::error::{handle_result, safe_send, SuperError},
 };
 
 use self::sdl_events::SdlEvents;
 
+/// Name of the threads opening media
+const OPENER_THREAD_NAME: &str = "media-opener";
+
+/// A media being opened by a worker thread, `EventMessage::MediaOpened` is sent once it's done
+struct Opening {
+    id: u64,
+    /// Set to give up opening, it stops the opened decoder as well
+    stop_flag: Arc<AtomicBool>,
+    /// The playing media has ended before it's opened, play it at once then
+    play_when_opened: bool,
+}
+
+impl Opening {
+    fn cancel(&self) {
+        self.stop_flag.store(true, Ordering::Release);
+    }
+}
+
 pub struct NtApp {
     sdl_context: Sdl,
-    audio_subsystem: AudioSubsystem,
+    /// None if there is no audio driver, media is played without sound then
+    audio_subsystem: Option<AudioSubsystem>,
     video_subsystem: VideoSubsystem,
+    event_subsystem: EventSubsystem,
     start_window: StartWindow,
     video_window: Option<VideoWindow>,
+    /// The next media of playlist, it's opened ahead to follow the playing one without gap
+    next_decoder: Option<MediaDecoder>,
+    /// The media to play, it's being opened
+    opening: Option<Opening>,
+    /// The next media of playlist, it's being opened
+    preloading: Option<Opening>,
+    /// Number of media opened ever, the id of next opening
+    open_count: u64,
+    playlist: Playlist,
+    player: Option<MediaPlayer>,
+    sounder: Option<Sounder>,
+    /// The selected playback device, `None` means the default device
+    audio_device: Option<String>,
 }
 
 impl NtApp {
-    pub fn new() -> Result<Self, SuperError> {
+    /// `renderer` overrides the renderer options of config
+    pub fn new(renderer: RendererOverrides) -> Result<Self, PlayerError> {
+        let options = PlayerOptions::load();
+        let mut renderer_options = options.renderer.clone();
+        renderer_options.apply(renderer);
+        // The video driver is chosen before the video subsystem is initialized
+        renderer_options.set_video_driver();
+        *RENDERER_OPTIONS.write().unwrap() = renderer_options;
+
         let _image_context = sdl2::image::init(InitFlag::JPG | InitFlag::PNG);
         let sdl_context = sdl2::init()?;
         let video_subsystem = sdl_context.video()?;
-        let audio_subsystem = sdl_context.audio()?;
+        // The app still plays video on a system without any audio driver
+        let audio_subsystem = match sdl_context.audio() {
+            Ok(audio_subsystem) => Some(audio_subsystem),
+            Err(err) => {
+                warn!("Audio is not available: {}", err);
+                AUDIO_DISABLED.store(true, Ordering::Release);
+                None
+            }
+        };
+        let event_subsystem = sdl_context.event()?;
+
+        // Engine events are delivered to main loop as sdl user events
+        event_subsystem.register_custom_event::<EventMessage>()?;
 
         let start_wind = StartWindow::new(&video_subsystem)?;
+        *DSP_SETTINGS.write().unwrap() = options.dsp.clone();
+        *VIDEO_FILTER_SETTINGS.write().unwrap() = options.video_filters.clone();
+        *NETWORK_OPTIONS.write().unwrap() = options.network.clone();
+        *DECODER_OPTIONS.write().unwrap() = options.decoder;
+        *TONE_MAP_OPERATOR.write().unwrap() = options.tone_map;
+        if let Some(audio_subsystem) = audio_subsystem.as_ref() {
+            info!("Playback devices: {:?}", playback_devices(audio_subsystem));
+        }
 
         Ok(Self {
             sdl_context,
             audio_subsystem,
             video_subsystem,
+            event_subsystem,
             start_window: start_wind,
             video_window: None,
+            next_decoder: None,
+            opening: None,
+            preloading: None,
+            open_count: 0,
+            playlist: Playlist::default(),
+            player: None,
+            sounder: None,
+            audio_device: options.audio_device,
         })
     }
 
-    pub fn run(&mut self) -> Result<(), SuperError> {
+    pub fn run(&mut self) -> Result<(), PlayerError> {
+        let bridge = Self::start_event_bridge(&self.event_subsystem)?;
+        let result = self.main_loop();
+        self.shutdown();
+
+        // Stop the event bridge
+        safe_send(EVENT_CHANNEL.0.send(EventMessage::Quit));
+        join_timeout(bridge, THREAD_JOIN_TIMEOUT);
+
+        result
+    }
+
+    fn main_loop(&mut self) -> Result<(), PlayerError> {
         let mut sdl_eventer = SdlEvents::new(&self.sdl_context)?;
-        let sender = &EVENT_CHANNEL.0;
-        let receiver = &EVENT_CHANNEL.1;
-        let mut decoder: Option<MediaDecoder> = None;
-        let mut player: Option<MediaPlayer> = None;
-        let mut sounder: Option<Sounder> = None;
-
-        loop {
-            if sdl_eventer.handle_events(&mut self.start_window, &mut self.video_window)?
-                == MainLoopState::Quit
-            {
-                break;
-            }
-
-            if let Ok(m) = receiver.recv_timeout(Duration::from_millis(50)) {
-                match m {
-                    EventMessage::Quit => break,
-                    EventMessage::Play(path) => {
-                        // If video window is not initialized yet, initialize it
-                        if self.video_window.is_none() {
-                            let wind = VideoWindow::new(&self.video_subsystem)?;
-                            self.video_window = Some(wind);
-                        }
-                        self.start_window.hide();
-                        self.video_window.as_mut().unwrap().show();
 
-                        // If the decoder is running, stop it first
-                        if let Some(mut decoder) = decoder {
-                            decoder.stop();
-                        }
-                        // if the player is running, stop it first
-                        if let Some(mut player) = player {
-                            player.stop();
-                        }
+        let mut last_present = Instant::now();
+        'main: loop {
+            // Sleep until any event arrived or it's time to present next frame,
+            // then handle all pending events at once
+            let timeout = PRESENT_INTERVAL.saturating_sub(last_present.elapsed());
+            for event in sdl_eventer.wait_events(timeout) {
+                let state = if event.is_user_event() {
+                    match event.as_user_event_type::<EventMessage>() {
+                        Some(message) => self.handle_message(message)?,
+                        None => MainLoopState::Continue,
+                    }
+                } else {
+                    sdl_eventer.handle_event(
+                        event,
+                        &mut self.start_window,
+                        &mut self.video_window,
+                    )?
+                };
 
-                        let mut md = MediaDecoder::new(path.to_str().unwrap())?;
-                        md.seek_to(0);
+                if state == MainLoopState::Quit {
+                    break 'main;
+                }
+            }
 
-                        let mut plr = MediaPlayer::new();
-                        plr.start()?;
+            // Present at most once per interval, and only if new frame arrived
+            // or the window content changed
+            if last_present.elapsed() >= PRESENT_INTERVAL {
+                if let Some(wind) = self.video_window.as_mut() {
+                    wind.render()?;
+                }
+                last_present = Instant::now();
+            }
+        }
 
-                        decoder = Some(md);
-                        player = Some(plr);
+        Ok(())
+    }
 
-                        // Everty time play new media, the audio summary will be changed,
-                        // that's why the sounder is initialized here after media decoder is initialized
-                        let r = AUDIO_SUMMARY.read().unwrap();
-                        if let Some(summary) = r.as_ref() {
-                            let sdr = Sounder::new(&self.audio_subsystem, summary);
-                            sounder = Some(sdr);
-                        }
-                    }
-                    EventMessage::Pause => {
-                        if let Some(player) = player.as_mut() {
-                            player.pause();
-                        }
-                    }
-                    EventMessage::Resume => {
-                        if let Some(player) = player.as_mut() {
-                            player.resume();
-                        }
-                    }
-                    EventMessage::Stop => {
-                        if let Some(decoder) = decoder.as_mut() {
-                            decoder.stop();
-                        }
-                        if let Some(player) = player.as_mut() {
-                            player.stop();
-                        }
-                    }
-                    EventMessage::Forward => {
-                        if let (Some(player), Some(decoder)) = (player.as_mut(), decoder.as_mut()) {
-                            let r = VIDEO_SUMMARY.read().unwrap();
-                            let summary = r.as_ref().unwrap();
-
-                            let pts = GLOBAL_PTS_MILLIS.load(Ordering::Acquire);
-                            let start = pts + FR_STEP;
-
-                            if start as u64 > summary.duration_millis {
-                                decoder.stop();
-                                player.stop();
-                            } else {
-                                player.seeking();
-                                decoder.seek_to(start);
-                            }
-                        }
-                    }
-                    EventMessage::Rewind => {
-                        if let (Some(player), Some(decoder)) = (player.as_mut(), decoder.as_mut()) {
-                            let adjust_diff = 2000;
-
-                            let pts = GLOBAL_PTS_MILLIS.load(Ordering::Acquire);
-                            let start = pts - FR_STEP - adjust_diff;
-                            player.seeking();
-                            decoder.seek_to(start);
-                        }
-                    }
-                    EventMessage::FileOpened(path) => {
-                        safe_send(sender.send(EventMessage::Play(path)));
-                    }
-                    EventMessage::DirOpened(_) => {
-                        todo!();
-                    }
-                    EventMessage::ShowError(msg) => {
-                        show_error(msg.as_str());
-                    }
-                    EventMessage::RenderVideo(frame) => {
-                        if let Some(wind) = self.video_window.as_mut() {
-                            wind.update_video_frame(frame);
-                        }
-                    }
-                    EventMessage::RenderAudio(frame) => {
-                        if let Some(sounder) = sounder.as_mut() {
-                            let result = sounder.play_sound(frame);
-                            handle_result(result);
-                        }
-                    }
-                    EventMessage::RenderSubtitle(_) => todo!(), // Render video and sound
-                    EventMessage::Resize((width, height)) => {
-                        if let Some(wind) = self.video_window.as_mut() {
-                            match player.as_mut() {
-                                Some(player) => {
-                                    player.pause();
-                                    wind.set_size(width, height);
-                                    // wind.clear_window();
-                                    player.resume();
-                                }
-                                None => {
-                                    wind.set_size(width, height);
-                                }
+    /// Stop the media threads and close the audio device
+    fn shutdown(&mut self) {
+        if let Some(opening) = self.opening.take() {
+            opening.cancel();
+        }
+        self.stop_preloading();
+        // The decoder is stopped together with the player
+        if let Some(mut player) = self.player.take() {
+            player.stop();
+        }
+        self.sounder = None;
+    }
+
+    /// Forward messages of `EVENT_CHANNEL` to sdl event queue, so that the main loop
+    /// can wait for sdl events and engine events at the same time
+    fn start_event_bridge(event_subsystem: &EventSubsystem) -> Result<JoinHandle<()>, PlayerError> {
+        let event_sender = event_subsystem.event_sender();
+        let builder = thread::Builder::new().name("event-bridge".to_string());
+        let handle = builder.spawn(move || {
+            while let Ok(message) = EVENT_CHANNEL.1.recv() {
+                let quit = matches!(message, EventMessage::Quit);
+                if let Err(err) = event_sender.push_custom_event(message) {
+                    error!("Failed to push engine event: {}", err);
+                }
+
+                if quit {
+                    break;
+                }
+            }
+
+            debug!("event bridge exited");
+        })?;
+
+        Ok(handle)
+    }
+
+    fn handle_message(&mut self, message: EventMessage) -> Result<MainLoopState, PlayerError> {
+        match message {
+            EventMessage::Quit => return Ok(MainLoopState::Quit),
+            EventMessage::Play(source) => {
+                // If video window is not initialized yet, initialize it
+                if self.video_window.is_none() {
+                    let wind = VideoWindow::new(&self.video_subsystem)?;
+                    self.video_window = Some(wind);
+                }
+                self.start_window.hide();
+                self.video_window.as_mut().unwrap().show();
+                self.video_window.as_mut().unwrap().load_media(&source);
+
+                // Media out of the playlist is played alone
+                if !self.playlist.select(&source) {
+                    self.playlist = Playlist::new(vec![source.clone()]);
+                }
+
+                self.stop_preloading();
+                // if the player is running, stop it and its decoder first
+                if let Some(mut player) = self.player.take() {
+                    player.stop();
+                }
+
+                // The media opened before is given up, network sources may take long to open,
+                // so the main loop isn't blocked by opening
+                if let Some(opening) = self.opening.take() {
+                    opening.cancel();
+                }
+                self.opening = Some(self.open_media(&source));
+            }
+            EventMessage::MediaOpened(id, source, result) => {
+                if self.opening.as_ref().map(|opening| opening.id) == Some(id) {
+                    self.opening = None;
+                    self.start_playing(&source, result)?;
+                } else if let Some(opening) = self.preloading.as_ref().filter(|o| o.id == id) {
+                    let play_now = opening.play_when_opened;
+                    self.preloading = None;
+                    match result {
+                        Ok(decoder) => {
+                            self.next_decoder = Some(decoder);
+                            if play_now {
+                                self.play_next();
                             }
                         }
+                        Err(err) => warn!("Failed to preload {}: {}", source, err),
                     }
-                    EventMessage::SetPosition { x, y } => {
-                        if let Some(wind) = self.video_window.as_mut() {
-                            wind.set_position(x, y);
-                        }
+                }
+                // Otherwise it's given up, the decoder is stopped once dropped
+            }
+            EventMessage::Pause => {
+                if let Some(player) = self.player.as_mut() {
+                    player.pause();
+                }
+                if let Some(sounder) = self.sounder.as_ref() {
+                    sounder.pause();
+                }
+            }
+            EventMessage::Resume => {
+                if let Some(player) = self.player.as_mut() {
+                    player.resume();
+                }
+                if let Some(sounder) = self.sounder.as_ref() {
+                    sounder.resume();
+                }
+            }
+            EventMessage::Stop => {
+                if let Some(opening) = self.opening.take() {
+                    opening.cancel();
+                }
+                self.stop_preloading();
+                if let Some(player) = self.player.as_mut() {
+                    player.stop();
+                }
+            }
+            EventMessage::Forward => {
+                self.seek(|player| player.seek_by(FR_STEP, SeekMode::Accurate));
+            }
+            EventMessage::Rewind => {
+                self.seek(|player| player.seek_by(-FR_STEP, SeekMode::Accurate));
+            }
+            EventMessage::Seek(position) => {
+                self.seek(|player| player.seek(position, SeekMode::Accurate));
+            }
+            EventMessage::SetRate(rate) => {
+                if let Some(player) = self.player.as_mut() {
+                    match player.set_rate(rate) {
+                        Ok(_) => info!("Playback rate changed to {}", rate),
+                        Err(err) => warn!("{}", err),
                     }
-                    EventMessage::UpVolume => {
-                        let mut volume = VOLUME.load(Ordering::Acquire);
-                        if volume <= MAX_VOLUME {
-                            volume += VOLUME_STEP;
-                            VOLUME.store(volume, Ordering::Release);
-                        }
+                }
+            }
+            EventMessage::SourceOpened(source) => {
+                self.playlist = Playlist::new(vec![source.clone()]);
+                safe_send(EVENT_CHANNEL.0.send(EventMessage::Play(source)));
+            }
+            EventMessage::DirOpened(paths) => {
+                self.playlist = Playlist::new(paths.into_iter().map(MediaSource::File).collect());
+                match self.playlist.current() {
+                    Some(source) => {
+                        safe_send(EVENT_CHANNEL.0.send(EventMessage::Play(source.clone())))
                     }
-                    EventMessage::DownVolume => {
-                        let mut volume = VOLUME.load(Ordering::Acquire);
-                        if volume >= VOLUME_STEP {
-                            volume -= VOLUME_STEP;
-                            VOLUME.store(volume, Ordering::Release);
+                    None => show_error("No media file found in the directory"),
+                }
+            }
+            EventMessage::EndOfMedia(id) => {
+                // Ignore the decoder which has been replaced
+                if self.is_current_decoder(id) {
+                    self.play_next();
+                }
+            }
+            // Failures of the decoder which has been replaced are ignored
+            EventMessage::AudioLost(id) if self.is_current_decoder(id) => {
+                warn!("Audio can't be decoded, play video only");
+                self.sounder = None;
+                AUDIO_DISABLED.store(true, Ordering::Release);
+                while AUDIO_BUFFER.pop().is_some() {}
+            }
+            EventMessage::VideoLost(id) if self.is_current_decoder(id) => {
+                warn!("Video can't be decoded, play audio only");
+                *VIDEO_SUMMARY.write().unwrap() = None;
+                if let Some(wind) = self.video_window.as_mut() {
+                    wind.invalidate();
+                }
+            }
+            EventMessage::MediaError(id, msg) if self.is_current_decoder(id) => {
+                show_error(msg.as_str());
+            }
+            EventMessage::AudioLost(_)
+            | EventMessage::VideoLost(_)
+            | EventMessage::MediaError(..) => {}
+            EventMessage::ShowError(msg) => {
+                show_error(msg.as_str());
+            }
+            EventMessage::RenderVideo(frame) => {
+                if let Some(wind) = self.video_window.as_mut() {
+                    wind.update_video_frame(frame);
+                }
+            }
+            EventMessage::RenderSubtitle(_) => debug!("Subtitle rendering is not supported yet"),
+            EventMessage::RenderThumbnail(thumbnail) => {
+                if let Some(wind) = self.video_window.as_mut() {
+                    wind.update_thumbnail(thumbnail);
+                }
+            }
+            EventMessage::MediaInfo(info) => {
+                if let Some(wind) = self.video_window.as_mut() {
+                    wind.set_media_info(info);
+                }
+            }
+            EventMessage::CycleVisualizer => {
+                if let Some(wind) = self.video_window.as_mut() {
+                    wind.cycle_visualizer();
+                }
+            }
+            EventMessage::Resize((width, height)) => {
+                if let Some(wind) = self.video_window.as_mut() {
+                    match self.player.as_mut() {
+                        Some(player) if player.state() == PlaybackState::Playing => {
+                            player.pause();
+                            wind.set_size(width, height);
+                            // wind.clear_window();
+                            player.resume();
                         }
-                    }
-                    EventMessage::SeekFinished => {
-                        if let Some(player) = player.as_mut() {
-                            player.seek_finished();
+                        _ => {
+                            wind.set_size(width, height);
                         }
                     }
-                    EventMessage::ExitVideoWindow => {
-                        // Stop the decoder and player before leave video window
-                        safe_send(sender.send(EventMessage::Stop));
+                }
+            }
+            EventMessage::SetPosition { x, y } => {
+                if let Some(wind) = self.video_window.as_mut() {
+                    wind.set_position(x, y);
+                }
+            }
+            EventMessage::CycleDisplayMode => {
+                if let Some(wind) = self.video_window.as_mut() {
+                    wind.cycle_display_mode();
+                }
+            }
+            EventMessage::Zoom(factor) => {
+                if let Some(wind) = self.video_window.as_mut() {
+                    wind.zoom(factor);
+                }
+            }
+            EventMessage::Pan(dx, dy) => {
+                if let Some(wind) = self.video_window.as_mut() {
+                    wind.pan(dx, dy);
+                }
+            }
+            EventMessage::VideoFilter(command) => {
+                let mut settings = VIDEO_FILTER_SETTINGS.write().unwrap();
+                settings.apply(command);
+                info!("Video filters changed: {:?}", *settings);
+            }
+            EventMessage::Screenshot => {
+                if let Some(wind) = self.video_window.as_ref() {
+                    handle_result(wind.screenshot());
+                }
+            }
+            EventMessage::UpVolume => {
+                let volume = VOLUME.load(Ordering::Acquire);
+                VOLUME.store((volume + VOLUME_STEP).min(MAX_VOLUME), Ordering::Release);
+                MUTED.store(false, Ordering::Release);
+                self.show_volume();
+            }
+            EventMessage::DownVolume => {
+                let volume = VOLUME.load(Ordering::Acquire);
+                VOLUME.store((volume - VOLUME_STEP).max(0), Ordering::Release);
+                self.show_volume();
+            }
+            EventMessage::ToggleMute => {
+                MUTED.fetch_xor(true, Ordering::AcqRel);
+                self.show_volume();
+            }
+            EventMessage::Dsp(command) => {
+                let mut settings = DSP_SETTINGS.write().unwrap();
+                settings.apply(command);
+                info!("Audio processing changed: {:?}", *settings);
+                if let Some(sounder) = self.sounder.as_mut() {
+                    sounder.update_dsp(&settings);
+                }
+            }
+            EventMessage::AudioDevicesChanged => {
+                // Reopen the output if the device is disconnected, or the media is played
+                // without sound because there was no device
+                let lost = self
+                    .sounder
+                    .as_ref()
+                    .map_or(true, |sounder| sounder.is_lost());
+                if lost && self.player.is_some() {
+                    warn!("Audio device is not available, reopen audio output");
+                    self.open_sounder();
+                }
+            }
+            EventMessage::NextAudioDevice => {
+                let devices = self
+                    .audio_subsystem
+                    .as_ref()
+                    .map(playback_devices)
+                    .unwrap_or_default();
+                let current = self
+                    .sounder
+                    .as_ref()
+                    .and_then(|sounder| sounder.device_name())
+                    .or(self.audio_device.as_deref());
+                let next = match current.and_then(|name| devices.iter().position(|d| d == name)) {
+                    Some(index) => devices.get(index + 1).cloned(),
+                    None => devices.first().cloned(),
+                };
 
-                        // Then return back to start window
-                        self.video_window.as_mut().unwrap().hide();
-                        self.start_window.show();
-                    }
+                info!(
+                    "Switch audio device to {}",
+                    next.as_deref().unwrap_or("default")
+                );
+                self.audio_device = next;
+                self.open_sounder();
+            }
+            EventMessage::AudioFormatChanged => {
+                info!("Reopen audio output for the new audio format");
+                self.open_sounder();
+            }
+            EventMessage::SeekFinished => {
+                if let Some(player) = self.player.as_mut() {
+                    player.seek_finished();
+                }
+            }
+            EventMessage::StateChanged(state) => {
+                debug!("Playback state changed to {:?}", state);
+                if let Some(wind) = self.video_window.as_mut() {
+                    wind.invalidate();
+                }
+            }
+            EventMessage::ExitVideoWindow => {
+                // Stop the decoder and player before leave video window
+                safe_send(EVENT_CHANNEL.0.send(EventMessage::Stop));
+
+                // Then return back to start window
+                self.video_window.as_mut().unwrap().hide();
+                self.start_window.show();
+            }
+        }
+
+        Ok(MainLoopState::Continue)
+    }
+
+    /// Open audio output for the playing media on the selected device,
+    /// the media is played without sound if no device is available
+    fn open_sounder(&mut self) {
+        // Close the old device first, so that the same device can be opened again
+        self.sounder = None;
+
+        let r = AUDIO_SUMMARY.read().unwrap();
+        if let (Some(summary), Some(player)) = (r.as_ref(), self.player.as_ref()) {
+            let device = self.audio_device.as_deref();
+            let sounder = match self.audio_subsystem.as_ref() {
+                Some(sys) => Sounder::new(sys, summary, player, device),
+                None => Err(PlayerError::NoAudioDevice(
+                    "Audio subsystem is not initialized".to_string(),
+                )),
+            };
+            match sounder {
+                Ok(sounder) => {
+                    AUDIO_DISABLED.store(false, Ordering::Release);
+                    self.sounder = Some(sounder);
                 }
+                Err(err) => {
+                    warn!("{}, play video only", err);
+                    AUDIO_DISABLED.store(true, Ordering::Release);
+                    // Nobody will consume the buffered audio frames
+                    while AUDIO_BUFFER.pop().is_some() {}
+                }
+            }
+        }
+    }
+
+    fn is_current_decoder(&self, id: u64) -> bool {
+        self.player.as_ref().map(|player| player.decoder().id()) == Some(id)
+    }
+
+    /// Seek the playing media, it's ignored if the media can't be seeked or it's stopped
+    fn seek(&mut self, seek: impl FnOnce(&mut MediaPlayer) -> Result<(), PlayerError>) {
+        if let Some(player) = self.player.as_mut() {
+            if let Err(err) = seek(player) {
+                debug!("Seek is ignored: {}", err);
+            }
+        }
+    }
+
+    /// Open the next media of playlist ahead, its frames are decoded but held back
+    fn preload_next(&mut self) {
+        self.stop_preloading();
+
+        if let Some(source) = self.playlist.peek_next().cloned() {
+            self.preloading = Some(self.open_media(&source));
+        }
+    }
+
+    /// Give up the next media of playlist, whether it's opened or being opened
+    fn stop_preloading(&mut self) {
+        if let Some(opening) = self.preloading.take() {
+            opening.cancel();
+        }
+        if let Some(mut decoder) = self.next_decoder.take() {
+            decoder.stop();
+        }
+    }
+
+    /// Open the media in a worker thread, the decoder is sent by `EventMessage::MediaOpened`
+    fn open_media(&mut self, source: &MediaSource) -> Opening {
+        self.open_count += 1;
+        let opening = Opening {
+            id: self.open_count,
+            stop_flag: Arc::new(AtomicBool::new(false)),
+            play_when_opened: false,
+        };
+
+        let id = opening.id;
+        let stop_flag = opening.stop_flag.clone();
+        let builder = thread::Builder::new().name(OPENER_THREAD_NAME.to_string());
+        let spawned = builder.spawn({
+            let source = source.clone();
+            move || {
+                let result = MediaDecoder::preload(&source, stop_flag).map_err(|e| e.to_string());
+                let message = EventMessage::MediaOpened(id, source, result);
+                safe_send(EVENT_CHANNEL.0.send(message));
             }
+        });
+        if let Err(err) = spawned {
+            let message = EventMessage::MediaOpened(id, source.clone(), Err(err.to_string()));
+            safe_send(EVENT_CHANNEL.0.send(message));
+        }
+
+        opening
+    }
 
-            if let Some(wind) = self.video_window.as_mut() {
-                wind.render()?;
+    /// Play the media opened by `open_media`
+    fn start_playing(
+        &mut self,
+        source: &MediaSource,
+        result: Result<MediaDecoder, String>,
+    ) -> Result<(), PlayerError> {
+        // A source which can't be opened, e.g. an unreachable URL, shouldn't stop the app
+        let mut md = match result {
+            Ok(md) => md,
+            Err(err) => {
+                error!("Failed to open {}: {}", source, err);
+                show_error(format!("Failed to open {}: {}", source, err).as_str());
+                safe_send(EVENT_CHANNEL.0.send(EventMessage::ExitVideoWindow));
+                return Ok(());
             }
+        };
+
+        // Try to play with sound again, the device may be available now
+        AUDIO_DISABLED.store(false, Ordering::Release);
+        md.activate();
+        if md.is_seekable() {
+            md.seek_to(0, SeekMode::Fast);
         }
 
+        let mut plr = MediaPlayer::new(md);
+        plr.start()?;
+
+        self.player = Some(plr);
+
+        // Everty time play new media, the audio summary will be changed,
+        // that's why the sounder is initialized here after media decoder is initialized
+        self.open_sounder();
+        self.preload_next();
+
         Ok(())
     }
+
+    /// Continue with the preloaded media when the decoding of current one is finished.
+    /// Its frames are queued right after the buffered frames, so the player and the audio
+    /// device keep running, the device is reopened only if the audio format is different
+    fn play_next(&mut self) {
+        let mut next = match self.next_decoder.take() {
+            Some(next) => next,
+            None => {
+                // Continue once the next media is opened
+                if let Some(opening) = self.preloading.as_mut() {
+                    opening.play_when_opened = true;
+                }
+                return;
+            }
+        };
+        let source = match self.playlist.advance() {
+            Some(source) => source.clone(),
+            None => {
+                next.stop();
+                return;
+            }
+        };
+
+        // The players are started according to the streams of media,
+        // play the next media from scratch if it has different streams
+        let has_audio = AUDIO_SUMMARY.read().unwrap().is_some();
+        let has_video = VIDEO_SUMMARY.read().unwrap().is_some();
+        let summaries = next.summaries();
+        if summaries.audio.is_some() != has_audio || summaries.video.is_some() != has_video {
+            next.stop();
+            safe_send(EVENT_CHANNEL.0.send(EventMessage::Play(source)));
+            return;
+        }
+
+        info!("Continue with {}", source);
+        next.activate();
+        if let Some(player) = self.player.as_mut() {
+            // The decoder of finished media is stopped once dropped
+            player.replace_decoder(next);
+        }
+        if let Some(wind) = self.video_window.as_mut() {
+            wind.load_media(&source);
+        }
+        self.preload_next();
+    }
+
+    fn show_volume(&mut self) {
+        if let Some(wind) = self.video_window.as_mut() {
+            wind.show_volume();
+        }
+    }
 }
 
 #[derive(Clone, PartialEq, Eq)]
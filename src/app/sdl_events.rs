@@ -1,80 +1,289 @@
+use std::time::Duration;
+
+use log::warn;
 use sdl2::{
+    clipboard::ClipboardUtil,
     event::{Event, WindowEvent},
-    keyboard::{Keycode, Mod},
-    EventPump, Sdl, video::FullscreenType,
+    keyboard::{Keycode, Mod, TextInputUtil},
+    video::FullscreenType,
+    EventPump, Sdl,
 };
 
 use super::MainLoopState;
 use crate::{
     entity::EventMessage,
-    global::{EVENT_CHANNEL, INIT_HEIGHT, INIT_WIDTH},
+    global::{
+        EVENT_CHANNEL, INIT_HEIGHT, INIT_WIDTH, MAX_PLAYBACK_RATE, MIN_PLAYBACK_RATE, PLAYBACK_RATE,
+    },
+    media::{source::MediaSource, video_filter::VideoFilterCommand},
+    sound::dsp::DspCommand,
     ui::{
-        start_window::StartWindow, video_window::VideoWindow, MouseDownParam, MouseMotionParam,
-        MouseUpParam, MouseWheelParam,
+        components::dialog::show_error,
+        start_window::{url_entry::UrlEntry, StartWindow},
+        video_window::VideoWindow,
+        MouseDownParam, MouseMotionParam, MouseUpParam, MouseWheelParam,
     },
-    util::error::{safe_send, SuperError},
+    util::error::{safe_send, PlayerError},
 };
 
+/// Zoom factor multiplied each time
+const ZOOM_STEP: f32 = 1.25;
+/// Pan amount each time, the whole range is `-1.0..=1.0`
+const PAN_STEP: f32 = 0.1;
+/// Balance amount each time, the whole range is `-1.0..=1.0`
+const BALANCE_STEP: f32 = 0.1;
+/// Change of brightness, contrast, saturation and gamma each time
+const PICTURE_ADJUST_STEP: f32 = 0.05;
+/// Change of playback speed each time
+const RATE_STEP: f64 = 0.25;
+const CTRL_MOD: Mod = Mod::LCTRLMOD.union(Mod::RCTRLMOD);
+
 pub(in crate::app) struct SdlEvents {
     event_pump: EventPump,
+    clipboard: ClipboardUtil,
+    /// Text input is on only while the URL entry is edited
+    text_input: TextInputUtil,
 }
 
 impl SdlEvents {
-    pub(in crate::app) fn new(ctx: &Sdl) -> Result<Self, SuperError> {
+    pub(in crate::app) fn new(ctx: &Sdl) -> Result<Self, PlayerError> {
         let event_pump = ctx.event_pump()?;
+        let video = ctx.video()?;
+        let clipboard = video.clipboard();
+        let text_input = video.text_input();
+        text_input.stop();
+
+        Ok(Self {
+            event_pump,
+            clipboard,
+            text_input,
+        })
+    }
+
+    /// Text of the clipboard, `None` if it's empty or can't be read
+    fn clipboard_text(&self) -> Option<String> {
+        match self.clipboard.clipboard_text() {
+            Ok(text) if !text.trim().is_empty() => Some(text),
+            Ok(_) => None,
+            Err(err) => {
+                warn!("Failed to read clipboard: {}", err);
+                None
+            }
+        }
+    }
+
+    /// Keys of the URL entry of start window, the media is opened once Return is pressed
+    fn handle_url_entry_key(
+        &mut self,
+        keycode: Option<Keycode>,
+        keymod: Mod,
+        start_window: &mut StartWindow,
+    ) -> Result<(), PlayerError> {
+        match keycode {
+            Some(Keycode::Return) | Some(Keycode::KpEnter) => {
+                let text = start_window.edit_url(UrlEntry::finish)?;
+                self.text_input.stop();
+                match MediaSource::parse(&text) {
+                    Some(source) => {
+                        safe_send(EVENT_CHANNEL.0.send(EventMessage::SourceOpened(source)))
+                    }
+                    None => show_error("Type a URL or file path to open"),
+                }
+            }
+            Some(Keycode::Escape) => {
+                start_window.edit_url(UrlEntry::cancel)?;
+                self.text_input.stop();
+            }
+            Some(Keycode::Backspace) => start_window.edit_url(UrlEntry::backspace)?,
+            Some(Keycode::V) if keymod.intersects(CTRL_MOD) => {
+                if let Some(text) = self.clipboard_text() {
+                    start_window.edit_url(|entry| entry.insert(&text))?;
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Wait until any event arrived or the timeout elapsed,
+    /// then take all the pending events
+    pub(in crate::app) fn wait_events(&mut self, timeout: Duration) -> Vec<Event> {
+        let mut events = vec![];
+        if let Some(event) = self
+            .event_pump
+            .wait_event_timeout(timeout.as_millis() as u32)
+        {
+            events.push(event);
+        }
+        events.extend(self.event_pump.poll_iter());
 
-        Ok(Self { event_pump })
+        events
     }
 
-    /// Handler for sdl events, if the return value is Ok(false),
+    /// Handler for sdl event, if the return value is Ok(MainLoopState::Quit),
     /// means the main loop should be terminated, otherwise just continue
-    pub(in crate::app) fn handle_events(
+    pub(in crate::app) fn handle_event(
         &mut self,
+        event: Event,
         start_window: &mut StartWindow,
         video_window: &mut Option<VideoWindow>,
-    ) -> Result<MainLoopState, SuperError> {
+    ) -> Result<MainLoopState, PlayerError> {
         let sender = &EVENT_CHANNEL.0;
 
-        for event in self.event_pump.poll_iter() {
-            match event {
-                Event::Quit { .. } => return Ok(MainLoopState::Quit),
-                Event::KeyDown {
-                    keycode,
-                    window_id,
-                    keymod,
-                    ..
-                } => {
-                    println!("mod: {}", keymod);
-                    match keycode {
-                        Some(Keycode::Escape) => {
-                            if window_id == start_window.id {
-                                return Ok(MainLoopState::Quit);
-                            }
-                            if let Some(wind) = video_window {
-                                if window_id == wind.id {
-                                    wind.set_fullscreen(FullscreenType::Off);
-                                }
-                            }
-                        }
-                        Some(Keycode::Up) => {
-                            safe_send(sender.send(EventMessage::UpVolume));
+        match event {
+            Event::Quit { .. } => return Ok(MainLoopState::Quit),
+            Event::KeyDown {
+                keycode,
+                window_id,
+                keymod,
+                ..
+            } => {
+                // Typing goes to the URL entry while it's edited
+                if window_id == start_window.id && start_window.is_editing_url() {
+                    self.handle_url_entry_key(keycode, keymod, start_window)?;
+                    return Ok(MainLoopState::Continue);
+                }
+
+                match keycode {
+                    Some(Keycode::Escape) => {
+                        if window_id == start_window.id {
+                            return Ok(MainLoopState::Quit);
                         }
-                        Some(Keycode::Down) => {
-                            safe_send(sender.send(EventMessage::DownVolume));
+                        if let Some(wind) = video_window {
+                            if window_id == wind.id {
+                                wind.set_fullscreen(FullscreenType::Off);
+                            }
                         }
-                        Some(Keycode::Left) => {
-                            safe_send(sender.send(EventMessage::Rewind));
+                    }
+                    // Move the visible area of zoomed picture if ctrl is pressed
+                    Some(Keycode::Up) if keymod.intersects(CTRL_MOD) => {
+                        safe_send(sender.send(EventMessage::Pan(0.0, -PAN_STEP)));
+                    }
+                    Some(Keycode::Down) if keymod.intersects(CTRL_MOD) => {
+                        safe_send(sender.send(EventMessage::Pan(0.0, PAN_STEP)));
+                    }
+                    Some(Keycode::Left) if keymod.intersects(CTRL_MOD) => {
+                        safe_send(sender.send(EventMessage::Pan(-PAN_STEP, 0.0)));
+                    }
+                    Some(Keycode::Right) if keymod.intersects(CTRL_MOD) => {
+                        safe_send(sender.send(EventMessage::Pan(PAN_STEP, 0.0)));
+                    }
+                    Some(Keycode::Up) => {
+                        safe_send(sender.send(EventMessage::UpVolume));
+                    }
+                    Some(Keycode::Down) => {
+                        safe_send(sender.send(EventMessage::DownVolume));
+                    }
+                    Some(Keycode::Left) => {
+                        safe_send(sender.send(EventMessage::Rewind));
+                    }
+                    Some(Keycode::Right) => {
+                        safe_send(sender.send(EventMessage::Forward));
+                    }
+                    // Slow down or speed up the playback
+                    Some(Keycode::Comma) => {
+                        let rate = (PLAYBACK_RATE.load() - RATE_STEP).max(MIN_PLAYBACK_RATE);
+                        safe_send(sender.send(EventMessage::SetRate(rate)));
+                    }
+                    Some(Keycode::Period) => {
+                        let rate = (PLAYBACK_RATE.load() + RATE_STEP).min(MAX_PLAYBACK_RATE);
+                        safe_send(sender.send(EventMessage::SetRate(rate)));
+                    }
+                    Some(Keycode::M) => {
+                        safe_send(sender.send(EventMessage::ToggleMute));
+                    }
+                    Some(Keycode::E) => {
+                        safe_send(sender.send(EventMessage::Dsp(DspCommand::CycleEqPreset)));
+                    }
+                    Some(Keycode::N) => {
+                        safe_send(sender.send(EventMessage::Dsp(DspCommand::CycleCompression)));
+                    }
+                    Some(Keycode::L) => {
+                        let command = DspCommand::ToggleLoudnessNormalization;
+                        safe_send(sender.send(EventMessage::Dsp(command)));
+                    }
+                    Some(Keycode::C) => {
+                        safe_send(sender.send(EventMessage::Dsp(DspCommand::CycleChannelMode)));
+                    }
+                    Some(Keycode::LeftBracket) => {
+                        let command = DspCommand::Balance(-BALANCE_STEP);
+                        safe_send(sender.send(EventMessage::Dsp(command)));
+                    }
+                    Some(Keycode::RightBracket) => {
+                        let command = DspCommand::Balance(BALANCE_STEP);
+                        safe_send(sender.send(EventMessage::Dsp(command)));
+                    }
+                    // Type the URL or path to open in the start window
+                    Some(Keycode::U) if keymod.intersects(CTRL_MOD) => {
+                        if window_id == start_window.id {
+                            start_window.edit_url(|entry| entry.begin(""))?;
+                            self.text_input.start();
                         }
-                        Some(Keycode::Right) => {
-                            safe_send(sender.send(EventMessage::Forward));
+                    }
+                    // Open the URL or path copied to clipboard, it can be edited in the start window
+                    Some(Keycode::V) if keymod.intersects(CTRL_MOD) => {
+                        let text = self.clipboard_text();
+                        if window_id == start_window.id {
+                            let text = text.unwrap_or_default();
+                            start_window.edit_url(|entry| entry.begin(&text))?;
+                            self.text_input.start();
+                        } else {
+                            match text.as_deref().and_then(MediaSource::parse) {
+                                Some(source) => {
+                                    safe_send(sender.send(EventMessage::SourceOpened(source)))
+                                }
+                                None => show_error("Copy a URL or file path to clipboard first"),
+                            }
                         }
-                        Some(Keycode::F4) => {
-                            return Ok(MainLoopState::Quit);
+                    }
+                    Some(Keycode::V) => {
+                        safe_send(sender.send(EventMessage::CycleVisualizer));
+                    }
+                    Some(Keycode::D) => {
+                        safe_send(sender.send(EventMessage::NextAudioDevice));
+                    }
+                    Some(Keycode::A) => {
+                        safe_send(sender.send(EventMessage::CycleDisplayMode));
+                    }
+                    Some(Keycode::Equals) | Some(Keycode::KpPlus) => {
+                        safe_send(sender.send(EventMessage::Zoom(ZOOM_STEP)));
+                    }
+                    Some(Keycode::Minus) | Some(Keycode::KpMinus) => {
+                        safe_send(sender.send(EventMessage::Zoom(1.0 / ZOOM_STEP)));
+                    }
+                    Some(Keycode::S) => {
+                        safe_send(sender.send(EventMessage::Screenshot));
+                    }
+                    Some(Keycode::F4) => {
+                        return Ok(MainLoopState::Quit);
+                    }
+                    Some(keycode) => {
+                        if let Some(command) = video_filter_command(keycode) {
+                            safe_send(sender.send(EventMessage::VideoFilter(command)));
                         }
-                        _ => {}
                     }
+                    _ => {}
                 }
-                Event::MouseMotion {
+            }
+            Event::TextInput {
+                window_id, text, ..
+            } => {
+                if window_id == start_window.id && start_window.is_editing_url() {
+                    start_window.edit_url(|entry| entry.insert(&text))?;
+                }
+            }
+            Event::MouseMotion {
+                timestamp,
+                window_id,
+                which,
+                mousestate,
+                x,
+                y,
+                xrel,
+                yrel,
+            } => {
+                let params = MouseMotionParam {
                     timestamp,
                     window_id,
                     which,
@@ -83,24 +292,23 @@ impl SdlEvents {
                     y,
                     xrel,
                     yrel,
-                } => {
-                    let params = MouseMotionParam {
-                        timestamp,
-                        window_id,
-                        which,
-                        mousestate,
-                        x,
-                        y,
-                        xrel,
-                        yrel,
-                    };
-
-                    start_window.on_mouse_motion(&params)?;
-                    if let Some(window) = video_window {
-                        window.on_mouse_motion(&params)?;
-                    }
+                };
+
+                start_window.on_mouse_motion(&params)?;
+                if let Some(window) = video_window {
+                    window.on_mouse_motion(&params)?;
                 }
-                Event::MouseButtonUp {
+            }
+            Event::MouseButtonUp {
+                timestamp,
+                window_id,
+                which,
+                mouse_btn,
+                clicks,
+                x,
+                y,
+            } => {
+                let params = MouseUpParam {
                     timestamp,
                     window_id,
                     which,
@@ -108,22 +316,22 @@ impl SdlEvents {
                     clicks,
                     x,
                     y,
-                } => {
-                    let params = MouseUpParam {
-                        timestamp,
-                        window_id,
-                        which,
-                        mouse_btn,
-                        clicks,
-                        x,
-                        y,
-                    };
-                    start_window.on_mouse_up(&params)?;
-                    if let Some(window) = video_window {
-                        window.on_mouse_up(&params)?;
-                    }
+                };
+                start_window.on_mouse_up(&params)?;
+                if let Some(window) = video_window {
+                    window.on_mouse_up(&params)?;
                 }
-                Event::MouseButtonDown {
+            }
+            Event::MouseButtonDown {
+                timestamp,
+                window_id,
+                which,
+                mouse_btn,
+                clicks,
+                x,
+                y,
+            } => {
+                let params = MouseDownParam {
                     timestamp,
                     window_id,
                     which,
@@ -131,61 +339,91 @@ impl SdlEvents {
                     clicks,
                     x,
                     y,
-                } => {
-                    let params = MouseDownParam {
-                        timestamp,
-                        window_id,
-                        which,
-                        mouse_btn,
-                        clicks,
-                        x,
-                        y,
-                    };
-
-                    start_window.on_mouse_down(&params)?;
-                    if let Some(window) = video_window {
-                        window.on_mouse_down(&params)?;
-                    }
+                };
+
+                start_window.on_mouse_down(&params)?;
+                if let Some(window) = video_window {
+                    window.on_mouse_down(&params)?;
                 }
-                Event::MouseWheel {
+            }
+            Event::MouseWheel {
+                timestamp,
+                window_id,
+                which,
+                x,
+                y,
+                direction,
+            } => {
+                let params = MouseWheelParam {
                     timestamp,
                     window_id,
                     which,
                     x,
                     y,
                     direction,
-                } => {
-                    let params = MouseWheelParam {
-                        timestamp,
-                        window_id,
-                        which,
-                        x,
-                        y,
-                        direction,
-                    };
-
-                    start_window.on_mouse_wheel(&params)?;
+                };
+
+                start_window.on_mouse_wheel(&params)?;
+                if let Some(window) = video_window {
+                    window.on_mouse_wheel(&params)?;
+                }
+            }
+            Event::Window {
+                timestamp: _,
+                window_id,
+                win_event,
+            } => match win_event {
+                WindowEvent::Resized(width, height) => {
                     if let Some(window) = video_window {
-                        window.on_mouse_wheel(&params)?;
+                        if window_id == window.id {
+                            window.on_resized(width as u32, height as u32);
+                        }
                     }
                 }
-                Event::Window {
-                    timestamp: _,
-                    window_id,
-                    win_event,
-                } => match win_event {
-                    WindowEvent::Resized(width, height) => {
-                        if let Some(window) = video_window {
-                            if window_id == window.id {
-                                window.on_resized(width as u32, height as u32);
-                            }
+                // The content of window is lost, render it again
+                WindowEvent::Exposed | WindowEvent::Restored => {
+                    if let Some(window) = video_window {
+                        if window_id == window.id {
+                            window.invalidate();
                         }
                     }
-                    _ => {}
-                },
-                _ => return Ok(MainLoopState::Continue),
+                }
+                _ => {}
+            },
+            Event::AudioDeviceAdded {
+                iscapture: false, ..
             }
+            | Event::AudioDeviceRemoved {
+                iscapture: false, ..
+            } => {
+                safe_send(sender.send(EventMessage::AudioDevicesChanged));
+            }
+            _ => return Ok(MainLoopState::Continue),
         }
+
         Ok(MainLoopState::Continue)
     }
 }
+
+/// Keys of video filters, the number keys decrease and increase picture adjustments in pairs
+fn video_filter_command(keycode: Keycode) -> Option<VideoFilterCommand> {
+    let command = match keycode {
+        Keycode::I => VideoFilterCommand::CycleDeinterlace,
+        Keycode::R => VideoFilterCommand::ToggleAutoRotate,
+        Keycode::X => VideoFilterCommand::ToggleCrop,
+        Keycode::O => VideoFilterCommand::ToggleDenoise,
+        Keycode::H => VideoFilterCommand::ToggleSharpen,
+        Keycode::Num1 => VideoFilterCommand::Brightness(-PICTURE_ADJUST_STEP),
+        Keycode::Num2 => VideoFilterCommand::Brightness(PICTURE_ADJUST_STEP),
+        Keycode::Num3 => VideoFilterCommand::Contrast(-PICTURE_ADJUST_STEP),
+        Keycode::Num4 => VideoFilterCommand::Contrast(PICTURE_ADJUST_STEP),
+        Keycode::Num5 => VideoFilterCommand::Saturation(-PICTURE_ADJUST_STEP),
+        Keycode::Num6 => VideoFilterCommand::Saturation(PICTURE_ADJUST_STEP),
+        Keycode::Num7 => VideoFilterCommand::Gamma(-PICTURE_ADJUST_STEP),
+        Keycode::Num8 => VideoFilterCommand::Gamma(PICTURE_ADJUST_STEP),
+        Keycode::Num0 => VideoFilterCommand::ResetAdjustments,
+        _ => return None,
+    };
+
+    Some(command)
+}
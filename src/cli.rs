@@ -0,0 +1,266 @@
+use std::path::PathBuf;
+
+use crate::{
+    media::source::MediaSource,
+    ui::renderer::{RendererBackend, RendererOverrides},
+    util::error::PlayerError,
+};
+
+/// Usage of command line
+pub const USAGE: &str = "\
+Usage:
+    ntplayer [<media>] [--renderer <backend>] [--vsync <on|off>] [--video-driver <name>]
+    ntplayer --extract-frames <media> --at <timestamps> [--out <dir>]
+    ntplayer --benchmark <media> [--threads <count>]
+
+Arguments:
+    <media>                   File path or URL to play, `-` reads the standard input
+
+Options:
+    --extract-frames <media>  Extract frames as PNG without opening a window
+    --at <timestamps>         Comma separated timestamps, formatted as
+                              `seconds`, `mm:ss` or `hh:mm:ss`, e.g. 90.5,01:30,1:02:03.250
+    --out <dir>               Output directory of extracted frames
+    --benchmark <media>       Decode the video as fast as possible and report the fps
+    --threads <count>         Decoding threads of the benchmark, 0 picks it from CPU cores
+    --renderer <backend>      Renderer of the windows: auto, software, opengl or opengles2
+    --vsync <on|off>          Wait for the vertical blank of the display when presenting
+    --video-driver <name>     Video driver of SDL, e.g. x11, wayland, or dummy for tests";
+
+/// The command parsed from command line arguments
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CliCommand {
+    /// Start the player with window, the media is played at once if it's specified,
+    /// and the renderer options override the config
+    Play(Option<MediaSource>, RendererOverrides),
+    /// Extract frames at specified timestamps, timestamps are in milliseconds
+    ExtractFrames {
+        media: PathBuf,
+        timestamps: Vec<i64>,
+        out_dir: Option<PathBuf>,
+    },
+    /// Report how fast the video is decoded, threads of config are used if not specified
+    Benchmark {
+        media: PathBuf,
+        threads: Option<usize>,
+    },
+}
+
+/// Parse the command line arguments, the program name should be excluded
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<CliCommand, PlayerError> {
+    let mut media = None;
+    let mut timestamps = None;
+    let mut out_dir = None;
+    let mut source = None;
+    let mut benchmark = None;
+    let mut threads = None;
+    let mut renderer = RendererOverrides::default();
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| PlayerError::InvalidArgument(format!("Missing value of {}\n\n{}", arg, USAGE)))
+        };
+
+        match arg.as_str() {
+            "--extract-frames" => media = Some(PathBuf::from(value()?)),
+            "--at" => {
+                let list = value()?
+                    .split(',')
+                    .map(parse_timestamp)
+                    .collect::<Result<Vec<i64>, PlayerError>>()?;
+                timestamps = Some(list);
+            }
+            "--out" => out_dir = Some(PathBuf::from(value()?)),
+            "--benchmark" => benchmark = Some(PathBuf::from(value()?)),
+            "--threads" => {
+                let count = value()?;
+                let count = count.parse::<usize>().map_err(|_| {
+                    PlayerError::InvalidArgument(format!("Invalid thread count: {}", count))
+                })?;
+                threads = Some(count);
+            }
+            "--renderer" => renderer.backend = Some(value()?.parse::<RendererBackend>()?),
+            "--vsync" => {
+                let vsync = value()?;
+                renderer.vsync = match vsync.as_str() {
+                    "on" => Some(true),
+                    "off" => Some(false),
+                    _ => {
+                        return Err(PlayerError::InvalidArgument(format!(
+                            "Invalid vsync: {}, it should be on or off",
+                            vsync
+                        )))
+                    }
+                };
+            }
+            "--video-driver" => renderer.video_driver = Some(value()?),
+            _ if source.is_none() && (arg == "-" || !arg.starts_with('-')) => {
+                source = MediaSource::parse(&arg);
+            }
+            _ => {
+                return Err(PlayerError::InvalidArgument(format!(
+                    "Unknown argument: {}\n\n{}",
+                    arg, USAGE
+                )))
+            }
+        }
+    }
+
+    let playing = benchmark.is_none() && media.is_none() && timestamps.is_none();
+    if !playing && renderer != RendererOverrides::default() {
+        return Err(PlayerError::InvalidArgument(format!(
+            "--renderer, --vsync and --video-driver should be used when playing\n\n{}",
+            USAGE
+        )));
+    }
+
+    match (benchmark, threads) {
+        (Some(media), threads) => return Ok(CliCommand::Benchmark { media, threads }),
+        (None, Some(_)) => {
+            return Err(PlayerError::InvalidArgument(format!(
+                "--threads should be used with --benchmark\n\n{}",
+                USAGE
+            )))
+        }
+        (None, None) => {}
+    }
+
+    match (media, timestamps) {
+        (None, None) => Ok(CliCommand::Play(source, renderer)),
+        (Some(media), Some(timestamps)) => Ok(CliCommand::ExtractFrames {
+            media,
+            timestamps,
+            out_dir,
+        }),
+        _ => Err(PlayerError::InvalidArgument(format!(
+            "--extract-frames and --at should be used together\n\n{}",
+            USAGE
+        ))),
+    }
+}
+
+/// Parse timestamp formatted as `seconds`, `mm:ss` or `hh:mm:ss` to milliseconds,
+/// the seconds part can have a fraction
+pub fn parse_timestamp(text: &str) -> Result<i64, PlayerError> {
+    let invalid = || PlayerError::InvalidArgument(format!("Invalid timestamp: {}", text));
+
+    let parts: Vec<&str> = text.trim().split(':').collect();
+    if parts.is_empty() || parts.len() > 3 {
+        return Err(invalid());
+    }
+
+    let (seconds, units) = parts.split_last().unwrap();
+    let seconds: f64 = seconds.parse().map_err(|_| invalid())?;
+    if seconds < 0.0 {
+        return Err(invalid());
+    }
+
+    let mut millis = (seconds * 1000.0).round() as i64;
+    let mut factor = 60_000;
+    for unit in units.iter().rev() {
+        let unit: i64 = unit.parse().map_err(|_| invalid())?;
+        if unit < 0 {
+            return Err(invalid());
+        }
+        millis += unit * factor;
+        factor *= 60;
+    }
+
+    Ok(millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(90500, parse_timestamp("90.5").unwrap());
+        assert_eq!(90000, parse_timestamp("01:30").unwrap());
+        assert_eq!(3723250, parse_timestamp("1:02:03.250").unwrap());
+        assert!(parse_timestamp("abc").is_err());
+        assert!(parse_timestamp("1:2:3:4").is_err());
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = ["--extract-frames", "a.mp4", "--at", "1,2.5", "--out", "shots"]
+            .iter()
+            .map(|s| s.to_string());
+        assert_eq!(
+            CliCommand::ExtractFrames {
+                media: PathBuf::from("a.mp4"),
+                timestamps: vec![1000, 2500],
+                out_dir: Some(PathBuf::from("shots")),
+            },
+            parse_args(args).unwrap()
+        );
+        let renderer = RendererOverrides::default();
+        assert_eq!(
+            CliCommand::Play(None, renderer.clone()),
+            parse_args(Vec::new()).unwrap()
+        );
+        assert_eq!(
+            CliCommand::Play(Some(MediaSource::Stdin), renderer.clone()),
+            parse_args(vec!["-".to_string()]).unwrap()
+        );
+        assert_eq!(
+            CliCommand::Play(
+                Some(MediaSource::Url("https://a.com/a.m3u8".to_string())),
+                renderer
+            ),
+            parse_args(vec!["https://a.com/a.m3u8".to_string()]).unwrap()
+        );
+        assert!(parse_args(vec!["--at".to_string(), "1".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_parse_benchmark() {
+        let args = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            CliCommand::Benchmark {
+                media: PathBuf::from("4k.mkv"),
+                threads: Some(8),
+            },
+            parse_args(args(&["--benchmark", "4k.mkv", "--threads", "8"])).unwrap()
+        );
+        assert_eq!(
+            CliCommand::Benchmark {
+                media: PathBuf::from("4k.mkv"),
+                threads: None,
+            },
+            parse_args(args(&["--benchmark", "4k.mkv"])).unwrap()
+        );
+        assert!(parse_args(args(&["--benchmark", "4k.mkv", "--threads", "many"])).is_err());
+        assert!(parse_args(args(&["--threads", "8"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_renderer() {
+        let args = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            CliCommand::Play(
+                None,
+                RendererOverrides {
+                    backend: Some(RendererBackend::Software),
+                    vsync: Some(false),
+                    video_driver: Some("dummy".to_string()),
+                }
+            ),
+            parse_args(args(&[
+                "--renderer",
+                "software",
+                "--vsync",
+                "off",
+                "--video-driver",
+                "dummy"
+            ]))
+            .unwrap()
+        );
+        assert!(parse_args(args(&["--renderer", "vulkan"])).is_err());
+        assert!(parse_args(args(&["--vsync", "maybe"])).is_err());
+        assert!(parse_args(args(&["--benchmark", "4k.mkv", "--renderer", "opengl"])).is_err());
+    }
+}
@@ -9,6 +9,15 @@ extern crate serde;
 extern crate serde_yaml;
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    media::{
+        decoder::DecoderOptions, source::NetworkOptions, tone_map::ToneMapOperator,
+        video_filter::VideoFilterSettings,
+    },
+    sound::dsp::DspSettings,
+    ui::renderer::RendererOptions,
+};
+
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct Config {
     /// Options for move action
@@ -17,6 +26,33 @@ pub struct Config {
     pub cp: CopyOptions,
     /// Options for delete action
     pub del: DeleteOptions,
+    /// Options for media playing
+    #[serde(default)]
+    pub player: PlayerOptions,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Default, Clone)]
+pub struct PlayerOptions {
+    /// Name of the audio playback device, the default device is used if it's not set
+    pub audio_device: Option<String>,
+    /// Audio processing applied at startup
+    #[serde(default)]
+    pub dsp: DspSettings,
+    /// Video filters applied at startup
+    #[serde(default)]
+    pub video_filters: VideoFilterSettings,
+    /// Timeouts and reconnection of network streams
+    #[serde(default)]
+    pub network: NetworkOptions,
+    /// Threads of video and audio decoders
+    #[serde(default)]
+    pub decoder: DecoderOptions,
+    /// How HDR videos are tone mapped to SDR
+    #[serde(default)]
+    pub tone_map: ToneMapOperator,
+    /// Renderer backend, vsync and video driver of the windows
+    #[serde(default)]
+    pub renderer: RendererOptions,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
@@ -62,7 +98,7 @@ pub struct Filters {
 
 impl Config {
     pub fn load() -> Option<Config> {
-        let f = File::open("config.yml").unwrap();
+        let f = File::open("config.yml").ok()?;
         match serde_yaml::from_reader(f) {
             Ok(config) => config,
             Err(e) => {
@@ -85,6 +121,13 @@ impl Config {
     }
 }
 
+impl PlayerOptions {
+    /// Load player options from config file, the default options are used if it's not available
+    pub fn load() -> PlayerOptions {
+        Config::load().map(|config| config.player).unwrap_or_default()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
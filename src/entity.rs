@@ -1,7 +1,17 @@
 use sdl2::video::WindowPos;
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
-use crate::media::decoder::{AudioFrame, SubtitleFrame, VideoFrame};
+use crate::{
+    media::{
+        decoder::{MediaDecoder, SubtitleFrame, VideoFrame},
+        metadata::MediaInfo,
+        player::state::PlaybackState,
+        source::MediaSource,
+        thumbnail::Thumbnail,
+        video_filter::VideoFilterCommand,
+    },
+    sound::dsp::DspCommand,
+};
 
 /// Message types for application related events
 pub enum EventMessage {
@@ -14,24 +24,46 @@ pub enum EventMessage {
     ShowError(String),
 
     // For media state control
-    Play(PathBuf),
+    Play(MediaSource),
     Pause,
     Resume,
     Stop,
     Forward,
     Rewind,
+    /// Seek to the position of playing media
+    Seek(Duration),
+    /// Change the playback speed, 1.0 is the normal speed
+    SetRate(f64),
 
     // Indicate that forward or rewind operation has been completed
     SeekFinished,
+    /// The playback state has changed to the new one
+    StateChanged(PlaybackState),
+    /// The decoder with the id has decoded all frames of its media
+    EndOfMedia(u64),
+    /// The audio of the decoder with the id can't be decoded, the video goes on
+    AudioLost(u64),
+    /// The video of the decoder with the id can't be decoded, the audio goes on
+    VideoLost(u64),
+    /// The decoder with the id failed to read or decode its media
+    MediaError(u64, String),
+
+    /// The media with the opening id is opened by a worker thread, or failed with the error
+    MediaOpened(u64, MediaSource, Result<MediaDecoder, String>),
 
     // File
-    FileOpened(PathBuf),
+    /// A file, URL or the standard input is opened
+    SourceOpened(MediaSource),
     DirOpened(Vec<PathBuf>),
 
     // Rendering
     RenderVideo(VideoFrame),
-    RenderAudio(AudioFrame),
     RenderSubtitle(SubtitleFrame),
+    RenderThumbnail(Thumbnail),
+    /// Tags and cover art of the opened media
+    MediaInfo(MediaInfo),
+    /// Switch between the spectrum and the waveform in audio-only mode
+    CycleVisualizer,
 
     // UI layout
     Resize((u32, u32)),
@@ -40,9 +72,33 @@ pub enum EventMessage {
         y: WindowPos,
     },
 
+    // Picture layout
+    CycleDisplayMode,
+    /// Multiply the zoom factor of picture
+    Zoom(f32),
+    /// Move the visible area of zoomed picture, values are in range `-1.0..=1.0`
+    Pan(f32, f32),
+
+    /// Change the video filters
+    VideoFilter(VideoFilterCommand),
+
+    /// Save the displayed video frame as PNG
+    Screenshot,
+
     // Volume control
     UpVolume,
     DownVolume,
+    ToggleMute,
+    /// Change the audio processing
+    Dsp(DspCommand),
+
+    // Audio device
+    /// Playback devices are added or removed
+    AudioDevicesChanged,
+    /// Switch to the next playback device, the default device is used after the last one
+    NextAudioDevice,
+    /// The playing audio doesn't fit the opened device, it has to be reopened
+    AudioFormatChanged,
 }
 
 pub struct MediaSelectedData {
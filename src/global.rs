@@ -1,16 +1,29 @@
-use crossbeam::channel::unbounded;
+use crossbeam::{atomic::AtomicCell, channel::unbounded};
 
 use sdl2::pixels::Color;
 use static_init::dynamic;
-use std::sync::{
-    atomic::{AtomicI16, AtomicI64},
-    RwLock,
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, AtomicI16, AtomicI64, AtomicU64},
+        Mutex, RwLock,
+    },
+    time::Duration,
 };
 
 use crate::{
-    media::decoder::{
-        AudioBuffer, AudioSummary, SubtitleBuffer, SubtitleSummary, VideoBuffer, VideoSummary,
+    media::{
+        decoder::{
+            AudioBuffer, AudioSummary, DecoderOptions, SubtitleBuffer, SubtitleSummary,
+            VideoBuffer, VideoSummary,
+        },
+        source::NetworkOptions,
+        tone_map::ToneMapOperator,
+        video_filter::VideoFilterSettings,
     },
+    sound::{dsp::DspSettings, visualizer::WINDOW_SIZE},
+    ui::renderer::RendererOptions,
+    util::buffer_pool::BufferPool,
     EventMessage,
 };
 use crossbeam::channel::{Receiver, Sender};
@@ -28,19 +41,40 @@ pub const APP_NAME: &str = "NT Player";
 pub const LOGO_PATH: &str = "./assets/logo.png";
 pub const INIT_WIDTH: u32 = 1024;
 pub const INIT_HEIGHT: u32 = 768;
+/// The interval between two presentations of video window, about 120 fps
+pub const PRESENT_INTERVAL: Duration = Duration::from_millis(8);
+/// The directory where screenshots are saved
+pub const SCREENSHOT_DIR: &str = "./screenshots";
+/// The longest time to wait for a background thread to exit on stop
+pub const THREAD_JOIN_TIMEOUT: Duration = Duration::from_secs(2);
 
 //
 // Media related
 //
 /// Forward or rewind amount each time, Unit: milliseconds
 pub const FR_STEP: i64 = 10000;
-/// Global volume, modify this value will affect to the play volume
-pub static VOLUME: AtomicI16 = AtomicI16::new(50);
-pub static VOLUME_STEP: i16 = 10;
-pub const MAX_VOLUME: i16 = 2000;
-pub const VOLUME_BENCHMARK: f32 = 50.0;
+/// Global volume in percent, 100 is the original loudness.
+/// It's applied by audio output, so the change takes effect immediately
+pub static VOLUME: AtomicI16 = AtomicI16::new(100);
+pub static VOLUME_STEP: i16 = 5;
+pub const MAX_VOLUME: i16 = 150;
+/// The maximum amplification of volume, unit: dB
+pub const MAX_GAIN_DB: f32 = 12.0;
+/// Mute the audio output without changing the volume
+pub static MUTED: AtomicBool = AtomicBool::new(false);
+/// Set if no audio device can be opened, the media is played without sound
+pub static AUDIO_DISABLED: AtomicBool = AtomicBool::new(false);
+/// Integrated loudness of the playing media measured by decoder ahead of playing, unit: LUFS
+pub static MEASURED_LOUDNESS: AtomicCell<f64> = AtomicCell::new(f64::NEG_INFINITY);
 /// Global play timestamp, unit milliseconds+
 pub static GLOBAL_PTS_MILLIS: AtomicI64 = AtomicI64::new(0);
+/// Media of `GLOBAL_PTS_MILLIS`, it's the `media` of the audio frame being played.
+/// Load it after the pts, pts starts over at the next media of playlist
+pub static GLOBAL_PTS_MEDIA: AtomicU64 = AtomicU64::new(0);
+/// Playback speed, 1.0 is the normal speed. Audio is resampled for it, so the pitch changes
+pub static PLAYBACK_RATE: AtomicCell<f64> = AtomicCell::new(1.0);
+pub const MIN_PLAYBACK_RATE: f64 = 0.25;
+pub const MAX_PLAYBACK_RATE: f64 = 4.0;
 pub static AUDIO_SUMMARY: RwLock<Option<AudioSummary>> = RwLock::new(None);
 pub static VIDEO_SUMMARY: RwLock<Option<VideoSummary>> = RwLock::new(None);
 pub static SUBTITLE_SUMMARY: RwLock<Option<SubtitleSummary>> = RwLock::new(None);
@@ -66,3 +100,31 @@ pub static AUDIO_BUFFER: AudioBuffer = AudioBuffer::new(50);
 pub static VIDEO_BUFFER: VideoBuffer = VideoBuffer::new(10);
 #[dynamic]
 pub static SUBTITLE_BUFFER: SubtitleBuffer = SubtitleBuffer::new(5);
+/// Audio processing applied by audio output, changes are handed to it by `Sounder::update_dsp`
+#[dynamic]
+pub static DSP_SETTINGS: RwLock<DspSettings> = RwLock::new(DspSettings::default());
+/// Video filters applied by decoder, modify it to take effect from the next frame
+#[dynamic]
+pub static VIDEO_FILTER_SETTINGS: RwLock<VideoFilterSettings> =
+    RwLock::new(VideoFilterSettings::default());
+/// Options used when network streams are opened
+#[dynamic]
+pub static NETWORK_OPTIONS: RwLock<NetworkOptions> = RwLock::new(NetworkOptions::default());
+/// Threads of the decoders opened from now on
+#[dynamic]
+pub static DECODER_OPTIONS: RwLock<DecoderOptions> = RwLock::new(DecoderOptions::default());
+/// How HDR videos are tone mapped to SDR, modify it to take effect from the next frame
+#[dynamic]
+pub static TONE_MAP_OPERATOR: RwLock<ToneMapOperator> = RwLock::new(ToneMapOperator::default());
+/// Renderer of the windows created from now on
+#[dynamic]
+pub static RENDERER_OPTIONS: RwLock<RendererOptions> = RwLock::new(RendererOptions::default());
+/// The latest mono samples sent to audio device, they are visualized in audio-only mode
+#[dynamic]
+pub static PLAYED_SAMPLES: Mutex<VecDeque<f32>> = Mutex::new(VecDeque::with_capacity(WINDOW_SIZE));
+/// Buffers of interleaved audio samples, enough for `AUDIO_BUFFER` and a preloaded media
+#[dynamic]
+pub static AUDIO_SAMPLE_POOL: BufferPool<f32> = BufferPool::new(64);
+/// Buffers of video planes copied from frames whose rows go upwards
+#[dynamic]
+pub static VIDEO_PLANE_POOL: BufferPool<u8> = BufferPool::new(64);
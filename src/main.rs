@@ -1,6 +1,7 @@
 // Hide console window on Windows platform, never remove it
 // #![windows_subsystem = "windows"]
 
+mod cli;
 mod config;
 mod entity;
 mod filemanager;
@@ -11,20 +12,67 @@ mod ui;
 mod util;
 mod sound;
 
-use log::error;
+use log::{error, info};
+use std::sync::Arc;
 
+use cli::CliCommand;
+use config::PlayerOptions;
 use entity::EventMessage;
+use global::EVENT_CHANNEL;
 use app::NtApp;
 use ui::components::dialog::show_error;
-use util::{error::SuperError, log_builder};
+use util::{
+    error::{safe_send, PlayerError},
+    log_builder,
+};
 
 // Four threads, one for decoding, one for playing audio,
 // one for playing video, maint thread for rendering audio and video
-fn main() -> Result<(), SuperError> {
+fn main() -> Result<(), PlayerError> {
     log_builder::load_logger(log::LevelFilter::Debug);
 
-    match NtApp::new() {
+    let (source, renderer) = match cli::parse_args(std::env::args().skip(1))? {
+        CliCommand::Play(source, renderer) => (source, renderer),
+        CliCommand::ExtractFrames {
+            media,
+            timestamps,
+            out_dir,
+        } => {
+            // Extract frames without opening any window. The command line tools run until
+            // done or the process is ended, so nothing interrupts them
+            let files = media::screenshot::extract_frames(
+                &media,
+                &timestamps,
+                out_dir.as_deref(),
+                Arc::default(),
+            )?;
+            for file in files {
+                info!("frame extracted: {}", file.display());
+                println!("{}", file.display());
+            }
+
+            return Ok(());
+        }
+        CliCommand::Benchmark { media, threads } => {
+            let mut options = PlayerOptions::load().decoder;
+            if let Some(threads) = threads {
+                options.threads = threads;
+            }
+            let result = media::benchmark::benchmark_decoding(&media, &options, Arc::default())?;
+            info!("decoding benchmark: {}", result);
+            println!("{}", result);
+
+            return Ok(());
+        }
+    };
+
+    match NtApp::new(renderer) {
         Ok(mut app) => {
+            // The message is handled once the main loop is running
+            if let Some(source) = source {
+                safe_send(EVENT_CHANNEL.0.send(EventMessage::SourceOpened(source)));
+            }
+
             // The error occurred while app running, should be logged and shown
             if let Err(err) = app.run() {
                 error!("{}", err);
@@ -35,7 +83,7 @@ fn main() -> Result<(), SuperError> {
             error!("{}", err);
             show_error(err.to_string().as_str());
             // App init error, it makes no sence to continue
-            panic!()
+            std::process::exit(1);
         }
     }
 
@@ -0,0 +1,315 @@
+use std::{
+    fmt,
+    path::Path,
+    sync::{atomic::AtomicBool, Arc},
+    time::{Duration, Instant},
+};
+
+use log::warn;
+use rsmpeg::{
+    avcodec::AVCodecContext,
+    avutil::AVFrame,
+    error::RsmpegError,
+    ffi::{
+        AVMediaType_AVMEDIA_TYPE_AUDIO as AVMEDIATYPE_AVMEDIA_TYPE_AUDIO,
+        AVMediaType_AVMEDIA_TYPE_VIDEO as AVMEDIATYPE_AVMEDIA_TYPE_VIDEO,
+        AVSampleFormat_AV_SAMPLE_FMT_FLTP as AVSAMPLEFORMAT_AV_SAMPLE_FMT_FLTP,
+    },
+};
+
+use crate::util::{
+    buffer_pool::{BufferPool, PooledBuffer},
+    error::PlayerError,
+    pixel_format::FramePlanes,
+    sample_format::interleave_samples,
+};
+
+use super::{
+    decoder::{DecoderOptions, MediaDecoder},
+    source::MediaSource,
+};
+
+/// Bytes in a MiB
+const MIB: f64 = 1024.0 * 1024.0;
+/// Idle buffers kept by the pools of a pass, as many as `VIDEO_PLANE_POOL` and
+/// `AUDIO_SAMPLE_POOL` keep
+const POOL_CAPACITY: usize = 64;
+
+/// How the decoded frames are handed off to the renderer and the audio device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HandOff {
+    /// Every frame is copied into new buffers
+    Copy,
+    /// Video frames are shared unless flipped, and samples are converted into pooled buffers
+    Share,
+}
+
+/// Cost of handing off all frames of the file in one way
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct HandOffCost {
+    pub video_frames: u64,
+    pub audio_frames: u64,
+    pub elapsed: Duration,
+    /// Bytes of pixels and samples handed off
+    pub handed_off_bytes: u64,
+    /// Bytes of pixels and samples copied while handing off
+    pub copied_bytes: u64,
+    /// Buffers allocated for the copies
+    pub allocations: usize,
+}
+
+impl HandOffCost {
+    /// Decoded video frames per second
+    pub fn fps(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.video_frames as f64 / secs
+        } else {
+            0.0
+        }
+    }
+}
+
+impl fmt::Display for HandOffCost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} video and {} audio frames in {:.2}s, {:.1} fps, \
+             {:.1} MiB handed off, {:.1} MiB copied, {} buffers allocated",
+            self.video_frames,
+            self.audio_frames,
+            self.elapsed.as_secs_f64(),
+            self.fps(),
+            self.handed_off_bytes as f64 / MIB,
+            self.copied_bytes as f64 / MIB,
+            self.allocations
+        )
+    }
+}
+
+/// Result of decoding a file as fast as possible, once copying every frame
+/// as frames used to be handed off, and once sharing them as playing does
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodeBenchmark {
+    /// Name of the video decoder
+    pub decoder_name: String,
+    /// Number of threads the decoders are opened with
+    pub threads: usize,
+    pub copy: HandOffCost,
+    pub share: HandOffCost,
+}
+
+impl fmt::Display for DecodeBenchmark {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} with {} threads", self.decoder_name, self.threads)?;
+        writeln!(f, "copy: {}", self.copy)?;
+        write!(f, "share: {}", self.share)
+    }
+}
+
+/// Decode all frames of the file twice and hand them off without playing them, it tells
+/// whether the machine keeps up with the media, how many threads help, and how much
+/// sharing the frames saves. Reading the media fails once `interrupt` is set
+pub fn benchmark_decoding(
+    media: &Path,
+    options: &DecoderOptions,
+    interrupt: Arc<AtomicBool>,
+) -> Result<DecodeBenchmark, PlayerError> {
+    let (decoder_name, copy) = decode_file(media, options, interrupt.clone(), HandOff::Copy)?;
+    let (_, share) = decode_file(media, options, interrupt, HandOff::Share)?;
+
+    Ok(DecodeBenchmark {
+        decoder_name,
+        threads: options.thread_count(),
+        copy,
+        share,
+    })
+}
+
+/// Decode the video and the audio of the file, returns the name of the video decoder as well
+fn decode_file(
+    media: &Path,
+    options: &DecoderOptions,
+    interrupt: Arc<AtomicBool>,
+    hand_off: HandOff,
+) -> Result<(String, HandOffCost), PlayerError> {
+    let source = MediaSource::File(media.to_path_buf());
+    let mut ctx = MediaDecoder::get_media_context(&source, interrupt)?;
+
+    let (video_index, codec) = ctx
+        .find_best_stream(AVMEDIATYPE_AVMEDIA_TYPE_VIDEO)?
+        .ok_or_else(|| PlayerError::InvalidMedia("No video stream found".to_string()))?;
+    let decoder_name = codec.name().to_str().unwrap_or("unknown").to_string();
+    let mut video = {
+        let stream = ctx.streams().get(video_index).ok_or_else(|| {
+            PlayerError::InvalidMedia("Video stream is not accessible".to_string())
+        })?;
+        MediaDecoder::open_decoder(&codec, &stream.codecpar(), options)?
+    };
+    // Audio is optional, a silent video is benchmarked as well
+    let mut audio = match ctx.find_best_stream(AVMEDIATYPE_AVMEDIA_TYPE_AUDIO)? {
+        Some((index, codec)) => {
+            let stream = ctx.streams().get(index).ok_or_else(|| {
+                PlayerError::InvalidMedia("Audio stream is not accessible".to_string())
+            })?;
+            let dctx = MediaDecoder::open_decoder(&codec, &stream.codecpar(), options)?;
+            Some((index as i32, dctx))
+        }
+        None => None,
+    };
+
+    // Pools of this pass only, so nothing else is counted
+    let plane_pool = BufferPool::new(POOL_CAPACITY);
+    let sample_pool = BufferPool::new(POOL_CAPACITY);
+    let mut cost = HandOffCost::default();
+    let start = Instant::now();
+    while let Some(packet) = ctx.read_packet()? {
+        let dctx = if packet.stream_index == video_index as i32 {
+            &mut video
+        } else {
+            match audio.as_mut() {
+                Some((index, dctx)) if packet.stream_index == *index => dctx,
+                _ => continue,
+            }
+        };
+        // Damaged packets are skipped as the player does
+        if let Err(err) = dctx.send_packet(Some(&packet)) {
+            warn!("Failed to decode packet: {}", err);
+            continue;
+        }
+        receive_frames(dctx, |frame| {
+            if packet.stream_index == video_index as i32 {
+                hand_off_video(frame, hand_off, &plane_pool, &mut cost);
+            } else {
+                hand_off_audio(&frame, hand_off, &sample_pool, &mut cost);
+            }
+        });
+    }
+    // The decoders hold the last frames until an empty packet is sent
+    video.send_packet(None)?;
+    receive_frames(&mut video, |frame| {
+        hand_off_video(frame, hand_off, &plane_pool, &mut cost)
+    });
+    if let Some((_, dctx)) = audio.as_mut() {
+        dctx.send_packet(None)?;
+        receive_frames(dctx, |frame| {
+            hand_off_audio(&frame, hand_off, &sample_pool, &mut cost)
+        });
+    }
+
+    cost.elapsed = start.elapsed();
+    cost.allocations += plane_pool.allocations() + sample_pool.allocations();
+    Ok((decoder_name, cost))
+}
+
+/// Take all frames decoded so far.
+/// A failure ends the frames of the packet, the next packets are still decoded as the player does
+fn receive_frames(dctx: &mut AVCodecContext, mut hand_off: impl FnMut(AVFrame)) {
+    loop {
+        match dctx.receive_frame() {
+            Ok(frame) => hand_off(frame),
+            Err(RsmpegError::DecoderDrainError | RsmpegError::DecoderFlushedError) => return,
+            Err(err) => {
+                // Asking again may fail the same way forever
+                warn!("Failed to decode frame: {}", err);
+                return;
+            }
+        }
+    }
+}
+
+/// Hand off the pixels of the frame, they're released at once as if rendered
+fn hand_off_video(
+    frame: AVFrame,
+    hand_off: HandOff,
+    pool: &BufferPool<u8>,
+    cost: &mut HandOffCost,
+) {
+    let planes = match hand_off {
+        HandOff::Copy => {
+            let planes = FramePlanes::copied(&frame, || PooledBuffer::from(Vec::new()));
+            cost.allocations += (0..).map_while(|index| planes.get(index)).count();
+            planes
+        }
+        HandOff::Share => FramePlanes::with_pool(frame, pool),
+    };
+
+    let bytes = planes.byte_size() as u64;
+    cost.video_frames += 1;
+    cost.handed_off_bytes += bytes;
+    if !planes.is_shared() {
+        cost.copied_bytes += bytes;
+    }
+}
+
+/// Convert the samples of the frame as the player does, they're released at once as if played.
+/// Only planar float stereo is converted by the player, other frames aren't counted
+fn hand_off_audio(
+    frame: &AVFrame,
+    hand_off: HandOff,
+    pool: &BufferPool<f32>,
+    cost: &mut HandOffCost,
+) {
+    if frame.format != AVSAMPLEFORMAT_AV_SAMPLE_FMT_FLTP || frame.channels != 2 {
+        return;
+    }
+
+    let mut samples = match hand_off {
+        HandOff::Copy => {
+            cost.allocations += 1;
+            PooledBuffer::from(Vec::new())
+        }
+        HandOff::Share => pool.take(),
+    };
+    interleave_samples(frame, &mut samples);
+
+    // Samples are always converted, so they're copied either way
+    let bytes = (samples.len() * std::mem::size_of::<f32>()) as u64;
+    cost.audio_frames += 1;
+    cost.handed_off_bytes += bytes;
+    cost.copied_bytes += bytes;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let share = HandOffCost {
+            video_frames: 600,
+            audio_frames: 470,
+            elapsed: Duration::from_millis(2500),
+            handed_off_bytes: 7204 * 1024 * 1024,
+            // Samples are converted either way
+            copied_bytes: 4 * 1024 * 1024,
+            allocations: 3,
+        };
+        assert_eq!(240.0, share.fps());
+        let result = DecodeBenchmark {
+            decoder_name: "hevc".to_string(),
+            threads: 8,
+            copy: HandOffCost {
+                elapsed: Duration::from_millis(4000),
+                copied_bytes: 7204 * 1024 * 1024,
+                allocations: 2270,
+                ..share
+            },
+            share,
+        };
+        assert_eq!(
+            "hevc with 8 threads\n\
+             copy: 600 video and 470 audio frames in 4.00s, 150.0 fps, \
+             7204.0 MiB handed off, 7204.0 MiB copied, 2270 buffers allocated\n\
+             share: 600 video and 470 audio frames in 2.50s, 240.0 fps, \
+             7204.0 MiB handed off, 4.0 MiB copied, 3 buffers allocated",
+            result.to_string()
+        );
+
+        let share = HandOffCost {
+            elapsed: Duration::ZERO,
+            ..share
+        };
+        assert_eq!(0.0, share.fps());
+    }
+}
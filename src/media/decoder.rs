@@ -1,305 +1,653 @@
 use std::{
-    error::Error,
-    ffi::CString,
+    collections::VecDeque,
     ops::{Deref, DerefMut},
     sync::{
-        atomic::{AtomicBool, AtomicI64, AtomicU8, Ordering},
+        atomic::{AtomicBool, AtomicI64, AtomicU64, AtomicU8, Ordering},
         Arc,
     },
-    thread,
+    thread::{self, JoinHandle},
     time::Duration,
 };
 
-use crossbeam::queue::ArrayQueue;
+use crossbeam::{atomic::AtomicCell, queue::ArrayQueue};
 use log::{debug, error, info, warn};
 use rsmpeg::{
-    avcodec::{AVCodec, AVCodecContext, AVPacket},
+    avcodec::{AVCodec, AVCodecContext, AVCodecParameters, AVPacket},
     avformat::AVFormatContextInput,
+    avutil::AVFrame,
+    error::RsmpegError,
     ffi::{
-        av_seek_frame, AVFormatContext,
+        av_seek_frame, avcodec_flush_buffers, AVFormatContext,
         AVMediaType_AVMEDIA_TYPE_ATTACHMENT as AVMEDIATYPE_AVMEDIA_TYPE_ATTACHMENT,
         AVMediaType_AVMEDIA_TYPE_AUDIO as AVMEDIATYPE_AVMEDIA_TYPE_AUDIO,
         AVMediaType_AVMEDIA_TYPE_DATA as AVMEDIATYPE_AVMEDIA_TYPE_DATA,
         AVMediaType_AVMEDIA_TYPE_NB as AVMEDIATYPE_AVMEDIA_TYPE_NB,
         AVMediaType_AVMEDIA_TYPE_SUBTITLE as AVMEDIATYPE_AVMEDIA_TYPE_SUBTITLE,
-        AVMediaType_AVMEDIA_TYPE_VIDEO as AVMEDIATYPE_AVMEDIA_TYPE_VIDEO, AVSEEK_FLAG_FRAME,
+        AVMediaType_AVMEDIA_TYPE_VIDEO as AVMEDIATYPE_AVMEDIA_TYPE_VIDEO, AVERROR_INVALIDDATA,
+        AVSEEK_FLAG_BACKWARD, AV_DISPOSITION_ATTACHED_PIC, AV_NOPTS_VALUE, AV_TIME_BASE,
+        FF_THREAD_FRAME, FF_THREAD_SLICE,
     },
 };
+use serde::{Deserialize, Serialize};
+use sysinfo::{System, SystemExt};
 
 use crate::{
     entity::EventMessage,
     global::{
-        AUDIO_BUFFER, AUDIO_SUMMARY, EVENT_CHANNEL, SUBTITLE_BUFFER, SUBTITLE_SUMMARY,
-        VIDEO_BUFFER, VIDEO_SUMMARY,
+        AUDIO_BUFFER, AUDIO_DISABLED, AUDIO_SUMMARY, DECODER_OPTIONS, EVENT_CHANNEL,
+        MEASURED_LOUDNESS, NETWORK_OPTIONS, SUBTITLE_BUFFER, SUBTITLE_SUMMARY, THREAD_JOIN_TIMEOUT,
+        VIDEO_BUFFER, VIDEO_FILTER_SETTINGS, VIDEO_SUMMARY,
+    },
+    media::{
+        metadata::MediaInfo,
+        packet_queue::PacketQueue,
+        source::{MediaInput, MediaSource},
+        tone_map::{HdrMetadata, ToneMapper},
+        video_filter::{self, VideoFilter},
+    },
+    sound::dsp::LoudnessMeter,
+    util::{
+        buffer_pool::PooledBuffer,
+        colorspace::{Colorimetry, RgbConverter},
+        error::{safe_send, PlayerError},
+        pixel_format::{parse_video_frame, FramePlanes},
+        sample_format,
+        worker::join_timeout,
     },
-    util::{error::safe_send, pixel_format::parse_video_frame, sample_format},
 };
 
-/// The wait duration if buffer queues are full
-const BUFFER_FULL_SLEEP_DURATION: Duration = Duration::from_millis(200);
-/// The maximum number of frames that will be dropped after seek
+/// The wait duration between attempts to push a frame, it's short to notice stop quickly
+const BUFFER_WAIT_DURATION: Duration = Duration::from_millis(10);
+/// Names of the demuxing and decoding threads
+const DEMUXER_THREAD_NAME: &str = "media-demuxer";
+const AUDIO_DECODER_THREAD_NAME: &str = "audio-decoder";
+const VIDEO_DECODER_THREAD_NAME: &str = "video-decoder";
+/// The wait duration for packets, it's short to notice stop and seek quickly
+const QUEUE_WAIT_DURATION: Duration = Duration::from_millis(20);
+/// Packets of a stream are read ahead until they last this long
+const QUEUE_DURATION: Duration = Duration::from_secs(2);
+/// Packets of a stream never take more memory than this, e.g. high bitrate video
+const MAX_QUEUE_BYTES: usize = 32 * 1024 * 1024;
+/// The wait duration of a preloaded decoder until it's activated
+const PRELOAD_SLEEP_DURATION: Duration = Duration::from_millis(20);
+/// The number of audio or video frames a preloaded decoder decodes ahead
+const PRELOAD_FRAMES: usize = 8;
+/// The number of frames that will be dropped after fast seek
 const MAX_SKIP_FRAMES: u8 = 5;
+/// Play interval of streams without frame rate, unit: milliseconds
+const DEFAULT_PLAY_INTERVAL: u64 = 40;
+/// A stream is given up after this number of packets in a row failed to be decoded
+const MAX_CONSECUTIVE_FAILURES: u32 = 32;
+/// FFmpeg decoders gain little from more threads, and some of them refuse more
+const MAX_DECODER_THREADS: usize = 16;
+
+/// Identifies the decoder which sent `EventMessage::EndOfMedia`
+static NEXT_DECODER_ID: AtomicU64 = AtomicU64::new(0);
+
+/// How the decoders of FFmpeg split the work between threads
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ThreadType {
+    /// Frame threading, and slice threading if the codec supports only that
+    #[default]
+    Auto,
+    /// Decode several frames at once, it delays the output by one frame per thread
+    Frame,
+    /// Decode slices of a frame at once, it adds no delay but needs streams with many slices
+    Slice,
+}
 
-pub struct MediaDecoder {
+impl ThreadType {
+    /// Value of `AVCodecContext::thread_type`
+    fn flags(self) -> i32 {
+        let flags = match self {
+            ThreadType::Auto => FF_THREAD_FRAME | FF_THREAD_SLICE,
+            ThreadType::Frame => FF_THREAD_FRAME,
+            ThreadType::Slice => FF_THREAD_SLICE,
+        };
+        flags as i32
+    }
+}
+
+/// Threads used by each decoder
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(default)]
+pub struct DecoderOptions {
+    /// Number of threads, 0 picks it from the number of CPU cores
+    pub threads: usize,
+    pub thread_type: ThreadType,
+}
+
+impl DecoderOptions {
+    /// Number of threads each decoder is opened with
+    pub fn thread_count(&self) -> usize {
+        if self.threads > 0 {
+            return self.threads.min(MAX_DECODER_THREADS);
+        }
+
+        // Hyper-threads help decoding little, so physical cores are counted if possible
+        let cores = System::new()
+            .physical_core_count()
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, |cores| cores.get()));
+        cores.clamp(1, MAX_DECODER_THREADS)
+    }
+}
+
+/// How precisely the decoder seeks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekMode {
+    /// Jump to a keyframe near the position, it's quick but may miss by a few seconds
+    Fast,
+    /// Start from the keyframe before the position, and drop frames until it's reached
+    Accurate,
+}
+
+/// A seek waiting for the demuxing thread
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SeekRequest {
+    /// Unit: milliseconds
+    position: i64,
+    mode: SeekMode,
+    /// Increased by every seek, frames decoded before the seek have an older serial
+    serial: u64,
+}
+
+/// State shared by the decoder and its threads
+struct Shared {
+    id: u64,
+    /// It interrupts the blocking reads of input as well
     stop_flag: Arc<AtomicBool>,
-    audio_seek_to: Arc<AtomicI64>,
-    video_seek_to: Arc<AtomicI64>,
+    /// Frames are pushed to the global buffers only when the decoder is active
+    active: AtomicBool,
+    seek_request: AtomicCell<Option<SeekRequest>>,
+    /// Serial of the latest seek
+    seek_serial: AtomicU64,
+    /// Pts of the latest frame pushed to the global buffers, unit: milliseconds, -1 if none
+    buffered_until: AtomicI64,
 }
 
-impl MediaDecoder {
-    pub fn new(path: &str) -> Result<Self, Box<dyn Error>> {
-        let stop_flag = Arc::new(AtomicBool::new(false));
-        let audio_seek_to = Arc::new(AtomicI64::new(-1));
-        let video_seek_to = Arc::new(AtomicI64::new(-1));
+impl Shared {
+    fn is_stopped(&self) -> bool {
+        self.stop_flag.load(Ordering::Acquire)
+    }
 
-        let ctx = MediaDecoder::get_media_context(&path)?;
-        let streams = Self::get_streams(&ctx);
+    fn is_active(&self) -> bool {
+        self.active.load(Ordering::Acquire)
+    }
+}
+
+/// Reads packets of the media in one thread, and decodes audio and video in their own threads,
+/// so that a slow video decoder doesn't starve audio
+pub struct MediaDecoder {
+    shared: Arc<Shared>,
+    summaries: MediaSummaries,
+    info: MediaInfo,
+    /// Live streams and the standard input can't be seeked
+    seekable: bool,
+    /// The demuxing and decoding threads, they're joined on stop
+    handles: Vec<JoinHandle<()>>,
+}
 
-        Self::start_task(ctx, streams, &stop_flag, &audio_seek_to, &video_seek_to);
+impl MediaDecoder {
+    /// Open the media and decode a few frames ahead without touching the playing media,
+    /// the frames are held back until `activate` is called.
+    /// Opening is given up once `stop_flag` is set, which stops the decoder later as well
+    pub fn preload(source: &MediaSource, stop_flag: Arc<AtomicBool>) -> Result<Self, PlayerError> {
+        let ctx = MediaDecoder::get_media_context(source, stop_flag.clone())?;
+        let (streams, summaries) = Self::get_streams(&ctx);
+        if summaries.audio.is_none() && summaries.video.is_none() {
+            return Err(PlayerError::InvalidMedia(format!(
+                "no audio or video stream can be decoded in {}",
+                source
+            )));
+        }
+        let info = MediaInfo::read(&ctx);
+        // Duration of live streams is unknown
+        let seekable = source.is_seekable() && ctx.duration > 0;
 
-        Ok(Self {
+        let shared = Shared {
+            id: NEXT_DECODER_ID.fetch_add(1, Ordering::Relaxed),
             stop_flag,
-            video_seek_to: video_seek_to.clone(),
-            audio_seek_to: audio_seek_to.clone(),
-        })
+            active: AtomicBool::new(false),
+            seek_request: AtomicCell::new(None),
+            seek_serial: AtomicU64::new(0),
+            buffered_until: AtomicI64::new(-1),
+        };
+        let mut decoder = Self {
+            shared: Arc::new(shared),
+            summaries,
+            info,
+            seekable,
+            handles: Vec::new(),
+        };
+        // The threads already started are stopped on drop if any of them fails
+        decoder.start_tasks(ctx, streams)?;
+
+        Ok(decoder)
     }
 
-    /// Seek to the specified position
-    /// `position` is the position to seek to, unit: milliseconds
-    pub fn seek_to(&mut self, position: i64) {
-        let mut position = position;
-        if position < 0 {
-            position = 0;
+    /// Make this media the playing one, its frames follow the frames already buffered
+    pub fn activate(&mut self) {
+        if self.shared.is_active() {
+            return;
         }
 
-        let vr = VIDEO_SUMMARY.read().unwrap();
-        let ar = AUDIO_SUMMARY.read().unwrap();
+        self.summaries.publish();
+        // Loudness of the new media is measured from scratch
+        MEASURED_LOUDNESS.store(f64::NEG_INFINITY);
+        let info = EventMessage::MediaInfo(self.info.clone());
+        safe_send(EVENT_CHANNEL.0.send(info));
+        self.shared.active.store(true, Ordering::Release);
+    }
 
-        if let Some(summary) = ar.as_ref() {
-            let start = position / 1000 * summary.timebase_inverse as i64;
-            self.audio_seek_to.store(start, Ordering::Release);
-        }
-        if let Some(summary) = vr.as_ref() {
-            let start = position / 1000 * summary.timebase_inverse as i64;
-            self.video_seek_to.store(start, Ordering::Release);
-        }
+    pub fn id(&self) -> u64 {
+        self.shared.id
     }
 
-    pub fn stop(&mut self) {
-        self.stop_flag.store(true, Ordering::SeqCst);
+    pub fn summaries(&self) -> &MediaSummaries {
+        &self.summaries
     }
 
-    fn start_task(
-        ctx: AVFormatContextInput,
-        streams: MediaStreams,
-        stop_flag: &Arc<AtomicBool>,
-        audio_seek_to: &Arc<AtomicI64>,
-        video_seek_to: &Arc<AtomicI64>,
-    ) {
-        let stop_flag = stop_flag.clone();
-        let audio_seek_to = audio_seek_to.clone();
-        let video_seek_to = video_seek_to.clone();
-        let sender = &EVENT_CHANNEL.0;
-        thread::spawn({
-            move || {
-                let mut audio_stream = streams.audio_stream;
-                let mut video_stream = streams.video_stream;
-                let subtitle_stream = streams.subtitle_stream;
-                let data_stream = streams.data_stream;
-                let attachment_stream = streams.attachment_stream;
-                let nb_stream = streams.nb_stream;
-
-                let mut ctx = ctx;
-                // The pointer of AVFormatContext
-                let ctx_ptr = ctx.as_mut_ptr();
-                // The number of audio frames that have bee dropped after seek
-                let mut audio_dropped_frames = u8::MAX;
-                // The number of Video frames that have been dropped after seek
-                let mut video_dropped_frames = u8::MAX;
-
-                loop {
-                    if stop_flag.load(std::sync::atomic::Ordering::SeqCst) {
-                        break;
-                    }
+    pub fn is_seekable(&self) -> bool {
+        self.seekable
+    }
 
-                    let seeked = if let Some(index) = &video_stream.index {
-                        // If current media is stream, seek with video stream
-                        Self::seek_to_stream(ctx_ptr, &video_seek_to, *index)
-                    } else if let Some(index) = &audio_stream.index {
-                        // Otherwise, seek with audio stream
-                        Self::seek_to_stream(ctx_ptr, &audio_seek_to, *index)
-                    } else {
-                        false
-                    };
+    /// The threads are gone once it's stopped, the media has to be opened again to play it
+    pub fn is_stopped(&self) -> bool {
+        self.shared.is_stopped()
+    }
 
-                    if seeked {
-                        // Set the skip number to 0 to indicate some frames need to be dropped later
-                        audio_dropped_frames = 0;
-                        video_dropped_frames = 0;
-                        // Clear old data
-                        Self::clear_buffer();
-                        // Send seek finish status
-                        safe_send(sender.send(EventMessage::SeekFinished));
-                    }
+    /// Seek to the specified position, `EventMessage::SeekFinished` is sent once it's done.
+    /// `position` is the position to seek to, unit: milliseconds
+    pub fn seek_to(&mut self, position: i64, mode: SeekMode) {
+        let position = position.max(0);
+        // The frames being pushed to the global buffers are dropped at once
+        let serial = self.shared.seek_serial.fetch_add(1, Ordering::AcqRel) + 1;
+        // A pending seek is replaced by the newer one
+        let request = SeekRequest {
+            position,
+            mode,
+            serial,
+        };
+        self.shared.seek_request.store(Some(request));
+        self.shared.buffered_until.store(-1, Ordering::Release);
+    }
 
-                    if AUDIO_BUFFER.is_full() || VIDEO_BUFFER.is_full() || SUBTITLE_BUFFER.is_full()
-                    {
-                        thread::sleep(BUFFER_FULL_SLEEP_DURATION);
-                        continue;
-                    }
+    /// Pts of the latest frame buffered for playing, unit: milliseconds, `None` if nothing
+    /// is buffered since the media is opened or seeked
+    pub fn buffered_until(&self) -> Option<i64> {
+        let pts = self.shared.buffered_until.load(Ordering::Acquire);
+        (pts >= 0).then_some(pts)
+    }
 
-                    let result = ctx.read_packet().unwrap();
-                    match result {
-                        Some(packet) => {
-                            // Only process the data in correct stream, ignore others
-                            let stream_index = Some(packet.stream_index);
-                            if stream_index == audio_stream.index {
-                                audio_stream.decoder_ctx = audio_stream
-                                    .decoder_ctx
-                                    .and_then(|dctx| {
-                                        let dctx = Self::decode_audio(dctx, &packet, &mut audio_dropped_frames);
-                                        Some(dctx)
-                                    })
-                                    .or_else(|| {
-                                        warn!("Audio stream founded but no decoder!");
-                                        None
-                                    });
-                            } else if stream_index == video_stream.index {
-                                video_stream.decoder_ctx = video_stream
-                                    .decoder_ctx
-                                    .and_then(|dctx| {
-                                        let dctx = Self::decode_video(dctx, &packet, &mut video_dropped_frames);
-                                        Some(dctx)
-                                    })
-                                    .or_else(|| {
-                                        warn!("Video stream founded but no decoder!");
-                                        None
-                                    });
-                            } else if stream_index == subtitle_stream.index {
-                                //todo!
-                            } else if stream_index == data_stream.index {
-                                info!("data stream packet readed");
-                            } else if stream_index == nb_stream.index {
-                                info!("nb stream packet readed");
-                            } else if stream_index == attachment_stream.index {
-                                info!("attachment stream packet readed");
-                            } else {
-                                debug!("unknown type of packet");
-                            }
-                        }
-                        None => {
-                            debug!("no more packets, stop decoding");
-                            break;
-                        }
-                    }
-                }
-            }
-        });
+    /// Stop decoding and wait for the threads to exit
+    pub fn stop(&mut self) {
+        self.shared.stop_flag.store(true, Ordering::SeqCst);
+        for handle in self.handles.drain(..) {
+            join_timeout(handle, THREAD_JOIN_TIMEOUT);
+        }
     }
 
-    #[inline]
-    fn seek_to_stream(
-        ctx_ptr: *mut AVFormatContext,
-        seek_to: &Arc<AtomicI64>,
-        stream_index: i32,
-    ) -> bool {
-        let position = seek_to.load(Ordering::Acquire);
-        if position < 0 {
-            return false;
+    /// Start the demuxing thread, and a decoding thread for each of audio and video
+    fn start_tasks(&mut self, ctx: MediaInput, streams: MediaStreams) -> Result<(), PlayerError> {
+        let mut streams = streams;
+        let summaries = self.summaries.clone();
+        let audio = summaries.audio.and_then(|summary| {
+            let timebase = (summary.timebase_num, summary.timebase_den);
+            let stream = &mut streams.audio_stream;
+            let (queue, dctx) = Self::stream_queue(stream, timebase, summary.play_interval)?;
+            Some((queue, dctx, summary))
+        });
+        let video = summaries.video.and_then(|summary| {
+            let timebase = (summary.timebase_num, summary.timebase_den);
+            let stream = &mut streams.video_stream;
+            let (queue, dctx) = Self::stream_queue(stream, timebase, summary.play_interval)?;
+            Some((queue, dctx, summary))
+        });
+        let audio_queue = audio.as_ref().map(|(queue, ..)| queue.clone());
+        let video_queue = video.as_ref().map(|(queue, ..)| queue.clone());
+
+        if let Some((queue, dctx, summary)) = audio {
+            let shared = self.shared.clone();
+            let other = video_queue.clone();
+            let builder = thread::Builder::new().name(AUDIO_DECODER_THREAD_NAME.to_string());
+            self.handles.push(builder.spawn(move || {
+                let decoder = AudioStreamDecoder::new(dctx, summary);
+                Self::decode_task(&shared, &queue, other.as_deref(), decoder);
+            })?);
+        }
+        if let Some((queue, dctx, summary)) = video {
+            let shared = self.shared.clone();
+            let other = audio_queue.clone();
+            let builder = thread::Builder::new().name(VIDEO_DECODER_THREAD_NAME.to_string());
+            self.handles.push(builder.spawn(move || {
+                let decoder = VideoStreamDecoder::new(dctx, summary);
+                Self::decode_task(&shared, &queue, other.as_deref(), decoder);
+            })?);
         }
 
-        unsafe { av_seek_frame(ctx_ptr, stream_index, position, AVSEEK_FLAG_FRAME as i32) };
+        let shared = self.shared.clone();
+        let builder = thread::Builder::new().name(DEMUXER_THREAD_NAME.to_string());
+        self.handles.push(builder.spawn(move || {
+            Self::demux_task(&shared, ctx, streams, audio_queue, video_queue);
+        })?);
 
-        seek_to.store(-1, Ordering::Release);
+        Ok(())
+    }
 
-        return true;
+    /// Take the decoder of the stream and create the queue of its packets
+    fn stream_queue(
+        stream: &mut StreamInfo,
+        timebase: (u64, u64),
+        play_interval: u64,
+    ) -> Option<(Arc<StreamQueue>, AVCodecContext)> {
+        let index = stream.index?;
+        let dctx = stream.decoder_ctx.take()?;
+        let queue = StreamQueue::new(index, timebase, play_interval as i64);
+
+        Some((Arc::new(queue), dctx))
     }
 
-    // Notice! The context should be returned to return back the ownership
-    fn decode_audio(dctx: AVCodecContext, packet: &AVPacket, dropped_frames: &mut u8) -> AVCodecContext {
-        let mut dctx = dctx;
-        if let Err(err) = dctx.send_packet(Some(packet)) {
-            error!("send packet to context error: {}", err);
-            return dctx;
-        }
+    /// Read packets into the queues of streams until stopped. Seeks are done here, and
+    /// `EventMessage::SeekFinished` is sent once the decoding threads have dropped old frames
+    fn demux_task(
+        shared: &Arc<Shared>,
+        ctx: MediaInput,
+        streams: MediaStreams,
+        audio_queue: Option<Arc<StreamQueue>>,
+        video_queue: Option<Arc<StreamQueue>>,
+    ) {
+        let id = shared.id;
+        let sender = &EVENT_CHANNEL.0;
+        let mut ctx = ctx;
+        // The pointer of AVFormatContext
+        let ctx_ptr = ctx.as_mut_ptr();
+        // Video goes first, so that it's preferred to seek with
+        let queues: Vec<&StreamQueue> = video_queue
+            .iter()
+            .chain(audio_queue.iter())
+            .map(Arc::as_ref)
+            .collect();
+        let audio_index = audio_queue.as_ref().map(|queue| queue.index);
+        // Failures of reading are reported after the frames before them
+        let mut sink = FrameSink::new(shared.clone());
+        // Serial of the latest seek
+        let mut serial = 0;
+        // Waiting for the decoding threads to drop the frames before seek
+        let mut seeking = false;
+        // All packets are read
+        let mut eof = false;
+        // `EventMessage::EndOfMedia` is sent, all streams are decoded to the end
+        let mut finished = false;
+        // Broken packets are skipped, reading is given up if it keeps failing
+        let mut read_failures = FailureCounter::default();
+
+        loop {
+            if shared.is_stopped() {
+                break;
+            }
 
-        match dctx.receive_frame() {
-            Ok(mut frame) => {
-                // If value of SKIPPED_FRAMES is smaller than MAX_SKIP_FRAMES,
-                // seek action is did a moment before, current frame may not be the correct one,
-                // drop some frames to get the correct one.
-                // Once dropped frames reached the max number, stop dropping.
-                if *dropped_frames < MAX_SKIP_FRAMES {
-                    *dropped_frames += 1;
-                    return dctx;
-                } else {
-                    *dropped_frames = u8::MAX;
+            if sink.is_active() {
+                sink.flush();
+            }
+
+            if let Some(request) = shared.seek_request.take() {
+                // If current media has video, seek with video stream,
+                // otherwise seek with audio stream
+                if let Some(queue) = queues.iter().find(|queue| !queue.packets.is_closed()) {
+                    let (num, den) = queue.timebase;
+                    let position = millis_to_timebase(request.position, num, den);
+                    Self::seek_stream(ctx_ptr, queue.index, position, request.mode);
+                }
+
+                // The packets read ahead are out of date
+                for queue in &queues {
+                    queue.packets.clear();
+                    queue.packets.push(QueueItem::Flush(request), 0, 0);
                 }
+                while SUBTITLE_BUFFER.pop().is_some() {}
+                serial = request.serial;
+                seeking = true;
+                eof = false;
+                finished = false;
+            }
 
-                let mut audio_frame = sample_format::parse_audio_frame(&mut frame);
+            if seeking && queues.iter().all(|queue| queue.is_flushed(serial)) {
+                // Send seek finish status
+                safe_send(sender.send(EventMessage::SeekFinished));
+                seeking = false;
+            }
+
+            // Nothing is left to read if every stream is given up
+            if queues.iter().all(|queue| queue.packets.is_closed()) {
+                eof = true;
+            }
 
-                // Push frame to buffer until succeeded
-                while let Err(f) = AUDIO_BUFFER.push(audio_frame) {
-                    audio_frame = f;
-                    thread::sleep(BUFFER_FULL_SLEEP_DURATION);
+            if eof {
+                if !finished && queues.iter().all(|queue| queue.is_ended(serial)) {
+                    sink.report(EventMessage::EndOfMedia(id));
+                    finished = true;
                 }
+                thread::sleep(QUEUE_WAIT_DURATION);
+                continue;
             }
-            Err(err) => {
-                error!("{}", err);
+
+            // Read ahead until every stream has enough packets, but never hold too much data
+            // of a stream, e.g. the video decoder is slower than playing
+            let mut open_queues = queues.iter().filter(|queue| !queue.packets.is_closed());
+            if open_queues.clone().any(|queue| queue.packets.is_full())
+                || open_queues.all(|queue| queue.packets.has_enough())
+            {
+                thread::sleep(BUFFER_WAIT_DURATION);
+                continue;
+            }
+
+            let result = match ctx.read_packet() {
+                Ok(result) => {
+                    read_failures.succeed();
+                    result
+                }
+                Err(err) if is_corrupt_packet(&err) && !read_failures.fail() => {
+                    warn!("Skip corrupt packet: {}", err);
+                    continue;
+                }
+                Err(err) => {
+                    // Network streams fail if the connection is lost and can't be
+                    // recovered, play the frames already read then
+                    error!("Failed to read packet: {}", err);
+                    let message = format!("Failed to read media: {}", err);
+                    sink.report(EventMessage::MediaError(id, message));
+                    None
+                }
+            };
+            match result {
+                Some(packet) => {
+                    // Only process the data in correct stream, ignore others
+                    let stream_index = Some(packet.stream_index);
+                    if let Some(queue) = queues
+                        .iter()
+                        .find(|queue| queue.index == packet.stream_index)
+                    {
+                        // Audio frames are not consumed if there's no audio device
+                        if stream_index == audio_index
+                            && AUDIO_DISABLED.load(Ordering::Acquire)
+                            && sink.is_active()
+                        {
+                            continue;
+                        }
+                        queue.push_packet(packet);
+                    } else if stream_index == streams.subtitle_stream.index {
+                        //todo!
+                    } else if stream_index == streams.data_stream.index {
+                        info!("data stream packet readed");
+                    } else if stream_index == streams.nb_stream.index {
+                        info!("nb stream packet readed");
+                    } else if stream_index == streams.attachment_stream.index {
+                        info!("attachment stream packet readed");
+                    } else {
+                        debug!("unknown type of packet");
+                    }
+                }
+                None => {
+                    debug!("no more packets, drain the decoders");
+                    for queue in &queues {
+                        queue.packets.push(QueueItem::End(serial), 0, 0);
+                    }
+                    eof = true;
+                }
             }
         }
 
-        dctx
+        debug!("demuxer of decoder {} exited", id);
     }
 
-    // Notice! The context should be returned to return back the ownership
-    fn decode_video(dctx: AVCodecContext, packet: &AVPacket, dropped_frames: &mut u8) -> AVCodecContext {
-        let mut dctx = dctx;
-        if let Err(err) = dctx.send_packet(Some(packet)) {
-            error!("send packet to context error: {}", err);
-            return dctx;
-        }
+    /// Decode the packets of a stream until stopped, the stream is given up if it keeps failing.
+    /// `other` is the queue of the other stream, the media goes on with it then
+    fn decode_task<D: StreamDecoder>(
+        shared: &Arc<Shared>,
+        queue: &StreamQueue,
+        other: Option<&StreamQueue>,
+        decoder: D,
+    ) {
+        let id = shared.id;
+        let mut decoder = decoder;
+        let mut sink = FrameSink::new(shared.clone());
+        // Frames decoded from the keyframe before the seek position are dropped
+        let mut skip = FrameSkip::None;
+        let mut failures = FailureCounter::default();
+        // The stream is decoded to the end after the seek with the serial,
+        // it's told to the demuxer once all frames are pushed to the global buffer
+        let mut ended = None;
+
+        loop {
+            if shared.is_stopped() {
+                break;
+            }
 
-        match dctx.receive_frame() {
-            Ok(frame) => {
-                // If value of SKIPPED_FRAMES is smaller than MAX_SKIP_FRAMES,
-                // seek action is did a moment before, current frame may not be the correct one,
-                // drop some frames to get the correct one.
-                // Once dropped frames reached the max number, stop dropping.
-                if *dropped_frames < MAX_SKIP_FRAMES {
-                    *dropped_frames += 1;
-                    return dctx;
-                } else {
-                    *dropped_frames = u8::MAX;
+            let idle = if sink.is_active() {
+                // Frames decoded ahead go first
+                if sink.flush() {
+                    if let Some(serial) = ended.take() {
+                        queue.ended.store(serial, Ordering::Release);
+                    }
                 }
+                queue.packets.is_closed()
+            } else {
+                sink.is_preloaded() || ended.is_some() || queue.packets.is_closed()
+            };
+            if idle {
+                thread::sleep(PRELOAD_SLEEP_DURATION);
+                continue;
+            }
 
-                let mut vf = parse_video_frame(&frame);
-                // Push frame to buffer until succeeded
-                while let Err(f) = VIDEO_BUFFER.push(vf) {
-                    vf = f;
-                    thread::sleep(BUFFER_FULL_SLEEP_DURATION);
+            let Some(item) = queue.packets.pop_timeout(QUEUE_WAIT_DURATION) else {
+                continue;
+            };
+            let result = match item {
+                QueueItem::Packet(packet) => decoder.decode(Some(&packet), &mut skip, &mut sink),
+                QueueItem::Flush(request) => {
+                    decoder.flush();
+                    skip = FrameSkip::after_seek(request);
+                    ended = None;
+                    sink.restart(request.serial);
+                    // Clear old data
+                    if sink.is_active() {
+                        D::clear_buffer();
+                    }
+                    queue.flushed.store(request.serial, Ordering::Release);
+                    continue;
+                }
+                QueueItem::End(serial) => {
+                    // Decoders hold the last frames until an empty packet is sent,
+                    // errors are only logged since nothing is left to recover
+                    let result = decoder.decode(None, &mut skip, &mut sink);
+                    failures.record(D::NAME, result);
+                    ended = Some(serial);
+                    continue;
+                }
+            };
+
+            if failures.record(D::NAME, result) {
+                queue.packets.close();
+                if other.map_or(false, |other| !other.packets.is_closed()) {
+                    // Go on with the other stream only
+                    sink.report(D::lost(id));
+                } else {
+                    let message = format!("The {} can't be decoded", D::NAME);
+                    sink.report(EventMessage::MediaError(id, message));
                 }
-            }
-            Err(err) => {
-                error!("{}", err);
             }
         }
 
-        dctx
+        debug!("{} decoder {} exited", D::NAME, id);
+    }
+
+    /// Seek the stream to `position` in its timebase
+    fn seek_stream(
+        ctx_ptr: *mut AVFormatContext,
+        stream_index: i32,
+        position: i64,
+        mode: SeekMode,
+    ) {
+        let flags = match mode {
+            // A keyframe near the position, `position` is a timestamp rather than a frame number
+            SeekMode::Fast => 0,
+            // The keyframe before the position, frames from there are decoded and dropped
+            SeekMode::Accurate => AVSEEK_FLAG_BACKWARD,
+        };
+        let ret = unsafe { av_seek_frame(ctx_ptr, stream_index, position, flags as i32) };
+        if ret < 0 {
+            warn!(
+                "Failed to seek stream {} to {}: {}",
+                stream_index, position, ret
+            );
+        }
     }
 
     /// Notice! DemuxerWithStreamInfo do not support multiple threads, so you have to create
     /// a new object for every thread which `DemuxerWithStreamInfo` will be used
-    pub fn get_media_context(path: &str) -> Result<AVFormatContextInput, Box<dyn Error>> {
-        let path = CString::new(path).unwrap();
-        let ctx = AVFormatContextInput::open(&path)?;
+    pub fn get_media_context(
+        source: &MediaSource,
+        interrupt: Arc<AtomicBool>,
+    ) -> Result<MediaInput, PlayerError> {
+        let options = NETWORK_OPTIONS.read().unwrap().clone();
 
-        Ok(ctx)
+        source.open(&options, interrupt)
     }
 
-    fn get_streams(ctx: &AVFormatContextInput) -> MediaStreams {
+    /// Open the decoder of a stream, threads are set as the options say
+    pub fn open_decoder(
+        codec: &AVCodec,
+        codecpar: &AVCodecParameters,
+        options: &DecoderOptions,
+    ) -> Result<AVCodecContext, RsmpegError> {
+        let mut decoder_ctx = AVCodecContext::new(codec);
+        decoder_ctx.apply_codecpar(codecpar)?;
+        // Threads can't be changed once the decoder is opened
+        let threads = options.thread_count();
+        unsafe {
+            let ptr = decoder_ctx.as_mut_ptr();
+            (*ptr).thread_count = threads as i32;
+            (*ptr).thread_type = options.thread_type.flags();
+        }
+        decoder_ctx.open(None)?;
+        debug!(
+            "decoder {} opened with {} threads",
+            codec.name().to_str().unwrap_or("unknown"),
+            threads
+        );
+
+        Ok(decoder_ctx)
+    }
+
+    /// Find the streams and their decoders, the summaries are returned
+    /// instead of saved to static, since the media may be preloaded.
+    /// Streams without frame count or duration are kept, e.g. in MKV, WebM, TS and live streams
+    fn get_streams(ctx: &AVFormatContextInput) -> (MediaStreams, MediaSummaries) {
         let streams = ctx.streams();
+        let options = *DECODER_OPTIONS.read().unwrap();
 
+        let mut summaries = MediaSummaries::default();
         let mut audio_stream = StreamInfo::default();
         let mut video_stream = StreamInfo::default();
         let mut subtitle_stream = StreamInfo::default();
@@ -309,7 +657,8 @@ impl MediaDecoder {
         let mut unknown_streams = Vec::<StreamInfo>::new();
 
         for stream in streams {
-            if stream.nb_frames <= 0 {
+            // Cover art is a video stream with single picture, it's read by `MediaInfo`
+            if stream.disposition & AV_DISPOSITION_ATTACHED_PIC as i32 != 0 {
                 continue;
             }
 
@@ -319,79 +668,82 @@ impl MediaDecoder {
             let mut decoder_name = String::default();
             let decoder_ctx = AVCodec::find_decoder(codecpar.codec_id).and_then(|d| {
                 decoder_name = d.name().to_str().unwrap_or("unknown").to_string();
-                let mut decoder_ctx = AVCodecContext::new(&d);
-
-                if let Err(err) = decoder_ctx.apply_codecpar(&codecpar) {
-                    error!("{}", err);
-                }
 
-                if let Err(err) = decoder_ctx.open(None) {
-                    error!("{}", err);
+                // A decoder which can't be opened would fail on every packet
+                match Self::open_decoder(&d, &codecpar, &options) {
+                    Ok(decoder_ctx) => Some(decoder_ctx),
+                    Err(err) => {
+                        error!("Failed to open decoder {}: {}", decoder_name, err);
+                        None
+                    }
                 }
-
-                Some(decoder_ctx)
             });
+            // Audio and video streams are played only if they can be decoded
+            let playable = decoder_ctx.is_some();
             let stream_info = StreamInfo {
                 decoder_ctx,
                 index: Some(stream.index),
             };
 
-            let duration = stream.duration as u64;
-            let frames = stream.nb_frames as u64;
-            let timebase_num = stream.time_base.num as u64;
-            let timebase_den = stream.time_base.den as u64;
-            let timebase_inverse = timebase_den / timebase_num;
-            let duration_millis = 1000 * duration / timebase_inverse;
-            let play_interval = duration_millis / frames;
+            let timing = StreamTiming {
+                time_base: (stream.time_base.num, stream.time_base.den),
+                duration: stream.duration,
+                nb_frames: stream.nb_frames,
+                avg_frame_rate: (stream.avg_frame_rate.num, stream.avg_frame_rate.den),
+                r_frame_rate: (stream.r_frame_rate.num, stream.r_frame_rate.den),
+                container_duration: ctx.duration,
+                sample_rate: codecpar.sample_rate,
+                frame_size: codecpar.frame_size,
+            }
+            .summarize();
+            let Timing {
+                duration,
+                duration_millis,
+                frames,
+                timebase_num,
+                timebase_den,
+                play_interval,
+            } = timing;
 
             match codec_type {
-                AVMEDIATYPE_AVMEDIA_TYPE_AUDIO => {
+                AVMEDIATYPE_AVMEDIA_TYPE_AUDIO if playable => {
                     audio_stream = stream_info;
-                    let audio_summary = Some(AudioSummary {
+                    summaries.audio = Some(AudioSummary {
                         decoder_name,
                         duration,
                         duration_millis,
                         frames,
                         timebase_num,
                         timebase_den,
-                        timebase_inverse,
                         play_interval,
                         channels: codecpar.channels as u8,
                         channel_layout: codecpar.channel_layout,
                         sample_rate: codecpar.sample_rate,
                         frame_size: codecpar.frame_size,
                     });
-
-                    // Save audio summary to static
-                    let mut w = AUDIO_SUMMARY.write().unwrap();
-                    *w = audio_summary;
                 }
-                AVMEDIATYPE_AVMEDIA_TYPE_VIDEO => {
+                AVMEDIATYPE_AVMEDIA_TYPE_VIDEO if playable => {
                     video_stream = stream_info;
-                    let video_summary = Some(VideoSummary {
+                    summaries.video = Some(VideoSummary {
                         decoder_name,
                         duration,
                         duration_millis,
                         frames,
                         timebase_num,
                         timebase_den,
-                        timebase_inverse,
                         play_interval,
                         width: codecpar.width as u32,
                         height: codecpar.height as u32,
+                        rotation: video_filter::stream_rotation(&stream),
+                        hdr_metadata: HdrMetadata::of_stream(&stream),
                     });
-
-                    // Save video summary to static
-                    let mut w = VIDEO_SUMMARY.write().unwrap();
-                    *w = video_summary;
+                }
+                AVMEDIATYPE_AVMEDIA_TYPE_AUDIO | AVMEDIATYPE_AVMEDIA_TYPE_VIDEO => {
+                    warn!("Stream {} is skipped, it can't be decoded", stream.index);
                 }
                 AVMEDIATYPE_AVMEDIA_TYPE_SUBTITLE => {
                     subtitle_stream = stream_info;
-                    let subtitle_summary = Some(SubtitleSummary);
-
-                    // Save subtitle summary to static
-                    let mut w = SUBTITLE_SUMMARY.write().unwrap();
-                    *w = subtitle_summary;
+                    summaries.subtitle = Some(SubtitleSummary);
                 }
                 AVMEDIATYPE_AVMEDIA_TYPE_ATTACHMENT => {
                     attachment_stream = stream_info;
@@ -408,7 +760,7 @@ impl MediaDecoder {
             }
         }
 
-        MediaStreams {
+        let streams = MediaStreams {
             audio_stream,
             video_stream,
             subtitle_stream,
@@ -416,64 +768,544 @@ impl MediaDecoder {
             nb_stream,
             data_stream,
             unknown_streams,
+        };
+
+        (streams, summaries)
+    }
+}
+
+impl Drop for MediaDecoder {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Summaries of all streams of one media
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MediaSummaries {
+    pub audio: Option<AudioSummary>,
+    pub video: Option<VideoSummary>,
+    pub subtitle: Option<SubtitleSummary>,
+}
+
+impl MediaSummaries {
+    /// Duration of the longest stream in milliseconds, 0 if it's unknown
+    pub fn duration_millis(&self) -> u64 {
+        let audio = self
+            .audio
+            .as_ref()
+            .map_or(0, |summary| summary.duration_millis);
+        let video = self
+            .video
+            .as_ref()
+            .map_or(0, |summary| summary.duration_millis);
+        audio.max(video)
+    }
+
+    /// Save the summaries to static, the old ones are replaced even if missing here
+    fn publish(&self) {
+        *AUDIO_SUMMARY.write().unwrap() = self.audio.clone();
+        *VIDEO_SUMMARY.write().unwrap() = self.video.clone();
+        *SUBTITLE_SUMMARY.write().unwrap() = self.subtitle.clone();
+    }
+}
+
+/// Destination of decoded frames. Frames of a preloaded decoder are kept here,
+/// and pushed to the global buffers once it's activated
+struct FrameSink {
+    shared: Arc<Shared>,
+    /// Serial of the seek the kept frames are decoded after
+    serial: u64,
+    audio: VecDeque<AudioFrame>,
+    video: VecDeque<VideoFrame>,
+    /// Failures are reported after the frames decoded before them
+    events: VecDeque<EventMessage>,
+}
+
+impl FrameSink {
+    fn new(shared: Arc<Shared>) -> Self {
+        Self {
+            shared,
+            serial: 0,
+            audio: VecDeque::new(),
+            video: VecDeque::new(),
+            events: VecDeque::new(),
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.shared.is_active()
+    }
+
+    /// Enough frames are decoded ahead
+    fn is_preloaded(&self) -> bool {
+        self.audio.len() >= PRELOAD_FRAMES || self.video.len() >= PRELOAD_FRAMES
+    }
+
+    fn push_audio(&mut self, mut frame: AudioFrame) {
+        frame.media = self.shared.id;
+        self.audio.push_back(frame);
+        if self.is_active() {
+            self.flush();
+        }
+    }
+
+    fn push_video(&mut self, mut frame: VideoFrame) {
+        frame.media = self.shared.id;
+        self.video.push_back(frame);
+        if self.is_active() {
+            self.flush();
+        }
+    }
+
+    /// Send the event to app, it's kept until activated if the media is preloaded
+    fn report(&mut self, event: EventMessage) {
+        self.events.push_back(event);
+        if self.is_active() {
+            self.flush();
+        }
+    }
+
+    /// Drop the kept frames, the following frames are decoded after the seek with the serial
+    fn restart(&mut self, serial: u64) {
+        self.audio.clear();
+        self.video.clear();
+        self.serial = serial;
+    }
+
+    /// The kept frames are decoded before the latest seek
+    fn is_outdated(&self) -> bool {
+        self.shared.seek_serial.load(Ordering::Acquire) != self.serial
+    }
+
+    /// Waiting for room in the global buffers is given up once stopped or seeked,
+    /// the out of date frames are dropped then
+    fn should_give_up(&mut self) -> bool {
+        if self.shared.is_stopped() {
+            return true;
+        }
+        if self.is_outdated() {
+            self.audio.clear();
+            self.video.clear();
+            return true;
+        }
+
+        false
+    }
+
+    /// Record the pts of the frame pushed to the global buffers
+    fn buffered(&self, pts_millis: i64) {
+        if !self.is_outdated() {
+            self.shared
+                .buffered_until
+                .fetch_max(pts_millis, Ordering::AcqRel);
+        }
+    }
+
+    /// Push the kept frames to the global buffers until succeeded, then send the kept events.
+    /// Returns false if it's given up
+    fn flush(&mut self) -> bool {
+        while let Some(mut frame) = self.audio.pop_front() {
+            let pts_millis = frame.pts_millis;
+            // Nobody consumes the audio frames if the audio device is gone
+            while let Err(f) = AUDIO_BUFFER.push(frame) {
+                if AUDIO_DISABLED.load(Ordering::Acquire) {
+                    break;
+                }
+                if self.should_give_up() {
+                    return false;
+                }
+                frame = f;
+                thread::sleep(BUFFER_WAIT_DURATION);
+            }
+            self.buffered(pts_millis);
+        }
+
+        while let Some(mut frame) = self.video.pop_front() {
+            let pts_millis = frame.pts_millis;
+            while let Err(f) = VIDEO_BUFFER.push(frame) {
+                if self.should_give_up() {
+                    return false;
+                }
+                frame = f;
+                thread::sleep(BUFFER_WAIT_DURATION);
+            }
+            self.buffered(pts_millis);
+        }
+
+        while let Some(event) = self.events.pop_front() {
+            safe_send(EVENT_CHANNEL.0.send(event));
+        }
+
+        true
+    }
+}
+
+/// Items sent from the demuxing thread to a decoding thread
+enum QueueItem {
+    Packet(AVPacket),
+    /// Frames decoded before the seek are out of date
+    Flush(SeekRequest),
+    /// All packets are read after the seek with the serial, drain the decoder
+    End(u64),
+}
+
+/// Packets of a stream, and how far its decoding thread has got
+struct StreamQueue {
+    /// Index of the stream in the media
+    index: i32,
+    /// Numerator and denominator of the timebase of the stream
+    timebase: (u64, u64),
+    /// Duration of packets which don't have one, unit: milliseconds
+    default_duration: i64,
+    packets: PacketQueue<QueueItem>,
+    /// Serial of the latest seek handled by the decoding thread
+    flushed: AtomicU64,
+    /// Serial of the seek after which the stream is decoded to the end, `u64::MAX` if not yet
+    ended: AtomicU64,
+}
+
+impl StreamQueue {
+    fn new(index: i32, timebase: (u64, u64), default_duration: i64) -> Self {
+        Self {
+            index,
+            timebase,
+            default_duration,
+            packets: PacketQueue::new(QUEUE_DURATION, MAX_QUEUE_BYTES),
+            flushed: AtomicU64::new(0),
+            ended: AtomicU64::new(u64::MAX),
+        }
+    }
+
+    fn push_packet(&self, packet: AVPacket) {
+        let (num, den) = self.timebase;
+        let duration = pts_to_millis(packet.duration, num, den)
+            .filter(|duration| *duration > 0)
+            .unwrap_or(self.default_duration);
+        let bytes = packet.size.max(0) as usize;
+        self.packets
+            .push(QueueItem::Packet(packet), bytes, duration);
+    }
+
+    /// The decoding thread has dropped the frames before the seek, or it's given up
+    fn is_flushed(&self, serial: u64) -> bool {
+        self.packets.is_closed() || self.flushed.load(Ordering::Acquire) == serial
+    }
+
+    /// All frames after the seek are pushed to the global buffer, or it's given up
+    fn is_ended(&self, serial: u64) -> bool {
+        self.packets.is_closed() || self.ended.load(Ordering::Acquire) == serial
+    }
+}
+
+/// Decodes the packets of a stream in its own thread
+trait StreamDecoder {
+    /// Name of the stream in logs and messages
+    const NAME: &'static str;
+
+    /// Decode the packet and send the frames to sink, the empty packet drains the decoder
+    fn decode(
+        &mut self,
+        packet: Option<&AVPacket>,
+        skip: &mut FrameSkip,
+        sink: &mut FrameSink,
+    ) -> Result<(), RsmpegError>;
+
+    /// Drop the frames held inside, they're out of date after seek
+    fn flush(&mut self);
+
+    /// Drop the frames in the global buffer of the stream
+    fn clear_buffer();
+
+    /// The event reported if the stream is given up but the media goes on
+    fn lost(id: u64) -> EventMessage;
+}
+
+struct AudioStreamDecoder {
+    dctx: AVCodecContext,
+    summary: AudioSummary,
+    /// Audio frames are measured before buffered, so the loudness is known ahead
+    loudness_meter: LoudnessMeter,
+}
+
+impl AudioStreamDecoder {
+    fn new(dctx: AVCodecContext, summary: AudioSummary) -> Self {
+        Self {
+            dctx,
+            summary,
+            loudness_meter: LoudnessMeter::new(),
+        }
+    }
+}
+
+impl StreamDecoder for AudioStreamDecoder {
+    const NAME: &'static str = "audio";
+
+    fn decode(
+        &mut self,
+        packet: Option<&AVPacket>,
+        skip: &mut FrameSkip,
+        sink: &mut FrameSink,
+    ) -> Result<(), RsmpegError> {
+        let summary = &self.summary;
+        self.dctx.send_packet(packet)?;
+
+        // A packet may contain several frames, and the empty packet gets all the rest
+        while let Some(mut frame) = receive_frame(&mut self.dctx)? {
+            // The frames right after seek may come before the position
+            let pts_millis = pts_to_millis(frame.pts, summary.timebase_num, summary.timebase_den);
+            if skip.drop_frame(pts_millis) {
+                continue;
+            }
+
+            let audio_frame = sample_format::parse_audio_frame(&mut frame, summary);
+            self.loudness_meter.feed(
+                &audio_frame.data,
+                audio_frame.sample_rate,
+                audio_frame.channels,
+            );
+            if sink.is_active() {
+                MEASURED_LOUDNESS.store(self.loudness_meter.integrated());
+            }
+
+            sink.push_audio(audio_frame);
         }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) {
+        unsafe { avcodec_flush_buffers(self.dctx.as_mut_ptr()) };
     }
 
     fn clear_buffer() {
-        while !AUDIO_BUFFER.is_empty() {
-            AUDIO_BUFFER.pop();
+        while AUDIO_BUFFER.pop().is_some() {}
+    }
+
+    fn lost(id: u64) -> EventMessage {
+        EventMessage::AudioLost(id)
+    }
+}
+
+struct VideoStreamDecoder {
+    dctx: AVCodecContext,
+    summary: VideoSummary,
+    filter: VideoFilter,
+    converter: RgbConverter,
+    tone_mapper: ToneMapper,
+}
+
+impl VideoStreamDecoder {
+    fn new(dctx: AVCodecContext, summary: VideoSummary) -> Self {
+        let filter = VideoFilter::new(summary.timebase_num, summary.timebase_den, summary.rotation);
+        let tone_mapper = ToneMapper::new(summary.hdr_metadata);
+        Self {
+            dctx,
+            summary,
+            filter,
+            converter: RgbConverter::default(),
+            tone_mapper,
+        }
+    }
+}
+
+impl StreamDecoder for VideoStreamDecoder {
+    const NAME: &'static str = "video";
+
+    /// The filtered frames are sent to sink, the empty packet drains the filters as well
+    fn decode(
+        &mut self,
+        packet: Option<&AVPacket>,
+        skip: &mut FrameSkip,
+        sink: &mut FrameSink,
+    ) -> Result<(), RsmpegError> {
+        let summary = &self.summary;
+        let filter = &mut self.filter;
+        let converter = &mut self.converter;
+        let tone_mapper = &mut self.tone_mapper;
+        self.dctx.send_packet(packet)?;
+
+        while let Some(frame) = receive_frame(&mut self.dctx)? {
+            // The frames right after seek may come before the position
+            let pts_millis = pts_to_millis(frame.pts, summary.timebase_num, summary.timebase_den);
+            if skip.drop_frame(pts_millis) {
+                continue;
+            }
+
+            // Don't hold the lock while filtering
+            let settings = VIDEO_FILTER_SETTINGS.read().unwrap().clone();
+            for frame in filter.filter(frame, &settings) {
+                let mut vf = parse_video_frame(frame, summary, converter, tone_mapper);
+                vf.rotation = filter.unapplied_rotation();
+                sink.push_video(vf);
+            }
         }
 
-        while !VIDEO_BUFFER.is_empty() {
-            VIDEO_BUFFER.pop();
+        // The empty packet means end of stream, filters hold some frames as well
+        if packet.is_none() {
+            for frame in filter.flush() {
+                let mut vf = parse_video_frame(frame, summary, converter, tone_mapper);
+                vf.rotation = filter.unapplied_rotation();
+                sink.push_video(vf);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) {
+        unsafe { avcodec_flush_buffers(self.dctx.as_mut_ptr()) };
+        self.filter.reset();
+    }
+
+    fn clear_buffer() {
+        while VIDEO_BUFFER.pop().is_some() {}
+    }
+
+    fn lost(id: u64) -> EventMessage {
+        EventMessage::VideoLost(id)
+    }
+}
+
+/// Counts the failures of a stream in a row
+#[derive(Debug, Default)]
+struct FailureCounter {
+    count: u32,
+}
+
+impl FailureCounter {
+    /// Returns true if there are too many failures and the stream should be given up
+    fn fail(&mut self) -> bool {
+        self.count += 1;
+        self.count >= MAX_CONSECUTIVE_FAILURES
+    }
+
+    fn succeed(&mut self) {
+        self.count = 0;
+    }
+
+    /// Count the result of decoding a packet of the stream,
+    /// returns true if the stream should be given up
+    fn record(&mut self, stream: &str, result: Result<(), RsmpegError>) -> bool {
+        match result {
+            Ok(_) => {
+                self.succeed();
+                false
+            }
+            Err(err) => {
+                warn!("Failed to decode {} packet: {}", stream, err);
+                self.fail()
+            }
+        }
+    }
+}
+
+/// Frames to drop after seek, they're decoded from the keyframe but not wanted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FrameSkip {
+    None,
+    /// Drop the number of frames
+    Count(u8),
+    /// Drop the frames before the position, unit: milliseconds
+    Until(i64),
+}
+
+impl FrameSkip {
+    fn after_seek(request: SeekRequest) -> Self {
+        match request.mode {
+            SeekMode::Fast => FrameSkip::Count(MAX_SKIP_FRAMES),
+            SeekMode::Accurate => FrameSkip::Until(request.position),
         }
+    }
 
-        while !SUBTITLE_BUFFER.is_empty() {
-            SUBTITLE_BUFFER.pop();
+    /// Whether the frame should be dropped, frames without pts are kept
+    fn drop_frame(&mut self, pts_millis: Option<i64>) -> bool {
+        match *self {
+            FrameSkip::None => false,
+            FrameSkip::Count(0) => {
+                *self = FrameSkip::None;
+                false
+            }
+            FrameSkip::Count(count) => {
+                *self = FrameSkip::Count(count - 1);
+                true
+            }
+            FrameSkip::Until(position) => match pts_millis {
+                Some(pts) if pts < position => true,
+                _ => {
+                    *self = FrameSkip::None;
+                    false
+                }
+            },
         }
     }
 }
 
+/// Damaged data can be skipped, other errors mean the input can't be read any more
+fn is_corrupt_packet(err: &RsmpegError) -> bool {
+    err.raw_error() == Some(AVERROR_INVALIDDATA)
+}
+
+/// Receive a decoded frame, `None` if the decoder needs more packets or it's drained
+fn receive_frame(dctx: &mut AVCodecContext) -> Result<Option<AVFrame>, RsmpegError> {
+    match dctx.receive_frame() {
+        Ok(frame) => Ok(Some(frame)),
+        Err(RsmpegError::DecoderDrainError | RsmpegError::DecoderFlushedError) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct VideoSummary {
     /// The name of decoder if any
     pub decoder_name: String,
-    /// The duration of whole media
+    /// The duration of whole media in timebase, 0 if it's unknown, e.g. live streams
     pub duration: u64,
-    /// Duration in milliseconds
+    /// Duration in milliseconds, 0 if it's unknown
     pub duration_millis: u64,
-    /// Number of frames in media
+    /// Number of frames in media, estimated if the container doesn't store it
     pub frames: u64,
     /// Number of timebase
     pub timebase_num: u64,
     /// Denominator of timebase
     pub timebase_den: u64,
-    /// The reciprocal of timebase
-    pub timebase_inverse: u64,
     /// Play interval with milliseconds
     pub play_interval: u64,
-    /// Width of video
+    /// Width of video as stored
     pub width: u32,
-    /// Height of video
+    /// Height of video as stored
     pub height: u32,
+    /// Clockwise rotation of the picture from the display matrix, one of 0, 90, 180 and 270
+    pub rotation: i32,
+    /// Luminance of HDR content from the side data of the stream
+    pub hdr_metadata: HdrMetadata,
+}
+
+impl VideoSummary {
+    /// Size of the picture as displayed, width and height are swapped for sideways video
+    /// if it's rotated, i.e. `auto_rotate` of the video filters is on
+    pub fn display_size(&self, auto_rotate: bool) -> (u32, u32) {
+        match self.rotation {
+            90 | 270 if auto_rotate => (self.height, self.width),
+            _ => (self.width, self.height),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct AudioSummary {
     /// The name of decoder if any
     pub decoder_name: String,
-    /// The duration of whole media
+    /// The duration of whole media in timebase, 0 if it's unknown, e.g. live streams
     pub duration: u64,
-    /// Duration in milliseconds
+    /// Duration in milliseconds, 0 if it's unknown
     pub duration_millis: u64,
-    /// Number of frames in media
+    /// Number of frames in media, estimated if the container doesn't store it
     pub frames: u64,
     /// Number of timebase
     pub timebase_num: u64,
     /// Denominator of timebase
     pub timebase_den: u64,
-    /// The reciprocal of timebase
-    pub timebase_inverse: u64,
     /// Play interval with milliseconds
     pub play_interval: u64,
     /// Number of channels
@@ -489,6 +1321,121 @@ pub struct AudioSummary {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SubtitleSummary;
 
+/// Timing values of a stream as reported by FFmpeg, any of them may be unknown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct StreamTiming {
+    /// Numerator and denominator of the timebase
+    time_base: (i32, i32),
+    /// Duration in timebase, `AV_NOPTS_VALUE` or 0 if unknown
+    duration: i64,
+    /// 0 if unknown
+    nb_frames: i64,
+    avg_frame_rate: (i32, i32),
+    r_frame_rate: (i32, i32),
+    /// Duration of the container in `AV_TIME_BASE` units, used if the stream has none
+    container_duration: i64,
+    /// Samples per second and per frame, 0 for video
+    sample_rate: i32,
+    frame_size: i32,
+}
+
+/// Timing fields of the stream summaries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Timing {
+    duration: u64,
+    duration_millis: u64,
+    frames: u64,
+    timebase_num: u64,
+    timebase_den: u64,
+    play_interval: u64,
+}
+
+impl StreamTiming {
+    /// Compute the summary fields with rational arithmetic, falling back to the container
+    /// duration and the frame rate if the stream doesn't store its duration or frame count
+    fn summarize(&self) -> Timing {
+        let (timebase_num, timebase_den) =
+            positive_rational(self.time_base).unwrap_or((1, AV_TIME_BASE as u64));
+
+        // `AV_NOPTS_VALUE` is negative, so only positive durations are known
+        let duration_millis = if self.duration > 0 {
+            rescale(self.duration as u64, 1000 * timebase_num, timebase_den)
+        } else if self.container_duration > 0 {
+            rescale(self.container_duration as u64, 1000, AV_TIME_BASE as u64)
+        } else {
+            0
+        };
+        let duration = if self.duration > 0 {
+            self.duration as u64
+        } else {
+            rescale(duration_millis, timebase_den, 1000 * timebase_num)
+        };
+
+        // Frames per second, audio frames are counted by samples
+        let frame_rate = positive_rational(self.avg_frame_rate)
+            .or_else(|| positive_rational(self.r_frame_rate))
+            .or_else(|| positive_rational((self.sample_rate, self.frame_size)));
+
+        let frames = if self.nb_frames > 0 {
+            self.nb_frames as u64
+        } else if let Some((num, den)) = frame_rate {
+            rescale(duration_millis, num, 1000 * den)
+        } else {
+            0
+        };
+
+        let play_interval = match frame_rate {
+            Some((num, den)) => rescale(1000, den, num),
+            None => duration_millis
+                .checked_div(frames)
+                .unwrap_or(DEFAULT_PLAY_INTERVAL),
+        };
+
+        Timing {
+            duration,
+            duration_millis,
+            frames,
+            timebase_num,
+            timebase_den,
+            play_interval,
+        }
+    }
+}
+
+/// The rational as unsigned numbers, `None` if it's unknown or invalid
+fn positive_rational((num, den): (i32, i32)) -> Option<(u64, u64)> {
+    if num > 0 && den > 0 {
+        Some((num as u64, den as u64))
+    } else {
+        None
+    }
+}
+
+/// `value * num / den` rounded to nearest, computed without overflow
+fn rescale(value: u64, num: u64, den: u64) -> u64 {
+    if den == 0 {
+        return 0;
+    }
+
+    let (value, num, den) = (value as u128, num as u128, den as u128);
+    ((value * num + den / 2) / den) as u64
+}
+
+/// Convert timestamp in the timebase to milliseconds, `None` if it's unknown
+fn pts_to_millis(pts: i64, timebase_num: u64, timebase_den: u64) -> Option<i64> {
+    if pts == AV_NOPTS_VALUE || timebase_den == 0 {
+        return None;
+    }
+
+    let millis = 1000 * pts as i128 * timebase_num as i128 / timebase_den as i128;
+    Some(millis as i64)
+}
+
+/// Convert milliseconds to timestamp in the timebase
+fn millis_to_timebase(millis: i64, timebase_num: u64, timebase_den: u64) -> i64 {
+    rescale(millis.max(0) as u64, timebase_den, 1000 * timebase_num) as i64
+}
+
 struct StreamInfo {
     decoder_ctx: Option<AVCodecContext>,
     index: Option<i32>,
@@ -523,9 +1470,10 @@ trait MediaBuffer {
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct AudioFrame {
+    /// Sample format of the decoded frame, type: `AVSampleFormat`
     pub format: i32,
-    /// Audio content, usually FLTP format, type: `AVSampleFormat`
-    pub data: Vec<f32>,
+    /// Interleaved samples, the buffer goes back to `AUDIO_SAMPLE_POOL` once played
+    pub data: PooledBuffer<f32>,
     /// display timestamp
     pub pts: i64,
     /// Pts in milliseconds
@@ -533,6 +1481,8 @@ pub struct AudioFrame {
     pub sample_rate: i32,
     pub channels: u8,
     pub channel_layout: u8,
+    /// Id of the decoder, frames of the next media in playlist have a greater one
+    pub media: u64,
 }
 
 pub struct AudioBuffer {
@@ -563,12 +1513,21 @@ impl DerefMut for AudioBuffer {
 
 pub struct VideoFrame {
     pub format: i32,
-    pub data: [Vec<u8>; 8],
+    /// Pixels, shared with the decoded frame whenever possible
+    pub planes: FramePlanes,
     pub width: usize,
     pub height: usize,
+    /// Aspect ratio of one pixel as `(num, den)`, `(0, 1)` if unknown
+    pub sample_aspect_ratio: (i32, i32),
+    /// Colors as tagged by the decoder, the frame is RGB24 if it was HDR or SDL can't convert them
+    pub colorimetry: Colorimetry,
     pub pts: i64,
     /// Pts in milliseconds
     pub pts_millis: i64,
+    /// Clockwise rotation applied when rendering, if it's not done by video filters
+    pub rotation: i32,
+    /// Id of the decoder, frames of the next media in playlist have a greater one
+    pub media: u64,
 }
 
 pub struct VideoBuffer {
@@ -630,3 +1589,781 @@ impl DerefMut for SubtitleBuffer {
         &mut self.inner
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use rsmpeg::{
+        avformat::AVFormatContextOutput,
+        avutil::ra,
+        ffi::{
+            av_samples_set_silence,
+            AVPixelFormat_AV_PIX_FMT_YUV420P as AVPIXELFORMAT_AV_PIX_FMT_YUV420P,
+            AVSampleFormat_AV_SAMPLE_FMT_S16, AVERROR_EXIT, AVFMT_GLOBALHEADER,
+            AV_CODEC_FLAG_GLOBAL_HEADER, AV_NOPTS_VALUE, FF_COMPLIANCE_EXPERIMENTAL,
+        },
+    };
+    use sdl2::audio::{AudioCallback, AudioFormat, AudioSpec};
+    use std::{
+        ffi::CString,
+        path::{Path, PathBuf},
+        ptr,
+        sync::{Mutex, MutexGuard, PoisonError},
+        time::Instant,
+    };
+
+    use super::*;
+    use crate::media::{
+        player::{traits::Player, vedio_player::VIDEO_THREAD_NAME, MediaPlayer},
+        source::tests::wav_data,
+    };
+
+    const UNKNOWN: i64 = AV_NOPTS_VALUE;
+
+    fn timing() -> StreamTiming {
+        StreamTiming {
+            time_base: (1, 1000),
+            duration: UNKNOWN,
+            nb_frames: 0,
+            avg_frame_rate: (0, 0),
+            r_frame_rate: (0, 0),
+            container_duration: UNKNOWN,
+            sample_rate: 0,
+            frame_size: 0,
+        }
+    }
+
+    /// Expected duration_millis, frames and play_interval of the timing
+    fn assert_timing(timing: StreamTiming, duration_millis: u64, frames: u64, play_interval: u64) {
+        let summary = timing.summarize();
+        assert_eq!(duration_millis, summary.duration_millis, "{:?}", timing);
+        assert_eq!(frames, summary.frames, "{:?}", timing);
+        assert_eq!(play_interval, summary.play_interval, "{:?}", timing);
+    }
+
+    /// A temp file path which isn't shared with other tests or test processes
+    pub(crate) fn temp_path(name: &str) -> PathBuf {
+        static COUNT: AtomicU64 = AtomicU64::new(0);
+        let count = COUNT.fetch_add(1, Ordering::Relaxed);
+        let name = format!("ntplayer_{}_{}_{}", std::process::id(), count, name);
+        std::env::temp_dir().join(name)
+    }
+
+    const CLIP_FRAME_RATE: i32 = 25;
+    const CLIP_SAMPLE_RATE: i32 = 48000;
+    /// Front left and front right, i.e. `AV_CH_LAYOUT_STEREO`
+    const CLIP_CHANNEL_LAYOUT: u64 = 0b11;
+
+    /// Open an encoder of the clip streams, video is 64x48 at 25 fps and audio is 48kHz stereo
+    fn open_encoder(output: &AVFormatContextOutput, name: &str, video: bool) -> AVCodecContext {
+        let codec = AVCodec::find_encoder_by_name(&CString::new(name).unwrap())
+            .unwrap_or_else(|| panic!("FFmpeg is built without encoder {}", name));
+        let mut encoder = AVCodecContext::new(&codec);
+        if video {
+            encoder.set_width(64);
+            encoder.set_height(48);
+            let pix_fmt = codec
+                .pix_fmts()
+                .and_then(|formats| formats.first().copied())
+                .unwrap_or(AVPIXELFORMAT_AV_PIX_FMT_YUV420P);
+            encoder.set_pix_fmt(pix_fmt);
+            encoder.set_time_base(ra(1, CLIP_FRAME_RATE));
+            encoder.set_framerate(ra(CLIP_FRAME_RATE, 1));
+            encoder.set_gop_size(CLIP_FRAME_RATE);
+            encoder.set_max_b_frames(0);
+        } else {
+            let sample_fmt = codec
+                .sample_fmts()
+                .and_then(|formats| formats.first().copied())
+                .unwrap_or(AVSampleFormat_AV_SAMPLE_FMT_S16);
+            encoder.set_sample_fmt(sample_fmt);
+            encoder.set_sample_rate(CLIP_SAMPLE_RATE);
+            encoder.set_channels(2);
+            encoder.set_channel_layout(CLIP_CHANNEL_LAYOUT);
+            encoder.set_time_base(ra(1, CLIP_SAMPLE_RATE));
+        }
+        // The native Vorbis encoder is experimental
+        encoder.set_strict_std_compliance(FF_COMPLIANCE_EXPERIMENTAL);
+        if output.oformat().flags & AVFMT_GLOBALHEADER as i32 != 0 {
+            encoder.set_flags(encoder.flags | AV_CODEC_FLAG_GLOBAL_HEADER as i32);
+        }
+        encoder.open(None).unwrap();
+
+        encoder
+    }
+
+    /// Send the frame to encoder, `None` drains it, and write the packets to the stream.
+    /// Packets are zeroed if `damaged` returns true for their pts in milliseconds
+    fn write_encoded(
+        output: &mut AVFormatContextOutput,
+        encoder: &mut AVCodecContext,
+        stream_index: i32,
+        frame: Option<&AVFrame>,
+        damaged: &dyn Fn(i64) -> bool,
+    ) {
+        encoder.send_frame(frame).unwrap();
+        loop {
+            let mut packet = match encoder.receive_packet() {
+                Ok(packet) => packet,
+                Err(RsmpegError::EncoderDrainError | RsmpegError::EncoderFlushedError) => break,
+                Err(err) => panic!("Failed to encode: {}", err),
+            };
+            let (num, den) = (encoder.time_base.num as i64, encoder.time_base.den as i64);
+            if damaged(packet.pts * 1000 * num / den) {
+                unsafe { ptr::write_bytes(packet.data, 0, packet.size as usize) };
+            }
+            let time_base = output
+                .streams()
+                .get(stream_index as usize)
+                .unwrap()
+                .time_base;
+            packet.rescale_ts(encoder.time_base, time_base);
+            packet.set_stream_index(stream_index);
+            output.interleaved_write_frame(&mut packet).unwrap();
+        }
+    }
+
+    /// Mux a clip of one second with the encoders into the file,
+    /// the container is chosen by the extension of `path`
+    fn mux_clip(path: &Path, video: Option<&str>, audio: Option<&str>) {
+        mux_damaged_clip(path, video, audio, 1, |_, _| false);
+    }
+
+    /// Mux a clip lasting `seconds`, the packets for which `damaged` returns true are zeroed.
+    /// `damaged` is called with whether the packet is video, and its pts in milliseconds
+    pub(crate) fn mux_damaged_clip(
+        path: &Path,
+        video: Option<&str>,
+        audio: Option<&str>,
+        seconds: i32,
+        damaged: impl Fn(bool, i64) -> bool,
+    ) {
+        let filename = CString::new(path.to_str().unwrap()).unwrap();
+        let mut output = AVFormatContextOutput::create(&filename, None).unwrap();
+        let mut encoders = vec![];
+        for (name, is_video) in [(video, true), (audio, false)] {
+            if let Some(name) = name {
+                let encoder = open_encoder(&output, name, is_video);
+                let mut stream = output.new_stream();
+                stream.set_codecpar(encoder.extract_codecpar());
+                stream.set_time_base(encoder.time_base);
+                encoders.push((encoder, is_video));
+            }
+        }
+        output.write_header(&mut None).unwrap();
+
+        for (index, (encoder, is_video)) in encoders.iter_mut().enumerate() {
+            let index = index as i32;
+            let is_video = *is_video;
+            let stream_damaged = |millis| damaged(is_video, millis);
+            if is_video {
+                for pts in 0..(CLIP_FRAME_RATE * seconds) as i64 {
+                    let mut frame = AVFrame::new();
+                    frame.set_format(encoder.pix_fmt);
+                    frame.set_width(64);
+                    frame.set_height(48);
+                    frame.set_pts(pts);
+                    frame.alloc_buffer().unwrap();
+                    // Mid gray in YUV, and gray in RGB
+                    for buffer in frame.buf.iter().filter(|buffer| !buffer.is_null()) {
+                        unsafe { ptr::write_bytes((**buffer).data, 128, (**buffer).size) };
+                    }
+                    write_encoded(&mut output, encoder, index, Some(&frame), &stream_damaged);
+                }
+            } else {
+                // Encoders without variable frame size take whole frames only
+                let frame_size = if encoder.frame_size > 0 {
+                    encoder.frame_size
+                } else {
+                    1024
+                };
+                let samples = CLIP_SAMPLE_RATE * seconds;
+                for start in (0..samples / frame_size).map(|i| i * frame_size) {
+                    let mut frame = AVFrame::new();
+                    frame.set_format(encoder.sample_fmt);
+                    frame.set_nb_samples(frame_size);
+                    frame.set_channel_layout(CLIP_CHANNEL_LAYOUT);
+                    frame.set_sample_rate(CLIP_SAMPLE_RATE);
+                    frame.set_pts(start as i64);
+                    frame.alloc_buffer().unwrap();
+                    unsafe {
+                        av_samples_set_silence(
+                            frame.extended_data,
+                            0,
+                            frame_size,
+                            2,
+                            encoder.sample_fmt,
+                        )
+                    };
+                    write_encoded(&mut output, encoder, index, Some(&frame), &stream_damaged);
+                }
+            }
+            write_encoded(&mut output, encoder, index, None, &stream_damaged);
+        }
+        output.write_trailer().unwrap();
+    }
+
+    /// Clips muxed by FFmpeg into the common containers are summarized right,
+    /// whether the container stores the duration and frame count of streams or not
+    #[test]
+    fn test_container_matrix() {
+        let clips = [
+            // MP4 stores duration and frame count of every stream
+            ("mp4", Some("mpeg4"), Some("aac")),
+            // MKV and WebM have the container duration only
+            ("mkv", Some("mpeg4"), Some("flac")),
+            ("webm", None, Some("vorbis")),
+            // TS has 90kHz timebase and no frame count
+            ("ts", Some("mpeg2video"), Some("mp2")),
+            // AVI uses the frame rate as timebase
+            ("avi", Some("mpeg4"), Some("mp2")),
+            // Ogg has the stream duration but no frame count
+            ("ogg", None, Some("flac")),
+        ];
+
+        for (extension, video, audio) in clips {
+            let path = temp_path(&format!("clip.{}", extension));
+            mux_clip(&path, video, audio);
+            let source = MediaSource::File(path.clone());
+            let ctx = MediaDecoder::get_media_context(&source, Arc::default()).unwrap();
+            let (_, summaries) = MediaDecoder::get_streams(&ctx);
+            drop(ctx);
+            std::fs::remove_file(&path).unwrap();
+
+            // Encoder delay and padding make the streams a bit longer or shorter
+            let about_one_second = 800..=1200;
+            assert_eq!(video.is_some(), summaries.video.is_some(), "{}", extension);
+            if let Some(video) = summaries.video {
+                let message = format!("{} {:?}", extension, video);
+                assert_eq!((64, 48), (video.width, video.height), "{}", message);
+                assert!(
+                    about_one_second.contains(&video.duration_millis),
+                    "{}",
+                    message
+                );
+                assert!(video.duration > 0, "{}", message);
+                assert!((23..=27).contains(&video.frames), "{}", message);
+                assert_eq!(40, video.play_interval, "{}", message);
+            }
+
+            let audio = summaries.audio.expect(extension);
+            let message = format!("{} {:?}", extension, audio);
+            assert_eq!(CLIP_SAMPLE_RATE, audio.sample_rate, "{}", message);
+            assert_eq!(2, audio.channels, "{}", message);
+            assert!(
+                about_one_second.contains(&audio.duration_millis),
+                "{}",
+                message
+            );
+            assert!(audio.frames > 0 && audio.play_interval > 0, "{}", message);
+        }
+    }
+
+    #[test]
+    fn test_unknown_timing() {
+        // Live streams have neither duration nor frame count
+        let live = StreamTiming {
+            time_base: (1, 90000),
+            avg_frame_rate: (25, 1),
+            ..timing()
+        };
+        assert_timing(live, 0, 0, 40);
+        assert_eq!(0, live.summarize().duration);
+
+        // Invalid timebase is replaced, nothing divides by zero
+        let broken = StreamTiming {
+            time_base: (0, 0),
+            nb_frames: -1,
+            avg_frame_rate: (25, 0),
+            ..timing()
+        };
+        let summary = broken.summarize();
+        assert_eq!(
+            (1, AV_TIME_BASE as u64),
+            (summary.timebase_num, summary.timebase_den)
+        );
+        assert_timing(broken, 0, 0, DEFAULT_PLAY_INTERVAL);
+    }
+
+    #[test]
+    fn test_millis_to_timebase() {
+        assert_eq!(900000, millis_to_timebase(10000, 1, 90000));
+        // Sub-second positions are kept
+        assert_eq!(1500, millis_to_timebase(1500, 1, 1000));
+        assert_eq!(45, millis_to_timebase(1500, 1001, 30000));
+        assert_eq!(0, millis_to_timebase(-20, 1, 1000));
+    }
+
+    #[test]
+    fn test_pts_to_millis() {
+        assert_eq!(Some(10000), pts_to_millis(900000, 1, 90000));
+        assert_eq!(Some(1501), pts_to_millis(45, 1001, 30000));
+        // Streams may start before zero
+        assert_eq!(Some(-20), pts_to_millis(-20, 1, 1000));
+        assert_eq!(None, pts_to_millis(UNKNOWN, 1, 1000));
+    }
+
+    #[test]
+    fn test_frame_skip() {
+        // Fast seek drops a fixed number of frames
+        let mut skip = FrameSkip::after_seek(SeekRequest {
+            position: 5000,
+            mode: SeekMode::Fast,
+            serial: 1,
+        });
+        let dropped = (0..10).filter(|_| skip.drop_frame(Some(9000))).count();
+        assert_eq!(MAX_SKIP_FRAMES as usize, dropped);
+        assert_eq!(FrameSkip::None, skip);
+
+        // Accurate seek drops the frames decoded from the keyframe before the position
+        let mut skip = FrameSkip::after_seek(SeekRequest {
+            position: 5000,
+            mode: SeekMode::Accurate,
+            serial: 1,
+        });
+        for pts in [4000, 4500, 4960] {
+            assert!(skip.drop_frame(Some(pts)));
+        }
+        assert!(!skip.drop_frame(Some(5000)));
+        // Dropping is over once the position is reached, even if pts goes back
+        assert!(!skip.drop_frame(Some(4980)));
+
+        // Frames without pts can't be compared, they're kept
+        let mut skip = FrameSkip::Until(5000);
+        assert!(!skip.drop_frame(None));
+        assert_eq!(FrameSkip::None, skip);
+    }
+
+    #[test]
+    fn test_display_size() {
+        let summary = VideoSummary {
+            decoder_name: String::from("h264"),
+            duration: 0,
+            duration_millis: 0,
+            frames: 0,
+            timebase_num: 1,
+            timebase_den: 1000,
+            play_interval: DEFAULT_PLAY_INTERVAL,
+            width: 1920,
+            height: 1080,
+            rotation: 90,
+            hdr_metadata: HdrMetadata::default(),
+        };
+        assert_eq!((1080, 1920), summary.display_size(true));
+        // The picture is shown as stored if it's not rotated
+        assert_eq!((1920, 1080), summary.display_size(false));
+
+        let summary = VideoSummary {
+            rotation: 180,
+            ..summary
+        };
+        assert_eq!((1920, 1080), summary.display_size(true));
+    }
+
+    #[test]
+    fn test_decoder_options() {
+        let options = DecoderOptions {
+            threads: 4,
+            thread_type: ThreadType::Slice,
+        };
+        assert_eq!(4, options.thread_count());
+        assert_eq!(FF_THREAD_SLICE as i32, options.thread_type.flags());
+
+        let options = DecoderOptions {
+            threads: 64,
+            ..Default::default()
+        };
+        assert_eq!(MAX_DECODER_THREADS, options.thread_count());
+
+        // Picked from CPU cores by default
+        let threads = DecoderOptions::default().thread_count();
+        assert!((1..=MAX_DECODER_THREADS).contains(&threads));
+
+        let options: DecoderOptions = serde_yaml::from_str("thread_type: frame").unwrap();
+        assert_eq!(0, options.threads);
+        assert_eq!(FF_THREAD_FRAME as i32, options.thread_type.flags());
+    }
+
+    /// Decoding errors injected among good packets
+    #[test]
+    fn test_failure_counter() {
+        let corrupt = || Err(RsmpegError::SendPacketError(AVERROR_INVALIDDATA));
+        let mut counter = FailureCounter::default();
+
+        // Failures are tolerated as long as some packets are decoded
+        for _ in 0..3 {
+            for _ in 1..MAX_CONSECUTIVE_FAILURES {
+                assert!(!counter.record("video", corrupt()));
+            }
+            assert!(!counter.record("video", Ok(())));
+        }
+
+        // The stream is given up if every packet fails
+        for _ in 1..MAX_CONSECUTIVE_FAILURES {
+            assert!(!counter.record("video", corrupt()));
+        }
+        assert!(counter.record("video", corrupt()));
+    }
+
+    #[test]
+    fn test_corrupt_packet() {
+        assert!(is_corrupt_packet(&RsmpegError::ReadFrameError(
+            AVERROR_INVALIDDATA
+        )));
+        // The input is gone, e.g. the connection is interrupted
+        assert!(!is_corrupt_packet(&RsmpegError::ReadFrameError(
+            AVERROR_EXIT
+        )));
+        assert!(!is_corrupt_packet(&RsmpegError::DecoderFlushedError));
+    }
+
+    /// Tests which activate a decoder share the global buffers and events, they run one by one
+    static ACTIVE_DECODER: Mutex<()> = Mutex::new(());
+
+    /// Hold it while a decoder is active, a failed test doesn't block the others
+    pub(crate) fn lock_active_decoder() -> MutexGuard<'static, ()> {
+        ACTIVE_DECODER
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// What a decoder sent until the end of its media
+    #[derive(Debug, Default)]
+    struct Playback {
+        audio_lost: bool,
+        video_lost: bool,
+        errors: Vec<String>,
+        /// Pts of the last frames, unit: milliseconds, -1 if none
+        audio_until: i64,
+        video_until: i64,
+    }
+
+    /// Activate the decoder and take its frames from the global buffers until the end of media
+    fn play_to_end(decoder: &mut MediaDecoder) -> Playback {
+        let id = decoder.id();
+        let mut playback = Playback {
+            audio_until: -1,
+            video_until: -1,
+            ..Playback::default()
+        };
+        let deadline = Instant::now() + Duration::from_secs(30);
+        decoder.activate();
+
+        while Instant::now() < deadline {
+            while let Some(frame) = AUDIO_BUFFER.pop() {
+                if frame.media == id {
+                    playback.audio_until = frame.pts_millis;
+                }
+            }
+            while let Some(frame) = VIDEO_BUFFER.pop() {
+                if frame.media == id {
+                    playback.video_until = frame.pts_millis;
+                }
+            }
+            match EVENT_CHANNEL.1.recv_timeout(BUFFER_WAIT_DURATION) {
+                Ok(EventMessage::EndOfMedia(media)) if media == id => return playback,
+                Ok(EventMessage::AudioLost(media)) if media == id => playback.audio_lost = true,
+                Ok(EventMessage::VideoLost(media)) if media == id => playback.video_lost = true,
+                Ok(EventMessage::MediaError(media, message)) if media == id => {
+                    playback.errors.push(message)
+                }
+                _ => {}
+            }
+        }
+
+        panic!("Decoder {} didn't reach the end: {:?}", id, playback);
+    }
+
+    /// Pts of the frames taken from the global buffers, unit: milliseconds
+    #[derive(Debug, Default)]
+    struct TakenFrames {
+        audio: Vec<i64>,
+        video: Vec<i64>,
+    }
+
+    impl TakenFrames {
+        /// Take the frames of the decoder from the global buffers
+        fn take(&mut self, id: u64) {
+            while let Some(frame) = AUDIO_BUFFER.pop() {
+                if frame.media == id {
+                    self.audio.push(frame.pts_millis);
+                }
+            }
+            while let Some(frame) = VIDEO_BUFFER.pop() {
+                if frame.media == id {
+                    self.video.push(frame.pts_millis);
+                }
+            }
+        }
+
+        /// Take the frames of the decoder until the event is sent
+        fn take_until(&mut self, id: u64, event: impl Fn(&EventMessage) -> bool) {
+            let deadline = Instant::now() + Duration::from_secs(30);
+            while Instant::now() < deadline {
+                self.take(id);
+                match EVENT_CHANNEL.1.recv_timeout(BUFFER_WAIT_DURATION) {
+                    Ok(message) if event(&message) => return,
+                    _ => {}
+                }
+            }
+
+            panic!("The event isn't sent by decoder {}: {:?}", id, self);
+        }
+    }
+
+    /// Wait for the seek to be finished, the frames are left in the global buffers.
+    /// Those before the seek are dropped by then, so it's where the frames after it start
+    fn wait_for_seek() {
+        let deadline = Instant::now() + Duration::from_secs(30);
+        while Instant::now() < deadline {
+            if let Ok(EventMessage::SeekFinished) =
+                EVENT_CHANNEL.1.recv_timeout(BUFFER_WAIT_DURATION)
+            {
+                return;
+            }
+        }
+
+        panic!("The seek isn't finished");
+    }
+
+    /// Frames and packets read before a seek are dropped, even if the decoding threads
+    /// are busy with them, and the media is decoded to the end again after seeking back
+    #[test]
+    fn test_seek_while_playing() {
+        let _active = lock_active_decoder();
+        let path = temp_path("seek.mkv");
+        // A keyframe every second
+        mux_damaged_clip(&path, Some("mpeg4"), Some("flac"), 5, |_, _| false);
+        let source = MediaSource::File(path.clone());
+        let mut decoder = MediaDecoder::preload(&source, Arc::default()).unwrap();
+        let id = decoder.id();
+        let end_of_media =
+            |event: &EventMessage| matches!(event, EventMessage::EndOfMedia(media) if *media == id);
+
+        decoder.activate();
+        let mut frames = TakenFrames::default();
+        let deadline = Instant::now() + Duration::from_secs(30);
+        while decoder.buffered_until().map_or(true, |pts| pts < 1000) {
+            assert!(
+                Instant::now() < deadline,
+                "Nothing is decoded: {:?}",
+                frames
+            );
+            frames.take(id);
+            thread::sleep(BUFFER_WAIT_DURATION);
+        }
+
+        // The global buffers are full of frames before the position, and more are queued
+        decoder.seek_to(3000, SeekMode::Accurate);
+        assert_eq!(None, decoder.buffered_until());
+        wait_for_seek();
+        let mut frames = TakenFrames::default();
+        frames.take_until(id, end_of_media);
+        frames.take(id);
+        assert_eq!(Some(&3000), frames.video.first(), "{:?}", frames);
+        assert!(
+            frames.video.windows(2).all(|pts| pts[0] < pts[1]),
+            "{:?}",
+            frames
+        );
+        // Audio frames last 96 milliseconds, the one across the position is dropped as well
+        let first_audio = frames.audio.first().copied();
+        assert!(
+            first_audio.map_or(false, |pts| (3000..3100).contains(&pts)),
+            "{:?}",
+            frames
+        );
+
+        // Seek back after the end, every stream is decoded to the end again
+        decoder.seek_to(1000, SeekMode::Accurate);
+        wait_for_seek();
+        let mut frames = TakenFrames::default();
+        frames.take_until(id, end_of_media);
+        frames.take(id);
+        assert_eq!(Some(&1000), frames.video.first(), "{:?}", frames);
+        assert!(
+            frames.video.last().map_or(false, |pts| *pts >= 4900),
+            "{:?}",
+            frames
+        );
+        let first_audio = frames.audio.first().copied();
+        assert!(
+            first_audio.map_or(false, |pts| (1000..1100).contains(&pts)),
+            "{:?}",
+            frames
+        );
+        assert!(
+            frames.audio.last().map_or(false, |pts| *pts >= 4800),
+            "{:?}",
+            frames
+        );
+
+        decoder.stop();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /// Packets zeroed in the middle of a clip are skipped, and a stream is given up
+    /// only if it keeps failing
+    #[test]
+    fn test_damaged_clip() {
+        let _active = lock_active_decoder();
+        // Frames of PNG and FLAC are decoded on their own, so they recover at once
+        let play_damaged = |damaged: fn(bool, i64) -> bool| {
+            let path = temp_path("damaged.mkv");
+            mux_damaged_clip(&path, Some("png"), Some("flac"), 5, damaged);
+            let source = MediaSource::File(path.clone());
+            let mut decoder = MediaDecoder::preload(&source, Arc::default()).unwrap();
+            let playback = play_to_end(&mut decoder);
+            decoder.stop();
+            std::fs::remove_file(&path).unwrap();
+            playback
+        };
+
+        // A few broken packets of each stream
+        let playback = play_damaged(|_, millis| (2000..2100).contains(&millis));
+        assert!(
+            !playback.audio_lost && !playback.video_lost,
+            "{:?}",
+            playback
+        );
+        assert!(playback.errors.is_empty(), "{:?}", playback);
+        assert!(playback.audio_until >= 4500, "{:?}", playback);
+        assert!(playback.video_until >= 4900, "{:?}", playback);
+
+        // The media goes on with audio
+        let playback = play_damaged(|video, millis| video && millis >= 1000);
+        assert!(
+            playback.video_lost && !playback.audio_lost,
+            "{:?}",
+            playback
+        );
+        assert!(playback.errors.is_empty(), "{:?}", playback);
+        assert!(playback.audio_until >= 4500, "{:?}", playback);
+        assert!((0..1000).contains(&playback.video_until), "{:?}", playback);
+
+        // The media goes on with video
+        let playback = play_damaged(|video, millis| !video && millis >= 1000);
+        assert!(
+            playback.audio_lost && !playback.video_lost,
+            "{:?}",
+            playback
+        );
+        assert!(playback.errors.is_empty(), "{:?}", playback);
+        assert!(playback.video_until >= 4900, "{:?}", playback);
+
+        // Nothing is left to play, the stream given up last reports the error
+        let playback = play_damaged(|_, millis| millis >= 1000);
+        assert!(!playback.errors.is_empty(), "{:?}", playback);
+        assert!(
+            playback.audio_until < 1000 && playback.video_until < 1000,
+            "{:?}",
+            playback
+        );
+    }
+
+    #[test]
+    fn test_preload_garbage() {
+        let path = temp_path("garbage.mp4");
+        std::fs::write(&path, vec![0x5a; 64 * 1024]).unwrap();
+
+        let result = MediaDecoder::preload(&MediaSource::File(path.clone()), Arc::default());
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+
+    /// Number of threads with the name alive in the process
+    #[cfg(target_os = "linux")]
+    fn threads_named(name: &str) -> usize {
+        std::fs::read_dir("/proc/self/task")
+            .unwrap()
+            .flatten()
+            .filter(|task| {
+                std::fs::read_to_string(task.path().join("comm"))
+                    .map_or(false, |comm| comm.trim_end() == name)
+            })
+            .count()
+    }
+
+    /// Resident memory of the process, unit: KiB
+    #[cfg(target_os = "linux")]
+    fn resident_kib() -> u64 {
+        let status = std::fs::read_to_string("/proc/self/status").unwrap();
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix("VmRSS:"))
+            .and_then(|value| value.trim().trim_end_matches("kB").trim().parse().ok())
+            .unwrap()
+    }
+
+    /// Play a bit of the media and stop it as the app does, the threads of
+    /// the decoder and the players are all gone once it's stopped
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_open_close_loop() {
+        let _active = lock_active_decoder();
+        let path = temp_path("open_close.mkv");
+        mux_clip(&path, Some("mpeg4"), Some("flac"));
+        let source = MediaSource::File(path.clone());
+        let spec = AudioSpec {
+            freq: CLIP_SAMPLE_RATE,
+            format: AudioFormat::F32LSB,
+            channels: 2,
+            silence: 0,
+            samples: 1024,
+            size: 1024 * 2 * 4,
+        };
+        let mut out = vec![0.0; 1024 * 2];
+
+        for _ in 0..100 {
+            let mut decoder = MediaDecoder::preload(&source, Arc::default()).unwrap();
+            decoder.activate();
+            // The threads of the decoder hold the state shared with it until they exit
+            let shared = Arc::downgrade(&decoder.shared);
+            let mut player = MediaPlayer::new(decoder);
+            let mut output = player.audio_output(&spec);
+            player.start().unwrap();
+            for _ in 0..4 {
+                output.callback(&mut out);
+                thread::sleep(BUFFER_WAIT_DURATION);
+            }
+
+            player.stop();
+            drop(output);
+            drop(player);
+            assert!(shared.upgrade().is_none(), "decoder threads are left");
+            // No other test plays video while the active decoder is locked
+            assert_eq!(0, threads_named(VIDEO_THREAD_NAME));
+
+            // Frames and events of the stopped media are left for nobody
+            while AUDIO_BUFFER.pop().is_some() {}
+            while VIDEO_BUFFER.pop().is_some() {}
+            while EVENT_CHANNEL.1.try_recv().is_ok() {}
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /// Leaking the media context on every open would take far more memory.
+    /// The memory of the whole process is measured, so run it alone:
+    /// `cargo test test_open_close_memory -- --ignored`
+    #[cfg(target_os = "linux")]
+    #[test]
+    #[ignore]
+    fn test_open_close_memory() {
+        let path = temp_path("open_close.wav");
+        std::fs::write(&path, wav_data()).unwrap();
+        let source = MediaSource::File(path.clone());
+        let open_close = |times: usize| {
+            for _ in 0..times {
+                let mut decoder = MediaDecoder::preload(&source, Arc::default()).unwrap();
+                decoder.stop();
+            }
+        };
+
+        // Warm up the allocator and the static tables of FFmpeg
+        open_close(20);
+        let resident = resident_kib();
+        open_close(300);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(resident_kib() < resident + 16 * 1024);
+    }
+}
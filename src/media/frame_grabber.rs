@@ -0,0 +1,249 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    ptr, slice,
+    sync::{atomic::AtomicBool, Arc},
+};
+
+use rsmpeg::{
+    avcodec::AVCodecContext,
+    avutil::AVFrame,
+    error::RsmpegError,
+    ffi::{
+        av_seek_frame, avcodec_flush_buffers,
+        AVMediaType_AVMEDIA_TYPE_VIDEO as AVMEDIATYPE_AVMEDIA_TYPE_VIDEO,
+        AVPixelFormat_AV_PIX_FMT_RGB24 as AVPIXELFORMAT_AV_PIX_FMT_RGB24, AVSEEK_FLAG_BACKWARD,
+        AV_NOPTS_VALUE, SWS_BICUBIC,
+    },
+    swscale::SwsContext,
+};
+
+use crate::util::{
+    colorspace::{set_yuv_source, Colorimetry, YuvMatrix, YuvRange},
+    error::PlayerError,
+};
+
+use super::{
+    decoder::MediaDecoder,
+    source::{MediaInput, MediaSource},
+    tone_map::{HdrMetadata, HdrTransfer, ToneMapper},
+};
+
+/// The maximum number of packets read while looking for a frame after seek
+const MAX_READ_PACKETS: u32 = 2000;
+
+/// RGB24 picture converted from a decoded frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct RgbImage {
+    /// Picture content, RGB24 format
+    pub data: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    /// Bytes per row of `data`
+    pub pitch: usize,
+}
+
+/// Grab single video frames at specified positions.
+///
+/// The grabber owns an independent demuxer and decoder, it never touches the
+/// global buffers and summaries, so it can be used beside the main `MediaDecoder`.
+/// Notice! Like `AVFormatContextInput`, the grabber should be used in one thread only.
+pub struct FrameGrabber {
+    ctx: MediaInput,
+    decoder_ctx: AVCodecContext,
+    stream_index: i32,
+    timebase_num: i64,
+    timebase_den: i64,
+    /// Pts of the start of the stream, positions are relative to it.
+    /// MPEG-TS files often start at about 1.4 seconds
+    start_time: i64,
+    /// Scale contexts, keyed by (source width, source height, source format, matrix, range,
+    /// target width, target height)
+    sws_ctxs: HashMap<(i32, i32, i32, YuvMatrix, YuvRange, u32, u32), SwsContext>,
+    /// HDR frames are tone mapped to SDR before they're scaled
+    tone_mapper: ToneMapper,
+}
+
+impl FrameGrabber {
+    /// Reading is interrupted once `interrupt` is set, the grabbing fails then
+    pub fn new(path: &str, interrupt: Arc<AtomicBool>) -> Result<Self, PlayerError> {
+        let source = MediaSource::File(PathBuf::from(path));
+        let ctx = MediaDecoder::get_media_context(&source, interrupt)?;
+
+        let (stream_index, decoder) = ctx
+            .find_best_stream(AVMEDIATYPE_AVMEDIA_TYPE_VIDEO)?
+            .ok_or_else(|| PlayerError::InvalidMedia("No video stream found".to_string()))?;
+        let stream = ctx.streams().get(stream_index).ok_or_else(|| {
+            PlayerError::InvalidMedia("Video stream is not accessible".to_string())
+        })?;
+
+        let tone_mapper = ToneMapper::new(HdrMetadata::of_stream(&stream));
+        let mut decoder_ctx = AVCodecContext::new(&decoder);
+        decoder_ctx.apply_codecpar(&stream.codecpar())?;
+        decoder_ctx.open(None)?;
+
+        let timebase_num = stream.time_base.num as i64;
+        let timebase_den = stream.time_base.den as i64;
+        let start_time = match stream.start_time {
+            AV_NOPTS_VALUE => 0,
+            start_time => start_time,
+        };
+
+        Ok(Self {
+            ctx,
+            decoder_ctx,
+            stream_index: stream_index as i32,
+            timebase_num,
+            timebase_den,
+            start_time,
+            sws_ctxs: HashMap::new(),
+            tone_mapper,
+        })
+    }
+
+    /// Grab the frame at `position_millis`.
+    /// If `accurate` is false, the keyframe nearest before the position is returned,
+    /// which is much faster, otherwise frames are decoded until the position is reached.
+    pub fn grab(
+        &mut self,
+        position_millis: i64,
+        accurate: bool,
+    ) -> Result<Option<AVFrame>, PlayerError> {
+        let timestamp =
+            self.start_time + position_millis * self.timebase_den / self.timebase_num / 1000;
+        let ret = unsafe {
+            av_seek_frame(
+                self.ctx.as_mut_ptr(),
+                self.stream_index,
+                timestamp,
+                AVSEEK_FLAG_BACKWARD as i32,
+            )
+        };
+        // The decoder would go on from the old position, the frame would be wrong
+        if ret < 0 {
+            return Err(RsmpegError::AVError(ret).into());
+        }
+        unsafe { avcodec_flush_buffers(self.decoder_ctx.as_mut_ptr()) };
+
+        let mut last_frame: Option<AVFrame> = None;
+        for _ in 0..MAX_READ_PACKETS {
+            let packet = self.ctx.read_packet()?;
+            if let Some(packet) = packet.as_ref() {
+                if packet.stream_index != self.stream_index {
+                    continue;
+                }
+            }
+
+            // Send `None` at the end of file to flush the frames left in decoder
+            let eof = packet.is_none();
+            self.decoder_ctx.send_packet(packet.as_ref())?;
+
+            while let Ok(frame) = self.decoder_ctx.receive_frame() {
+                if !accurate || frame.pts >= timestamp {
+                    return Ok(Some(frame));
+                }
+                last_frame = Some(frame);
+            }
+
+            if eof {
+                break;
+            }
+        }
+
+        Ok(last_frame)
+    }
+
+    /// Position of the grabbed frame in milliseconds, `None` if it has no pts
+    pub fn position_millis(&self, frame: &AVFrame) -> Option<i64> {
+        if frame.pts == AV_NOPTS_VALUE {
+            return None;
+        }
+
+        Some((frame.pts - self.start_time) * 1000 * self.timebase_num / self.timebase_den)
+    }
+
+    /// Convert the frame to RGB24 with specified size, the colors are converted
+    /// as the frame is tagged, and HDR frames are tone mapped as they're played
+    pub fn to_rgb(
+        &mut self,
+        frame: &AVFrame,
+        width: u32,
+        height: u32,
+    ) -> Result<RgbImage, PlayerError> {
+        let colorimetry = Colorimetry::of_frame(frame);
+        let matrix = colorimetry.matrix(frame.height.max(0) as usize);
+        let range = colorimetry.range();
+        let tone_mapped = match HdrTransfer::of(&colorimetry) {
+            Some(transfer) => Some(self.tone_mapper.map(frame, &colorimetry, transfer)?),
+            None => None,
+        };
+        // The SDR picture of HDR frames is only scaled
+        let (format, src_slice, src_stride) = match tone_mapped.as_ref() {
+            Some((buffer, pitch)) => (
+                AVPIXELFORMAT_AV_PIX_FMT_RGB24,
+                [buffer.as_ptr(), ptr::null(), ptr::null(), ptr::null()],
+                [*pitch as i32, 0, 0, 0],
+            ),
+            None => (
+                frame.format,
+                [0, 1, 2, 3].map(|index| frame.data[index] as *const u8),
+                [0, 1, 2, 3].map(|index| frame.linesize[index]),
+            ),
+        };
+
+        let key = (
+            frame.width,
+            frame.height,
+            format,
+            matrix,
+            range,
+            width,
+            height,
+        );
+        if !self.sws_ctxs.contains_key(&key) {
+            let mut sws_ctx = SwsContext::get_context(
+                frame.width,
+                frame.height,
+                format,
+                width as i32,
+                height as i32,
+                AVPIXELFORMAT_AV_PIX_FMT_RGB24,
+                SWS_BICUBIC,
+            )
+            .ok_or(PlayerError::ScaleContext)?;
+            if tone_mapped.is_none() {
+                set_yuv_source(&mut sws_ctx, matrix, range);
+            }
+            self.sws_ctxs.insert(key, sws_ctx);
+        }
+        let sws_ctx = self.sws_ctxs.get_mut(&key).unwrap();
+
+        let mut rgb_frame = AVFrame::new();
+        rgb_frame.set_format(AVPIXELFORMAT_AV_PIX_FMT_RGB24);
+        rgb_frame.set_width(width as i32);
+        rgb_frame.set_height(height as i32);
+        rgb_frame.alloc_buffer()?;
+
+        unsafe {
+            sws_ctx.scale(
+                src_slice.as_ptr(),
+                src_stride.as_ptr(),
+                0,
+                frame.height,
+                rgb_frame.data.as_ptr(),
+                rgb_frame.linesize.as_ptr(),
+            )?;
+        }
+
+        let pitch = rgb_frame.linesize[0] as usize;
+        let data =
+            unsafe { slice::from_raw_parts(rgb_frame.data[0], pitch * height as usize) }.to_vec();
+
+        Ok(RgbImage {
+            data,
+            width,
+            height,
+            pitch,
+        })
+    }
+}
@@ -0,0 +1,105 @@
+use std::{ffi::CString, slice};
+
+use rsmpeg::{
+    avformat::{AVFormatContextInput, AVStreamRef},
+    avutil::AVDictionary,
+    ffi::{
+        AVMediaType_AVMEDIA_TYPE_ATTACHMENT as AVMEDIATYPE_AVMEDIA_TYPE_ATTACHMENT,
+        AVMediaType_AVMEDIA_TYPE_AUDIO as AVMEDIATYPE_AVMEDIA_TYPE_AUDIO,
+        AV_DISPOSITION_ATTACHED_PIC,
+    },
+};
+
+/// Descriptive information of the media, mostly useful for music files
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MediaInfo {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    /// The embedded cover picture, encoded as JPEG, PNG or other image format
+    pub cover_art: Option<Vec<u8>>,
+}
+
+impl MediaInfo {
+    /// Read tags and cover art from the opened media
+    pub fn read(ctx: &AVFormatContextInput) -> Self {
+        // Some formats, e.g. Ogg, store the tags in the audio stream instead of the container
+        let streams: Vec<_> = ctx.streams().into_iter().collect();
+        let audio_metadata = streams
+            .iter()
+            .find(|stream| stream.codecpar().codec_type == AVMEDIATYPE_AVMEDIA_TYPE_AUDIO)
+            .and_then(|stream| stream.metadata());
+        let tag = |key: &str| {
+            ctx.metadata()
+                .and_then(|dict| get_tag(&dict, key))
+                .or_else(|| audio_metadata.as_ref().and_then(|dict| get_tag(dict, key)))
+        };
+
+        Self {
+            title: tag("title"),
+            artist: tag("artist").or_else(|| tag("album_artist")),
+            album: tag("album"),
+            cover_art: Self::read_cover_art(&streams),
+        }
+    }
+
+    /// The cover is either an attached picture of a video stream (MP3, M4A, FLAC),
+    /// or an image in an attachment stream (Matroska)
+    fn read_cover_art(streams: &[AVStreamRef]) -> Option<Vec<u8>> {
+        let attached_pic = streams
+            .iter()
+            .filter(|stream| stream.disposition & AV_DISPOSITION_ATTACHED_PIC as i32 != 0)
+            .find_map(|stream| {
+                let packet = &stream.attached_pic;
+                copy_bytes(packet.data, packet.size)
+            });
+        if attached_pic.is_some() {
+            return attached_pic;
+        }
+
+        streams
+            .iter()
+            .filter(|stream| stream.codecpar().codec_type == AVMEDIATYPE_AVMEDIA_TYPE_ATTACHMENT)
+            .filter(|stream| {
+                stream
+                    .metadata()
+                    .and_then(|dict| get_tag(&dict, "mimetype"))
+                    .map_or(false, |mimetype| mimetype.starts_with("image/"))
+            })
+            .find_map(|stream| {
+                let codecpar = stream.codecpar();
+                copy_bytes(codecpar.extradata, codecpar.extradata_size)
+            })
+    }
+
+    /// Text shown for the media, e.g. "Artist - Title"
+    pub fn display_title(&self) -> Option<String> {
+        match (self.artist.as_ref(), self.title.as_ref()) {
+            (Some(artist), Some(title)) => Some(format!("{} - {}", artist, title)),
+            (None, Some(title)) => Some(title.clone()),
+            (Some(artist), None) => Some(artist.clone()),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Get the value of tag, the key is case insensitive
+fn get_tag(dict: &AVDictionary, key: &str) -> Option<String> {
+    let key = CString::new(key).ok()?;
+    let value = dict
+        .get(&key, None, 0)?
+        .value()
+        .to_string_lossy()
+        .trim()
+        .to_string();
+
+    (!value.is_empty()).then_some(value)
+}
+
+fn copy_bytes(data: *const u8, size: i32) -> Option<Vec<u8>> {
+    if data.is_null() || size <= 0 {
+        return None;
+    }
+
+    Some(unsafe { slice::from_raw_parts(data, size as usize) }.to_vec())
+}
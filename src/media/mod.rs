@@ -1,2 +1,12 @@
+pub mod benchmark;
 pub mod decoder;
-pub mod player;
\ No newline at end of file
+pub mod frame_grabber;
+pub mod metadata;
+pub mod packet_queue;
+pub mod player;
+pub mod playlist;
+pub mod screenshot;
+pub mod source;
+pub mod thumbnail;
+pub mod tone_map;
+pub mod video_filter;
\ No newline at end of file
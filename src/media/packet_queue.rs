@@ -0,0 +1,182 @@
+use std::{
+    collections::VecDeque,
+    sync::{Condvar, Mutex},
+    time::Duration,
+};
+
+/// A queue from the demuxing thread to a decoding thread. It's sized in duration and bytes
+/// instead of items, since packets of different streams vary a lot in both
+pub struct PacketQueue<T> {
+    inner: Mutex<Inner<T>>,
+    /// Notified when an item is pushed or the queue is closed
+    pushed: Condvar,
+    /// Duration worth buffering ahead, unit: milliseconds
+    enough_millis: i64,
+    /// The queue is full beyond this, whatever the duration is
+    max_bytes: usize,
+}
+
+struct Inner<T> {
+    /// Items with their size in bytes and duration in milliseconds
+    items: VecDeque<(T, usize, i64)>,
+    bytes: usize,
+    duration_millis: i64,
+    /// Nothing is accepted once closed, e.g. the stream can't be decoded
+    closed: bool,
+}
+
+impl<T> PacketQueue<T> {
+    pub fn new(enough_duration: Duration, max_bytes: usize) -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                items: VecDeque::new(),
+                bytes: 0,
+                duration_millis: 0,
+                closed: false,
+            }),
+            pushed: Condvar::new(),
+            enough_millis: enough_duration.as_millis() as i64,
+            max_bytes,
+        }
+    }
+
+    /// Append the item, it's dropped and false is returned if the queue is closed
+    pub fn push(&self, item: T, bytes: usize, duration_millis: i64) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.closed {
+            return false;
+        }
+
+        let duration_millis = duration_millis.max(0);
+        inner.items.push_back((item, bytes, duration_millis));
+        inner.bytes += bytes;
+        inner.duration_millis += duration_millis;
+        self.pushed.notify_one();
+
+        true
+    }
+
+    /// Take the first item, wait until an item arrives or the timeout expires
+    pub fn pop_timeout(&self, timeout: Duration) -> Option<T> {
+        let inner = self.inner.lock().unwrap();
+        let (mut inner, _) = self
+            .pushed
+            .wait_timeout_while(inner, timeout, |inner| {
+                inner.items.is_empty() && !inner.closed
+            })
+            .unwrap();
+
+        let (item, bytes, duration_millis) = inner.items.pop_front()?;
+        inner.bytes -= bytes;
+        inner.duration_millis -= duration_millis;
+
+        Some(item)
+    }
+
+    /// Drop all items, e.g. they're out of date after seek
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.items.clear();
+        inner.bytes = 0;
+        inner.duration_millis = 0;
+    }
+
+    /// Drop all items and refuse new ones
+    pub fn close(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.closed = true;
+        inner.items.clear();
+        inner.bytes = 0;
+        inner.duration_millis = 0;
+        self.pushed.notify_all();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.inner.lock().unwrap().closed
+    }
+
+    /// Enough duration is buffered, more items are welcome only if other queues need them
+    pub fn has_enough(&self) -> bool {
+        self.inner.lock().unwrap().duration_millis >= self.enough_millis
+    }
+
+    /// No more items should be pushed until some are taken
+    pub fn is_full(&self) -> bool {
+        self.inner.lock().unwrap().bytes >= self.max_bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, thread, time::Instant};
+
+    use super::*;
+
+    #[test]
+    fn test_limits() {
+        let queue = PacketQueue::new(Duration::from_secs(1), 1000);
+        for i in 0..24 {
+            assert!(queue.push(i, 10, 40));
+        }
+        assert!(!queue.has_enough());
+        // Packets without duration count as zero
+        assert!(queue.push(24, 10, -1));
+        assert!(!queue.has_enough());
+        assert!(queue.push(25, 10, 40));
+        assert!(queue.has_enough());
+        assert!(!queue.is_full());
+
+        // A few big packets fill the queue long before it has enough duration
+        let queue = PacketQueue::new(Duration::from_secs(1), 1000);
+        queue.push(0, 600, 40);
+        assert!(!queue.is_full());
+        queue.push(1, 600, 40);
+        assert!(queue.is_full() && !queue.has_enough());
+
+        // Taking items makes room
+        assert_eq!(Some(0), queue.pop_timeout(Duration::ZERO));
+        assert!(!queue.is_full());
+        assert_eq!(Some(1), queue.pop_timeout(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_clear_and_close() {
+        let queue = PacketQueue::new(Duration::from_millis(100), 100);
+        queue.push(0, 100, 100);
+        queue.clear();
+        assert!(!queue.is_full() && !queue.has_enough());
+        assert_eq!(None, queue.pop_timeout(Duration::ZERO));
+
+        queue.push(1, 10, 10);
+        queue.close();
+        assert!(queue.is_closed());
+        assert!(!queue.push(2, 10, 10));
+        assert_eq!(None, queue.pop_timeout(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_pop_wait() {
+        let queue = Arc::new(PacketQueue::new(Duration::from_secs(1), 1000));
+
+        let start = Instant::now();
+        assert_eq!(None, queue.pop_timeout(Duration::from_millis(20)));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        // The waiting thread is woken up by push and close
+        let pop = || {
+            let queue = queue.clone();
+            thread::spawn(move || queue.pop_timeout(Duration::from_secs(10)))
+        };
+        let consumer = pop();
+        thread::sleep(Duration::from_millis(20));
+        queue.push(7, 10, 10);
+        assert_eq!(Some(7), consumer.join().unwrap());
+
+        let consumer = pop();
+        thread::sleep(Duration::from_millis(20));
+        let start = Instant::now();
+        queue.close();
+        assert_eq!(None, consumer.join().unwrap());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -1,156 +1,318 @@
-use crossbeam::atomic::AtomicCell;
+use crossbeam::queue::ArrayQueue;
 use log::info;
-use std::{
-    cell::Cell,
-    sync::{atomic::Ordering, Arc},
-    thread::{self, JoinHandle},
-    time::Duration,
-};
+use sdl2::audio::{AudioCallback, AudioSpec};
+use std::sync::{atomic::Ordering, Arc};
 
-use super::traits::Player;
+use super::state::{PlaybackState, StateMachine};
 use crate::{
     entity::EventMessage,
-    global::{AUDIO_BUFFER, AUDIO_SUMMARY, EVENT_CHANNEL, GLOBAL_PTS_MILLIS},
-    util::error::{safe_send, SuperError},
+    global::{
+        AUDIO_BUFFER, AUDIO_SUMMARY, DSP_SETTINGS, EVENT_CHANNEL, GLOBAL_PTS_MEDIA,
+        GLOBAL_PTS_MILLIS, MEASURED_LOUDNESS, PLAYBACK_RATE, PLAYED_SAMPLES,
+    },
+    media::decoder::AudioFrame,
+    sound::{
+        dsp::{DspChain, DspUpdate},
+        visualizer::WINDOW_SIZE,
+        volume::{self, GainRamp},
+    },
+    util::error::PlayerError,
 };
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum State {
-    Playing,
-    Paused,
-    Stopped,
-    Seeking,
-    SeekFinished,
-
-    ReadyToPlay,
-    ReadyToPause,
-    ReadyToResume,
-    ReadyToStop,
-}
-
 pub struct AudioPlayer {
-    /// State of the audio player
-    state: Arc<AtomicCell<State>>,
-    /// Thread id
-    tid: Cell<Option<JoinHandle<()>>>,
+    /// State of playback, shared with the audio output callback
+    state: StateMachine,
+    /// The first frame of a new format, it's handed over to the output of reopened device.
+    /// It holds one frame at most, and never blocks the device callback
+    pending_frame: Arc<ArrayQueue<AudioFrame>>,
 }
 
 impl AudioPlayer {
-    /// `es`: System event sender
-    /// `ts`: Benchmark timestamp sender
-    pub fn new() -> Self {
+    pub fn new(state: StateMachine) -> Self {
         Self {
-            state: Arc::new(AtomicCell::new(State::Stopped)),
-            tid: Cell::new(None),
+            state,
+            pending_frame: Arc::new(ArrayQueue::new(1)),
         }
     }
 
-    /// Set the buffer queue which will be used for audio play
-    pub fn start(&mut self) -> Result<(), SuperError> {
-        let summary = AUDIO_SUMMARY.read().unwrap();
-        if summary.is_none() {
-            return Ok(());
+    /// Audio is pulled by the output callback, so there is no thread to start here
+    pub fn start(&mut self) -> Result<(), PlayerError> {
+        if let Some(summary) = AUDIO_SUMMARY.read().unwrap().as_ref() {
+            info!("Starting audio player, summary: {:?}", summary);
         }
 
-        // If the thread is already running, stop it first
-        if let Some(_tid) = self.tid.take() {
-            if !_tid.is_finished() {
-                _tid.join().expect("Join audio thread failed!");
-            }
+        Ok(())
+    }
+
+    /// Create the callback which pulls audio frames for the output device
+    pub fn output(&self, spec: &AudioSpec) -> AudioOutput {
+        // Samples held by the device but not played yet, it's the latency of output
+        let latency_millis = 1000 * spec.samples as i64 / spec.freq.max(1) as i64;
+        let settings = DSP_SETTINGS.read().unwrap().clone();
+        let frame = self
+            .pending_frame
+            .pop()
+            .filter(|frame| frame.sample_rate == spec.freq && frame.channels == spec.channels);
+
+        AudioOutput {
+            state: self.state.clone(),
+            pending_frame: self.pending_frame.clone(),
+            format_changed: false,
+            frame,
+            pos: 0,
+            frac: 0.0,
+            latency_millis,
+            gain: GainRamp::new(spec.freq, spec.channels, volume::target_gain()),
+            dsp: DspChain::new(spec.freq, spec.channels, settings),
+            sample_rate: spec.freq,
+            channels: spec.channels.max(1) as usize,
         }
+    }
+}
 
-        let summary = summary.as_ref().unwrap();
-        info!("Starting audio player, summary: {:?}", summary);
+/// The callback of audio device, it pulls samples from `AUDIO_BUFFER` whenever the device
+/// needs more data, and updates `GLOBAL_PTS_MILLIS` with the samples actually played,
+/// so it's the authoritative audio clock.
+pub struct AudioOutput {
+    state: StateMachine,
+    pending_frame: Arc<ArrayQueue<AudioFrame>>,
+    /// A frame which doesn't fit the device is reached, the device has to be reopened
+    format_changed: bool,
+    /// The frame being played
+    frame: Option<AudioFrame>,
+    /// Position of next sample in `frame`
+    pos: usize,
+    /// Position between the sample frames at `pos` and the next, if it's not played at 1.0x
+    frac: f64,
+    /// Duration of the samples buffered by device, unit: milliseconds
+    latency_millis: i64,
+    /// Volume and mute are applied here, so that changes are heard immediately
+    gain: GainRamp,
+    dsp: DspChain,
+    /// Sample rate of the output device
+    sample_rate: i32,
+    /// Channels of the output device
+    channels: usize,
+}
 
-        let state = self.state.clone();
-        let sleep_duration = Duration::from_millis(summary.play_interval);
+impl AudioOutput {
+    /// Swap in the audio processing filters prepared for the new settings
+    pub fn apply_dsp(&mut self, update: &mut DspUpdate) {
+        self.dsp.apply(update);
+    }
 
-        let tid = thread::spawn({
-            state.store(State::Playing);
-            move || loop {
-                // Check player state
-                match state.load() {
-                    // Ready to stop or already stopped, exit thread
-                    State::ReadyToStop => {
-                        state.store(State::Stopped);
+    fn fill(&mut self, out: &mut [f32]) {
+        let rate = PLAYBACK_RATE.load();
+        let mut written = 0;
+        while written < out.len() && !self.format_changed {
+            if self.frame.is_none() {
+                // Buffer is exhausted, the rest is filled with silence
+                match AUDIO_BUFFER.pop() {
+                    Some(frame) if !self.fits(&frame) => {
+                        // The next media of playlist has another format,
+                        // play silence until the device is reopened for it.
+                        // Nothing is logged here, the app does it for the event.
+                        // The queue is empty since the flag is cleared only by seeking
+                        let _ = self.pending_frame.push(frame);
+                        self.format_changed = true;
+                        // The receiver is gone only when the app is quitting
+                        let _ = EVENT_CHANNEL.0.send(EventMessage::AudioFormatChanged);
                         break;
                     }
-                    State::Stopped => break,
-                    // Ready to pause or already paused,
-                    // do nothing and continue loop
-                    State::ReadyToPause => {
-                        state.store(State::Paused);
-                        thread::sleep(sleep_duration);
-                        continue;
-                    }
-                    State::Paused => {
-                        thread::sleep(sleep_duration);
-                        continue;
-                    }
-                    // Ready to play or already playing, just go on
-                    State::ReadyToPlay | State::ReadyToResume => {
-                        state.store(State::Playing);
-                    }
-                    State::Playing => {
-                        // go on
-                    }
-                    State::Seeking => {
-                        GLOBAL_PTS_MILLIS.store(-1, Ordering::Release);
-                        thread::sleep(sleep_duration);
-                        continue;
+                    Some(frame) => {
+                        self.frame = Some(frame);
+                        self.pos = 0;
                     }
-                    State::SeekFinished => {
-                        state.store(State::Playing);
-                    },
+                    None => break,
                 }
+            }
 
-                // Play audio
-                if let Some(frame) = AUDIO_BUFFER.pop() {
-                    // Update timestamp of playing
-                    GLOBAL_PTS_MILLIS.store(frame.pts_millis, Ordering::Release);
-                    safe_send(EVENT_CHANNEL.0.send(EventMessage::RenderAudio(frame)));
-                }
+            let frame = self.frame.as_ref().unwrap();
+            let count = copy_at_rate(
+                &frame.data,
+                self.channels,
+                &mut self.pos,
+                &mut self.frac,
+                &mut out[written..],
+                rate,
+            );
+            written += count;
 
-                thread::sleep(sleep_duration);
+            self.update_clock(rate);
+            if self.pos >= frame.data.len() {
+                self.frame = None;
+            } else if count == 0 {
+                // Not enough room for a whole sample frame
+                break;
             }
-        });
+        }
 
-        self.tid.set(Some(tid));
+        out[written..].fill(0.0);
 
-        Ok(())
+        self.dsp.process(out, MEASURED_LOUDNESS.load());
+        self.tap(out);
+        self.gain.apply(out, volume::target_gain());
     }
-}
 
-impl Player for AudioPlayer {
-    fn play(&mut self) {
-        self.state.store(State::ReadyToPlay);
+    fn fits(&self, frame: &AudioFrame) -> bool {
+        frame.sample_rate == self.sample_rate && frame.channels as usize == self.channels
     }
 
-    fn pause(&mut self) {
-        self.state.store(State::ReadyToPause);
+    /// Keep the latest samples for visualization, they're skipped if the lock is busy
+    fn tap(&self, out: &[f32]) {
+        if let Ok(mut played) = PLAYED_SAMPLES.try_lock() {
+            for frame in out.chunks_exact(self.channels) {
+                played.push_back(frame.iter().sum::<f32>() / self.channels as f32);
+            }
+
+            let excess = played.len().saturating_sub(WINDOW_SIZE);
+            played.drain(..excess);
+        }
+    }
+
+    /// Update the play timestamp with the position of current frame
+    fn update_clock(&self, rate: f64) {
+        if let Some(frame) = self.frame.as_ref() {
+            let samples_per_second = frame.sample_rate as i64 * frame.channels.max(1) as i64;
+            let offset_millis = 1000 * self.pos as i64 / samples_per_second.max(1);
+            // The samples held by device cover more of the media if it's played faster
+            let latency_millis = (self.latency_millis as f64 * rate) as i64;
+            let pts = frame.pts_millis + offset_millis - latency_millis;
+            // Stored before the pts, so a new pts is never paired with the previous media
+            GLOBAL_PTS_MEDIA.store(frame.media, Ordering::Release);
+            GLOBAL_PTS_MILLIS.store(pts.max(0), Ordering::Release);
+        }
     }
+}
+
+impl AudioCallback for AudioOutput {
+    type Channel = f32;
 
-    fn resume(&mut self) {
-        self.state.store(State::ReadyToResume);
+    fn callback(&mut self, out: &mut [f32]) {
+        match self.state.get() {
+            PlaybackState::Stopped => {
+                self.frame = None;
+                out.fill(0.0);
+            }
+            PlaybackState::Paused => out.fill(0.0),
+            PlaybackState::Seeking => {
+                // The frame being played is out of date
+                self.frame = None;
+                self.frac = 0.0;
+                while self.pending_frame.pop().is_some() {}
+                self.format_changed = false;
+                self.dsp.reset();
+                GLOBAL_PTS_MILLIS.store(-1, Ordering::Release);
+                out.fill(0.0);
+            }
+            PlaybackState::Playing => self.fill(out),
+        }
     }
+}
 
-    fn stop(&mut self) {
-        self.state.store(State::ReadyToStop);
+/// Copy interleaved samples of `data` from `pos` to `out`, and advance `pos`.
+/// `rate` sample frames of `data` are played for each frame of `out`, the samples between
+/// are interpolated linearly, `frac` keeps the position between two frames of `data`.
+/// Returns the number of samples written
+fn copy_at_rate(
+    data: &[f32],
+    channels: usize,
+    pos: &mut usize,
+    frac: &mut f64,
+    out: &mut [f32],
+    rate: f64,
+) -> usize {
+    if rate == 1.0 {
+        *frac = 0.0;
+        let count = (data.len() - *pos).min(out.len());
+        out[..count].copy_from_slice(&data[*pos..*pos + count]);
+        *pos += count;
+        return count;
     }
 
-    fn fast_forward(&mut self) {
-        todo!();
+    let frames = data.len() / channels;
+    let mut index = *pos / channels;
+    let mut written = 0;
+    while written + channels <= out.len() && index < frames {
+        // The last frame has nothing to interpolate with
+        let next = (index + 1).min(frames - 1);
+        for channel in 0..channels {
+            let current = data[index * channels + channel];
+            let following = data[next * channels + channel];
+            out[written + channel] = current + (following - current) * *frac as f32;
+        }
+        written += channels;
+
+        *frac += rate;
+        let step = frac.floor();
+        *frac -= step;
+        index += step as usize;
     }
 
-    fn fast_rewind(&mut self) {
-        todo!();
+    *pos = (index * channels).min(data.len());
+    written
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stereo frames whose left channel counts up and right channel counts down
+    fn ramp(frames: usize) -> Vec<f32> {
+        (0..frames).flat_map(|i| [i as f32, -(i as f32)]).collect()
     }
 
-    fn seeking(&mut self) {
-        self.state.store(State::Seeking);
+    #[test]
+    fn test_copy_at_normal_rate() {
+        let data = ramp(4);
+        let mut out = [0.0; 6];
+        let (mut pos, mut frac) = (0, 0.0);
+
+        assert_eq!(
+            6,
+            copy_at_rate(&data, 2, &mut pos, &mut frac, &mut out, 1.0)
+        );
+        assert_eq!([0.0, -0.0, 1.0, -1.0, 2.0, -2.0], out);
+        // The rest of the frame is less than the output
+        assert_eq!(
+            2,
+            copy_at_rate(&data, 2, &mut pos, &mut frac, &mut out, 1.0)
+        );
+        assert_eq!(data.len(), pos);
     }
 
-    fn seek_finished(&mut self) {
-        self.state.store(State::SeekFinished);
+    #[test]
+    fn test_copy_at_rate() {
+        // Twice as fast, every other frame is played
+        let data = ramp(8);
+        let mut out = [0.0; 8];
+        let (mut pos, mut frac) = (0, 0.0);
+        assert_eq!(
+            8,
+            copy_at_rate(&data, 2, &mut pos, &mut frac, &mut out, 2.0)
+        );
+        assert_eq!([0.0, -0.0, 2.0, -2.0, 4.0, -4.0, 6.0, -6.0], out);
+        assert_eq!(data.len(), pos);
+
+        // Half speed, the frames between are interpolated
+        let mut out = [0.0; 8];
+        let (mut pos, mut frac) = (0, 0.0);
+        assert_eq!(
+            8,
+            copy_at_rate(&data, 2, &mut pos, &mut frac, &mut out, 0.5)
+        );
+        assert_eq!([0.0, -0.0, 0.5, -0.5, 1.0, -1.0, 1.5, -1.5], out);
+        assert_eq!(4, pos);
+        // It goes on from where it stopped
+        let mut out = [0.0; 2];
+        copy_at_rate(&data, 2, &mut pos, &mut frac, &mut out, 0.5);
+        assert_eq!([2.0, -2.0], out);
+
+        // Nothing is written if there's no room for a whole frame
+        let mut out = [0.0; 1];
+        assert_eq!(
+            0,
+            copy_at_rate(&data, 2, &mut pos, &mut frac, &mut out, 1.5)
+        );
     }
 }
@@ -1,25 +1,68 @@
 pub mod audio_player;
+pub mod state;
 pub mod traits;
 pub mod vedio_player;
 
-use crate::util::error::SuperError;
+use log::debug;
+use sdl2::audio::AudioSpec;
+use std::{mem, ops::Range, sync::atomic::Ordering, time::Duration};
 
-use self::{audio_player::AudioPlayer, traits::Player, vedio_player::VideoPlayer};
+use crate::{
+    global::{GLOBAL_PTS_MILLIS, MAX_PLAYBACK_RATE, MIN_PLAYBACK_RATE, PLAYBACK_RATE},
+    media::decoder::{MediaDecoder, SeekMode},
+    util::error::PlayerError,
+};
 
+use self::{
+    audio_player::{AudioOutput, AudioPlayer},
+    state::{PlaybackState, StateMachine},
+    traits::Player,
+    vedio_player::VideoPlayer,
+};
+
+/// Plays the media of a decoder, it's the only way to control the playback
 pub struct MediaPlayer {
+    /// The state shared by the audio and video player
+    state: StateMachine,
+    /// The playing media
+    decoder: MediaDecoder,
+    /// The position of last seek, unit: milliseconds.
+    /// It's the position until the frames there are played
+    seek_target: Option<i64>,
     audio_player: AudioPlayer,
     video_player: VideoPlayer,
 }
 
 impl MediaPlayer {
-    pub fn new() -> Self {
+    pub fn new(decoder: MediaDecoder) -> Self {
+        let state = StateMachine::new();
         Self {
-            audio_player: AudioPlayer::new(),
-            video_player: VideoPlayer::new(),
+            audio_player: AudioPlayer::new(state.clone()),
+            video_player: VideoPlayer::new(state.clone()),
+            state,
+            decoder,
+            seek_target: None,
         }
     }
 
-    pub fn start(&mut self)-> Result<(), SuperError>{
+    pub fn decoder(&self) -> &MediaDecoder {
+        &self.decoder
+    }
+
+    /// Continue with the next media without stopping the playback, the old decoder is returned
+    pub fn replace_decoder(&mut self, decoder: MediaDecoder) -> MediaDecoder {
+        self.seek_target = None;
+        mem::replace(&mut self.decoder, decoder)
+    }
+
+    pub fn start(&mut self)-> Result<(), PlayerError>{
+        // Nothing would feed the players
+        if self.decoder.is_stopped() {
+            return Err(PlayerError::NotPlaying("The decoder is stopped"));
+        }
+        // The playing thread exits at once if it's stopped
+        self.state.change_from(PlaybackState::Stopped, PlaybackState::Playing)?;
+
         let audio_player = &mut self.audio_player;
         let video_player = &mut self.video_player;
 
@@ -28,54 +71,240 @@ impl MediaPlayer {
 
         Ok(())
     }
+
+    /// Create the callback of audio device, which pulls the decoded audio frames
+    pub fn audio_output(&self, spec: &AudioSpec) -> AudioOutput {
+        self.audio_player.output(spec)
+    }
+
+    /// Illegal transitions are ignored, e.g. resume while seeking
+    fn change_state(&self, from: Option<PlaybackState>, next: PlaybackState) {
+        let result = match from {
+            Some(from) => self.state.change_from(from, next),
+            None => self.state.change_to(next),
+        };
+        if let Err(err) = result {
+            debug!("{}", err);
+        }
+    }
 }
 
 impl Player for MediaPlayer {
-    fn play(&mut self) {
-        self.audio_player.play();
-        self.video_player.play();
+    fn play(&mut self) -> Result<(), PlayerError> {
+        self.start()
     }
 
     fn pause(&mut self) {
-        self.audio_player.pause();
-        self.video_player.pause();
+        self.change_state(None, PlaybackState::Paused);
     }
 
     fn resume(&mut self) {
-        self.audio_player.resume();
-        self.video_player.resume();
+        self.change_state(Some(PlaybackState::Paused), PlaybackState::Playing);
     }
 
     fn stop(&mut self) {
-        self.audio_player.stop();
-        self.video_player.stop();
+        self.change_state(None, PlaybackState::Stopped);
+        self.decoder.stop();
+        self.video_player.join();
+    }
+
+    fn seek(&mut self, position: Duration, mode: SeekMode) -> Result<(), PlayerError> {
+        // Live streams and the standard input can't be seeked
+        if !self.decoder.is_seekable() {
+            return Err(PlayerError::Unsupported(
+                "the media can't be seeked".to_string(),
+            ));
+        }
+
+        // Nothing is played after seeking to the end, the decoder reports the end of media
+        // as usual, so the playlist goes on with the next media
+        let duration = self.decoder.summaries().duration_millis();
+        let (position, mode) = match position.as_millis() as u64 {
+            millis if duration > 0 && millis >= duration => (duration as i64, SeekMode::Accurate),
+            millis => (millis as i64, mode),
+        };
+
+        // Seeking again before the last seek is finished only moves the target
+        if self.state.get() != PlaybackState::Seeking {
+            self.state.change_to(PlaybackState::Seeking)?;
+        }
+        self.seek_target = Some(position);
+        // The clock is invalid until the frames at the position are played
+        GLOBAL_PTS_MILLIS.store(-1, Ordering::Release);
+        self.decoder.seek_to(position, mode);
+
+        Ok(())
+    }
+
+    fn seek_finished(&mut self) {
+        // It stays paused if it's paused while seeking
+        self.change_state(Some(PlaybackState::Seeking), PlaybackState::Playing);
     }
 
-    fn fast_forward(&mut self) {
-        self.audio_player.fast_forward();
-        self.video_player.fast_forward();
+    fn set_rate(&mut self, rate: f64) -> Result<(), PlayerError> {
+        if !(MIN_PLAYBACK_RATE..=MAX_PLAYBACK_RATE).contains(&rate) {
+            return Err(PlayerError::InvalidArgument(format!(
+                "Playback rate {} is out of range {}..={}",
+                rate, MIN_PLAYBACK_RATE, MAX_PLAYBACK_RATE
+            )));
+        }
+
+        PLAYBACK_RATE.store(rate);
+        Ok(())
     }
 
-    fn fast_rewind(&mut self) {
-        self.audio_player.fast_rewind();
-        self.video_player.fast_rewind();
+    fn state(&self) -> PlaybackState {
+        self.state.get()
     }
 
-    fn seeking(&mut self) {
-        self.audio_player.seeking();
-        self.video_player.seeking();
+    fn position(&self) -> Duration {
+        let clock = GLOBAL_PTS_MILLIS.load(Ordering::Acquire);
+        let millis = match self.seek_target {
+            Some(target) if clock < 0 || self.state.get() == PlaybackState::Seeking => target,
+            _ => clock,
+        };
+        Duration::from_millis(millis.max(0) as u64)
     }
 
-    fn seek_finished(&mut self) {
-        self.audio_player.seek_finished();
-        self.video_player.seek_finished();
+    fn duration(&self) -> Duration {
+        Duration::from_millis(self.decoder.summaries().duration_millis())
+    }
+
+    fn rate(&self) -> f64 {
+        PLAYBACK_RATE.load()
+    }
+
+    fn buffered_ranges(&self) -> Vec<Range<Duration>> {
+        let start = self.position();
+        match self.decoder.buffered_until() {
+            Some(end) if end as u64 > start.as_millis() as u64 => {
+                vec![start..Duration::from_millis(end as u64)]
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Drop for MediaPlayer {
+    fn drop(&mut self) {
+        self.stop();
     }
 }
 
-enum PlayerState {
-    NONE,
-    PLAYING,
-    SEEKING,
-    PAUSING,
-    STOPPED,
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        entity::EventMessage,
+        global::{AUDIO_BUFFER, EVENT_CHANNEL},
+        media::{
+            decoder::tests::{lock_active_decoder, temp_path},
+            source::{tests::wav_data, MediaSource},
+        },
+    };
+    use std::{
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+
+    /// Play one second audio without starting the player
+    fn with_player(name: &str, test: impl FnOnce(MediaPlayer)) {
+        let _active = lock_active_decoder();
+        let path = temp_path(name);
+        std::fs::write(&path, wav_data()).unwrap();
+        let source = MediaSource::File(path.clone());
+        let decoder = MediaDecoder::preload(&source, Arc::default()).unwrap();
+
+        test(MediaPlayer::new(decoder));
+        // State changes are left for nobody
+        while EVENT_CHANNEL.1.try_recv().is_ok() {}
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_seek_stopped() {
+        with_player("seek_stopped.wav", |mut player| {
+            assert_eq!(Duration::from_secs(1), player.duration());
+
+            // Nothing is playing
+            let result = player.seek(Duration::from_millis(500), SeekMode::Accurate);
+            assert!(matches!(
+                result,
+                Err(PlayerError::IllegalTransition(
+                    PlaybackState::Stopped,
+                    PlaybackState::Seeking
+                ))
+            ));
+            assert!(player.seek_by(-100, SeekMode::Fast).is_err());
+            assert_eq!(PlaybackState::Stopped, player.state());
+            assert!(player.buffered_ranges().is_empty());
+
+            // Seeking beyond the end needs a playing media as well
+            assert!(player.seek(Duration::from_secs(2), SeekMode::Fast).is_err());
+            assert_eq!(PlaybackState::Stopped, player.state());
+        });
+    }
+
+    #[test]
+    fn test_set_rate() {
+        with_player("set_rate.wav", |mut player| {
+            for rate in [0.0, 0.2, 4.5, f64::NAN, f64::INFINITY] {
+                assert!(player.set_rate(rate).is_err(), "{}", rate);
+            }
+
+            player.set_rate(MAX_PLAYBACK_RATE).unwrap();
+            assert_eq!(MAX_PLAYBACK_RATE, player.rate());
+            player.set_rate(1.0).unwrap();
+            assert_eq!(1.0, player.rate());
+        });
+    }
+
+    /// A stopped player doesn't claim to play, since its decoder threads are gone
+    #[test]
+    fn test_play_after_stop() {
+        with_player("play_after_stop.wav", |mut player| {
+            player.play().unwrap();
+            assert_eq!(PlaybackState::Playing, player.state());
+            assert!(player.play().is_err());
+
+            player.stop();
+            assert_eq!(PlaybackState::Stopped, player.state());
+            assert!(matches!(player.play(), Err(PlayerError::NotPlaying(_))));
+            assert_eq!(PlaybackState::Stopped, player.state());
+        });
+    }
+
+    /// The media ends at once, and the app goes on with the playlist as usual
+    #[test]
+    fn test_seek_past_end() {
+        let _active = lock_active_decoder();
+        let path = temp_path("seek_past_end.wav");
+        std::fs::write(&path, wav_data()).unwrap();
+        let mut decoder =
+            MediaDecoder::preload(&MediaSource::File(path.clone()), Arc::default()).unwrap();
+        let id = decoder.id();
+        decoder.activate();
+        let mut player = MediaPlayer::new(decoder);
+        player.start().unwrap();
+
+        player.seek(Duration::from_secs(5), SeekMode::Fast).unwrap();
+        assert_eq!(PlaybackState::Seeking, player.state());
+        assert_eq!(Duration::from_secs(1), player.position());
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut seek_finished = false;
+        loop {
+            assert!(Instant::now() < deadline, "the end of media isn't reported");
+            while AUDIO_BUFFER.pop().is_some() {}
+            match EVENT_CHANNEL.1.recv_timeout(Duration::from_millis(10)) {
+                Ok(EventMessage::SeekFinished) => seek_finished = true,
+                Ok(EventMessage::EndOfMedia(media)) if media == id => break,
+                _ => {}
+            }
+        }
+        assert!(seek_finished);
+
+        player.stop();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,155 @@
+use std::sync::Arc;
+
+use crossbeam::atomic::AtomicCell;
+
+use crate::{
+    entity::EventMessage,
+    global::EVENT_CHANNEL,
+    util::error::{safe_send, PlayerError},
+};
+
+/// State of playback, shared by the audio and the video player
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaybackState {
+    Stopped,
+    Playing,
+    Paused,
+    /// Waiting for the decoder to reach the new position
+    Seeking,
+}
+
+impl PlaybackState {
+    /// Whether the state can be changed to `next`
+    pub fn can_change_to(self, next: PlaybackState) -> bool {
+        use PlaybackState::*;
+
+        matches!(
+            (self, next),
+            (Stopped, Playing)
+                | (Playing, Paused)
+                | (Paused, Playing)
+                | (Playing | Paused, Seeking)
+                // Paused while seeking, it stays paused when the seek is finished
+                | (Seeking, Playing | Paused)
+                | (Playing | Paused | Seeking, Stopped)
+        )
+    }
+}
+
+/// The playback state machine, transitions are validated and broadcast as
+/// `EventMessage::StateChanged`. Clones share the same state
+#[derive(Debug, Clone)]
+pub struct StateMachine {
+    state: Arc<AtomicCell<PlaybackState>>,
+}
+
+impl StateMachine {
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(AtomicCell::new(PlaybackState::Stopped)),
+        }
+    }
+
+    pub fn get(&self) -> PlaybackState {
+        self.state.load()
+    }
+
+    /// Change the state to `next`, the state is kept if the transition is illegal
+    pub fn change_to(&self, next: PlaybackState) -> Result<(), PlayerError> {
+        // The state may be changed by other threads meanwhile
+        let mut current = self.state.load();
+        loop {
+            if !current.can_change_to(next) {
+                return Err(PlayerError::IllegalTransition(current, next));
+            }
+
+            match self.state.compare_exchange(current, next) {
+                Ok(_) => break,
+                Err(state) => current = state,
+            }
+        }
+
+        safe_send(EVENT_CHANNEL.0.send(EventMessage::StateChanged(next)));
+
+        Ok(())
+    }
+
+    /// Change the state to `next` only if it's `from` now
+    pub fn change_from(&self, from: PlaybackState, next: PlaybackState) -> Result<(), PlayerError> {
+        if !from.can_change_to(next) {
+            return Err(PlayerError::IllegalTransition(from, next));
+        }
+
+        self.state
+            .compare_exchange(from, next)
+            .map_err(|current| PlayerError::IllegalTransition(current, next))?;
+        safe_send(EVENT_CHANNEL.0.send(EventMessage::StateChanged(next)));
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use PlaybackState::*;
+
+    #[test]
+    fn test_transitions() {
+        let machine = StateMachine::new();
+        assert_eq!(Stopped, machine.get());
+
+        for next in [
+            Playing, Seeking, Paused, Seeking, Playing, Paused, Playing, Stopped,
+        ] {
+            machine.change_to(next).unwrap();
+            assert_eq!(next, machine.get());
+        }
+
+        // Clones share the state
+        let clone = machine.clone();
+        clone.change_to(Playing).unwrap();
+        assert_eq!(Playing, machine.get());
+    }
+
+    #[test]
+    fn test_illegal_transitions() {
+        let machine = StateMachine::new();
+        // Nothing to seek, pause or stop while stopped
+        for next in [Seeking, Paused, Stopped] {
+            assert!(matches!(
+                machine.change_to(next),
+                Err(PlayerError::IllegalTransition(Stopped, n)) if n == next
+            ));
+            assert_eq!(Stopped, machine.get());
+        }
+
+        machine.change_to(Playing).unwrap();
+        assert!(machine.change_to(Playing).is_err());
+
+        machine.change_to(Paused).unwrap();
+        assert!(machine.change_to(Paused).is_err());
+
+        machine.change_to(Seeking).unwrap();
+        assert!(machine.change_to(Seeking).is_err());
+        assert_eq!(Seeking, machine.get());
+    }
+
+    #[test]
+    fn test_change_from() {
+        let machine = StateMachine::new();
+        machine.change_to(Playing).unwrap();
+        machine.change_to(Seeking).unwrap();
+        // Paused while seeking, finishing the seek doesn't resume it
+        machine.change_to(Paused).unwrap();
+        assert!(matches!(
+            machine.change_from(Seeking, Playing),
+            Err(PlayerError::IllegalTransition(Paused, Playing))
+        ));
+        assert_eq!(Paused, machine.get());
+
+        machine.change_from(Paused, Playing).unwrap();
+        assert_eq!(Playing, machine.get());
+        assert!(machine.change_from(Playing, Playing).is_err());
+    }
+}
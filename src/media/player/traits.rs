@@ -1,10 +1,37 @@
+use std::{ops::Range, time::Duration};
+
+use crate::{media::decoder::SeekMode, util::error::PlayerError};
+
+use super::state::PlaybackState;
+
+/// Playback controls shared by the UI, key handlers and remote controls
 pub trait Player {
-    fn play(&mut self);
+    /// Start playing from the stopped state, it fails once the decoder is stopped
+    fn play(&mut self) -> Result<(), PlayerError>;
     fn pause(&mut self);
     fn resume(&mut self);
     fn stop(&mut self);
-    fn fast_forward(&mut self);
-    fn fast_rewind(&mut self);
-    fn seeking(&mut self);
+    /// Seek to the position, the media ends as if it's played through if it's beyond the end
+    fn seek(&mut self, position: Duration, mode: SeekMode) -> Result<(), PlayerError>;
+    /// The decoder has reached the position
     fn seek_finished(&mut self);
+    /// Change the playback speed, 1.0 is the normal speed
+    fn set_rate(&mut self, rate: f64) -> Result<(), PlayerError>;
+
+    fn state(&self) -> PlaybackState;
+    /// The position being played, it's the target position while seeking
+    fn position(&self) -> Duration;
+    /// Duration of the media, zero if it's unknown
+    fn duration(&self) -> Duration;
+    /// Playback speed, 1.0 is the normal speed
+    fn rate(&self) -> f64;
+    /// Parts of the media decoded ahead and ready to play
+    fn buffered_ranges(&self) -> Vec<Range<Duration>>;
+
+    /// Seek forward from the current position, or backward if `offset` is negative,
+    /// unit: milliseconds
+    fn seek_by(&mut self, offset: i64, mode: SeekMode) -> Result<(), PlayerError> {
+        let position = self.position().as_millis() as i64 + offset;
+        self.seek(Duration::from_millis(position.max(0) as u64), mode)
+    }
 }
@@ -1,159 +1,153 @@
-use crossbeam::atomic::AtomicCell;
 use log::info;
 // use tracing::{info, debug};
 use std::{
     cell::Cell,
-    sync::Arc,
+    sync::atomic::Ordering,
     thread::{self, JoinHandle},
     time::Duration,
 };
 
 use crate::{
     entity::EventMessage,
-    global::{EVENT_CHANNEL, VIDEO_BUFFER, VIDEO_SUMMARY},
-    util::error::{safe_send, SuperError},
+    global::{
+        AUDIO_DISABLED, AUDIO_SUMMARY, EVENT_CHANNEL, GLOBAL_PTS_MEDIA, GLOBAL_PTS_MILLIS,
+        PLAYBACK_RATE, THREAD_JOIN_TIMEOUT, VIDEO_BUFFER, VIDEO_FILTER_SETTINGS, VIDEO_SUMMARY,
+    },
+    util::{
+        error::{safe_send, PlayerError},
+        worker::join_timeout,
+    },
 };
 
-use super::traits::Player;
+use super::state::{PlaybackState, StateMachine};
+
+/// The maximum time to wait for the audio clock before showing a frame, unit: milliseconds
+const MAX_SYNC_WAIT_MILLIS: u64 = 500;
+/// The wait duration if there's no frame to play
+const EMPTY_BUFFER_SLEEP_DURATION: Duration = Duration::from_millis(5);
+/// Name of the video playing thread
+pub const VIDEO_THREAD_NAME: &str = "video-player";
 
 pub struct VideoPlayer {
-    /// State of the audio player
-    state: Arc<AtomicCell<State>>,
+    /// State of playback, shared with the playing thread
+    state: StateMachine,
     /// Thread id
     tid: Cell<Option<JoinHandle<()>>>,
 }
 
 impl VideoPlayer {
-    pub fn new() -> Self {
+    pub fn new(state: StateMachine) -> Self {
         Self {
-            state: Arc::new(AtomicCell::new(State::Stopped)),
+            state,
             tid: Cell::new(None),
         }
     }
 
-    pub fn start(&mut self) -> Result<(), SuperError> {
+    pub fn start(&mut self) -> Result<(), PlayerError> {
         let summary = VIDEO_SUMMARY.read().unwrap();
         if summary.is_none() {
             return Ok(());
         }
 
-        // If the thread is already running, stop it first
-        if let Some(_tid) = self.tid.take() {
-            if !_tid.is_finished() {
-                _tid.join().expect("Join audio thread failed!");
-            }
-        }
-
         let sender = &EVENT_CHANNEL.0;
         let summary = summary.as_ref().unwrap();
         info!("Starting video player, summary: {:?}", summary);
-        sender.send(EventMessage::Resize((summary.width, summary.height)))?;
+        let auto_rotate = VIDEO_FILTER_SETTINGS.read().unwrap().auto_rotate;
+        sender.send(EventMessage::Resize(summary.display_size(auto_rotate)))?;
 
         let state = self.state.clone();
-        let sleep_duration = Duration::from_millis(summary.play_interval);
-        let tid = thread::spawn({
+        let interval_millis = summary.play_interval;
+        let sleep_duration = Duration::from_millis(interval_millis);
+        // Audio output is the master clock if there's audio
+        let has_audio = AUDIO_SUMMARY.read().unwrap().is_some();
+        let builder = thread::Builder::new().name(VIDEO_THREAD_NAME.to_string());
+        let tid = builder.spawn({
             move || {
                 const MAX_WAIT_COUNT: u8 = 2;
                 let mut wait_count: u8 = 0;
+                // The frame of next media in playlist waits here until its audio is played
+                let mut waiting = None;
 
-                state.store(State::Playing);
                 loop {
                     // Check player state
-                    match state.load() {
-                        // Ready to stop or already stopped, exit thread
-                        State::ReadyToStop => {
-                            state.store(State::Stopped);
-                            break;
-                        }
-                        State::Stopped => break,
-                        // Ready to pause or already paused,
-                        // do nothing and continue loop
-                        State::ReadyToPause => {
-                            state.store(State::Paused);
+                    match state.get() {
+                        // Exit thread once stopped
+                        PlaybackState::Stopped => break,
+                        // Do nothing and continue loop
+                        PlaybackState::Paused => {
                             thread::sleep(sleep_duration);
                             continue;
                         }
-                        State::Paused => {
+                        PlaybackState::Seeking => {
+                            // The waiting frame is out of date
+                            waiting = None;
                             thread::sleep(sleep_duration);
                             continue;
                         }
-                        // Ready to play or already playing, just go on
-                        State::ReadyToPlay | State::ReadyToResume => {
-                            state.store(State::Playing);
-                        }
-                        State::Playing => {
+                        PlaybackState::Playing => {
                             // go on
                         }
-                        State::Seeking => {
-                            thread::sleep(sleep_duration);
-                            continue;
-                        }
-                        State::SeekFinished => {
-                            state.store(State::Playing);
-                        }
                     }
 
                     // Play video
-                    if let Some(frame) = VIDEO_BUFFER.pop() {
-                        // Send video data to UI
-                        safe_send(sender.send(EventMessage::RenderVideo(frame)));
-
-                        thread::sleep(sleep_duration);
+                    if let Some(frame) = waiting.take().or_else(|| VIDEO_BUFFER.pop()) {
+                        // Follow the audio clock if there's audio, otherwise play at frame rate
+                        let audio_clock = has_audio && !AUDIO_DISABLED.load(Ordering::Acquire);
+                        let clock = GLOBAL_PTS_MILLIS.load(Ordering::Acquire);
+                        let clock_media = GLOBAL_PTS_MEDIA.load(Ordering::Acquire);
+                        if audio_clock && clock >= 0 {
+                            // Pts starts over at the next media, so frames are compared
+                            // with the clock of their own media only
+                            if frame.media > clock_media {
+                                // The audio of previous media is still being played
+                                waiting = Some(frame);
+                                thread::sleep(EMPTY_BUFFER_SLEEP_DURATION);
+                                continue;
+                            }
+                            if frame.media < clock_media {
+                                // The previous media is over, its frames are late
+                                continue;
+                            }
+
+                            let diff = frame.pts_millis - clock;
+                            if diff < -(interval_millis as i64) {
+                                // The frame is late, drop it to catch up with audio
+                                continue;
+                            }
+                            if diff > 0 {
+                                let wait = (diff as u64).min(MAX_SYNC_WAIT_MILLIS);
+                                thread::sleep(Duration::from_millis(wait));
+                            }
+
+                            // Send video data to UI
+                            safe_send(sender.send(EventMessage::RenderVideo(frame)));
+                        } else {
+                            // Video is the clock when it's played without audio
+                            if !audio_clock {
+                                GLOBAL_PTS_MILLIS.store(frame.pts_millis, Ordering::Release);
+                            }
+
+                            // Send video data to UI
+                            safe_send(sender.send(EventMessage::RenderVideo(frame)));
+
+                            thread::sleep(sleep_duration.div_f64(PLAYBACK_RATE.load()));
+                        }
+                    } else {
+                        thread::sleep(EMPTY_BUFFER_SLEEP_DURATION);
                     }
                 }
             }
-        });
+        })?;
 
         self.tid.set(Some(tid));
 
         Ok(())
     }
-}
-
-impl Player for VideoPlayer {
-    fn play(&mut self) {
-        self.state.store(State::ReadyToPlay);
-    }
 
-    fn pause(&mut self) {
-        self.state.store(State::ReadyToPause);
-    }
-
-    fn resume(&mut self) {
-        self.state.store(State::ReadyToResume);
-    }
-
-    fn stop(&mut self) {
-        self.state.store(State::ReadyToStop);
-    }
-
-    fn fast_forward(&mut self) {
-        todo!();
-    }
-
-    fn fast_rewind(&mut self) {
-        todo!();
-    }
-
-    fn seeking(&mut self) {
-        self.state.store(State::Seeking);
-    }
-
-    fn seek_finished(&mut self) {
-        self.state.store(State::SeekFinished);
+    /// Wait for the thread to exit, it exits once the playback is stopped
+    pub fn join(&mut self) {
+        if let Some(tid) = self.tid.take() {
+            join_timeout(tid, THREAD_JOIN_TIMEOUT);
+        }
     }
 }
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum State {
-    Playing,
-    Paused,
-    Stopped,
-    Seeking,
-    SeekFinished,
-
-    ReadyToPlay,
-    ReadyToPause,
-    ReadyToResume,
-    ReadyToStop,
-}
@@ -0,0 +1,123 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use super::source::MediaSource;
+
+/// Files with these extensions are picked when a directory is opened
+const MEDIA_EXTENSIONS: [&str; 18] = [
+    "aac", "ape", "avi", "flac", "flv", "m4a", "mkv", "mov", "mp3", "mp4", "mpg", "ogg", "opus",
+    "ts", "wav", "webm", "wma", "wmv",
+];
+
+/// Media played one after another, e.g. the tracks of an album
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Playlist {
+    items: Vec<MediaSource>,
+    current: usize,
+}
+
+impl Playlist {
+    pub fn new(items: Vec<MediaSource>) -> Self {
+        Self { items, current: 0 }
+    }
+
+    /// Media files of the directory sorted by name, subdirectories are not included
+    pub fn media_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+        let mut files: Vec<PathBuf> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file() && is_media_file(path))
+            .collect();
+        files.sort();
+
+        Ok(files)
+    }
+
+    pub fn current(&self) -> Option<&MediaSource> {
+        self.items.get(self.current)
+    }
+
+    /// The media played after current one
+    pub fn peek_next(&self) -> Option<&MediaSource> {
+        self.items.get(self.current + 1)
+    }
+
+    /// Move to the next media and return it, `None` if current one is the last
+    pub fn advance(&mut self) -> Option<&MediaSource> {
+        if self.current + 1 >= self.items.len() {
+            return None;
+        }
+
+        self.current += 1;
+        self.current()
+    }
+
+    /// Make the media current one if it's in the list
+    /// # Returns
+    /// `false` if the media is not found
+    pub fn select(&mut self, source: &MediaSource) -> bool {
+        match self.items.iter().position(|item| item == source) {
+            Some(index) => {
+                self.current = index;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+fn is_media_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map_or(false, |ext| {
+            MEDIA_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playlist() -> Playlist {
+        Playlist::new(vec![
+            source("01.flac"),
+            source("02.flac"),
+            source("03.flac"),
+        ])
+    }
+
+    fn source(path: &str) -> MediaSource {
+        MediaSource::File(PathBuf::from(path))
+    }
+
+    #[test]
+    fn test_advance() {
+        let mut list = playlist();
+        assert_eq!(Some(&source("01.flac")), list.current());
+        assert_eq!(Some(&source("02.flac")), list.peek_next());
+        assert_eq!(Some(&source("02.flac")), list.advance());
+        assert_eq!(Some(&source("03.flac")), list.advance());
+        assert_eq!(None, list.peek_next());
+        assert_eq!(None, list.advance());
+        assert_eq!(Some(&source("03.flac")), list.current());
+        assert_eq!(None, Playlist::default().current());
+    }
+
+    #[test]
+    fn test_select() {
+        let mut list = playlist();
+        assert!(list.select(&source("02.flac")));
+        assert_eq!(Some(&source("03.flac")), list.peek_next());
+        assert!(!list.select(&source("04.flac")));
+        assert_eq!(Some(&source("02.flac")), list.current());
+    }
+
+    #[test]
+    fn test_is_media_file() {
+        assert!(is_media_file(Path::new("album/01 Intro.FLAC")));
+        assert!(is_media_file(Path::new("movie.mkv")));
+        assert!(!is_media_file(Path::new("cover.jpg")));
+        assert!(!is_media_file(Path::new("README")));
+    }
+}
@@ -0,0 +1,295 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    ptr, slice,
+    sync::{atomic::AtomicBool, Arc},
+};
+
+use log::info;
+use rsmpeg::{
+    avutil::AVFrame,
+    ffi::{
+        AVPixelFormat_AV_PIX_FMT_RGB24 as AVPIXELFORMAT_AV_PIX_FMT_RGB24,
+        AVPixelFormat_AV_PIX_FMT_YUV420P as AVPIXELFORMAT_AV_PIX_FMT_YUV420P, SWS_BICUBIC,
+    },
+    swscale::SwsContext,
+};
+use sdl2::{image::SaveSurface, pixels::PixelFormatEnum, surface::Surface};
+
+use crate::{
+    global::SCREENSHOT_DIR,
+    util::{colorspace::set_yuv_source, error::PlayerError},
+};
+
+use super::{
+    decoder::VideoFrame,
+    frame_grabber::{FrameGrabber, RgbImage},
+    video_filter::normalize_rotation,
+};
+
+/// Build the screenshot file path from the media name and the timestamp,
+/// formatted as `<dir>/<media name>_<hh>-<mm>-<ss>-<mmm>.png`
+pub fn screenshot_path(dir: &Path, media: &Path, pts_millis: i64) -> PathBuf {
+    let name = media
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "screenshot".to_string());
+
+    let millis = pts_millis.max(0);
+    let file_name = format!(
+        "{}_{:02}-{:02}-{:02}-{:03}.png",
+        name,
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    );
+
+    dir.join(file_name)
+}
+
+/// Save the frame as PNG with its source resolution,
+/// it's turned as it's shown if the filters didn't rotate it
+pub fn save_video_frame(frame: &VideoFrame, path: &Path) -> Result<(), PlayerError> {
+    let image = video_frame_to_rgb(frame)?;
+    save_png(rotate_image(image, frame.rotation), path)
+}
+
+/// Turn the picture clockwise, the rotation is rounded to quarter turns
+pub fn rotate_image(image: RgbImage, rotation: i32) -> RgbImage {
+    let rotation = normalize_rotation(rotation);
+    if rotation == 0 {
+        return image;
+    }
+
+    let (width, height) = (image.width as usize, image.height as usize);
+    let (rotated_width, rotated_height) = match rotation {
+        180 => (width, height),
+        _ => (height, width),
+    };
+    let pitch = rotated_width * 3;
+    let mut data = vec![0; pitch * rotated_height];
+    for row in 0..height {
+        for col in 0..width {
+            let (x, y) = match rotation {
+                90 => (height - 1 - row, col),
+                180 => (width - 1 - col, height - 1 - row),
+                _ => (row, width - 1 - col),
+            };
+            let src = row * image.pitch + col * 3;
+            let dst = y * pitch + x * 3;
+            data[dst..dst + 3].copy_from_slice(&image.data[src..src + 3]);
+        }
+    }
+
+    RgbImage {
+        data,
+        width: rotated_width as u32,
+        height: rotated_height as u32,
+        pitch,
+    }
+}
+
+/// Convert the displayed video frame to RGB24 without scaling
+pub fn video_frame_to_rgb(frame: &VideoFrame) -> Result<RgbImage, PlayerError> {
+    // Frames whose colors SDL can't convert are RGB24 already
+    if frame.format == AVPIXELFORMAT_AV_PIX_FMT_RGB24 {
+        let (data, pitch) = frame
+            .planes
+            .get(0)
+            .ok_or_else(|| PlayerError::InvalidMedia("video frame without pixels".to_string()))?;
+        return Ok(RgbImage {
+            data: data.to_vec(),
+            width: frame.width as u32,
+            height: frame.height as u32,
+            pitch,
+        });
+    }
+    if frame.format != AVPIXELFORMAT_AV_PIX_FMT_YUV420P {
+        return Err(PlayerError::Unsupported(format!(
+            "screenshot of pixel format {}",
+            frame.format
+        )));
+    }
+
+    let width = frame.width as i32;
+    let height = frame.height as i32;
+    let mut sws_ctx = SwsContext::get_context(
+        width,
+        height,
+        frame.format,
+        width,
+        height,
+        AVPIXELFORMAT_AV_PIX_FMT_RGB24,
+        SWS_BICUBIC,
+    )
+    .ok_or(PlayerError::ScaleContext)?;
+    let colorimetry = frame.colorimetry;
+    set_yuv_source(
+        &mut sws_ctx,
+        colorimetry.matrix(frame.height),
+        colorimetry.range(),
+    );
+
+    let mut rgb_frame = AVFrame::new();
+    rgb_frame.set_format(AVPIXELFORMAT_AV_PIX_FMT_RGB24);
+    rgb_frame.set_width(width);
+    rgb_frame.set_height(height);
+    rgb_frame.alloc_buffer()?;
+
+    // Rows of the planes may be padded, so the pitches are passed as strides
+    let planes = [0, 1, 2].map(|index| frame.planes.get(index));
+    let [Some((y, y_pitch)), Some((u, u_pitch)), Some((v, v_pitch))] = planes else {
+        return Err(PlayerError::InvalidMedia(
+            "video frame without pixels".to_string(),
+        ));
+    };
+    let src_slice: [*const u8; 4] = [y.as_ptr(), u.as_ptr(), v.as_ptr(), ptr::null()];
+    let src_stride: [i32; 4] = [y_pitch as i32, u_pitch as i32, v_pitch as i32, 0];
+    unsafe {
+        sws_ctx.scale(
+            src_slice.as_ptr(),
+            src_stride.as_ptr(),
+            0,
+            height,
+            rgb_frame.data.as_ptr(),
+            rgb_frame.linesize.as_ptr(),
+        )?;
+    }
+
+    let pitch = rgb_frame.linesize[0] as usize;
+    let data =
+        unsafe { slice::from_raw_parts(rgb_frame.data[0], pitch * height as usize) }.to_vec();
+
+    Ok(RgbImage {
+        data,
+        width: width as u32,
+        height: height as u32,
+        pitch,
+    })
+}
+
+pub fn save_png(image: RgbImage, path: &Path) -> Result<(), PlayerError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut image = image;
+    let surface = Surface::from_data(
+        &mut image.data,
+        image.width,
+        image.height,
+        image.pitch as u32,
+        PixelFormatEnum::RGB24,
+    )?;
+    surface.save(path)?;
+
+    info!("screenshot saved to {}", path.display());
+
+    Ok(())
+}
+
+/// Extract frames at specified timestamps and save them as PNG, no window is required.
+/// Timestamps are in milliseconds, the output directory defaults to `SCREENSHOT_DIR`.
+/// Reading the media fails once `interrupt` is set
+pub fn extract_frames(
+    media: &Path,
+    timestamps: &[i64],
+    out_dir: Option<&Path>,
+    interrupt: Arc<AtomicBool>,
+) -> Result<Vec<PathBuf>, PlayerError> {
+    let path = media
+        .to_str()
+        .ok_or_else(|| PlayerError::InvalidMedia(format!("{}", media.display())))?;
+    let out_dir = out_dir.unwrap_or_else(|| Path::new(SCREENSHOT_DIR));
+
+    let mut grabber = FrameGrabber::new(path, interrupt)?;
+    let mut saved = vec![];
+    for &timestamp in timestamps {
+        let frame = grabber.grab(timestamp, true)?.ok_or_else(|| {
+            PlayerError::InvalidMedia(format!("no frame found at {} milliseconds", timestamp))
+        })?;
+
+        let image = grabber.to_rgb(&frame, frame.width as u32, frame.height as u32)?;
+        // Named by the position of the frame, which is at or right after the timestamp
+        let position = grabber.position_millis(&frame).unwrap_or(timestamp);
+        let file = screenshot_path(out_dir, media, position);
+        save_png(image, &file)?;
+        saved.push(file);
+    }
+
+    Ok(saved)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::media::decoder::tests::{mux_damaged_clip, temp_path};
+
+    use super::*;
+
+    /// Frames are extracted at the positions from the start of the media,
+    /// also from MPEG-TS whose timestamps don't start at zero
+    #[test]
+    fn test_extract_frames() {
+        for (extension, codec) in [("mkv", "mpeg4"), ("ts", "mpeg2video")] {
+            let media = temp_path(&format!("clip.{}", extension));
+            mux_damaged_clip(&media, Some(codec), None, 5, |_, _| false);
+            let out_dir = temp_path("frames");
+
+            let files =
+                extract_frames(&media, &[0, 3000, 4520], Some(&out_dir), Arc::default()).unwrap();
+            let names: Vec<_> = files
+                .iter()
+                .map(|file| file.file_name().unwrap().to_string_lossy().to_string())
+                .collect();
+            let stem = media.file_stem().unwrap().to_string_lossy().to_string();
+            assert_eq!(
+                vec![
+                    format!("{}_00-00-00-000.png", stem),
+                    format!("{}_00-00-03-000.png", stem),
+                    format!("{}_00-00-04-520.png", stem),
+                ],
+                names,
+                "{}",
+                extension
+            );
+            assert!(files.iter().all(|file| file.is_file()), "{}", extension);
+
+            fs::remove_dir_all(&out_dir).unwrap();
+            fs::remove_file(&media).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_rotate_image() {
+        // 3x2 pixels numbered by rows, lines are padded as converted frames are
+        let image = RgbImage {
+            data: [[1, 2, 3], [4, 5, 6]]
+                .iter()
+                .flat_map(|row| row.iter().flat_map(|v| [*v; 3]).chain([0; 3]))
+                .collect(),
+            width: 3,
+            height: 2,
+            pitch: 12,
+        };
+        // The pixels of the turned picture by rows
+        let pixels = |image: &RgbImage| -> Vec<Vec<u8>> {
+            (0..image.height as usize)
+                .map(|row| {
+                    (0..image.width as usize)
+                        .map(|col| image.data[row * image.pitch + col * 3])
+                        .collect()
+                })
+                .collect()
+        };
+
+        let turned = rotate_image(image.clone(), 90);
+        assert_eq!((2, 3, 6), (turned.width, turned.height, turned.pitch));
+        assert_eq!(vec![vec![4, 1], vec![5, 2], vec![6, 3]], pixels(&turned));
+        let turned = rotate_image(image.clone(), 180);
+        assert_eq!(vec![vec![6, 5, 4], vec![3, 2, 1]], pixels(&turned));
+        let turned = rotate_image(image.clone(), -90);
+        assert_eq!(vec![vec![3, 6], vec![2, 5], vec![1, 4]], pixels(&turned));
+        assert_eq!(image, rotate_image(image.clone(), 0));
+    }
+}
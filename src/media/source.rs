@@ -0,0 +1,450 @@
+use std::{
+    ffi::{c_int, c_void, CString},
+    fmt::{self, Display},
+    ops::{Deref, DerefMut},
+    path::{Path, PathBuf},
+    ptr::{self, NonNull},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use rsmpeg::{
+    avformat::AVFormatContextInput,
+    avutil::AVDictionary,
+    error::RsmpegError,
+    ffi::{
+        av_dict_free, avformat_alloc_context, avformat_find_stream_info, avformat_open_input,
+        AVIOInterruptCB,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::util::error::PlayerError;
+
+/// Schemes of URLs which are opened as network streams
+const NETWORK_SCHEMES: [&str; 12] = [
+    "http", "https", "hls", "rtsp", "rtsps", "rtmp", "rtmps", "rtp", "udp", "tcp", "srt", "mms",
+];
+
+/// Where the media is read from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MediaSource {
+    File(PathBuf),
+    /// Network stream, e.g. http(s), rtsp, rtmp, udp or HLS playlist
+    Url(String),
+    /// Media piped to the standard input
+    Stdin,
+}
+
+/// Options for opening network streams
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct NetworkOptions {
+    /// Give up if the stream sends nothing for this long, unit: seconds
+    pub timeout_secs: u64,
+    /// Reconnect to http(s) and HLS streams if the connection is lost
+    pub reconnect: bool,
+    /// The maximum delay between two reconnections, unit: seconds
+    pub reconnect_delay_max_secs: u64,
+}
+
+impl Default for NetworkOptions {
+    fn default() -> Self {
+        Self {
+            timeout_secs: 10,
+            reconnect: true,
+            reconnect_delay_max_secs: 5,
+        }
+    }
+}
+
+impl MediaSource {
+    /// Parse the text typed or pasted by user, `-` means the standard input,
+    /// text with a known network scheme is a URL, others are file paths
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        if text.is_empty() {
+            return None;
+        }
+        if text == "-" {
+            return Some(Self::Stdin);
+        }
+        if let Some(path) = text.strip_prefix("file://") {
+            return Some(Self::File(PathBuf::from(path)));
+        }
+
+        match scheme(text) {
+            Some(scheme) if NETWORK_SCHEMES.contains(&scheme.to_ascii_lowercase().as_str()) => {
+                Some(Self::Url(text.to_string()))
+            }
+            _ => Some(Self::File(PathBuf::from(text))),
+        }
+    }
+
+    /// The path of local file, `None` for other sources
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::File(path) => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Whether the media can be read again from the start, the standard input can't
+    pub fn is_seekable(&self) -> bool {
+        !matches!(self, Self::Stdin)
+    }
+
+    /// Short name of the media, e.g. used as the name of screenshots
+    pub fn name(&self) -> String {
+        match self {
+            Self::File(path) => path.file_name().map_or_else(
+                || path.display().to_string(),
+                |name| name.to_string_lossy().to_string(),
+            ),
+            Self::Url(url) => {
+                // The last segment of the path, or the host if there's no path
+                let url = url.split(['?', '#']).next().unwrap_or_default();
+                let url = url.split_once("://").map_or(url, |(_, rest)| rest);
+                url.trim_end_matches('/')
+                    .rsplit('/')
+                    .next()
+                    .unwrap_or_default()
+                    .to_string()
+            }
+            Self::Stdin => "stdin".to_string(),
+        }
+    }
+
+    /// The name of input passed to FFmpeg
+    fn input_name(&self) -> String {
+        match self {
+            Self::File(path) => path.to_string_lossy().to_string(),
+            Self::Url(url) => url.clone(),
+            Self::Stdin => "pipe:0".to_string(),
+        }
+    }
+
+    /// Protocol options passed to FFmpeg when the input is opened
+    fn input_options(&self, options: &NetworkOptions) -> Vec<(&'static str, String)> {
+        let url = match self {
+            Self::Url(url) => url,
+            _ => return vec![],
+        };
+
+        // Unit of the timeout is microsecond
+        let mut input_options =
+            vec![("rw_timeout", (options.timeout_secs * 1_000_000).to_string())];
+        let scheme = scheme(url).unwrap_or_default().to_ascii_lowercase();
+        if options.reconnect && matches!(scheme.as_str(), "http" | "https" | "hls") {
+            input_options.extend([
+                ("reconnect", "1".to_string()),
+                ("reconnect_streamed", "1".to_string()),
+                ("reconnect_on_network_error", "1".to_string()),
+                (
+                    "reconnect_delay_max",
+                    options.reconnect_delay_max_secs.to_string(),
+                ),
+            ]);
+        }
+
+        input_options
+    }
+
+    /// Open the media and find info of all streams,
+    /// blocking operations of the media are aborted once `interrupt` is set
+    pub fn open(
+        &self,
+        options: &NetworkOptions,
+        interrupt: Arc<AtomicBool>,
+    ) -> Result<MediaInput, PlayerError> {
+        let name = CString::new(self.input_name())
+            .map_err(|_| PlayerError::InvalidMedia(self.to_string()))?;
+
+        let mut dict = None;
+        for (key, value) in self.input_options(options) {
+            let key = CString::new(key).unwrap();
+            let value = CString::new(value).unwrap();
+            dict = Some(match dict {
+                Some(dict) => AVDictionary::set(dict, &key, &value, 0),
+                None => AVDictionary::new(&key, &value, 0),
+            });
+        }
+        let mut dict_ptr = dict.map_or(ptr::null_mut(), |dict| dict.into_raw().as_ptr());
+
+        // `AVFormatContextInput::open` doesn't take options, open it the same way with options.
+        // The callback is copied by the I/O context when opening, so it's set ahead
+        let mut ctx_ptr = unsafe { avformat_alloc_context() };
+        if ctx_ptr.is_null() {
+            unsafe { av_dict_free(&mut dict_ptr) };
+            return Err(PlayerError::InvalidMedia(self.to_string()));
+        }
+        unsafe {
+            (*ctx_ptr).interrupt_callback = AVIOInterruptCB {
+                callback: Some(is_interrupted),
+                opaque: Arc::as_ptr(&interrupt) as *mut c_void,
+            };
+        }
+        // The context is freed by FFmpeg if it fails
+        let ret = unsafe {
+            avformat_open_input(&mut ctx_ptr, name.as_ptr(), ptr::null_mut(), &mut dict_ptr)
+        };
+        // The options not consumed are left in the dictionary
+        unsafe { av_dict_free(&mut dict_ptr) };
+        if ret < 0 {
+            return Err(RsmpegError::OpenInputError(ret).into());
+        }
+
+        // The context is freed on drop from now on
+        let mut input = MediaInput {
+            ctx: unsafe { AVFormatContextInput::from_raw(NonNull::new(ctx_ptr).unwrap()) },
+            _interrupt: interrupt,
+        };
+        let ret = unsafe { avformat_find_stream_info(input.as_mut_ptr(), ptr::null_mut()) };
+        if ret < 0 {
+            return Err(RsmpegError::FindStreamInfoError(ret).into());
+        }
+
+        Ok(input)
+    }
+}
+
+/// An opened media which can be interrupted
+pub struct MediaInput {
+    ctx: AVFormatContextInput,
+    /// The interrupt callback of `ctx` reads the flag,
+    /// it's declared after `ctx` so that it's dropped later
+    _interrupt: Arc<AtomicBool>,
+}
+
+impl Deref for MediaInput {
+    type Target = AVFormatContextInput;
+
+    fn deref(&self) -> &Self::Target {
+        &self.ctx
+    }
+}
+
+impl DerefMut for MediaInput {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.ctx
+    }
+}
+
+/// Interrupt callback of FFmpeg, a non-zero value aborts the blocking operation
+unsafe extern "C" fn is_interrupted(opaque: *mut c_void) -> c_int {
+    let interrupt = &*(opaque as *const AtomicBool);
+    interrupt.load(Ordering::Acquire) as c_int
+}
+
+impl Display for MediaSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File(path) => write!(f, "{}", path.display()),
+            Self::Url(url) => write!(f, "{}", url),
+            Self::Stdin => write!(f, "stdin"),
+        }
+    }
+}
+
+impl From<PathBuf> for MediaSource {
+    fn from(path: PathBuf) -> Self {
+        Self::File(path)
+    }
+}
+
+/// The scheme of URL, `None` if the text is not a URL.
+/// Windows paths like `C:\a.mp4` are not URLs since the scheme must be followed by `//`
+fn scheme(text: &str) -> Option<&str> {
+    let (scheme, rest) = text.split_once("://")?;
+    let valid = !scheme.is_empty()
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    if valid && !rest.is_empty() {
+        Some(scheme)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+
+    /// One second of silent mono PCM in WAV container
+    pub(crate) fn wav_data() -> Vec<u8> {
+        let sample_rate: u32 = 8000;
+        let data_size = sample_rate * 2;
+        let mut data = Vec::new();
+        data.extend_from_slice(b"RIFF");
+        data.extend_from_slice(&(36 + data_size).to_le_bytes());
+        data.extend_from_slice(b"WAVEfmt ");
+        data.extend_from_slice(&16u32.to_le_bytes());
+        // PCM, mono
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&sample_rate.to_le_bytes());
+        data.extend_from_slice(&(sample_rate * 2).to_le_bytes());
+        // Block align and bits per sample
+        data.extend_from_slice(&2u16.to_le_bytes());
+        data.extend_from_slice(&16u16.to_le_bytes());
+        data.extend_from_slice(b"data");
+        data.extend_from_slice(&data_size.to_le_bytes());
+        data.resize(data.len() + data_size as usize, 0);
+
+        data
+    }
+
+    /// Serve the body to every request, returns the port
+    fn serve(body: Vec<u8>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                // Read the whole request header before responding
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    match stream.read(&mut buf) {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
+                    }
+                }
+
+                let header = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: audio/wav\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+                let _ = stream
+                    .write_all(header.as_bytes())
+                    .and_then(|_| stream.write_all(&body));
+            }
+        });
+
+        port
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Some(MediaSource::Stdin), MediaSource::parse("-"));
+        assert_eq!(None, MediaSource::parse("  "));
+        assert_eq!(
+            Some(MediaSource::Url("rtsp://cam.local/live".to_string())),
+            MediaSource::parse(" rtsp://cam.local/live\n")
+        );
+        assert_eq!(
+            Some(MediaSource::Url("HTTPS://a.com/index.m3u8".to_string())),
+            MediaSource::parse("HTTPS://a.com/index.m3u8")
+        );
+        assert_eq!(
+            Some(MediaSource::File(PathBuf::from("/music/a.flac"))),
+            MediaSource::parse("file:///music/a.flac")
+        );
+        assert_eq!(
+            Some(MediaSource::File(PathBuf::from("C:\\movies\\a.mkv"))),
+            MediaSource::parse("C:\\movies\\a.mkv")
+        );
+        assert_eq!(
+            Some(MediaSource::File(PathBuf::from("unknown://a"))),
+            MediaSource::parse("unknown://a")
+        );
+    }
+
+    #[test]
+    fn test_name() {
+        assert_eq!(
+            "a.mkv",
+            MediaSource::File(PathBuf::from("/movies/a.mkv")).name()
+        );
+        assert_eq!(
+            "index.m3u8",
+            MediaSource::Url("https://a.com/live/index.m3u8?token=1".to_string()).name()
+        );
+        assert_eq!(
+            "cam.local",
+            MediaSource::Url("rtsp://cam.local/".to_string()).name()
+        );
+        assert_eq!("stdin", MediaSource::Stdin.name());
+    }
+
+    #[test]
+    fn test_input_options() {
+        let options = NetworkOptions::default();
+        let http = MediaSource::Url("http://a.com/a.mp4".to_string());
+        let input_options = http.input_options(&options);
+        assert!(input_options.contains(&("rw_timeout", "10000000".to_string())));
+        assert!(input_options.contains(&("reconnect", "1".to_string())));
+
+        let rtsp = MediaSource::Url("rtsp://cam.local/live".to_string());
+        assert_eq!(1, rtsp.input_options(&options).len());
+        assert!(MediaSource::Stdin.input_options(&options).is_empty());
+        assert!(MediaSource::File(PathBuf::from("a.mp4"))
+            .input_options(&options)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_open_http() {
+        let port = serve(wav_data());
+        let source = MediaSource::Url(format!("http://127.0.0.1:{}/a.wav", port));
+        let ctx = source
+            .open(&NetworkOptions::default(), Arc::default())
+            .unwrap();
+        let streams = ctx.streams();
+        assert_eq!(1, streams.num());
+        assert_eq!(8000, streams.get(0).unwrap().codecpar().sample_rate);
+    }
+
+    #[test]
+    fn test_open_timeout() {
+        // Connections are accepted but never answered
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let _streams: Vec<_> = listener.incoming().collect();
+        });
+
+        let options = NetworkOptions {
+            timeout_secs: 1,
+            reconnect: false,
+            ..Default::default()
+        };
+        let source = MediaSource::Url(format!("http://127.0.0.1:{}/a.wav", port));
+        let start = Instant::now();
+        assert!(source.open(&options, Arc::default()).is_err());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_open_interrupted() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let _streams: Vec<_> = listener.incoming().collect();
+        });
+
+        // Interrupt long before the timeout
+        let interrupt = Arc::new(AtomicBool::new(false));
+        thread::spawn({
+            let interrupt = interrupt.clone();
+            move || {
+                thread::sleep(Duration::from_millis(200));
+                interrupt.store(true, Ordering::Release);
+            }
+        });
+
+        let source = MediaSource::Url(format!("http://127.0.0.1:{}/a.wav", port));
+        let start = Instant::now();
+        assert!(source.open(&NetworkOptions::default(), interrupt).is_err());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,257 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+use crossbeam::channel::{unbounded, Receiver, Sender};
+use log::{debug, error, warn};
+
+use crate::{
+    entity::EventMessage,
+    global::{EVENT_CHANNEL, THREAD_JOIN_TIMEOUT},
+    util::{
+        error::{safe_send, PlayerError},
+        worker::join_timeout,
+    },
+};
+
+use super::frame_grabber::{FrameGrabber, RgbImage};
+
+/// Width of thumbnail, the height is computed with the aspect ratio of the video
+pub const THUMBNAIL_WIDTH: u32 = 160;
+/// Maximum number of thumbnails kept in cache
+const THUMBNAIL_CACHE_SIZE: usize = 64;
+/// Thumbnails are cached by position bucket, positions in the same bucket share
+/// the same thumbnail, unit: milliseconds
+const THUMBNAIL_BUCKET_MILLIS: i64 = 2000;
+
+/// A scaled down picture of the video at the specified position
+#[derive(Debug, Clone, PartialEq)]
+pub struct Thumbnail {
+    pub image: RgbImage,
+    /// The position requested, unit: milliseconds
+    pub position_millis: i64,
+}
+
+/// Provide thumbnails of the media in a separate thread.
+///
+/// An independent `FrameGrabber` is used for thumbnails, because
+/// `AVFormatContextInput` can not be shared between threads, and the main
+/// `MediaDecoder` and its buffers must not be disturbed by thumbnail seeking.
+pub struct Thumbnailer {
+    sender: Option<Sender<i64>>,
+    /// Interrupts the grabbing in progress, so that the worker notices it's dropped
+    interrupt: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Thumbnailer {
+    pub fn new(path: &Path) -> Result<Self, PlayerError> {
+        let path = path
+            .to_str()
+            .ok_or_else(|| PlayerError::InvalidMedia(format!("{}", path.display())))?
+            .to_string();
+        let (sender, receiver) = unbounded::<i64>();
+        let interrupt = Arc::new(AtomicBool::new(false));
+
+        let builder = thread::Builder::new().name("thumbnailer".to_string());
+        let worker_interrupt = interrupt.clone();
+        let handle = builder.spawn(move || {
+            let worker = match ThumbnailWorker::new(&path, worker_interrupt) {
+                Ok(worker) => worker,
+                Err(err) => {
+                    warn!("Thumbnail is not available for {}: {}", path, err);
+                    return;
+                }
+            };
+
+            worker.run(receiver);
+        })?;
+
+        Ok(Self {
+            sender: Some(sender),
+            interrupt,
+            handle: Some(handle),
+        })
+    }
+
+    /// Request the thumbnail at specified position, unit: milliseconds.
+    /// The result will be sent back as `EventMessage::RenderThumbnail`
+    pub fn request(&self, position_millis: i64) {
+        if let Some(sender) = self.sender.as_ref() {
+            safe_send(sender.send(position_millis.max(0)));
+        }
+    }
+}
+
+impl Drop for Thumbnailer {
+    fn drop(&mut self) {
+        // Drop the sender to disconnect the channel, the worker will exit then.
+        // A grabbing blocked in reading fails at once
+        self.sender.take();
+        self.interrupt.store(true, Ordering::Release);
+        if let Some(handle) = self.handle.take() {
+            join_timeout(handle, THREAD_JOIN_TIMEOUT);
+        }
+    }
+}
+
+struct ThumbnailWorker {
+    grabber: FrameGrabber,
+    cache: ThumbnailCache,
+}
+
+impl ThumbnailWorker {
+    fn new(path: &str, interrupt: Arc<AtomicBool>) -> Result<Self, PlayerError> {
+        Ok(Self {
+            grabber: FrameGrabber::new(path, interrupt)?,
+            cache: ThumbnailCache::new(THUMBNAIL_CACHE_SIZE),
+        })
+    }
+
+    fn run(mut self, receiver: Receiver<i64>) {
+        while let Ok(mut position) = receiver.recv() {
+            // Only the latest request matters, the mouse has moved away from older ones
+            while let Ok(p) = receiver.try_recv() {
+                position = p;
+            }
+
+            let bucket = position / THUMBNAIL_BUCKET_MILLIS;
+            let image = match self.cache.get(bucket) {
+                Some(image) => image.clone(),
+                None => match self.extract(bucket * THUMBNAIL_BUCKET_MILLIS) {
+                    Ok(Some(image)) => {
+                        self.cache.put(bucket, image.clone());
+                        image
+                    }
+                    Ok(None) => continue,
+                    Err(err) => {
+                        error!("Failed to extract thumbnail: {}", err);
+                        continue;
+                    }
+                },
+            };
+
+            safe_send(EVENT_CHANNEL.0.send(EventMessage::RenderThumbnail(Thumbnail {
+                image,
+                position_millis: position,
+            })));
+        }
+
+        debug!("thumbnail worker exited");
+    }
+
+    /// Decode the keyframe nearest before `position_millis` and scale it down
+    fn extract(&mut self, position_millis: i64) -> Result<Option<RgbImage>, PlayerError> {
+        let frame = match self.grabber.grab(position_millis, false)? {
+            Some(frame) => frame,
+            None => return Ok(None),
+        };
+
+        let width = THUMBNAIL_WIDTH;
+        let height = ((frame.height as u64 * width as u64 / frame.width.max(1) as u64) as u32)
+            .max(2)
+            & !1;
+
+        Ok(Some(self.grabber.to_rgb(&frame, width, height)?))
+    }
+}
+
+/// A simple LRU cache for thumbnails, the least recently used one
+/// will be removed once the capacity is reached
+struct ThumbnailCache {
+    capacity: usize,
+    items: HashMap<i64, RgbImage>,
+    /// Keys ordered from least recently used to most recently used
+    order: VecDeque<i64>,
+}
+
+impl ThumbnailCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            items: HashMap::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    fn get(&mut self, key: i64) -> Option<&RgbImage> {
+        if self.items.contains_key(&key) {
+            self.touch(key);
+        }
+
+        self.items.get(&key)
+    }
+
+    fn put(&mut self, key: i64, image: RgbImage) {
+        if self.items.insert(key, image).is_some() {
+            self.touch(key);
+            return;
+        }
+
+        self.order.push_back(key);
+        if self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.items.remove(&oldest);
+            }
+        }
+    }
+
+    fn touch(&mut self, key: i64) {
+        if let Some(index) = self.order.iter().position(|k| *k == key) {
+            self.order.remove(index);
+        }
+        self.order.push_back(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(width: u32) -> RgbImage {
+        RgbImage {
+            data: vec![0; width as usize * 3],
+            width,
+            height: 1,
+            pitch: width as usize * 3,
+        }
+    }
+
+    #[test]
+    fn test_cache_eviction() {
+        let mut cache = ThumbnailCache::new(2);
+        cache.put(1, image(1));
+        cache.put(2, image(2));
+        cache.put(3, image(3));
+        // The oldest one is evicted
+        assert_eq!(None, cache.get(1));
+        assert_eq!(Some(2), cache.get(2).map(|image| image.width));
+        assert_eq!(Some(3), cache.get(3).map(|image| image.width));
+        assert_eq!(2, cache.items.len());
+    }
+
+    #[test]
+    fn test_cache_recently_used() {
+        let mut cache = ThumbnailCache::new(2);
+        cache.put(1, image(1));
+        cache.put(2, image(2));
+        // Reading 1 makes 2 the least recently used one
+        assert!(cache.get(1).is_some());
+        cache.put(3, image(3));
+        assert_eq!(None, cache.get(2));
+        assert!(cache.get(1).is_some());
+
+        // Replacing an item keeps the size and refreshes it
+        cache.put(1, image(10));
+        cache.put(4, image(4));
+        assert_eq!(None, cache.get(3));
+        assert_eq!(Some(10), cache.get(1).map(|image| image.width));
+        assert_eq!(vec![4, 1], cache.order.iter().copied().collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,707 @@
+use std::{
+    mem,
+    os::raw::{c_int, c_uint},
+    ptr, slice,
+    sync::Arc,
+    thread::{self, JoinHandle},
+};
+
+use crossbeam::channel::{self, Sender};
+use log::warn;
+
+use rsmpeg::{
+    avformat::AVStream,
+    avutil::AVFrame,
+    ffi::{
+        av_frame_get_side_data, av_stream_get_side_data,
+        AVColorPrimaries_AVCOL_PRI_BT709 as AVCOLORPRIMARIES_AVCOL_PRI_BT709,
+        AVColorTransferCharacteristic_AVCOL_TRC_ARIB_STD_B67 as AVCOLORTRANSFERCHARACTERISTIC_AVCOL_TRC_ARIB_STD_B67,
+        AVColorTransferCharacteristic_AVCOL_TRC_SMPTE2084 as AVCOLORTRANSFERCHARACTERISTIC_AVCOL_TRC_SMPTE2084,
+        AVFrameSideDataType_AV_FRAME_DATA_CONTENT_LIGHT_LEVEL as AVFRAMESIDEDATATYPE_AV_FRAME_DATA_CONTENT_LIGHT_LEVEL,
+        AVFrameSideDataType_AV_FRAME_DATA_MASTERING_DISPLAY_METADATA as AVFRAMESIDEDATATYPE_AV_FRAME_DATA_MASTERING_DISPLAY_METADATA,
+        AVPacketSideDataType_AV_PKT_DATA_CONTENT_LIGHT_LEVEL as AVPACKETSIDEDATATYPE_AV_PKT_DATA_CONTENT_LIGHT_LEVEL,
+        AVPacketSideDataType_AV_PKT_DATA_MASTERING_DISPLAY_METADATA as AVPACKETSIDEDATATYPE_AV_PKT_DATA_MASTERING_DISPLAY_METADATA,
+        AVRational,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    global::{THREAD_JOIN_TIMEOUT, TONE_MAP_OPERATOR, VIDEO_FILTER_SETTINGS, VIDEO_PLANE_POOL},
+    media::video_filter::PictureAdjustments,
+    util::{
+        buffer_pool::PooledBuffer,
+        colorspace::{Colorimetry, RgbScaler},
+        error::PlayerError,
+        worker::join_timeout,
+    },
+};
+
+/// HDR content of this luminance is shown as SDR white, see BT.2408, unit: nits
+const REFERENCE_WHITE: f32 = 203.0;
+/// Nominal peak of HLG displays, also assumed for PQ content without metadata, unit: nits
+const DEFAULT_PEAK: f32 = 1000.0;
+/// System gamma of HLG on the display of `DEFAULT_PEAK`
+const HLG_GAMMA: f32 = 1.2;
+/// Gamma of SDR displays, see BT.1886
+const SDR_GAMMA: f32 = 2.4;
+/// Luma coefficients of BT.2020, the OOTF of HLG scales all channels by the luminance
+const BT2020_LUMA: [f32; 3] = [0.2627, 0.6780, 0.0593];
+/// Converts linear light of BT.2020 primaries to BT.709 ones
+const BT2020_TO_BT709: [[f32; 3]; 3] = [
+    [1.6605, -0.5876, -0.0728],
+    [-0.1246, 1.1329, -0.0083],
+    [-0.0182, -0.1006, 1.1187],
+];
+/// Samples of the tone curve, values between them are interpolated
+const CURVE_SIZE: usize = 1024;
+/// Entries of the table encoding linear light to 8 bits, it's fine enough for dark areas
+const ENCODE_SIZE: usize = 1 << 16;
+/// Rows of a frame are split between threads, 4K frames are too many pixels for one
+const MAX_THREADS: usize = 8;
+const WORKER_THREAD_NAME: &str = "tone-mapper";
+/// 16-bit RGB of the native endianness, the planes are green, blue and red
+#[cfg(target_endian = "little")]
+const GBRP16: i32 = rsmpeg::ffi::AVPixelFormat_AV_PIX_FMT_GBRP16LE;
+#[cfg(target_endian = "big")]
+const GBRP16: i32 = rsmpeg::ffi::AVPixelFormat_AV_PIX_FMT_GBRP16BE;
+
+/// `AVMasteringDisplayMetadata` of libavutil, which has no binding
+#[repr(C)]
+#[allow(dead_code)]
+struct MasteringDisplayMetadata {
+    display_primaries: [[AVRational; 2]; 3],
+    white_point: [AVRational; 2],
+    min_luminance: AVRational,
+    max_luminance: AVRational,
+    has_primaries: c_int,
+    has_luminance: c_int,
+}
+
+/// `AVContentLightMetadata` of libavutil, which has no binding
+#[repr(C)]
+#[allow(dead_code)]
+struct ContentLightMetadata {
+    max_cll: c_uint,
+    max_fall: c_uint,
+}
+
+/// Curves compressing the highlights of HDR content into the SDR range
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ToneMapOperator {
+    /// Filmic curve of Uncharted 2, it keeps the contrast but darkens midtones a bit
+    Hable,
+    /// Soft and simple, highlights look flat
+    Reinhard,
+    /// EETF of ITU-R BT.2390, tones below the knee are kept as they are
+    #[default]
+    Bt2390,
+}
+
+impl ToneMapOperator {
+    /// Map linear light in `0.0..=peak` into `0.0..=1.0`, both are relative to `REFERENCE_WHITE`
+    fn map(self, x: f32, peak: f32) -> f32 {
+        if peak <= 1.0 {
+            return x.min(1.0);
+        }
+
+        match self {
+            ToneMapOperator::Hable => hable(x) / hable(peak),
+            ToneMapOperator::Reinhard => x * (1.0 + x / (peak * peak)) / (1.0 + x),
+            ToneMapOperator::Bt2390 => bt2390(x, peak),
+        }
+    }
+}
+
+fn hable(x: f32) -> f32 {
+    const A: f32 = 0.15;
+    const B: f32 = 0.50;
+    const C: f32 = 0.10;
+    const D: f32 = 0.20;
+    const E: f32 = 0.02;
+    const F: f32 = 0.30;
+    (x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F) - E / F
+}
+
+/// The EETF works on PQ signals, the peak is rolled off to `REFERENCE_WHITE`
+fn bt2390(x: f32, peak: f32) -> f32 {
+    let source_peak = nits_to_pq(peak * REFERENCE_WHITE);
+    let signal = nits_to_pq(x * REFERENCE_WHITE) / source_peak;
+    let target_peak = nits_to_pq(REFERENCE_WHITE) / source_peak;
+    let knee = (1.5 * target_peak - 0.5).max(0.0);
+
+    let mapped = if signal < knee {
+        signal
+    } else {
+        // Hermite spline from the knee to the target peak
+        let t = (signal - knee) / (1.0 - knee);
+        let (t2, t3) = (t * t, t * t * t);
+        (2.0 * t3 - 3.0 * t2 + 1.0) * knee
+            + (t3 - 2.0 * t2 + t) * (1.0 - knee)
+            + (-2.0 * t3 + 3.0 * t2) * target_peak
+    };
+
+    pq_to_nits(mapped * source_peak) / REFERENCE_WHITE
+}
+
+/// Constants of the perceptual quantizer, see SMPTE ST 2084
+const PQ_M1: f32 = 0.159_301_76;
+const PQ_M2: f32 = 78.843_75;
+const PQ_C1: f32 = 0.835_937_5;
+const PQ_C2: f32 = 18.851_563;
+const PQ_C3: f32 = 18.6875;
+
+/// EOTF of PQ, the signal is in `0.0..=1.0`
+fn pq_to_nits(signal: f32) -> f32 {
+    let p = signal.max(0.0).powf(1.0 / PQ_M2);
+    let linear = (p - PQ_C1).max(0.0) / (PQ_C2 - PQ_C3 * p);
+    10000.0 * linear.powf(1.0 / PQ_M1)
+}
+
+/// Inverse EOTF of PQ
+fn nits_to_pq(nits: f32) -> f32 {
+    let y = (nits / 10000.0).max(0.0).powf(PQ_M1);
+    ((PQ_C1 + PQ_C2 * y) / (1.0 + PQ_C3 * y)).powf(PQ_M2)
+}
+
+/// Inverse OETF of HLG, returns scene light in `0.0..=1.0`
+fn hlg_to_scene(signal: f32) -> f32 {
+    const A: f32 = 0.178_832_77;
+    const B: f32 = 0.284_668_92;
+    const C: f32 = 0.559_910_7;
+    if signal <= 0.5 {
+        signal * signal / 3.0
+    } else {
+        (((signal - C) / A).exp() + B) / 12.0
+    }
+}
+
+/// Transfer characteristics of HDR videos, SDR ones need no tone mapping
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HdrTransfer {
+    /// Perceptual quantizer of HDR10, SMPTE ST 2084
+    Pq,
+    /// Hybrid log-gamma, ARIB STD-B67
+    Hlg,
+}
+
+impl HdrTransfer {
+    /// `None` if the frame is SDR
+    pub fn of(colorimetry: &Colorimetry) -> Option<Self> {
+        match colorimetry.color_trc {
+            AVCOLORTRANSFERCHARACTERISTIC_AVCOL_TRC_SMPTE2084 => Some(HdrTransfer::Pq),
+            AVCOLORTRANSFERCHARACTERISTIC_AVCOL_TRC_ARIB_STD_B67 => Some(HdrTransfer::Hlg),
+            _ => None,
+        }
+    }
+
+    /// Linear light of the signal in `0.0..=1.0`. It's relative to `REFERENCE_WHITE` for PQ,
+    /// and it's scene light for HLG, whose OOTF needs all channels
+    fn to_linear(self, signal: f32) -> f32 {
+        match self {
+            HdrTransfer::Pq => pq_to_nits(signal) / REFERENCE_WHITE,
+            HdrTransfer::Hlg => hlg_to_scene(signal),
+        }
+    }
+}
+
+/// Luminance of HDR content, from the side data of the stream or the frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct HdrMetadata {
+    /// Peak luminance of the mastering display, unit: nits
+    pub mastering_peak: Option<u32>,
+    /// Maximum content light level, unit: nits
+    pub max_cll: Option<u32>,
+}
+
+impl HdrMetadata {
+    pub fn of_stream(stream: &AVStream) -> Self {
+        let side_data = |data_type| {
+            let mut size = 0;
+            let data = unsafe { av_stream_get_side_data(stream.as_ptr(), data_type, &mut size) };
+            (data as *const u8, size as usize)
+        };
+        let (mastering, mastering_size) =
+            side_data(AVPACKETSIDEDATATYPE_AV_PKT_DATA_MASTERING_DISPLAY_METADATA);
+        let (light, light_size) = side_data(AVPACKETSIDEDATATYPE_AV_PKT_DATA_CONTENT_LIGHT_LEVEL);
+
+        Self {
+            mastering_peak: unsafe { mastering_peak(mastering, mastering_size) },
+            max_cll: unsafe { max_cll(light, light_size) },
+        }
+    }
+
+    /// HEVC carries the metadata on some frames only, so the latest one is kept
+    pub fn update(&mut self, frame: &AVFrame) {
+        let side_data = |data_type| {
+            let side_data = unsafe { av_frame_get_side_data(frame.as_ptr(), data_type) };
+            match side_data.is_null() {
+                true => (ptr::null(), 0),
+                false => unsafe { ((*side_data).data as *const u8, (*side_data).size as usize) },
+            }
+        };
+        let (mastering, mastering_size) =
+            side_data(AVFRAMESIDEDATATYPE_AV_FRAME_DATA_MASTERING_DISPLAY_METADATA);
+        let (light, light_size) = side_data(AVFRAMESIDEDATATYPE_AV_FRAME_DATA_CONTENT_LIGHT_LEVEL);
+
+        if let Some(peak) = unsafe { mastering_peak(mastering, mastering_size) } {
+            self.mastering_peak = Some(peak);
+        }
+        if let Some(peak) = unsafe { max_cll(light, light_size) } {
+            self.max_cll = Some(peak);
+        }
+    }
+
+    /// The brightest light of the content, unit: nits.
+    /// HLG is relative to the display, so its nominal peak is used
+    pub fn peak(&self, transfer: HdrTransfer) -> f32 {
+        match transfer {
+            HdrTransfer::Pq => self
+                .max_cll
+                .or(self.mastering_peak)
+                .map_or(DEFAULT_PEAK, |nits| nits as f32)
+                .max(REFERENCE_WHITE),
+            HdrTransfer::Hlg => DEFAULT_PEAK,
+        }
+    }
+}
+
+/// # Safety
+/// `data` is null or points to `size` bytes of `AVMasteringDisplayMetadata`
+unsafe fn mastering_peak(data: *const u8, size: usize) -> Option<u32> {
+    if data.is_null() || size < mem::size_of::<MasteringDisplayMetadata>() {
+        return None;
+    }
+
+    let metadata = ptr::read_unaligned(data as *const MasteringDisplayMetadata);
+    let luminance = metadata.max_luminance;
+    (metadata.has_luminance != 0 && luminance.num > 0 && luminance.den > 0)
+        .then(|| (luminance.num / luminance.den) as u32)
+}
+
+/// # Safety
+/// `data` is null or points to `size` bytes of `AVContentLightMetadata`
+unsafe fn max_cll(data: *const u8, size: usize) -> Option<u32> {
+    if data.is_null() || size < mem::size_of::<ContentLightMetadata>() {
+        return None;
+    }
+
+    let metadata = ptr::read_unaligned(data as *const ContentLightMetadata);
+    (metadata.max_cll > 0).then_some(metadata.max_cll)
+}
+
+/// Tone curve of an operator sampled in `0.0..=peak`
+struct ToneCurve {
+    operator: ToneMapOperator,
+    peak: f32,
+    samples: Vec<f32>,
+}
+
+impl ToneCurve {
+    fn new(operator: ToneMapOperator, peak: f32) -> Self {
+        let samples = (0..CURVE_SIZE)
+            .map(|index| operator.map(peak * index as f32 / (CURVE_SIZE - 1) as f32, peak))
+            .collect();
+        Self {
+            operator,
+            peak,
+            samples,
+        }
+    }
+
+    fn map(&self, x: f32) -> f32 {
+        let position = (x / self.peak).clamp(0.0, 1.0) * (CURVE_SIZE - 1) as f32;
+        let index = (position as usize).min(CURVE_SIZE - 2);
+        let fraction = position - index as f32;
+        self.samples[index] + (self.samples[index + 1] - self.samples[index]) * fraction
+    }
+}
+
+/// What the worker threads need to map pixels from 16-bit HDR to 8-bit SDR,
+/// the tables are shared by the workers and kept between frames
+#[derive(Clone)]
+struct PixelMapper {
+    /// Linear light of each 16-bit code value
+    decode: Arc<[f32]>,
+    curve: Arc<ToneCurve>,
+    /// 8-bit codes of linear light in `0.0..=1.0`, indexed by `ENCODE_SIZE` steps
+    encode: Arc<[u8]>,
+    /// Apply the OOTF of HLG to get display light
+    hlg: bool,
+    /// Convert BT.2020 primaries to BT.709
+    gamut: bool,
+    /// Made on the SDR picture, since the `eq` filter can't work on HDR frames
+    adjustments: Option<PictureAdjustments>,
+}
+
+impl PixelMapper {
+    fn map(&self, rgb: [u16; 3]) -> [u8; 3] {
+        let mut rgb = rgb.map(|code| self.decode[code as usize]);
+        if self.hlg {
+            let luma: f32 = rgb.iter().zip(BT2020_LUMA).map(|(c, k)| c * k).sum();
+            let gain = DEFAULT_PEAK / REFERENCE_WHITE * luma.powf(HLG_GAMMA - 1.0);
+            rgb = rgb.map(|c| c * gain);
+        }
+        if self.gamut {
+            rgb = BT2020_TO_BT709
+                .map(|row| (row[0] * rgb[0] + row[1] * rgb[1] + row[2] * rgb[2]).max(0.0));
+        }
+
+        // Scaling all channels by the curve of the brightest one keeps the hue
+        let signal = rgb[0].max(rgb[1]).max(rgb[2]);
+        if signal > 0.0 {
+            let scale = self.curve.map(signal) / signal;
+            rgb = rgb.map(|c| c * scale);
+        }
+
+        let rgb = rgb.map(|c| self.encode[(c.min(1.0) * (ENCODE_SIZE - 1) as f32 + 0.5) as usize]);
+        match self.adjustments {
+            Some(adjustments) => adjustments
+                .apply(rgb.map(|c| c as f32 / 255.0))
+                .map(|c| (c * 255.0 + 0.5) as u8),
+            None => rgb,
+        }
+    }
+}
+
+/// Rows of a frame for one worker. The pixels are lent by `ToneMapper::map`,
+/// which waits until `done` is sent or dropped before it touches them again
+struct RowJob {
+    mapper: PixelMapper,
+    /// Red, green and blue planes of the rows
+    planes: [*const u16; 3],
+    /// RGB24 of the rows
+    output: *mut u8,
+    pixels: usize,
+    done: Sender<()>,
+}
+
+// SAFETY: The pointers are used by one worker only, and they're valid until the job is dropped
+unsafe impl Send for RowJob {}
+
+impl RowJob {
+    fn run(&self) {
+        let [r, g, b] = self
+            .planes
+            .map(|plane| unsafe { slice::from_raw_parts(plane, self.pixels) });
+        let output = unsafe { slice::from_raw_parts_mut(self.output, self.pixels * 3) };
+        for (index, pixel) in output.chunks_exact_mut(3).enumerate() {
+            pixel.copy_from_slice(&self.mapper.map([r[index], g[index], b[index]]));
+        }
+    }
+}
+
+/// Threads mapping the rows of frames, they're kept as long as the tone mapper,
+/// so no thread is started for each frame
+struct ToneMapWorkers {
+    jobs: Vec<Sender<RowJob>>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl ToneMapWorkers {
+    fn new(threads: usize) -> Result<Self, PlayerError> {
+        let mut workers = Self {
+            jobs: Vec::with_capacity(threads),
+            handles: Vec::with_capacity(threads),
+        };
+        for _ in 0..threads {
+            let (sender, receiver) = channel::bounded::<RowJob>(1);
+            let builder = thread::Builder::new().name(WORKER_THREAD_NAME.to_string());
+            // The threads already started are joined on drop if any of them fails
+            workers.handles.push(builder.spawn(move || {
+                for job in receiver {
+                    job.run();
+                    let _ = job.done.send(());
+                }
+            })?);
+            workers.jobs.push(sender);
+        }
+
+        Ok(workers)
+    }
+}
+
+impl Drop for ToneMapWorkers {
+    fn drop(&mut self) {
+        // The workers exit once their channels are closed
+        self.jobs.clear();
+        for handle in self.handles.drain(..) {
+            join_timeout(handle, THREAD_JOIN_TIMEOUT);
+        }
+    }
+}
+
+/// Tone maps HDR frames to 8-bit SDR RGB24 on CPU, in the video decoding thread
+pub struct ToneMapper {
+    scaler: RgbScaler,
+    metadata: HdrMetadata,
+    /// 16-bit RGB converted by swscale, reused between frames
+    planes: [Vec<u16>; 3],
+    /// Linear light of 16-bit code values and the transfer it's computed for
+    decode: Option<(HdrTransfer, Arc<[f32]>)>,
+    curve: Option<Arc<ToneCurve>>,
+    encode: Arc<[u8]>,
+    /// Started with the first HDR frame, SDR videos need none
+    workers: Option<ToneMapWorkers>,
+}
+
+impl ToneMapper {
+    /// `metadata` is the one of the stream, frames may update it
+    pub fn new(metadata: HdrMetadata) -> Self {
+        let encode = (0..ENCODE_SIZE)
+            .map(|index| {
+                let linear = index as f32 / (ENCODE_SIZE - 1) as f32;
+                (linear.powf(1.0 / SDR_GAMMA) * 255.0 + 0.5) as u8
+            })
+            .collect();
+        Self {
+            scaler: RgbScaler::new(GBRP16),
+            metadata,
+            planes: Default::default(),
+            decode: None,
+            curve: None,
+            encode,
+            workers: None,
+        }
+    }
+
+    /// Returns the pooled RGB24 pixels and the pitch of them
+    pub fn map(
+        &mut self,
+        frame: &AVFrame,
+        colorimetry: &Colorimetry,
+        transfer: HdrTransfer,
+    ) -> Result<(PooledBuffer<u8>, usize), PlayerError> {
+        self.metadata.update(frame);
+        let operator = *TONE_MAP_OPERATOR.read().unwrap();
+        let adjustments = VIDEO_FILTER_SETTINGS.read().unwrap().adjustments();
+        let peak = self.metadata.peak(transfer) / REFERENCE_WHITE;
+        if self.decode.as_ref().map_or(true, |(t, _)| *t != transfer) {
+            let decode = (0..=u16::MAX)
+                .map(|code| transfer.to_linear(code as f32 / u16::MAX as f32))
+                .collect();
+            self.decode = Some((transfer, decode));
+        }
+        if self.curve.as_ref().map_or(true, |curve| {
+            curve.operator != operator || curve.peak != peak
+        }) {
+            self.curve = Some(Arc::new(ToneCurve::new(operator, peak)));
+        }
+
+        let width = frame.width.max(0) as usize;
+        let height = frame.height.max(0) as usize;
+        let pitch = width * 3;
+        let mut buffer = VIDEO_PLANE_POOL.take();
+        buffer.resize(pitch * height, 0);
+        if buffer.is_empty() {
+            return Ok((buffer, pitch));
+        }
+
+        for plane in self.planes.iter_mut() {
+            plane.resize(width * height, 0);
+        }
+        let [g, b, r] = &mut self.planes;
+        let dst: [*mut u8; 4] = [
+            g.as_mut_ptr() as *mut u8,
+            b.as_mut_ptr() as *mut u8,
+            r.as_mut_ptr() as *mut u8,
+            ptr::null_mut(),
+        ];
+        let stride = (width * 2) as i32;
+        let dst_stride: [i32; 4] = [stride, stride, stride, 0];
+        let matrix = colorimetry.matrix(height);
+        unsafe {
+            self.scaler
+                .scale(frame, matrix, colorimetry.range(), &dst, &dst_stride)?
+        };
+
+        let mapper = PixelMapper {
+            decode: self.decode.as_ref().unwrap().1.clone(),
+            curve: self.curve.clone().unwrap(),
+            encode: self.encode.clone(),
+            hlg: transfer == HdrTransfer::Hlg,
+            gamut: colorimetry.color_primaries != AVCOLORPRIMARIES_AVCOL_PRI_BT709,
+            adjustments: (!adjustments.is_unchanged()).then_some(adjustments),
+        };
+        if self.workers.is_none() {
+            let threads = thread::available_parallelism()
+                .map_or(1, |threads| threads.get())
+                .clamp(1, MAX_THREADS);
+            self.workers = Some(ToneMapWorkers::new(threads)?);
+        }
+        let workers = self.workers.as_ref().unwrap();
+
+        let rows = (height + workers.jobs.len() - 1) / workers.jobs.len();
+        let [g, b, r] = &self.planes;
+        let chunks = buffer
+            .chunks_mut(pitch * rows)
+            .zip(g.chunks(width * rows))
+            .zip(b.chunks(width * rows))
+            .zip(r.chunks(width * rows));
+        let mut pending = Vec::with_capacity(workers.jobs.len());
+        for ((((output, g), b), r), worker) in chunks.zip(&workers.jobs) {
+            let (done, finished) = channel::bounded(1);
+            let job = RowJob {
+                mapper: mapper.clone(),
+                planes: [r.as_ptr(), g.as_ptr(), b.as_ptr()],
+                output: output.as_mut_ptr(),
+                pixels: g.len(),
+                done,
+            };
+            match worker.send(job) {
+                Ok(_) => pending.push(finished),
+                // The worker has gone, the rows are mapped here
+                Err(err) => err.into_inner().run(),
+            }
+        }
+        // The pixels are lent to the workers until then
+        let mut lost = false;
+        for finished in pending {
+            lost |= finished.recv().is_err();
+        }
+        if lost {
+            warn!("A tone mapping thread has exited, the threads are started again");
+            self.workers = None;
+        }
+
+        Ok((buffer, pitch))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPERATORS: [ToneMapOperator; 3] = [
+        ToneMapOperator::Hable,
+        ToneMapOperator::Reinhard,
+        ToneMapOperator::Bt2390,
+    ];
+
+    #[test]
+    fn test_pq() {
+        assert!(pq_to_nits(0.0) < 0.001);
+        assert!((pq_to_nits(1.0) - 10000.0).abs() < 1.0);
+        for nits in [0.1, 100.0, 203.0, 1000.0, 4000.0] {
+            assert!((pq_to_nits(nits_to_pq(nits)) - nits).abs() < nits * 0.001);
+        }
+        // 100 nits is about half of the signal
+        assert!((nits_to_pq(100.0) - 0.508).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_hlg() {
+        assert_eq!(0.0, hlg_to_scene(0.0));
+        assert!((hlg_to_scene(0.5) - 1.0 / 12.0).abs() < 1e-6);
+        assert!((hlg_to_scene(1.0) - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_operators() {
+        let peak = 1000.0 / REFERENCE_WHITE;
+        for operator in OPERATORS {
+            assert!(operator.map(0.0, peak).abs() < 1e-3, "{:?}", operator);
+            assert!(
+                (operator.map(peak, peak) - 1.0).abs() < 1e-3,
+                "{:?}",
+                operator
+            );
+
+            let mut previous = 0.0;
+            for step in 1..=100 {
+                let mapped = operator.map(peak * step as f32 / 100.0, peak);
+                assert!(mapped >= previous, "{:?} at {}", operator, step);
+                previous = mapped;
+            }
+        }
+
+        // Dark tones are kept by BT.2390
+        let dark = 0.1;
+        assert!((ToneMapOperator::Bt2390.map(dark, peak) - dark).abs() < 1e-3);
+        // SDR content is only clipped
+        assert_eq!(0.5, ToneMapOperator::Hable.map(0.5, 1.0));
+    }
+
+    #[test]
+    fn test_tone_curve() {
+        let peak = 4000.0 / REFERENCE_WHITE;
+        let curve = ToneCurve::new(ToneMapOperator::Reinhard, peak);
+        for x in [0.0, 0.3, 1.0, 7.7, peak] {
+            let expected = ToneMapOperator::Reinhard.map(x, peak);
+            assert!((curve.map(x) - expected).abs() < 1e-3, "{}", x);
+        }
+        assert_eq!(curve.map(peak), curve.map(peak * 2.0));
+    }
+
+    #[test]
+    fn test_hdr_metadata() {
+        let mut metadata = HdrMetadata::default();
+        assert_eq!(DEFAULT_PEAK, metadata.peak(HdrTransfer::Pq));
+
+        let mastering = MasteringDisplayMetadata {
+            display_primaries: [[AVRational { num: 0, den: 1 }; 2]; 3],
+            white_point: [AVRational { num: 0, den: 1 }; 2],
+            min_luminance: AVRational {
+                num: 50,
+                den: 10000,
+            },
+            max_luminance: AVRational {
+                num: 40_000_000,
+                den: 10000,
+            },
+            has_primaries: 0,
+            has_luminance: 1,
+        };
+        let size = mem::size_of::<MasteringDisplayMetadata>();
+        let data = &mastering as *const _ as *const u8;
+        metadata.mastering_peak = unsafe { mastering_peak(data, size) };
+        assert_eq!(Some(4000), metadata.mastering_peak);
+        assert_eq!(None, unsafe { mastering_peak(data, size - 1) });
+        assert_eq!(4000.0, metadata.peak(HdrTransfer::Pq));
+
+        let light = ContentLightMetadata {
+            max_cll: 1500,
+            max_fall: 400,
+        };
+        let data = &light as *const _ as *const u8;
+        metadata.max_cll = unsafe { max_cll(data, mem::size_of::<ContentLightMetadata>()) };
+        assert_eq!(1500.0, metadata.peak(HdrTransfer::Pq));
+        // HLG is scene referred
+        assert_eq!(DEFAULT_PEAK, metadata.peak(HdrTransfer::Hlg));
+    }
+
+    /// The rows of every frame are mapped by the threads started for the first one
+    #[test]
+    fn test_workers_kept() {
+        let mut frame = AVFrame::new();
+        frame.set_format(rsmpeg::ffi::AVPixelFormat_AV_PIX_FMT_YUV420P10LE);
+        frame.set_width(64);
+        frame.set_height(48);
+        frame.alloc_buffer().unwrap();
+        for (index, rows) in [48, 24, 24].into_iter().enumerate() {
+            let size = frame.linesize[index] as usize / 2 * rows;
+            let plane = unsafe { slice::from_raw_parts_mut(frame.data[index] as *mut u16, size) };
+            plane.fill(512);
+        }
+        let colorimetry = Colorimetry {
+            color_trc: AVCOLORTRANSFERCHARACTERISTIC_AVCOL_TRC_SMPTE2084,
+            ..Default::default()
+        };
+        let threads = |mapper: &ToneMapper| -> Vec<_> {
+            let workers = mapper.workers.as_ref().unwrap();
+            workers.handles.iter().map(|h| h.thread().id()).collect()
+        };
+
+        let mut mapper = ToneMapper::new(HdrMetadata::default());
+        assert!(mapper.workers.is_none());
+        mapper.map(&frame, &colorimetry, HdrTransfer::Pq).unwrap();
+        let started = threads(&mapper);
+        assert!((1..=MAX_THREADS).contains(&started.len()));
+        for _ in 0..3 {
+            let (rgb, pitch) = mapper.map(&frame, &colorimetry, HdrTransfer::Pq).unwrap();
+            assert_eq!((64 * 3, 64 * 3 * 48), (pitch, rgb.len()));
+        }
+        assert_eq!(started, threads(&mapper));
+    }
+}
@@ -0,0 +1,806 @@
+use std::{ffi::CString, ptr};
+
+use log::{info, warn};
+use rsmpeg::{
+    avfilter::{AVFilter, AVFilterGraph, AVFilterInOut},
+    avutil::AVFrame,
+    avformat::AVStream,
+    ffi::{
+        av_display_rotation_get, av_frame_get_side_data, av_stream_get_side_data,
+        AVFrameSideDataType_AV_FRAME_DATA_DISPLAYMATRIX as AVFRAMESIDEDATATYPE_AV_FRAME_DATA_DISPLAYMATRIX,
+        AVPacketSideDataType_AV_PKT_DATA_DISPLAYMATRIX as AVPACKETSIDEDATATYPE_AV_PKT_DATA_DISPLAYMATRIX,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    media::tone_map::HdrTransfer,
+    util::{colorspace::Colorimetry, error::PlayerError},
+};
+
+/// Instance names of the buffer source and sink in the graph
+const SOURCE_NAME: &str = "in";
+const SINK_NAME: &str = "out";
+/// `parse_video_frame` understands this format only, so SDR graphs end with it.
+/// HDR frames are tone mapped from any format, their graphs keep the bits they're decoded with
+const OUTPUT_FORMAT: &str = "yuv420p";
+/// Limits of the picture adjustments, see the `eq` filter of ffmpeg
+const BRIGHTNESS_RANGE: (f32, f32) = (-1.0, 1.0);
+const CONTRAST_RANGE: (f32, f32) = (0.0, 2.0);
+const SATURATION_RANGE: (f32, f32) = (0.0, 3.0);
+const GAMMA_RANGE: (f32, f32) = (0.1, 10.0);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Deinterlace {
+    Off,
+    /// Deinterlace once interlaced frames are decoded
+    #[default]
+    Auto,
+    /// Deinterlace every frame, for content which isn't flagged correctly
+    On,
+}
+
+impl Deinterlace {
+    pub fn next(&self) -> Self {
+        match self {
+            Deinterlace::Off => Deinterlace::Auto,
+            Deinterlace::Auto => Deinterlace::On,
+            Deinterlace::On => Deinterlace::Off,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Deinterlacer {
+    #[default]
+    Yadif,
+    /// Better quality than yadif but slower
+    Bwdif,
+}
+
+/// Pixels removed from each side of the picture, after it's rotated
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(default)]
+pub struct Crop {
+    pub left: u32,
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+}
+
+impl Crop {
+    fn is_empty(&self) -> bool {
+        self.left == 0 && self.top == 0 && self.right == 0 && self.bottom == 0
+    }
+}
+
+/// Options of the video filters, they can be changed while playing
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct VideoFilterSettings {
+    pub deinterlace: Deinterlace,
+    pub deinterlacer: Deinterlacer,
+    /// Rotate the picture as the display matrix of the video says
+    pub auto_rotate: bool,
+    pub crop_enabled: bool,
+    pub crop: Crop,
+    pub denoise: bool,
+    pub sharpen: bool,
+    /// In range `-1.0..=1.0`, 0 means unchanged
+    pub brightness: f32,
+    /// In range `0.0..=2.0`, 1 means unchanged
+    pub contrast: f32,
+    /// In range `0.0..=3.0`, 1 means unchanged
+    pub saturation: f32,
+    /// In range `0.1..=10.0`, 1 means unchanged
+    pub gamma: f32,
+}
+
+impl Default for VideoFilterSettings {
+    fn default() -> Self {
+        Self {
+            deinterlace: Deinterlace::default(),
+            deinterlacer: Deinterlacer::default(),
+            auto_rotate: true,
+            crop_enabled: false,
+            crop: Crop::default(),
+            denoise: false,
+            sharpen: false,
+            brightness: 0.0,
+            contrast: 1.0,
+            saturation: 1.0,
+            gamma: 1.0,
+        }
+    }
+}
+
+/// Runtime changes of `VideoFilterSettings`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VideoFilterCommand {
+    CycleDeinterlace,
+    ToggleAutoRotate,
+    ToggleCrop,
+    ToggleDenoise,
+    ToggleSharpen,
+    /// Change the adjustment by the amount
+    Brightness(f32),
+    Contrast(f32),
+    Saturation(f32),
+    Gamma(f32),
+    /// Restore brightness, contrast, saturation and gamma
+    ResetAdjustments,
+}
+
+impl VideoFilterSettings {
+    pub fn apply(&mut self, command: VideoFilterCommand) {
+        let adjust =
+            |value: f32, delta: f32, (min, max): (f32, f32)| (value + delta).clamp(min, max);
+
+        match command {
+            VideoFilterCommand::CycleDeinterlace => self.deinterlace = self.deinterlace.next(),
+            VideoFilterCommand::ToggleAutoRotate => self.auto_rotate = !self.auto_rotate,
+            VideoFilterCommand::ToggleCrop => self.crop_enabled = !self.crop_enabled,
+            VideoFilterCommand::ToggleDenoise => self.denoise = !self.denoise,
+            VideoFilterCommand::ToggleSharpen => self.sharpen = !self.sharpen,
+            VideoFilterCommand::Brightness(delta) => {
+                self.brightness = adjust(self.brightness, delta, BRIGHTNESS_RANGE)
+            }
+            VideoFilterCommand::Contrast(delta) => {
+                self.contrast = adjust(self.contrast, delta, CONTRAST_RANGE)
+            }
+            VideoFilterCommand::Saturation(delta) => {
+                self.saturation = adjust(self.saturation, delta, SATURATION_RANGE)
+            }
+            VideoFilterCommand::Gamma(delta) => self.gamma = adjust(self.gamma, delta, GAMMA_RANGE),
+            VideoFilterCommand::ResetAdjustments => {
+                let default = Self::default();
+                self.brightness = default.brightness;
+                self.contrast = default.contrast;
+                self.saturation = default.saturation;
+                self.gamma = default.gamma;
+            }
+        }
+    }
+
+    pub fn adjustments(&self) -> PictureAdjustments {
+        PictureAdjustments {
+            brightness: self.brightness,
+            contrast: self.contrast,
+            saturation: self.saturation,
+            gamma: self.gamma,
+        }
+    }
+
+    /// Describe the filter graph in the syntax of ffmpeg
+    /// # Arguments
+    /// * `interlaced`: Interlaced frames have been decoded
+    /// * `rotation`: Clockwise rotation of the picture, unit: degree
+    /// * `hdr`: The frames are tone mapped later, `eq` works on 8-bit SDR,
+    ///   so the tone mapper makes the picture adjustments instead
+    /// # Returns
+    /// `None` if no filter is needed, then the frames are passed through
+    pub fn graph_description(&self, interlaced: bool, rotation: i32, hdr: bool) -> Option<String> {
+        let mut filters = vec![];
+
+        // Deinterlace first, the other filters work on whole frames
+        let deinterlace = match self.deinterlace {
+            Deinterlace::Off => None,
+            Deinterlace::Auto if interlaced => Some("interlaced"),
+            Deinterlace::Auto => None,
+            Deinterlace::On => Some("all"),
+        };
+        if let Some(deint) = deinterlace {
+            let name = match self.deinterlacer {
+                Deinterlacer::Yadif => "yadif",
+                Deinterlacer::Bwdif => "bwdif",
+            };
+            filters.push(format!("{}=mode=send_frame:deint={}", name, deint));
+        }
+
+        if self.auto_rotate {
+            filters.extend(rotation_filters(rotation).iter().map(|f| f.to_string()));
+        }
+
+        if self.crop_enabled && !self.crop.is_empty() {
+            let crop = &self.crop;
+            filters.push(format!(
+                "crop=iw-{}:ih-{}:{}:{}",
+                crop.left + crop.right,
+                crop.top + crop.bottom,
+                crop.left,
+                crop.top
+            ));
+        }
+
+        if self.denoise {
+            filters.push("hqdn3d".to_string());
+        }
+        if self.sharpen {
+            filters.push("unsharp=5:5:1.0".to_string());
+        }
+
+        if !hdr && !self.adjustments().is_unchanged() {
+            filters.push(format!(
+                "eq=brightness={:.2}:contrast={:.2}:saturation={:.2}:gamma={:.2}",
+                self.brightness, self.contrast, self.saturation, self.gamma
+            ));
+        }
+
+        if filters.is_empty() {
+            return None;
+        }
+
+        if !hdr {
+            filters.push(format!("format={}", OUTPUT_FORMAT));
+        }
+        Some(filters.join(","))
+    }
+}
+
+/// Brightness, contrast, saturation and gamma of `VideoFilterSettings`. They're made by
+/// the `eq` filter for SDR frames, and by the tone mapper on the SDR picture of HDR frames
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PictureAdjustments {
+    pub brightness: f32,
+    pub contrast: f32,
+    pub saturation: f32,
+    pub gamma: f32,
+}
+
+impl PictureAdjustments {
+    pub fn is_unchanged(&self) -> bool {
+        *self == VideoFilterSettings::default().adjustments()
+    }
+
+    /// Adjust the gamma encoded RGB in `0.0..=1.0` as `eq` adjusts YUV, the luma is
+    /// changed by brightness, contrast and gamma, and the chroma by saturation
+    pub fn apply(&self, rgb: [f32; 3]) -> [f32; 3] {
+        let luma = 0.2126 * rgb[0] + 0.7152 * rgb[1] + 0.0722 * rgb[2];
+        let adjusted = (self.contrast * (luma - 0.5) + 0.5 + self.brightness).clamp(0.0, 1.0);
+        let adjusted = adjusted.powf(1.0 / self.gamma);
+
+        rgb.map(|c| (adjusted + self.saturation * (c - luma)).clamp(0.0, 1.0))
+    }
+}
+
+/// Round the rotation to quarter turns in range `0..360`, unit: degree
+pub fn normalize_rotation(rotation: i32) -> i32 {
+    ((rotation as f64 / 90.0).round() as i32).rem_euclid(4) * 90
+}
+
+/// Filters turning the picture clockwise, the rotation is rounded to quarter turns
+fn rotation_filters(rotation: i32) -> &'static [&'static str] {
+    match normalize_rotation(rotation) {
+        90 => &["transpose=clock"],
+        180 => &["hflip", "vflip"],
+        270 => &["transpose=cclock"],
+        _ => &[],
+    }
+}
+
+/// What the graph is configured for, it's rebuilt once any of them changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct GraphKey {
+    description: Option<String>,
+    width: i32,
+    height: i32,
+    format: i32,
+    sample_aspect_ratio: (i32, i32),
+}
+
+/// Filter graph of libavfilter between the video decoder and `parse_video_frame`.
+/// It's built from `VideoFilterSettings` and the properties of decoded frames,
+/// and it's rebuilt whenever they change
+pub struct VideoFilter {
+    graph: Option<AVFilterGraph>,
+    key: Option<GraphKey>,
+    /// Time base of the video stream as `(num, den)`
+    timebase: (u64, u64),
+    /// Interlaced frames have been seen, deinterlacing is kept on after that
+    interlaced: bool,
+    /// Clockwise rotation of the picture, unit: degree
+    rotation: i32,
+    /// The rotation should be done by the graph but it's failed to build
+    rotation_unapplied: bool,
+}
+
+impl VideoFilter {
+    /// `rotation` is the clockwise rotation of the stream, frames with display matrix
+    /// override it
+    pub fn new(timebase_num: u64, timebase_den: u64, rotation: i32) -> Self {
+        Self {
+            graph: None,
+            key: None,
+            timebase: (timebase_num, timebase_den),
+            interlaced: false,
+            rotation,
+            rotation_unapplied: false,
+        }
+    }
+
+    /// Clockwise rotation which is left to the renderer, unit: degree
+    pub fn unapplied_rotation(&self) -> i32 {
+        match self.rotation_unapplied {
+            true => normalize_rotation(self.rotation),
+            false => 0,
+        }
+    }
+
+    /// Filter one decoded frame
+    /// # Returns
+    /// The filtered frames, there can be none or more than one, e.g. when deinterlacing
+    pub fn filter(&mut self, frame: AVFrame, settings: &VideoFilterSettings) -> Vec<AVFrame> {
+        self.interlaced |= frame.interlaced_frame != 0;
+        if let Some(rotation) = display_rotation(&frame) {
+            self.rotation = rotation;
+        }
+
+        let hdr = HdrTransfer::of(&Colorimetry::of_frame(&frame)).is_some();
+        let key = GraphKey {
+            description: settings.graph_description(self.interlaced, self.rotation, hdr),
+            width: frame.width,
+            height: frame.height,
+            format: frame.format,
+            sample_aspect_ratio: (frame.sample_aspect_ratio.num, frame.sample_aspect_ratio.den),
+        };
+
+        let mut frames = vec![];
+        if self.key.as_ref() != Some(&key) {
+            // Frames held by the old graph are still valid
+            frames.extend(self.flush());
+            self.graph = key.description.as_ref().and_then(|description| {
+                match self.build(description, &frame) {
+                    Ok(graph) => {
+                        info!("Video filters: {}", description);
+                        Some(graph)
+                    }
+                    Err(err) => {
+                        warn!("Failed to build video filters {}: {}", description, err);
+                        None
+                    }
+                }
+            });
+            self.key = Some(key);
+            self.rotation_unapplied = settings.auto_rotate && self.graph.is_none();
+        }
+
+        match self.graph.as_mut() {
+            Some(graph) => frames.extend(Self::run(graph, Some(frame))),
+            None => frames.push(frame),
+        }
+
+        frames
+    }
+
+    /// Take the frames held by filters, e.g. at the end of stream
+    pub fn flush(&mut self) -> Vec<AVFrame> {
+        match self.graph.as_mut() {
+            Some(graph) => Self::run(graph, None),
+            None => vec![],
+        }
+    }
+
+    /// Drop the frames held by filters, e.g. after seeking
+    pub fn reset(&mut self) {
+        self.graph = None;
+        self.key = None;
+    }
+
+    fn build(&self, description: &str, frame: &AVFrame) -> Result<AVFilterGraph, PlayerError> {
+        let (sar_num, sar_den) = match frame.sample_aspect_ratio.num {
+            0 => (1, 1),
+            _ => (frame.sample_aspect_ratio.num, frame.sample_aspect_ratio.den),
+        };
+        let args = format!(
+            "video_size={}x{}:pix_fmt={}:time_base={}/{}:pixel_aspect={}/{}",
+            frame.width,
+            frame.height,
+            frame.format,
+            self.timebase.0,
+            self.timebase.1,
+            sar_num,
+            sar_den
+        );
+
+        let graph = AVFilterGraph::new();
+        let buffer = AVFilter::get_by_name(&CString::new("buffer")?)?;
+        let buffersink = AVFilter::get_by_name(&CString::new("buffersink")?)?;
+        let source_name = CString::new(SOURCE_NAME)?;
+        let sink_name = CString::new(SINK_NAME)?;
+        {
+            let mut source =
+                graph.create_filter_context(&buffer, &source_name, Some(&CString::new(args)?))?;
+            let mut sink = graph.create_filter_context(&buffersink, &sink_name, None)?;
+
+            // The output of source is the input of the parsed filters, and vice versa
+            let outputs = AVFilterInOut::new(&source_name, &mut source, 0);
+            let inputs = AVFilterInOut::new(&sink_name, &mut sink, 0);
+            graph.parse_ptr(&CString::new(description)?, Some(inputs), Some(outputs))?;
+        }
+        graph.config()?;
+
+        Ok(graph)
+    }
+
+    /// Send the frame to graph and take all available frames, `None` flushes the graph
+    fn run(graph: &mut AVFilterGraph, frame: Option<AVFrame>) -> Vec<AVFrame> {
+        let mut frames = vec![];
+
+        let source_name = CString::new(SOURCE_NAME).unwrap();
+        if let Some(mut source) = graph.get_filter(&source_name) {
+            if let Err(err) = source.buffersrc_add_frame(frame, None) {
+                warn!("Failed to filter video frame: {}", err);
+                return frames;
+            }
+        }
+
+        let sink_name = CString::new(SINK_NAME).unwrap();
+        if let Some(mut sink) = graph.get_filter(&sink_name) {
+            while let Ok(frame) = sink.buffersink_get_frame(None) {
+                frames.push(frame);
+            }
+        }
+
+        frames
+    }
+}
+
+/// Clockwise rotation from the display matrix of the stream, unit: degree.
+/// Phone cameras store the video sideways and rotate it by this
+pub fn stream_rotation(stream: &AVStream) -> i32 {
+    let matrix = unsafe {
+        av_stream_get_side_data(
+            stream.as_ptr(),
+            AVPACKETSIDEDATATYPE_AV_PKT_DATA_DISPLAYMATRIX,
+            ptr::null_mut(),
+        )
+    };
+
+    matrix_rotation(matrix as *const i32).map_or(0, normalize_rotation)
+}
+
+/// Clockwise rotation from the display matrix of the frame, unit: degree
+fn display_rotation(frame: &AVFrame) -> Option<i32> {
+    let side_data = unsafe {
+        av_frame_get_side_data(
+            frame.as_ptr(),
+            AVFRAMESIDEDATATYPE_AV_FRAME_DATA_DISPLAYMATRIX,
+        )
+    };
+    if side_data.is_null() {
+        return None;
+    }
+
+    matrix_rotation(unsafe { (*side_data).data } as *const i32)
+}
+
+fn matrix_rotation(matrix: *const i32) -> Option<i32> {
+    if matrix.is_null() {
+        return None;
+    }
+
+    // The matrix gives counterclockwise rotation
+    let rotation = unsafe { av_display_rotation_get(matrix) };
+    if rotation.is_nan() {
+        return None;
+    }
+
+    Some(-rotation.round() as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use rsmpeg::ffi::{
+        AVColorPrimaries_AVCOL_PRI_BT2020 as AVCOLORPRIMARIES_AVCOL_PRI_BT2020,
+        AVColorRange_AVCOL_RANGE_MPEG as AVCOLORRANGE_AVCOL_RANGE_MPEG,
+        AVColorSpace_AVCOL_SPC_BT2020_NCL as AVCOLORSPACE_AVCOL_SPC_BT2020_NCL,
+        AVColorTransferCharacteristic_AVCOL_TRC_SMPTE2084 as AVCOLORTRANSFERCHARACTERISTIC_AVCOL_TRC_SMPTE2084,
+        AVPixelFormat_AV_PIX_FMT_RGB24 as AVPIXELFORMAT_AV_PIX_FMT_RGB24,
+        AVPixelFormat_AV_PIX_FMT_YUV420P as AVPIXELFORMAT_AV_PIX_FMT_YUV420P,
+        AVPixelFormat_AV_PIX_FMT_YUV420P10LE as AVPIXELFORMAT_AV_PIX_FMT_YUV420P10LE,
+    };
+
+    use crate::{
+        media::{
+            decoder::VideoSummary,
+            tone_map::{HdrMetadata, ToneMapper},
+        },
+        util::{
+            colorspace::RgbConverter,
+            pixel_format::{parse_video_frame, FramePlanes},
+        },
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_no_filter_by_default() {
+        let settings = VideoFilterSettings::default();
+        assert_eq!(None, settings.graph_description(false, 0, false));
+        assert_eq!(None, settings.graph_description(false, 360, false));
+    }
+
+    #[test]
+    fn test_auto_deinterlace() {
+        let mut settings = VideoFilterSettings::default();
+        assert_eq!(
+            Some("yadif=mode=send_frame:deint=interlaced,format=yuv420p".to_string()),
+            settings.graph_description(true, 0, false)
+        );
+
+        settings.deinterlace = Deinterlace::On;
+        settings.deinterlacer = Deinterlacer::Bwdif;
+        assert_eq!(
+            Some("bwdif=mode=send_frame:deint=all,format=yuv420p".to_string()),
+            settings.graph_description(false, 0, false)
+        );
+
+        settings.deinterlace = Deinterlace::Off;
+        assert_eq!(None, settings.graph_description(true, 0, false));
+    }
+
+    #[test]
+    fn test_rotation() {
+        assert_eq!(&["transpose=clock"], rotation_filters(90));
+        assert_eq!(&["transpose=cclock"], rotation_filters(-90));
+        assert_eq!(&["hflip", "vflip"], rotation_filters(180));
+        assert!(rotation_filters(0).is_empty());
+        assert!(rotation_filters(-360).is_empty());
+        assert_eq!(270, normalize_rotation(-90));
+        assert_eq!(90, normalize_rotation(91));
+        assert_eq!(0, normalize_rotation(360));
+
+        let mut settings = VideoFilterSettings::default();
+        assert_eq!(
+            Some("transpose=clock,format=yuv420p".to_string()),
+            settings.graph_description(false, 90, false)
+        );
+        settings.auto_rotate = false;
+        assert_eq!(None, settings.graph_description(false, 90, false));
+    }
+
+    #[test]
+    fn test_filter_order() {
+        let settings = VideoFilterSettings {
+            deinterlace: Deinterlace::On,
+            crop_enabled: true,
+            crop: Crop {
+                left: 10,
+                top: 20,
+                right: 30,
+                bottom: 40,
+            },
+            denoise: true,
+            sharpen: true,
+            brightness: 0.1,
+            ..Default::default()
+        };
+        assert_eq!(
+            Some(
+                "yadif=mode=send_frame:deint=all,transpose=cclock,crop=iw-40:ih-60:10:20,\
+                 hqdn3d,unsharp=5:5:1.0,\
+                 eq=brightness=0.10:contrast=1.00:saturation=1.00:gamma=1.00,format=yuv420p"
+                    .to_string()
+            ),
+            settings.graph_description(false, 270, false)
+        );
+        // HDR frames keep their bits for tone mapping and aren't adjusted
+        assert_eq!(
+            Some(
+                "yadif=mode=send_frame:deint=all,transpose=cclock,crop=iw-40:ih-60:10:20,\
+                 hqdn3d,unsharp=5:5:1.0"
+                    .to_string()
+            ),
+            settings.graph_description(false, 270, true)
+        );
+
+        let settings = VideoFilterSettings {
+            deinterlace: Deinterlace::Off,
+            contrast: 1.5,
+            ..Default::default()
+        };
+        assert_eq!(None, settings.graph_description(false, 0, true));
+    }
+
+    #[test]
+    fn test_picture_adjustments() {
+        let close = |a: [f32; 3], b: [f32; 3]| a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-5);
+        let gray = [0.5; 3];
+        let adjustments = VideoFilterSettings::default().adjustments();
+        assert!(adjustments.is_unchanged());
+        assert!(close([0.2, 0.4, 0.6], adjustments.apply([0.2, 0.4, 0.6])));
+
+        let brighter = PictureAdjustments {
+            brightness: 0.1,
+            ..adjustments
+        };
+        assert!(!brighter.is_unchanged());
+        assert!(close([0.6; 3], brighter.apply(gray)));
+        let contrast = PictureAdjustments {
+            contrast: 2.0,
+            ..adjustments
+        };
+        assert!(close([1.0; 3], contrast.apply([0.75; 3])));
+        assert!(close(gray, contrast.apply(gray)));
+        let gamma = PictureAdjustments {
+            gamma: 2.0,
+            ..adjustments
+        };
+        assert!(close(gray, gamma.apply([0.25; 3])));
+
+        // Colors keep their luma without saturation
+        let gray_scale = PictureAdjustments {
+            saturation: 0.0,
+            ..adjustments
+        };
+        assert!(close([0.2126; 3], gray_scale.apply([1.0, 0.0, 0.0])));
+    }
+
+    #[test]
+    fn test_apply_command() {
+        let mut settings = VideoFilterSettings::default();
+        settings.apply(VideoFilterCommand::CycleDeinterlace);
+        assert_eq!(Deinterlace::On, settings.deinterlace);
+        settings.apply(VideoFilterCommand::ToggleAutoRotate);
+        assert!(!settings.auto_rotate);
+        settings.apply(VideoFilterCommand::Brightness(5.0));
+        assert_eq!(1.0, settings.brightness);
+        settings.apply(VideoFilterCommand::Gamma(-5.0));
+        assert_eq!(0.1, settings.gamma);
+        settings.apply(VideoFilterCommand::ResetAdjustments);
+        assert_eq!(0.0, settings.brightness);
+        assert_eq!(1.0, settings.gamma);
+    }
+
+    #[test]
+    fn test_crop_frame() {
+        // Every pixel holds its column and row, chroma planes use other factors
+        let value = |col: usize, row: usize, factor: usize| ((col + row * factor) % 256) as u8;
+        let mut frame = AVFrame::new();
+        frame.set_format(AVPIXELFORMAT_AV_PIX_FMT_YUV420P);
+        frame.set_width(64);
+        frame.set_height(48);
+        frame.set_pts(0);
+        frame.alloc_buffer().unwrap();
+        for (index, (width, rows, factor)) in [(64, 48, 3), (32, 24, 5), (32, 24, 7)]
+            .into_iter()
+            .enumerate()
+        {
+            let linesize = frame.linesize[index] as usize;
+            for row in 0..rows {
+                for col in 0..width {
+                    unsafe {
+                        *frame.data[index].add(row * linesize + col) = value(col, row, factor)
+                    };
+                }
+            }
+        }
+
+        let settings = VideoFilterSettings {
+            crop_enabled: true,
+            crop: Crop {
+                left: 10,
+                top: 4,
+                right: 6,
+                bottom: 8,
+            },
+            ..Default::default()
+        };
+        let mut filter = VideoFilter::new(1, 25, 0);
+        let mut frames = filter.filter(frame, &settings);
+        assert_eq!(1, frames.len());
+        let cropped = frames.remove(0);
+        assert_eq!((48, 36), (cropped.width, cropped.height));
+
+        // Planes are read as `parse_video_frame` does, the lines of cropped frames are padded
+        let planes = FramePlanes::new(cropped);
+        for (index, (width, rows, offset, factor)) in [
+            (48, 36, (10, 4), 3),
+            (24, 18, (5, 2), 5),
+            (24, 18, (5, 2), 7),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let (plane, pitch) = planes.get(index).unwrap();
+            for row in 0..rows {
+                for col in 0..width {
+                    assert_eq!(
+                        value(col + offset.0, row + offset.1, factor),
+                        plane[row * pitch + col]
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_tone_map_pq_frame() {
+        // Gray of 50 nits, its PQ signal 0.439 is below the knee of the tone curves
+        let (luma, chroma) = (449, 512);
+        let mut frame = AVFrame::new();
+        frame.set_format(AVPIXELFORMAT_AV_PIX_FMT_YUV420P10LE);
+        frame.set_width(64);
+        frame.set_height(48);
+        frame.set_pts(0);
+        frame.alloc_buffer().unwrap();
+        unsafe {
+            let raw = &mut *frame.as_mut_ptr();
+            raw.colorspace = AVCOLORSPACE_AVCOL_SPC_BT2020_NCL;
+            raw.color_range = AVCOLORRANGE_AVCOL_RANGE_MPEG;
+            raw.color_primaries = AVCOLORPRIMARIES_AVCOL_PRI_BT2020;
+            raw.color_trc = AVCOLORTRANSFERCHARACTERISTIC_AVCOL_TRC_SMPTE2084;
+        }
+        for (index, (width, rows, value)) in [(64, 48, luma), (32, 24, chroma), (32, 24, chroma)]
+            .into_iter()
+            .enumerate()
+        {
+            let linesize = frame.linesize[index] as usize / 2;
+            let plane = frame.data[index] as *mut u16;
+            for row in 0..rows {
+                for col in 0..width {
+                    unsafe { *plane.add(row * linesize + col) = value };
+                }
+            }
+        }
+
+        // Adjusting the picture would cut the frame to 8 bits
+        let settings = VideoFilterSettings {
+            crop_enabled: true,
+            crop: Crop {
+                left: 16,
+                top: 8,
+                right: 0,
+                bottom: 0,
+            },
+            brightness: 0.5,
+            ..Default::default()
+        };
+        let mut filter = VideoFilter::new(1, 25, 0);
+        let mut frames = filter.filter(frame, &settings);
+        assert_eq!(1, frames.len());
+        let cropped = frames.remove(0);
+        assert_eq!(AVPIXELFORMAT_AV_PIX_FMT_YUV420P10LE, cropped.format);
+        assert_eq!(
+            AVCOLORTRANSFERCHARACTERISTIC_AVCOL_TRC_SMPTE2084,
+            cropped.color_trc
+        );
+
+        let summary = VideoSummary {
+            decoder_name: "hevc".to_string(),
+            duration: 0,
+            duration_millis: 0,
+            frames: 1,
+            timebase_num: 1,
+            timebase_den: 25,
+            play_interval: 40,
+            width: 64,
+            height: 48,
+            rotation: 0,
+            hdr_metadata: HdrMetadata::default(),
+        };
+        let mut tone_mapper = ToneMapper::new(summary.hdr_metadata);
+        let vf = parse_video_frame(
+            cropped,
+            &summary,
+            &mut RgbConverter::default(),
+            &mut tone_mapper,
+        );
+        assert_eq!(AVPIXELFORMAT_AV_PIX_FMT_RGB24, vf.format);
+        assert_eq!((48, 40), (vf.width, vf.height));
+
+        // 50 nits of the reference white of 203 nits, encoded with the gamma of 2.4
+        let expected = (50.0f64 / 203.0).powf(1.0 / 2.4) * 255.0;
+        let (rgb, pitch) = vf.planes.get(0).unwrap();
+        for row in 0..vf.height {
+            for value in &rgb[row * pitch..row * pitch + vf.width * 3] {
+                assert!(
+                    (*value as f64 - expected).abs() <= 3.0,
+                    "{} != {}",
+                    value,
+                    expected
+                );
+            }
+        }
+    }
+}
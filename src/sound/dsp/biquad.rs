@@ -0,0 +1,63 @@
+use std::f64::consts::PI;
+
+/// Second order IIR filter, processed in transposed direct form II
+#[derive(Debug, Clone, Copy)]
+pub struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    z1: f64,
+    z2: f64,
+}
+
+impl Biquad {
+    /// Create filter with coefficients which are already normalized by `a0`
+    pub fn new(b0: f64, b1: f64, b2: f64, a1: f64, a2: f64) -> Self {
+        Self {
+            b0,
+            b1,
+            b2,
+            a1,
+            a2,
+            z1: 0.0,
+            z2: 0.0,
+        }
+    }
+
+    /// Peaking EQ filter from the Audio EQ Cookbook
+    /// # Arguments
+    /// * `sample_rate`: Sample rate of the signal, unit: Hz
+    /// * `freq`: Center frequency, unit: Hz
+    /// * `q`: Quality factor, a larger value means a narrower band
+    /// * `gain_db`: Gain at the center frequency, unit: dB
+    pub fn peaking(sample_rate: f64, freq: f64, q: f64, gain_db: f64) -> Self {
+        let a = 10f64.powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * freq / sample_rate;
+        let alpha = w0.sin() / (2.0 * q);
+        let cos_w0 = w0.cos();
+
+        let a0 = 1.0 + alpha / a;
+        Self::new(
+            (1.0 + alpha * a) / a0,
+            -2.0 * cos_w0 / a0,
+            (1.0 - alpha * a) / a0,
+            -2.0 * cos_w0 / a0,
+            (1.0 - alpha / a) / a0,
+        )
+    }
+
+    #[inline]
+    pub fn process(&mut self, input: f64) -> f64 {
+        let output = self.b0 * input + self.z1;
+        self.z1 = self.b1 * input - self.a1 * output + self.z2;
+        self.z2 = self.b2 * input - self.a2 * output;
+        output
+    }
+
+    pub fn reset(&mut self) {
+        self.z1 = 0.0;
+        self.z2 = 0.0;
+    }
+}
@@ -0,0 +1,111 @@
+use serde::{Deserialize, Serialize};
+
+/// How the left and right channels are mixed, only stereo audio is affected
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChannelMode {
+    #[default]
+    Stereo,
+    /// Exchange the left and right channels
+    Swap,
+    /// Both channels play the average of them
+    Mono,
+}
+
+impl ChannelMode {
+    pub fn next(&self) -> Self {
+        match self {
+            ChannelMode::Stereo => ChannelMode::Swap,
+            ChannelMode::Swap => ChannelMode::Mono,
+            ChannelMode::Mono => ChannelMode::Stereo,
+        }
+    }
+}
+
+/// Mix the interleaved stereo samples in place
+pub fn apply_channel_mode(samples: &mut [f32], channels: u8, mode: ChannelMode) {
+    if channels != 2 {
+        return;
+    }
+
+    for frame in samples.chunks_exact_mut(2) {
+        match mode {
+            ChannelMode::Stereo => return,
+            ChannelMode::Swap => frame.swap(0, 1),
+            ChannelMode::Mono => {
+                let mixed = (frame[0] + frame[1]) / 2.0;
+                frame[0] = mixed;
+                frame[1] = mixed;
+            }
+        }
+    }
+}
+
+/// Attenuate one side of the interleaved stereo samples in place.
+/// `balance` is in range `-1.0..=1.0`, negative values move the sound to the left
+pub fn apply_balance(samples: &mut [f32], channels: u8, balance: f32) {
+    if channels != 2 || balance == 0.0 {
+        return;
+    }
+
+    let balance = balance.clamp(-1.0, 1.0);
+    let left = (1.0 - balance).min(1.0);
+    let right = (1.0 + balance).min(1.0);
+    for frame in samples.chunks_exact_mut(2) {
+        frame[0] *= left;
+        frame[1] *= right;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sound::dsp::tests::{rms, sine_wave};
+
+    /// Sine on the left channel and silence on the right
+    fn left_only() -> Vec<f32> {
+        let mut samples = sine_wave(440.0, 48000, 2, 0.5, 0.1);
+        samples.iter_mut().skip(1).step_by(2).for_each(|s| *s = 0.0);
+        samples
+    }
+
+    fn channel_levels(samples: &[f32]) -> (f32, f32) {
+        let left: Vec<f32> = samples.iter().step_by(2).copied().collect();
+        let right: Vec<f32> = samples.iter().skip(1).step_by(2).copied().collect();
+        (rms(&left), rms(&right))
+    }
+
+    #[test]
+    fn test_swap() {
+        let mut samples = left_only();
+        apply_channel_mode(&mut samples, 2, ChannelMode::Swap);
+        let (left, right) = channel_levels(&samples);
+        assert_eq!(0.0, left);
+        assert!(right > 0.3);
+    }
+
+    #[test]
+    fn test_mono() {
+        let mut samples = left_only();
+        let (before, _) = channel_levels(&samples);
+        apply_channel_mode(&mut samples, 2, ChannelMode::Mono);
+        let (left, right) = channel_levels(&samples);
+        assert_eq!(left, right);
+        assert!((left - before / 2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_balance() {
+        let mut samples = sine_wave(440.0, 48000, 2, 0.5, 0.1);
+        let (before, _) = channel_levels(&samples);
+        apply_balance(&mut samples, 2, 0.5);
+        let (left, right) = channel_levels(&samples);
+        assert!((left - before / 2.0).abs() < 1e-6);
+        assert_eq!(before, right);
+
+        // Mono audio is untouched
+        let mut samples = sine_wave(440.0, 48000, 1, 0.5, 0.1);
+        let expected = samples.clone();
+        apply_balance(&mut samples, 1, 1.0);
+        assert_eq!(expected, samples);
+    }
+}
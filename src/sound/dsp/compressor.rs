@@ -0,0 +1,137 @@
+use serde::{Deserialize, Serialize};
+
+/// Time for the envelope to follow a rising level, unit: milliseconds
+const ATTACK_MILLIS: f32 = 5.0;
+/// Time for the envelope to follow a falling level, unit: milliseconds
+const RELEASE_MILLIS: f32 = 200.0;
+/// The minimum level of envelope, avoids log of zero
+const MIN_LEVEL: f32 = 1e-9;
+
+/// Dynamic range compression
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    #[default]
+    Off,
+    /// Gentle compression that keeps most of the dynamics
+    Normal,
+    /// Strong compression for quiet listening, loud scenes are reduced and dialogues raised
+    Night,
+}
+
+impl Compression {
+    pub fn next(&self) -> Self {
+        match self {
+            Compression::Off => Compression::Normal,
+            Compression::Normal => Compression::Night,
+            Compression::Night => Compression::Off,
+        }
+    }
+
+    /// Threshold (dBFS), ratio and makeup gain (dB) of the mode
+    fn params(&self) -> Option<(f32, f32, f32)> {
+        match self {
+            Compression::Off => None,
+            Compression::Normal => Some((-18.0, 3.0, 3.0)),
+            Compression::Night => Some((-30.0, 6.0, 10.0)),
+        }
+    }
+}
+
+/// Feed-forward compressor with a peak envelope linked across channels
+pub struct Compressor {
+    /// `None` means compression is off
+    params: Option<(f32, f32, f32)>,
+    channels: usize,
+    attack_coef: f32,
+    release_coef: f32,
+    envelope: f32,
+}
+
+impl Compressor {
+    pub fn new(sample_rate: i32, channels: u8, mode: Compression) -> Self {
+        let coef = |millis: f32| (-1000.0 / (millis * sample_rate.max(1) as f32)).exp();
+
+        Self {
+            params: mode.params(),
+            channels: channels.max(1) as usize,
+            attack_coef: coef(ATTACK_MILLIS),
+            release_coef: coef(RELEASE_MILLIS),
+            envelope: 0.0,
+        }
+    }
+
+    /// Compress the interleaved samples in place
+    pub fn process(&mut self, samples: &mut [f32]) {
+        let (threshold_db, ratio, makeup_db) = match self.params {
+            Some(params) => params,
+            None => return,
+        };
+
+        for frame in samples.chunks_mut(self.channels) {
+            let peak = frame
+                .iter()
+                .fold(0f32, |peak, sample| peak.max(sample.abs()));
+            let coef = if peak > self.envelope {
+                self.attack_coef
+            } else {
+                self.release_coef
+            };
+            self.envelope = coef * self.envelope + (1.0 - coef) * peak;
+
+            let level_db = 20.0 * self.envelope.max(MIN_LEVEL).log10();
+            let over_db = (level_db - threshold_db).max(0.0);
+            let gain_db = makeup_db - over_db * (1.0 - 1.0 / ratio);
+            let gain = 10f32.powf(gain_db / 20.0);
+
+            frame.iter_mut().for_each(|sample| *sample *= gain);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.envelope = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sound::dsp::tests::{rms, sine_wave};
+
+    const RATE: i32 = 48000;
+
+    fn output_level_db(mode: Compression, amplitude: f32) -> f32 {
+        let mut samples = sine_wave(1000.0, RATE, 2, amplitude, 1.0);
+        Compressor::new(RATE, 2, mode).process(&mut samples);
+
+        // Skip the attack phase
+        let skip = samples.len() / 2;
+        20.0 * rms(&samples[skip..]).log10()
+    }
+
+    #[test]
+    fn test_off_is_transparent() {
+        let input = 20.0 * rms(&sine_wave(1000.0, RATE, 2, 0.5, 1.0)).log10();
+        assert!((output_level_db(Compression::Off, 0.5) - input).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_night_mode_reduces_range() {
+        // 40dB between the loud and the quiet sine
+        let loud = output_level_db(Compression::Night, 1.0);
+        let quiet = output_level_db(Compression::Night, 0.01);
+
+        assert!(loud - quiet < 20.0);
+        // The loud one is reduced and the quiet one is raised by makeup gain
+        assert!(loud < -3.0 - 10.0);
+        assert!(quiet > -43.0 + 5.0);
+    }
+
+    #[test]
+    fn test_normal_keeps_more_dynamics() {
+        let night =
+            output_level_db(Compression::Night, 1.0) - output_level_db(Compression::Night, 0.01);
+        let normal =
+            output_level_db(Compression::Normal, 1.0) - output_level_db(Compression::Normal, 0.01);
+        assert!(normal > night);
+    }
+}
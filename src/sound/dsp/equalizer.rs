@@ -0,0 +1,154 @@
+use serde::{Deserialize, Serialize};
+
+use super::biquad::Biquad;
+
+/// Number of bands of the graphic equalizer
+pub const BAND_COUNT: usize = 10;
+/// Center frequencies of the bands, one octave apart, unit: Hz
+pub const BAND_FREQUENCIES: [f64; BAND_COUNT] = [
+    31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+];
+/// Quality factor of one octave bandwidth
+const BAND_Q: f64 = 1.41;
+/// Bands too close to the Nyquist frequency are skipped, relative to the sample rate
+const MAX_RELATIVE_FREQUENCY: f64 = 0.45;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EqPreset {
+    #[default]
+    Flat,
+    Rock,
+    Pop,
+    Jazz,
+    Classical,
+    BassBoost,
+    Vocal,
+    /// Gains are taken from `DspSettings::eq_gains`
+    Custom,
+}
+
+impl EqPreset {
+    /// The preset after current one when user cycles presets
+    pub fn next(&self) -> Self {
+        match self {
+            EqPreset::Flat => EqPreset::Rock,
+            EqPreset::Rock => EqPreset::Pop,
+            EqPreset::Pop => EqPreset::Jazz,
+            EqPreset::Jazz => EqPreset::Classical,
+            EqPreset::Classical => EqPreset::BassBoost,
+            EqPreset::BassBoost => EqPreset::Vocal,
+            EqPreset::Vocal => EqPreset::Custom,
+            EqPreset::Custom => EqPreset::Flat,
+        }
+    }
+
+    /// Gains of every band, unit: dB
+    pub fn gains(&self, custom: &[f32; BAND_COUNT]) -> [f32; BAND_COUNT] {
+        match self {
+            EqPreset::Flat => [0.0; BAND_COUNT],
+            EqPreset::Rock => [5.0, 4.0, 3.0, 1.0, -1.0, -1.0, 1.0, 3.0, 4.0, 5.0],
+            EqPreset::Pop => [-1.0, 1.0, 3.0, 4.0, 3.0, 0.0, -1.0, -1.0, -1.0, -1.0],
+            EqPreset::Jazz => [3.0, 2.0, 1.0, 2.0, -1.0, -1.0, 0.0, 1.0, 2.0, 3.0],
+            EqPreset::Classical => [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -3.0, -3.0, -3.0, -5.0],
+            EqPreset::BassBoost => [6.0, 5.0, 4.0, 2.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            EqPreset::Vocal => [-2.0, -2.0, -1.0, 1.0, 3.0, 3.0, 2.0, 1.0, 0.0, -1.0],
+            EqPreset::Custom => *custom,
+        }
+    }
+}
+
+/// Graphic equalizer made of one peaking filter per band and channel
+pub struct Equalizer {
+    /// Filters of every channel, bands with 0dB gain are left out
+    filters: Vec<Vec<Biquad>>,
+}
+
+impl Equalizer {
+    pub fn new(sample_rate: i32, channels: u8, gains: &[f32; BAND_COUNT]) -> Self {
+        let sample_rate = sample_rate as f64;
+        let bands: Vec<Biquad> = BAND_FREQUENCIES
+            .iter()
+            .zip(gains.iter())
+            .filter(|(freq, gain)| **gain != 0.0 && **freq < sample_rate * MAX_RELATIVE_FREQUENCY)
+            .map(|(freq, gain)| Biquad::peaking(sample_rate, *freq, BAND_Q, *gain as f64))
+            .collect();
+
+        Self {
+            filters: vec![bands; channels.max(1) as usize],
+        }
+    }
+
+    /// Filter the interleaved samples in place
+    pub fn process(&mut self, samples: &mut [f32]) {
+        if self.filters.iter().all(|bands| bands.is_empty()) {
+            return;
+        }
+
+        let channels = self.filters.len();
+        for frame in samples.chunks_mut(channels) {
+            for (sample, bands) in frame.iter_mut().zip(self.filters.iter_mut()) {
+                let mut value = *sample as f64;
+                for band in bands.iter_mut() {
+                    value = band.process(value);
+                }
+                *sample = value as f32;
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.filters.iter_mut().flatten().for_each(Biquad::reset);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sound::dsp::tests::{rms, sine_wave};
+
+    const RATE: i32 = 48000;
+
+    /// Ratio of output level to input level of a mono sine, the filter transient is skipped
+    fn level_ratio(gains: &[f32; BAND_COUNT], freq: f64) -> f32 {
+        let input = sine_wave(freq, RATE, 1, 0.5, 1.0);
+        let mut output = input.clone();
+        Equalizer::new(RATE, 1, gains).process(&mut output);
+
+        let skip = RATE as usize / 10;
+        rms(&output[skip..]) / rms(&input[skip..])
+    }
+
+    #[test]
+    fn test_flat_is_transparent() {
+        let gains = EqPreset::Flat.gains(&[0.0; BAND_COUNT]);
+        assert!((level_ratio(&gains, 440.0) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_band_boost() {
+        let mut gains = [0.0; BAND_COUNT];
+        gains[5] = 6.0;
+        // The sine at the center frequency is boosted by 6dB
+        let expected = 10f32.powf(6.0 / 20.0);
+        assert!((level_ratio(&gains, 1000.0) - expected).abs() < 0.05);
+        // Frequencies far away are untouched
+        assert!((level_ratio(&gains, 16000.0) - 1.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_band_cut() {
+        let mut gains = [0.0; BAND_COUNT];
+        gains[1] = -12.0;
+        assert!(level_ratio(&gains, 62.0) < 0.3);
+        assert!((level_ratio(&gains, 4000.0) - 1.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_preset_cycle() {
+        let mut preset = EqPreset::Flat;
+        for _ in 0..8 {
+            preset = preset.next();
+        }
+        assert_eq!(EqPreset::Flat, preset);
+    }
+}
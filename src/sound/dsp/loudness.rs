@@ -0,0 +1,232 @@
+use std::f64::consts::PI;
+
+use super::biquad::Biquad;
+
+/// Duration of the gating block, unit: milliseconds
+const BLOCK_MILLIS: usize = 400;
+/// Blocks overlap by 75%, so a new block starts every step
+const STEP_MILLIS: usize = 100;
+const STEPS_PER_BLOCK: usize = BLOCK_MILLIS / STEP_MILLIS;
+/// Blocks quieter than this are ignored, unit: LUFS
+const ABSOLUTE_GATE: f64 = -70.0;
+/// Blocks quieter than the ungated loudness minus this are ignored, unit: LU
+const RELATIVE_GATE: f64 = 10.0;
+/// Blocks are counted in bins of this width from the absolute gate up, unit: LU
+const BIN_WIDTH: f64 = 0.1;
+/// Blocks louder than the absolute gate plus this fall into the last bin, unit: LU
+const HISTOGRAM_RANGE: f64 = 100.0;
+const BIN_COUNT: usize = (HISTOGRAM_RANGE / BIN_WIDTH) as usize;
+
+/// Integrated loudness meter as specified by ITU-R BS.1770 and EBU R128.
+/// The channels are weighted equally, which is right for mono and stereo
+#[derive(Default)]
+pub struct LoudnessMeter {
+    sample_rate: i32,
+    channels: u8,
+    /// K-weighting filters of every channel
+    filters: Vec<(Biquad, Biquad)>,
+    /// Samples per channel in one step
+    step_samples: usize,
+    /// Sum of squared samples of current step and number of samples per channel in it
+    step_energy: f64,
+    step_count: usize,
+    /// Mean square of the latest steps, a block is made of them
+    recent_steps: Vec<f64>,
+    /// Sum of mean squares and number of the blocks above the absolute gate in every bin,
+    /// so the memory is bounded however long the media is
+    histogram: Vec<(f64, u64)>,
+    /// Sum of mean squares and number of all the blocks in `histogram`
+    block_energy: f64,
+    block_count: u64,
+}
+
+impl LoudnessMeter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Measure the interleaved samples, the meter restarts if the format changed
+    pub fn feed(&mut self, samples: &[f32], sample_rate: i32, channels: u8) {
+        if sample_rate <= 0 || channels == 0 {
+            return;
+        }
+        if sample_rate != self.sample_rate || channels != self.channels {
+            self.reset(sample_rate, channels);
+        }
+
+        for frame in samples.chunks_exact(channels as usize) {
+            for (sample, (shelf, high_pass)) in frame.iter().zip(self.filters.iter_mut()) {
+                let weighted = high_pass.process(shelf.process(*sample as f64));
+                self.step_energy += weighted * weighted;
+            }
+
+            self.step_count += 1;
+            if self.step_count >= self.step_samples {
+                self.finish_step();
+            }
+        }
+    }
+
+    /// The integrated loudness of the measured samples, unit: LUFS.
+    /// Negative infinity means it's too quiet or not measured yet
+    pub fn integrated(&self) -> f64 {
+        if self.block_count == 0 {
+            return f64::NEG_INFINITY;
+        }
+
+        // The bin of the relative gate is counted as a whole, like libebur128 does
+        let ungated = self.block_energy / self.block_count as f64;
+        let first_bin = bin_index(loudness(ungated) - RELATIVE_GATE);
+        let (sum, count) = self.histogram[first_bin..]
+            .iter()
+            .fold((0.0, 0), |(sum, count), bin| (sum + bin.0, count + bin.1));
+        if count == 0 {
+            return f64::NEG_INFINITY;
+        }
+
+        loudness(sum / count as f64)
+    }
+
+    fn reset(&mut self, sample_rate: i32, channels: u8) {
+        let rate = sample_rate as f64;
+        self.sample_rate = sample_rate;
+        self.channels = channels;
+        self.filters = vec![(high_shelf(rate), high_pass(rate)); channels as usize];
+        self.step_samples = (sample_rate as usize * STEP_MILLIS / 1000).max(1);
+        self.step_energy = 0.0;
+        self.step_count = 0;
+        self.recent_steps.clear();
+        self.histogram = vec![(0.0, 0); BIN_COUNT];
+        self.block_energy = 0.0;
+        self.block_count = 0;
+    }
+
+    fn finish_step(&mut self) {
+        self.recent_steps
+            .push(self.step_energy / self.step_count as f64);
+        self.step_energy = 0.0;
+        self.step_count = 0;
+
+        if self.recent_steps.len() > STEPS_PER_BLOCK {
+            self.recent_steps.remove(0);
+        }
+        if self.recent_steps.len() == STEPS_PER_BLOCK {
+            let block = self.recent_steps.iter().sum::<f64>() / STEPS_PER_BLOCK as f64;
+            let block_loudness = loudness(block);
+            if block_loudness > ABSOLUTE_GATE {
+                let bin = &mut self.histogram[bin_index(block_loudness)];
+                bin.0 += block;
+                bin.1 += 1;
+                self.block_energy += block;
+                self.block_count += 1;
+            }
+        }
+    }
+}
+
+fn loudness(energy: f64) -> f64 {
+    -0.691 + 10.0 * energy.log10()
+}
+
+/// The histogram bin of a loudness, values out of the range go to the first or last bin
+fn bin_index(loudness: f64) -> usize {
+    let index = ((loudness - ABSOLUTE_GATE) / BIN_WIDTH).floor();
+    (index.max(0.0) as usize).min(BIN_COUNT - 1)
+}
+
+/// First stage of K-weighting, models the acoustic effect of the head
+fn high_shelf(sample_rate: f64) -> Biquad {
+    let f0 = 1681.974450955533;
+    let gain = 3.999843853973347;
+    let q = 0.7071752369554196;
+
+    let k = (PI * f0 / sample_rate).tan();
+    let vh = 10f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+
+    Biquad::new(
+        (vh + vb * k / q + k * k) / a0,
+        2.0 * (k * k - vh) / a0,
+        (vh - vb * k / q + k * k) / a0,
+        2.0 * (k * k - 1.0) / a0,
+        (1.0 - k / q + k * k) / a0,
+    )
+}
+
+/// Second stage of K-weighting, the RLB high pass filter
+fn high_pass(sample_rate: f64) -> Biquad {
+    let f0 = 38.13547087602444;
+    let q = 0.5003270373238773;
+
+    let k = (PI * f0 / sample_rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+
+    Biquad::new(
+        1.0,
+        -2.0,
+        1.0,
+        2.0 * (k * k - 1.0) / a0,
+        (1.0 - k / q + k * k) / a0,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sound::dsp::tests::sine_wave;
+
+    fn measure(amplitude_db: f32, sample_rate: i32) -> f64 {
+        let amplitude = 10f32.powf(amplitude_db / 20.0);
+        let samples = sine_wave(1000.0, sample_rate, 2, amplitude, 5.0);
+
+        let mut meter = LoudnessMeter::new();
+        // Feed in small pieces like the decoded frames
+        for chunk in samples.chunks(2048) {
+            meter.feed(chunk, sample_rate, 2);
+        }
+        meter.integrated()
+    }
+
+    #[test]
+    fn test_reference_sine() {
+        // EBU Tech 3341: stereo 1kHz sine at -23dBFS reads -23LUFS
+        assert!((measure(-23.0, 48000) + 23.0).abs() < 0.1);
+        assert!((measure(-23.0, 44100) + 23.0).abs() < 0.1);
+        assert!((measure(-20.0, 48000) + 20.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_silence_is_gated() {
+        let mut meter = LoudnessMeter::new();
+        meter.feed(&vec![0.0; 48000 * 2 * 2], 48000, 2);
+        assert_eq!(f64::NEG_INFINITY, meter.integrated());
+    }
+
+    #[test]
+    fn test_relative_gate() {
+        // A quiet part far below the loud part doesn't lower the loudness
+        let mut samples = sine_wave(1000.0, 48000, 2, 10f32.powf(-23.0 / 20.0), 5.0);
+        samples.extend(sine_wave(1000.0, 48000, 2, 10f32.powf(-60.0 / 20.0), 5.0));
+
+        let mut meter = LoudnessMeter::new();
+        meter.feed(&samples, 48000, 2);
+        assert!((meter.integrated() + 23.0).abs() < 0.2);
+    }
+
+    #[test]
+    fn test_gated_mean_of_blocks() {
+        // Both parts pass the gates, the result is the mean energy of them
+        let mut samples = sine_wave(1000.0, 48000, 2, 10f32.powf(-20.0 / 20.0), 10.0);
+        samples.extend(sine_wave(1000.0, 48000, 2, 10f32.powf(-26.0 / 20.0), 10.0));
+
+        let mut meter = LoudnessMeter::new();
+        for chunk in samples.chunks(2048) {
+            meter.feed(chunk, 48000, 2);
+        }
+        let expected = 10.0 * ((10f64.powf(-2.0) + 10f64.powf(-2.6)) / 2.0).log10();
+        assert!((meter.integrated() - expected).abs() < 0.1);
+        // The blocks are counted in the fixed bins
+        assert_eq!(BIN_COUNT, meter.histogram.len());
+    }
+}
@@ -0,0 +1,291 @@
+mod biquad;
+mod channel;
+mod compressor;
+mod equalizer;
+mod loudness;
+
+use serde::{Deserialize, Serialize};
+use std::mem;
+
+pub use self::loudness::LoudnessMeter;
+
+use self::channel::{apply_balance, apply_channel_mode, ChannelMode};
+use self::compressor::{Compression, Compressor};
+use self::equalizer::{EqPreset, Equalizer, BAND_COUNT};
+
+/// The maximum gain of loudness normalization in both directions, unit: dB
+const MAX_NORMALIZATION_DB: f32 = 12.0;
+/// How much of the remaining normalization gain is applied per buffer,
+/// the measured loudness drifts at the beginning, so it's followed slowly
+const NORMALIZATION_SMOOTHING: f32 = 0.05;
+
+/// Options of the audio processing, they can be changed while playing
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct DspSettings {
+    pub eq_preset: EqPreset,
+    /// Gains of the custom preset, unit: dB
+    pub eq_gains: [f32; BAND_COUNT],
+    pub compression: Compression,
+    /// Adjust the audio to `target_loudness` according to EBU R128
+    pub loudness_normalization: bool,
+    /// Unit: LUFS
+    pub target_loudness: f32,
+    pub channel_mode: ChannelMode,
+    /// In range `-1.0..=1.0`, negative values move the sound to the left
+    pub balance: f32,
+}
+
+impl Default for DspSettings {
+    fn default() -> Self {
+        Self {
+            eq_preset: EqPreset::default(),
+            eq_gains: [0.0; BAND_COUNT],
+            compression: Compression::default(),
+            loudness_normalization: false,
+            target_loudness: -18.0,
+            channel_mode: ChannelMode::default(),
+            balance: 0.0,
+        }
+    }
+}
+
+/// Runtime changes of `DspSettings`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DspCommand {
+    CycleEqPreset,
+    CycleCompression,
+    ToggleLoudnessNormalization,
+    CycleChannelMode,
+    /// Move the balance by the amount
+    Balance(f32),
+}
+
+impl DspSettings {
+    pub fn apply(&mut self, command: DspCommand) {
+        match command {
+            DspCommand::CycleEqPreset => self.eq_preset = self.eq_preset.next(),
+            DspCommand::CycleCompression => self.compression = self.compression.next(),
+            DspCommand::ToggleLoudnessNormalization => {
+                self.loudness_normalization = !self.loudness_normalization
+            }
+            DspCommand::CycleChannelMode => self.channel_mode = self.channel_mode.next(),
+            DspCommand::Balance(delta) => {
+                self.balance = (self.balance + delta).clamp(-1.0, 1.0);
+            }
+        }
+    }
+}
+
+/// Filters built for new settings out of the audio callback, which mustn't allocate,
+/// they're swapped into the `DspChain` by `DspChain::apply`
+pub struct DspUpdate {
+    settings: DspSettings,
+    equalizer: Option<Equalizer>,
+    compressor: Option<Compressor>,
+}
+
+impl DspUpdate {
+    /// Build the filters whose options are changed from `old` to `new`
+    pub fn new(sample_rate: i32, channels: u8, old: &DspSettings, new: &DspSettings) -> Self {
+        let equalizer = (old.eq_preset != new.eq_preset || old.eq_gains != new.eq_gains)
+            .then(|| Equalizer::new(sample_rate, channels, &new.eq_preset.gains(&new.eq_gains)));
+        let compressor = (old.compression != new.compression)
+            .then(|| Compressor::new(sample_rate, channels, new.compression));
+
+        Self {
+            settings: new.clone(),
+            equalizer,
+            compressor,
+        }
+    }
+}
+
+/// The processing applied to decoded audio before it's sent to the device:
+/// channel mode, equalizer, loudness normalization, compression and balance
+pub struct DspChain {
+    settings: DspSettings,
+    channels: u8,
+    equalizer: Equalizer,
+    compressor: Compressor,
+    /// Current gain of loudness normalization, linear
+    normalization_gain: f32,
+}
+
+impl DspChain {
+    pub fn new(sample_rate: i32, channels: u8, settings: DspSettings) -> Self {
+        let gains = settings.eq_preset.gains(&settings.eq_gains);
+
+        Self {
+            equalizer: Equalizer::new(sample_rate, channels, &gains),
+            compressor: Compressor::new(sample_rate, channels, settings.compression),
+            settings,
+            channels,
+            normalization_gain: 1.0,
+        }
+    }
+
+    /// Swap in the filters prepared for new settings, the replaced filters are moved
+    /// into `update`, so they are freed by the caller rather than the audio thread
+    pub fn apply(&mut self, update: &mut DspUpdate) {
+        if let Some(equalizer) = update.equalizer.as_mut() {
+            mem::swap(&mut self.equalizer, equalizer);
+        }
+        if let Some(compressor) = update.compressor.as_mut() {
+            mem::swap(&mut self.compressor, compressor);
+        }
+        self.settings = update.settings.clone();
+    }
+
+    /// Clear the filter states, e.g. after seeking
+    pub fn reset(&mut self) {
+        self.equalizer.reset();
+        self.compressor.reset();
+    }
+
+    /// Process the interleaved samples in place
+    /// # Arguments
+    /// * `samples`: The interleaved samples with the channels of this chain
+    /// * `measured_loudness`: The integrated loudness of the media measured ahead, unit: LUFS
+    pub fn process(&mut self, samples: &mut [f32], measured_loudness: f64) {
+        apply_channel_mode(samples, self.channels, self.settings.channel_mode);
+        self.equalizer.process(samples);
+        self.normalize(samples, measured_loudness);
+        self.compressor.process(samples);
+        apply_balance(samples, self.channels, self.settings.balance);
+    }
+
+    fn normalize(&mut self, samples: &mut [f32], measured_loudness: f64) {
+        let target_db = if self.settings.loudness_normalization && measured_loudness.is_finite() {
+            (self.settings.target_loudness - measured_loudness as f32)
+                .clamp(-MAX_NORMALIZATION_DB, MAX_NORMALIZATION_DB)
+        } else {
+            0.0
+        };
+
+        let target = 10f32.powf(target_db / 20.0);
+        self.normalization_gain += (target - self.normalization_gain) * NORMALIZATION_SMOOTHING;
+        if (self.normalization_gain - 1.0).abs() > f32::EPSILON {
+            samples
+                .iter_mut()
+                .for_each(|sample| *sample *= self.normalization_gain);
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    /// Interleaved sine wave with the same signal on every channel
+    pub fn sine_wave(
+        freq: f64,
+        sample_rate: i32,
+        channels: u8,
+        amplitude: f32,
+        secs: f64,
+    ) -> Vec<f32> {
+        let count = (sample_rate as f64 * secs) as usize;
+        (0..count)
+            .flat_map(|i| {
+                let value =
+                    amplitude * (2.0 * PI * freq * i as f64 / sample_rate as f64).sin() as f32;
+                std::iter::repeat(value).take(channels as usize)
+            })
+            .collect()
+    }
+
+    pub fn rms(samples: &[f32]) -> f32 {
+        let sum: f64 = samples.iter().map(|s| (*s as f64) * (*s as f64)).sum();
+        (sum / samples.len().max(1) as f64).sqrt() as f32
+    }
+
+    #[test]
+    fn test_default_is_transparent() {
+        let input = sine_wave(1000.0, 48000, 2, 0.5, 0.5);
+        let mut output = input.clone();
+        DspChain::new(48000, 2, DspSettings::default()).process(&mut output, -30.0);
+        assert_eq!(input, output);
+    }
+
+    #[test]
+    fn test_loudness_normalization() {
+        let settings = DspSettings {
+            loudness_normalization: true,
+            target_loudness: -18.0,
+            ..Default::default()
+        };
+        let mut chain = DspChain::new(48000, 2, settings);
+
+        // Measured 6 LU quieter than the target, the gain approaches 6dB
+        let mut output = vec![];
+        for _ in 0..200 {
+            let mut samples = sine_wave(1000.0, 48000, 2, 0.1, 0.01);
+            chain.process(&mut samples, -24.0);
+            output = samples;
+        }
+        let expected = rms(&sine_wave(1000.0, 48000, 2, 0.1, 0.01)) * 10f32.powf(6.0 / 20.0);
+        assert!((rms(&output) - expected).abs() / expected < 0.01);
+
+        // The gain is capped
+        let mut chain = DspChain::new(48000, 2, chain.settings.clone());
+        for _ in 0..200 {
+            let mut samples = sine_wave(1000.0, 48000, 2, 0.01, 0.01);
+            chain.process(&mut samples, -70.0);
+            output = samples;
+        }
+        let expected = rms(&sine_wave(1000.0, 48000, 2, 0.01, 0.01)) * 10f32.powf(12.0 / 20.0);
+        assert!((rms(&output) - expected).abs() / expected < 0.01);
+    }
+
+    #[test]
+    fn test_apply_command() {
+        let mut settings = DspSettings::default();
+        settings.apply(DspCommand::CycleEqPreset);
+        assert_eq!(EqPreset::Rock, settings.eq_preset);
+        settings.apply(DspCommand::CycleCompression);
+        assert_eq!(Compression::Normal, settings.compression);
+        settings.apply(DspCommand::ToggleLoudnessNormalization);
+        assert!(settings.loudness_normalization);
+        settings.apply(DspCommand::CycleChannelMode);
+        assert_eq!(ChannelMode::Swap, settings.channel_mode);
+        settings.apply(DspCommand::Balance(-2.0));
+        assert_eq!(-1.0, settings.balance);
+    }
+
+    #[test]
+    fn test_update_keeps_unchanged_filters() {
+        let mut chain = DspChain::new(48000, 2, DspSettings::default());
+        let settings = DspSettings {
+            channel_mode: ChannelMode::Swap,
+            ..Default::default()
+        };
+        let mut update = DspUpdate::new(48000, 2, &chain.settings, &settings);
+        assert!(update.equalizer.is_none() && update.compressor.is_none());
+        chain.apply(&mut update);
+        assert_eq!(settings, chain.settings);
+    }
+
+    #[test]
+    fn test_update_swaps_filters() {
+        let mut chain = DspChain::new(48000, 2, DspSettings::default());
+        let settings = DspSettings {
+            eq_preset: EqPreset::BassBoost,
+            ..Default::default()
+        };
+        let mut update = DspUpdate::new(48000, 2, &chain.settings, &settings);
+        chain.apply(&mut update);
+        assert_eq!(settings, chain.settings);
+
+        // The new equalizer boosts the bass, the flat one is handed back
+        let input = sine_wave(62.0, 48000, 2, 0.1, 1.0);
+        let mut output = input.clone();
+        chain.process(&mut output, -30.0);
+        assert!(rms(&output) > rms(&input) * 1.5);
+
+        let mut flat = input.clone();
+        update.equalizer.unwrap().process(&mut flat);
+        assert_eq!(input, flat);
+    }
+}
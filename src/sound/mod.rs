@@ -1,3 +1,6 @@
+pub mod dsp;
 mod sounder;
+pub mod visualizer;
+pub mod volume;
 
-pub use sounder::Sounder;
\ No newline at end of file
+pub use sounder::{playback_devices, Sounder};
\ No newline at end of file
@@ -1,62 +1,107 @@
+use log::{info, warn};
 use sdl2::{
-    audio::{AudioCallback, AudioQueue, AudioSpecDesired},
+    audio::{AudioDevice, AudioSpecDesired, AudioStatus},
     AudioSubsystem,
 };
 
 use crate::{
-    media::decoder::{AudioFrame, AudioSummary},
-    util::error::SuperError,
+    global::DSP_SETTINGS,
+    media::{
+        decoder::AudioSummary,
+        player::{audio_player::AudioOutput, MediaPlayer},
+    },
+    sound::dsp::{DspSettings, DspUpdate},
+    util::error::PlayerError,
 };
 
 pub struct Sounder {
-    device: AudioQueue<f32>,
+    device: AudioDevice<AudioOutput>,
+    /// Name of the opened device, `None` means the default device
+    device_name: Option<String>,
+    /// The audio processing settings used by the device callback
+    dsp_settings: DspSettings,
 }
 
 impl Sounder {
-    pub fn new(sys: &AudioSubsystem, summary: &AudioSummary) -> Self {
+    /// Open the audio device, the samples are pulled from the audio player
+    /// by the device callback once the device is resumed.
+    /// If the specified device can't be opened, the default device will be tried
+    /// # Arguments
+    /// * `device_name`: Name of the playback device, `None` means the default device
+    pub fn new(
+        sys: &AudioSubsystem,
+        summary: &AudioSummary,
+        player: &MediaPlayer,
+        device_name: Option<&str>,
+    ) -> Result<Self, PlayerError> {
         let spec = AudioSpecDesired {
             freq: Some(summary.sample_rate),
             channels: Some(summary.channels),
             samples: None,
         };
 
-        let device = sys.open_queue::<f32, _>(None, &spec).unwrap();
-        Self { device }
-    }
+        // The callback is made with the current settings
+        let dsp_settings = DSP_SETTINGS.read().unwrap().clone();
+        let result = sys.open_playback(device_name, &spec, |spec| player.audio_output(&spec));
+        let (result, opened_name) = match (result, device_name) {
+            (Err(err), Some(name)) => {
+                warn!(
+                    "Failed to open audio device {}: {}, use the default device",
+                    name, err
+                );
+                let result = sys.open_playback(None, &spec, |spec| player.audio_output(&spec));
+                (result, None)
+            }
+            (result, name) => (result, name),
+        };
 
-    pub fn play_sound(&self, frame: AudioFrame) -> Result<(), SuperError> {
-        self.device.queue_audio(&frame.data)?;
-        self.device.resume();
+        let device = result.map_err(PlayerError::NoAudioDevice)?;
+        device.resume();
+        info!("Audio device opened: {}", opened_name.unwrap_or("default"));
 
-        Ok(())
+        Ok(Self {
+            device,
+            device_name: opened_name.map(|name| name.to_owned()),
+            dsp_settings,
+        })
     }
-}
 
-struct S16CallBack {
-    data: Vec<i16>,
-    pos: usize,
-    volume: f32,
-}
+    pub fn pause(&self) {
+        self.device.pause();
+    }
 
-impl AudioCallback for S16CallBack {
-    type Channel = i16;
-
-    fn callback(&mut self, out: &mut [i16]) {
-        for dst in out.iter_mut() {
-            let mut value = *self.data.get(self.pos).unwrap_or(&0);
-            let f32_value = (value as f32) * self.volume;
-            if f32_value > i16::MAX as f32 {
-                value = i16::MAX;
-            } else if f32_value < i16::MIN as f32 {
-                value = i16::MIN;
-            } else {
-                value = f32_value as i16;
-            }
+    pub fn resume(&self) {
+        self.device.resume();
+    }
 
-            // Set volume
-            *dst = value;
-            // Record current play position
-            self.pos += 1;
+    /// Apply new audio processing settings, the filters are built here
+    /// and swapped into the callback, which is blocked only for the swap
+    pub fn update_dsp(&mut self, settings: &DspSettings) {
+        if self.dsp_settings == *settings {
+            return;
         }
+
+        let spec = self.device.spec();
+        let mut update = DspUpdate::new(spec.freq, spec.channels, &self.dsp_settings, settings);
+        self.device.lock().apply_dsp(&mut update);
+        self.dsp_settings = settings.clone();
+        // The replaced filters are dropped here, out of the callback
+    }
+
+    pub fn device_name(&self) -> Option<&str> {
+        self.device_name.as_deref()
     }
+
+    /// A disconnected device is stopped by sdl, while paused or playing otherwise
+    pub fn is_lost(&self) -> bool {
+        self.device.status() == AudioStatus::Stopped
+    }
+}
+
+/// Names of the available playback devices
+pub fn playback_devices(sys: &AudioSubsystem) -> Vec<String> {
+    let count = sys.num_audio_playback_devices().unwrap_or(0);
+    (0..count)
+        .filter_map(|index| sys.audio_playback_device_name(index).ok())
+        .collect()
 }
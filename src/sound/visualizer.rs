@@ -0,0 +1,177 @@
+use std::f32::consts::PI;
+
+/// Number of mono samples analyzed for one picture, must be a power of two
+pub const WINDOW_SIZE: usize = 2048;
+/// Levels under this are shown as nothing, unit: dBFS
+const MIN_DB: f32 = -80.0;
+/// The lowest frequency shown in the spectrum, unit: Hz
+const MIN_FREQUENCY: f32 = 40.0;
+
+/// How the played audio is visualized
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VisualizerMode {
+    #[default]
+    Spectrum,
+    Waveform,
+}
+
+impl VisualizerMode {
+    pub fn next(&self) -> Self {
+        match self {
+            VisualizerMode::Spectrum => VisualizerMode::Waveform,
+            VisualizerMode::Waveform => VisualizerMode::Spectrum,
+        }
+    }
+}
+
+/// Compute the spectrum of mono samples.
+/// # Arguments
+/// * `samples`: The latest played samples, only the last `WINDOW_SIZE` ones are used
+/// * `sample_rate`: Sample rate of `samples`
+/// * `bands`: Number of bands, they are spaced logarithmically
+/// # Returns
+/// Level of every band in range `0.0..=1.0`
+pub fn spectrum(samples: &[f32], sample_rate: i32, bands: usize) -> Vec<f32> {
+    if bands == 0 || sample_rate <= 0 {
+        return vec![];
+    }
+
+    // Hann windowed, zero padded if there are not enough samples
+    let start = samples.len().saturating_sub(WINDOW_SIZE);
+    let mut re = vec![0f32; WINDOW_SIZE];
+    let mut im = vec![0f32; WINDOW_SIZE];
+    for (i, sample) in samples[start..].iter().enumerate() {
+        let window = 0.5 - 0.5 * (2.0 * PI * i as f32 / (WINDOW_SIZE - 1) as f32).cos();
+        re[i] = sample * window;
+    }
+    fft(&mut re, &mut im);
+
+    // Magnitudes of positive frequencies, scaled so that a full scale sine reads 0dB
+    let bins = WINDOW_SIZE / 2;
+    let scale = 4.0 / WINDOW_SIZE as f32;
+    let magnitudes: Vec<f32> = (0..bins)
+        .map(|i| (re[i] * re[i] + im[i] * im[i]).sqrt() * scale)
+        .collect();
+
+    let nyquist = sample_rate as f32 / 2.0;
+    let bin_width = nyquist / bins as f32;
+    let ratio = (nyquist / MIN_FREQUENCY).powf(1.0 / bands as f32);
+    (0..bands)
+        .map(|band| {
+            let low = MIN_FREQUENCY * ratio.powi(band as i32);
+            let high = low * ratio;
+            let first = ((low / bin_width) as usize).min(bins - 1);
+            let last = ((high / bin_width).ceil() as usize).clamp(first + 1, bins);
+
+            let peak = magnitudes[first..last].iter().fold(0f32, |a, b| a.max(*b));
+            let db = 20.0 * peak.max(1e-9).log10();
+            ((db - MIN_DB) / -MIN_DB).clamp(0.0, 1.0)
+        })
+        .collect()
+}
+
+/// Reduce mono samples to `points` values for drawing, each one is the peak of its part
+/// with sign, in range `-1.0..=1.0`
+pub fn waveform(samples: &[f32], points: usize) -> Vec<f32> {
+    if points == 0 || samples.is_empty() {
+        return vec![0.0; points];
+    }
+
+    let start = samples.len().saturating_sub(WINDOW_SIZE);
+    let samples = &samples[start..];
+    (0..points)
+        .map(|point| {
+            let first = point * samples.len() / points;
+            let last = ((point + 1) * samples.len() / points).max(first + 1);
+            samples[first..last.min(samples.len())]
+                .iter()
+                .fold(0f32, |peak, s| if s.abs() > peak.abs() { *s } else { peak })
+                .clamp(-1.0, 1.0)
+        })
+        .collect()
+}
+
+/// In-place iterative radix-2 FFT, the length must be a power of two
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    debug_assert!(n.is_power_of_two() && im.len() == n);
+
+    // Bit reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let a = start + k;
+                let b = a + len / 2;
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sound::dsp::tests::sine_wave;
+
+    use super::*;
+
+    /// Seconds of one window at 48kHz
+    const WINDOW_SECS: f64 = WINDOW_SIZE as f64 / 48000.0;
+
+    #[test]
+    fn test_spectrum_peak() {
+        let bands = spectrum(&sine_wave(1000.0, 48000, 1, 1.0, WINDOW_SECS), 48000, 32);
+        let loudest = bands
+            .iter()
+            .enumerate()
+            .fold(0, |max, (i, v)| if *v > bands[max] { i } else { max });
+
+        // The band containing 1kHz is the loudest one and reads near full scale
+        let ratio = (24000f32 / MIN_FREQUENCY).powf(1.0 / 32.0);
+        let expected = ((1000.0 / MIN_FREQUENCY).ln() / ratio.ln()) as usize;
+        assert_eq!(expected, loudest);
+        assert!(bands[loudest] > 0.9);
+        // Far away bands are much quieter
+        assert!(bands[0] < 0.5);
+        assert!(bands[31] < 0.5);
+    }
+
+    #[test]
+    fn test_spectrum_silence() {
+        let bands = spectrum(&[0.0; 100], 48000, 16);
+        assert_eq!(16, bands.len());
+        assert!(bands.iter().all(|v| *v == 0.0));
+    }
+
+    #[test]
+    fn test_waveform() {
+        let points = waveform(&sine_wave(100.0, 48000, 1, 0.5, WINDOW_SECS), 64);
+        assert_eq!(64, points.len());
+        assert!(points.iter().all(|p| p.abs() <= 0.5));
+        assert!(points.iter().any(|p| *p > 0.4));
+        assert!(points.iter().any(|p| *p < -0.4));
+        assert_eq!(vec![0.0; 8], waveform(&[], 8));
+    }
+}
@@ -0,0 +1,134 @@
+use std::sync::atomic::Ordering;
+
+use crate::global::{MAX_GAIN_DB, MUTED, VOLUME};
+
+/// Gain changes are spread over this duration to avoid clicks, unit: milliseconds
+const RAMP_MILLIS: u32 = 30;
+/// Samples above this level are compressed softly by the limiter
+const LIMITER_THRESHOLD: f32 = 0.9;
+/// Gain of the lowest audible volume, only 0% is silent
+const MIN_GAIN_DB: f32 = -40.0;
+
+/// Convert the volume percent to linear gain with a perceptual curve.
+/// The cube root of gain goes linearly from `MIN_GAIN_DB` at 0% to unity gain at 100%,
+/// so 10% is about -32dB and 50% about -13dB. The amplification is capped at `MAX_GAIN_DB`
+pub fn volume_to_gain(percent: i16) -> f32 {
+    if percent <= 0 {
+        return 0.0;
+    }
+
+    let floor = 10f32.powf(MIN_GAIN_DB / 60.0);
+    let root = floor + (1.0 - floor) * percent as f32 / 100.0;
+    root.powi(3).min(10f32.powf(MAX_GAIN_DB / 20.0))
+}
+
+/// The gain that output should reach according to global volume and mute state
+pub fn target_gain() -> f32 {
+    if MUTED.load(Ordering::Acquire) {
+        return 0.0;
+    }
+
+    volume_to_gain(VOLUME.load(Ordering::Acquire))
+}
+
+/// Compress the samples above `LIMITER_THRESHOLD` so that the output never clips
+#[inline]
+pub fn soft_limit(sample: f32) -> f32 {
+    let level = sample.abs();
+    if level <= LIMITER_THRESHOLD {
+        return sample;
+    }
+
+    let headroom = 1.0 - LIMITER_THRESHOLD;
+    let limited = LIMITER_THRESHOLD + headroom * ((level - LIMITER_THRESHOLD) / headroom).tanh();
+    limited.copysign(sample)
+}
+
+/// Apply gain to the output samples, the gain moves towards the target smoothly.
+/// The limiter works only while amplifying, samples are untouched at unity gain
+pub struct GainRamp {
+    current: f32,
+    /// The maximum gain change per sample
+    step: f32,
+}
+
+impl GainRamp {
+    /// `sample_rate` and `channels` are the spec of output device
+    pub fn new(sample_rate: i32, channels: u8, initial: f32) -> Self {
+        let ramp_samples =
+            (sample_rate.max(1) as u32 * channels.max(1) as u32 * RAMP_MILLIS / 1000).max(1);
+        // Full scale change from 0 to unity gain takes `RAMP_MILLIS`
+        Self {
+            current: initial,
+            step: 1.0 / ramp_samples as f32,
+        }
+    }
+
+    pub fn apply(&mut self, samples: &mut [f32], target: f32) {
+        for sample in samples.iter_mut() {
+            if self.current < target {
+                self.current = (self.current + self.step).min(target);
+            } else if self.current > target {
+                self.current = (self.current - self.step).max(target);
+            }
+
+            *sample *= self.current;
+            if self.current > 1.0 {
+                *sample = soft_limit(*sample);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_volume_to_gain() {
+        let db = |percent: i16| 20.0 * volume_to_gain(percent).log10();
+        assert_eq!(0.0, volume_to_gain(0));
+        assert!((volume_to_gain(100) - 1.0).abs() < 1e-6);
+        // Low volumes are still audible
+        assert!(db(1) > MIN_GAIN_DB && db(1) < MIN_GAIN_DB + 1.0);
+        assert!((db(10) + 31.9).abs() < 0.1);
+        assert!((db(50) + 13.0).abs() < 0.1);
+        assert!((0..100).all(|p| volume_to_gain(p) < volume_to_gain(p + 1)));
+        // Amplification is capped
+        assert!(volume_to_gain(i16::MAX) <= 10f32.powf(MAX_GAIN_DB / 20.0) + 1e-6);
+    }
+
+    #[test]
+    fn test_soft_limit() {
+        assert_eq!(0.5, soft_limit(0.5));
+        assert_eq!(-0.5, soft_limit(-0.5));
+        assert!(soft_limit(10.0) <= 1.0);
+        assert!(soft_limit(-10.0) >= -1.0);
+        assert!(soft_limit(1.0) < 1.0);
+        assert!(soft_limit(0.95) > soft_limit(0.92));
+    }
+
+    #[test]
+    fn test_gain_ramp() {
+        let mut ramp = GainRamp::new(1000, 1, 0.0);
+        let mut samples = vec![1.0; 10];
+        ramp.apply(&mut samples, 1.0);
+        // 30 samples are needed to reach the target, the gain increases gradually
+        assert!(samples.windows(2).all(|w| w[0] < w[1]));
+        assert!(samples[9] < 0.5);
+
+        let mut samples = vec![0.5; 100];
+        ramp.apply(&mut samples, 1.0);
+        assert_eq!(0.5, samples[99]);
+
+        // No limiter at unity gain
+        let mut samples = vec![0.95; 10];
+        ramp.apply(&mut samples, 1.0);
+        assert_eq!(vec![0.95; 10], samples);
+
+        let mut ramp = GainRamp::new(1000, 1, 2.0);
+        let mut samples = vec![0.95; 10];
+        ramp.apply(&mut samples, 2.0);
+        assert!(samples.iter().all(|&sample| sample > 0.95 && sample <= 1.0));
+    }
+}
@@ -4,7 +4,9 @@
 /// once to avaoid multiple mut borrow at the same time.
 pub mod components;
 pub mod foundation;
+pub mod renderer;
 pub mod start_window;
+pub mod texture_cache;
 pub mod video_window;
 
 use std::{
@@ -19,7 +21,7 @@ use sdl2::{
     video::{Window, WindowPos},
 };
 
-use crate::util::error::SuperError;
+use crate::util::error::PlayerError;
 
 pub trait TControl {
     /// Handle mouse botton down event
@@ -27,36 +29,36 @@ pub trait TControl {
     /// * `bool` - true if the event is handled, false if the event is rejected.
     /// The reason for rejection may be the cursor is not in the area of current control,
     /// or other reasons.
-    /// * `SuperError` - the error information
-    fn on_mouse_down(&mut self, params: &MouseDownParam) -> Result<bool, SuperError>;
+    /// * `PlayerError` - the error information
+    fn on_mouse_down(&mut self, params: &MouseDownParam) -> Result<bool, PlayerError>;
     /// Handle mouse botton up event
     /// # Returns
     /// * `bool` - true if the event is handled, false if the event is rejected.
     /// The reason for rejection may be the cursor is not in the area of current control,
     /// or other reasons.
-    /// * `SuperError` - the error information
-    fn on_mouse_up(&mut self, params: &MouseUpParam) -> Result<bool, SuperError>;
+    /// * `PlayerError` - the error information
+    fn on_mouse_up(&mut self, params: &MouseUpParam) -> Result<bool, PlayerError>;
     /// Handle mouse move event
     /// # Returns
     /// * `bool` - true if the event is handled, false if the event is rejected.
     /// The reason for rejection may be the cursor is not in the area of current control,
     /// or other reasons.
-    /// * `SuperError` - the error information
-    fn on_mouse_motion(&mut self, params: &MouseMotionParam) -> Result<bool, SuperError>;
+    /// * `PlayerError` - the error information
+    fn on_mouse_motion(&mut self, params: &MouseMotionParam) -> Result<bool, PlayerError>;
     /// Handle mouse wheel event
     /// # Returns
     /// * `bool` - true if the event is handled, false if the event is rejected.
     /// The reason for rejection may be the cursor is not in the area of current control,
     /// or other reasons.
-    /// * `SuperError` - the error information
-    fn on_mouse_wheel(&mut self, params: &MouseWheelParam) -> Result<bool, SuperError>;
+    /// * `PlayerError` - the error information
+    fn on_mouse_wheel(&mut self, params: &MouseWheelParam) -> Result<bool, PlayerError>;
     /// Handle control resized event
     /// # Returns
     /// * `bool` - true if the event is handled, false if the event is rejected.
     /// The reason for rejection may be the cursor is not in the area of current control,
     /// or other reasons.
-    /// * `SuperError` - the error information
-    fn on_resized(&mut self, width: u32, height: u32) -> Result<bool, SuperError>;
+    /// * `PlayerError` - the error information
+    fn on_resized(&mut self, width: u32, height: u32) -> Result<bool, PlayerError>;
     /// Set the size of current control
     /// # Arguments
     /// * `width` - the new width of the control
@@ -72,11 +74,11 @@ pub trait TControl {
     /// * `bool` - true if the event is handled, false if the event is rejected.
     /// The reason for rejection may be the cursor is not in the area of current control,
     /// or other reasons.
-    /// * `SuperError` - the error information
+    /// * `PlayerError` - the error information
     /// # Warning
     /// * This function is not implemented yet,
     /// any one want to call this function should overwrite it with your own implementation
-    fn render(&mut self) -> Result<bool, SuperError>;
+    fn render(&mut self) -> Result<bool, PlayerError>;
     /// Get the position of current control
     /// # Returns
     /// * `i32` - the x position of control
@@ -152,7 +154,7 @@ impl RectangleControl {
         width: u32,
         height: u32,
         canvas: Rc<RefCell<Canvas<Window>>>,
-    ) -> Result<Self, SuperError> {
+    ) -> Result<Self, PlayerError> {
         let center_x = x + width as i32 / 2;
         let center_y = y + height as i32 / 2;
 
@@ -168,7 +170,7 @@ impl RectangleControl {
 }
 
 impl TControl for RectangleControl {
-    fn on_mouse_down(&mut self, params: &MouseDownParam) -> Result<bool, SuperError> {
+    fn on_mouse_down(&mut self, params: &MouseDownParam) -> Result<bool, PlayerError> {
         if !self.is_in_area(params.x, params.y) {
             return Ok(false);
         }
@@ -176,7 +178,7 @@ impl TControl for RectangleControl {
         Ok(true)
     }
 
-    fn on_mouse_up(&mut self, params: &MouseUpParam) -> Result<bool, SuperError> {
+    fn on_mouse_up(&mut self, params: &MouseUpParam) -> Result<bool, PlayerError> {
         if !self.is_in_area(params.x, params.y) {
             return Ok(false);
         }
@@ -184,7 +186,7 @@ impl TControl for RectangleControl {
         Ok(true)
     }
 
-    fn on_mouse_motion(&mut self, params: &MouseMotionParam) -> Result<bool, SuperError> {
+    fn on_mouse_motion(&mut self, params: &MouseMotionParam) -> Result<bool, PlayerError> {
         if !self.is_in_area(params.x, params.y) {
             return Ok(false);
         }
@@ -192,7 +194,7 @@ impl TControl for RectangleControl {
         Ok(true)
     }
 
-    fn on_mouse_wheel(&mut self, params: &MouseWheelParam) -> Result<bool, SuperError> {
+    fn on_mouse_wheel(&mut self, params: &MouseWheelParam) -> Result<bool, PlayerError> {
         if !self.is_in_area(params.x, params.y) {
             return Ok(false);
         }
@@ -200,8 +202,8 @@ impl TControl for RectangleControl {
         Ok(true)
     }
 
-    fn on_resized(&mut self, _width: u32, _height: u32) -> Result<bool, SuperError> {
-        todo!()
+    fn on_resized(&mut self, _width: u32, _height: u32) -> Result<bool, PlayerError> {
+        Ok(false)
     }
 
     fn set_size(&mut self, width: u32, height: u32) {
@@ -229,8 +231,8 @@ impl TControl for RectangleControl {
         );
     }
 
-    fn render(&mut self) -> Result<bool, SuperError> {
-        todo!()
+    fn render(&mut self) -> Result<bool, PlayerError> {
+        Ok(false)
     }
 
     fn get_position(&self) -> (i32, i32) {
@@ -285,7 +287,7 @@ impl Button {
         width: u32,
         height: u32,
         canvas: Rc<RefCell<Canvas<Window>>>,
-    ) -> Result<Self, SuperError> {
+    ) -> Result<Self, PlayerError> {
         let inner = RectangleControl::new(x, y, width, height, canvas)?;
 
         Ok(Self { inner })
@@ -355,19 +357,19 @@ impl TCircularControl for CircularControl {
 }
 
 impl TControl for CircularControl {
-    fn on_mouse_down(&mut self, _params: &MouseDownParam) -> Result<bool, SuperError> {
+    fn on_mouse_down(&mut self, _params: &MouseDownParam) -> Result<bool, PlayerError> {
         todo!()
     }
 
-    fn on_mouse_up(&mut self, _params: &MouseUpParam) -> Result<bool, SuperError> {
+    fn on_mouse_up(&mut self, _params: &MouseUpParam) -> Result<bool, PlayerError> {
         todo!()
     }
 
-    fn on_mouse_motion(&mut self, _params: &MouseMotionParam) -> Result<bool, SuperError> {
+    fn on_mouse_motion(&mut self, _params: &MouseMotionParam) -> Result<bool, PlayerError> {
         todo!()
     }
 
-    fn on_mouse_wheel(&mut self, _params: &MouseWheelParam) -> Result<bool, SuperError> {
+    fn on_mouse_wheel(&mut self, _params: &MouseWheelParam) -> Result<bool, PlayerError> {
         todo!()
     }
 
@@ -379,7 +381,7 @@ impl TControl for CircularControl {
         todo!()
     }
 
-    fn render(&mut self) -> Result<bool, SuperError> {
+    fn render(&mut self) -> Result<bool, PlayerError> {
         todo!()
     }
 
@@ -395,7 +397,7 @@ impl TControl for CircularControl {
         todo!()
     }
 
-    fn on_resized(&mut self, width: u32, height: u32) -> Result<bool, SuperError> {
+    fn on_resized(&mut self, width: u32, height: u32) -> Result<bool, PlayerError> {
         todo!()
     }
 }
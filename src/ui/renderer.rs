@@ -0,0 +1,186 @@
+use std::str::FromStr;
+
+use log::info;
+use sdl2::{
+    hint::{self, Hint},
+    render::{self, Canvas},
+    video::{Window, WindowBuilder},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{global::RENDERER_OPTIONS, util::error::PlayerError};
+
+/// Render drivers of SDL which can be chosen
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RendererBackend {
+    /// The one SDL prefers, accelerated ones come first
+    #[default]
+    Auto,
+    /// Draws on CPU, it works without GPU and with the `dummy` video driver
+    Software,
+    OpenGl,
+    OpenGles2,
+}
+
+impl RendererBackend {
+    /// Name of the render driver in SDL, `None` lets SDL choose
+    fn driver_name(self) -> Option<&'static str> {
+        match self {
+            RendererBackend::Auto => None,
+            RendererBackend::Software => Some("software"),
+            RendererBackend::OpenGl => Some("opengl"),
+            RendererBackend::OpenGles2 => Some("opengles2"),
+        }
+    }
+}
+
+impl FromStr for RendererBackend {
+    type Err = PlayerError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "auto" => Ok(RendererBackend::Auto),
+            "software" => Ok(RendererBackend::Software),
+            "opengl" => Ok(RendererBackend::OpenGl),
+            "opengles2" => Ok(RendererBackend::OpenGles2),
+            _ => Err(PlayerError::InvalidArgument(format!(
+                "Unknown renderer: {}, it should be auto, software, opengl or opengles2",
+                name
+            ))),
+        }
+    }
+}
+
+/// How the windows are rendered, it takes effect on the windows created from now on
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(default)]
+pub struct RendererOptions {
+    pub backend: RendererBackend,
+    /// Wait for the vertical blank of the display when presenting
+    pub vsync: bool,
+    /// Video driver of SDL, e.g. `x11`, `wayland`, or `dummy` and `offscreen` for automated
+    /// tests. It's chosen by SDL if it's not set
+    pub video_driver: Option<String>,
+}
+
+impl RendererOptions {
+    pub fn apply(&mut self, overrides: RendererOverrides) {
+        if let Some(backend) = overrides.backend {
+            self.backend = backend;
+        }
+        if let Some(vsync) = overrides.vsync {
+            self.vsync = vsync;
+        }
+        if let Some(driver) = overrides.video_driver {
+            self.video_driver = Some(driver);
+        }
+    }
+
+    /// It must be called before the video subsystem is initialized,
+    /// the chosen driver overrides the `SDL_VIDEODRIVER` environment variable
+    pub fn set_video_driver(&self) {
+        if let Some(driver) = &self.video_driver {
+            hint::set_with_priority("SDL_VIDEODRIVER", driver, &Hint::Override);
+        }
+    }
+}
+
+/// Renderer options given on command line, they override the config
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RendererOverrides {
+    pub backend: Option<RendererBackend>,
+    pub vsync: Option<bool>,
+    pub video_driver: Option<String>,
+}
+
+/// Create the window with the flags which the backend needs
+pub fn build_window(builder: &mut WindowBuilder) -> Result<Window, PlayerError> {
+    let backend = RENDERER_OPTIONS.read().unwrap().backend;
+    // OpenGL is asked for only if it's chosen, it fails on machines without GPU
+    if matches!(
+        backend,
+        RendererBackend::OpenGl | RendererBackend::OpenGles2
+    ) {
+        builder.opengl();
+    }
+
+    Ok(builder.build()?)
+}
+
+/// Create the canvas of the window on the chosen backend
+pub fn build_canvas(window: Window) -> Result<Canvas<Window>, PlayerError> {
+    let options = RENDERER_OPTIONS.read().unwrap().clone();
+    let mut builder = window.into_canvas();
+    if let Some(name) = options.backend.driver_name() {
+        let index = render::drivers()
+            .position(|driver| driver.name == name)
+            .ok_or_else(|| PlayerError::Unsupported(format!("renderer {}", name)))?;
+        builder = builder.index(index as u32);
+    }
+    if options.vsync {
+        builder = builder.present_vsync();
+    }
+
+    let canvas = builder.build()?;
+    info!("Renderer: {}", canvas.info().name);
+
+    Ok(canvas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backend_from_str() {
+        assert_eq!(RendererBackend::Software, "software".parse().unwrap());
+        assert_eq!(RendererBackend::OpenGles2, "opengles2".parse().unwrap());
+        assert!("vulkan".parse::<RendererBackend>().is_err());
+    }
+
+    #[test]
+    fn test_options() {
+        let mut options: RendererOptions =
+            serde_yaml::from_str("backend: opengl\nvsync: true").unwrap();
+        assert_eq!(RendererBackend::OpenGl, options.backend);
+        assert!(options.vsync);
+        assert_eq!(None, options.video_driver);
+
+        options.apply(RendererOverrides {
+            backend: Some(RendererBackend::Software),
+            vsync: None,
+            video_driver: Some("dummy".to_string()),
+        });
+        assert_eq!(RendererBackend::Software, options.backend);
+        assert!(options.vsync);
+        assert_eq!(Some("dummy".to_string()), options.video_driver);
+    }
+
+    /// The video driver hint and `RENDERER_OPTIONS` are process wide,
+    /// and SDL is initialized in a test thread, so run it alone:
+    /// `cargo test test_dummy_video_driver -- --ignored`
+    #[test]
+    #[ignore]
+    fn test_dummy_video_driver() {
+        let options = RendererOptions {
+            backend: RendererBackend::Software,
+            vsync: false,
+            video_driver: Some("dummy".to_string()),
+        };
+        options.set_video_driver();
+        let previous = std::mem::replace(&mut *RENDERER_OPTIONS.write().unwrap(), options);
+
+        let sdl = sdl2::init().unwrap();
+        let video = sdl.video().unwrap();
+        assert_eq!("dummy", video.current_video_driver());
+
+        let window = build_window(video.window("test", 64, 48).hidden()).unwrap();
+        let mut canvas = build_canvas(window).unwrap();
+        assert_eq!("software", canvas.info().name);
+        canvas.clear();
+        canvas.present();
+
+        *RENDERER_OPTIONS.write().unwrap() = previous;
+    }
+}
@@ -1,5 +1,6 @@
 mod play_button;
 mod titlebar;
+pub mod url_entry;
 
 use std::{
     cell::RefCell,
@@ -17,12 +18,13 @@ use sdl2::{
 };
 
 use crate::global::{APP_NAME, LOGO_PATH};
-use crate::util::error::SuperError;
+use crate::util::error::PlayerError;
 
-use self::{play_button::PlayButton, titlebar::TitleBar};
+use self::{play_button::PlayButton, titlebar::TitleBar, url_entry::UrlEntry};
 
 use super::{
-    MouseDownParam, MouseMotionParam, MouseUpParam, MouseWheelParam, RectangleControl, TControl,
+    renderer, MouseDownParam, MouseMotionParam, MouseUpParam, MouseWheelParam, RectangleControl,
+    TControl,
 };
 
 pub struct StartWindow {
@@ -30,10 +32,11 @@ pub struct StartWindow {
     inner: RectangleControl,
     title_bar: TitleBar,
     play_button: PlayButton,
+    url_entry: UrlEntry,
 }
 
 impl StartWindow {
-    pub fn new(sys: &VideoSubsystem) -> Result<Self, SuperError> {
+    pub fn new(sys: &VideoSubsystem) -> Result<Self, PlayerError> {
         let wind = Self::prepare_window(sys)?;
         let window_id = wind.id();
         let (x, y) = wind.position();
@@ -44,12 +47,15 @@ impl StartWindow {
         let play_button = PlayButton::default(canvas.clone())?
             .with_size(100, 100)
             .with_position(width as i32 / 2 - 50, height as i32 / 2 - 50);
+        // The entry covers the bottom of window
+        let url_entry = UrlEntry::new(0, height as i32 - 24, width, 24, canvas.clone())?;
 
         let mut inst = Self {
             id: window_id,
             inner: RectangleControl::new(x, y, width, height, canvas)?,
             title_bar: TitleBar,
             play_button,
+            url_entry,
         };
 
         inst.render()?;
@@ -66,15 +72,14 @@ impl StartWindow {
         self.canvas.borrow_mut().window_mut().hide();
     }
 
-    fn prepare_window(sys: &VideoSubsystem) -> Result<Window, SuperError> {
-        let mut wind = sys
-            .window("NT Player", 200, 200)
-            .borderless()
-            .allow_highdpi()
-            .position_centered()
-            .resizable()
-            .opengl()
-            .build()?;
+    fn prepare_window(sys: &VideoSubsystem) -> Result<Window, PlayerError> {
+        let mut wind = renderer::build_window(
+            sys.window("NT Player", 200, 200)
+                .borderless()
+                .allow_highdpi()
+                .position_centered()
+                .resizable(),
+        )?;
 
         wind.set_title(APP_NAME)?;
 
@@ -84,8 +89,8 @@ impl StartWindow {
         Ok(wind)
     }
 
-    fn prepare_canvas(wind: Window) -> Result<Canvas<Window>, SuperError> {
-        let mut canvas = wind.into_canvas().build()?;
+    fn prepare_canvas(wind: Window) -> Result<Canvas<Window>, PlayerError> {
+        let mut canvas = renderer::build_canvas(wind)?;
         canvas.set_draw_color(Color::RGB(200, 200, 200));
         canvas.set_blend_mode(BlendMode::Blend);
         canvas.clear();
@@ -94,14 +99,14 @@ impl StartWindow {
         Ok(canvas)
     }
 
-    pub fn on_mouse_down(&mut self, params: &MouseDownParam) -> Result<bool, SuperError> {
+    pub fn on_mouse_down(&mut self, params: &MouseDownParam) -> Result<bool, PlayerError> {
         if params.window_id != self.id {
             return Ok(false);
         }
         Ok(true)
     }
 
-    pub fn on_mouse_up(&mut self, params: &MouseUpParam) -> Result<bool, SuperError> {
+    pub fn on_mouse_up(&mut self, params: &MouseUpParam) -> Result<bool, PlayerError> {
         if params.window_id != self.id {
             return Ok(false);
         }
@@ -111,7 +116,7 @@ impl StartWindow {
         Ok(true)
     }
 
-    pub fn on_mouse_motion(&mut self, params: &MouseMotionParam) -> Result<bool, SuperError> {
+    pub fn on_mouse_motion(&mut self, params: &MouseMotionParam) -> Result<bool, PlayerError> {
         if params.window_id != self.id {
             return Ok(true);
         }
@@ -121,20 +126,33 @@ impl StartWindow {
         Ok(true)
     }
 
-    pub fn on_mouse_wheel(&mut self, params: &MouseWheelParam) -> Result<bool, SuperError> {
+    pub fn on_mouse_wheel(&mut self, params: &MouseWheelParam) -> Result<bool, PlayerError> {
         if params.window_id != self.id {
             return Ok(false);
         }
         Ok(true)
     }
 
+    pub fn is_editing_url(&self) -> bool {
+        self.url_entry.is_editing()
+    }
+
+    /// Change the URL entry and show it
+    pub fn edit_url<T>(&mut self, edit: impl FnOnce(&mut UrlEntry) -> T) -> Result<T, PlayerError> {
+        let result = edit(&mut self.url_entry);
+        self.render()?;
+
+        Ok(result)
+    }
+
     pub fn set_size(&mut self, width: u32, height: u32) {}
 
     pub fn set_position(&mut self, x: WindowPos, y: WindowPos) {}
 
-    pub fn render(&mut self) -> Result<bool, SuperError> {
+    pub fn render(&mut self) -> Result<bool, PlayerError> {
         // Render content
         self.play_button.render()?;
+        self.url_entry.render()?;
 
         // Display on screen
         let canvas = self.canvas.clone();
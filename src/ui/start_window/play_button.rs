@@ -16,8 +16,9 @@ use sdl2::{
 use crate::{
     entity::EventMessage,
     global::EVENT_CHANNEL,
+    media::{playlist::Playlist, source::MediaSource},
     ui::{Button, MouseUpParam, TControl},
-    util::error::{safe_send, SuperError},
+    util::error::{safe_send, PlayerError},
 };
 
 pub struct PlayButton {
@@ -25,7 +26,7 @@ pub struct PlayButton {
 }
 
 impl PlayButton {
-    pub fn default(canvas: Rc<RefCell<Canvas<Window>>>) -> Result<Self, SuperError> {
+    pub fn default(canvas: Rc<RefCell<Canvas<Window>>>) -> Result<Self, PlayerError> {
         Self::new(0, 0, 50, 30, canvas)
     }
 
@@ -35,7 +36,7 @@ impl PlayButton {
         width: u32,
         height: u32,
         canvas: Rc<RefCell<Canvas<Window>>>,
-    ) -> Result<Self, SuperError> {
+    ) -> Result<Self, PlayerError> {
         let inner = Button::new(x, y, width, height, canvas)?;
 
         Ok(Self { inner })
@@ -56,7 +57,7 @@ impl PlayButton {
         self
     }
 
-    pub fn on_mouse_up(&mut self, params: &MouseUpParam) -> Result<bool, SuperError> {
+    pub fn on_mouse_up(&mut self, params: &MouseUpParam) -> Result<bool, PlayerError> {
         if !self.inner.on_mouse_up(params)? {
             return Ok(false);
         }
@@ -66,7 +67,15 @@ impl PlayButton {
                 // Play button is clicked, open file
                 let path = rfd::FileDialog::new().pick_file();
                 if let Some(path) = path {
-                    safe_send(EVENT_CHANNEL.0.send(EventMessage::FileOpened(path)));
+                    let source = MediaSource::File(path);
+                    safe_send(EVENT_CHANNEL.0.send(EventMessage::SourceOpened(source)));
+                }
+            }
+            MouseButton::Right => {
+                // Open a directory, its media files are played one after another
+                if let Some(dir) = rfd::FileDialog::new().pick_folder() {
+                    let files = Playlist::media_files(&dir)?;
+                    safe_send(EVENT_CHANNEL.0.send(EventMessage::DirOpened(files)));
                 }
             }
             _ => {}
@@ -75,7 +84,7 @@ impl PlayButton {
         Ok(true)
     }
 
-    pub fn render(&mut self) -> Result<(), SuperError> {
+    pub fn render(&mut self) -> Result<(), PlayerError> {
         let mut canvas_mut = self.canvas.borrow_mut();
         let texture_creator = canvas_mut.texture_creator();
         let sfs = Surface::from_file("./assets/play_black_circle.png")?;
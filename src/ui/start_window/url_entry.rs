@@ -0,0 +1,144 @@
+use std::{
+    cell::RefCell,
+    mem,
+    ops::{Deref, DerefMut},
+    rc::Rc,
+};
+
+use sdl2::{
+    gfx::primitives::DrawRenderer, pixels::Color, rect::Rect, render::Canvas, video::Window,
+};
+
+use crate::{ui::RectangleControl, util::error::PlayerError};
+
+/// Width and height of one character of the built-in font
+const CHAR_SIZE: i32 = 8;
+/// Space between the border and the text
+const PADDING: i32 = 4;
+const PROMPT: &str = "URL: ";
+/// Shown after the text where the typed characters go
+const CURSOR: char = '_';
+const BACKGROUND_COLOR: Color = Color::RGB(40, 40, 40);
+const TEXT_COLOR: Color = Color::RGB(220, 220, 220);
+
+/// One line field to type the URL or path of the media to open,
+/// it's shown over the start window while editing
+pub struct UrlEntry {
+    inner: RectangleControl,
+    text: String,
+    editing: bool,
+}
+
+impl UrlEntry {
+    pub fn new(
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        canvas: Rc<RefCell<Canvas<Window>>>,
+    ) -> Result<Self, PlayerError> {
+        Ok(Self {
+            inner: RectangleControl::new(x, y, width, height, canvas)?,
+            text: String::new(),
+            editing: false,
+        })
+    }
+
+    pub fn is_editing(&self) -> bool {
+        self.editing
+    }
+
+    /// Start editing with the text, e.g. the text of clipboard
+    pub fn begin(&mut self, text: &str) {
+        self.editing = true;
+        self.text.clear();
+        self.insert(text);
+    }
+
+    /// Append the typed or pasted text, line breaks and other control characters are dropped
+    pub fn insert(&mut self, text: &str) {
+        self.text.extend(text.chars().filter(|c| !c.is_control()));
+    }
+
+    pub fn backspace(&mut self) {
+        self.text.pop();
+    }
+
+    pub fn cancel(&mut self) {
+        self.editing = false;
+        self.text.clear();
+    }
+
+    /// Stop editing, returns the typed text
+    pub fn finish(&mut self) -> String {
+        self.editing = false;
+        mem::take(&mut self.text).trim().to_string()
+    }
+
+    pub fn render(&self) -> Result<(), PlayerError> {
+        if !self.editing {
+            return Ok(());
+        }
+
+        let mut canvas = self.canvas.borrow_mut();
+        canvas.set_draw_color(BACKGROUND_COLOR);
+        canvas.fill_rect(Rect::new(self.x, self.y, self.width, self.height))?;
+
+        let max_chars = (self.width as i32 - 2 * PADDING) / CHAR_SIZE;
+        let text = visible_text(&self.text, max_chars.max(0) as usize);
+        let y = self.y + (self.height as i32 - CHAR_SIZE) / 2;
+        canvas.string((self.x + PADDING) as i16, y as i16, &text, TEXT_COLOR)?;
+
+        Ok(())
+    }
+}
+
+/// The prompt, the end of the text and the cursor in `max_chars` characters,
+/// the beginning of a long text is cut off, since the end is where it's edited.
+/// The built-in font has ASCII characters only
+fn visible_text(text: &str, max_chars: usize) -> String {
+    let room = max_chars.saturating_sub(PROMPT.len() + 1);
+    let skipped = text.chars().count().saturating_sub(room);
+    let ascii = |c: char| if c.is_ascii() { c } else { '?' };
+    let tail = text.chars().skip(skipped).map(ascii);
+
+    PROMPT
+        .chars()
+        .chain(tail)
+        .chain([CURSOR])
+        .take(max_chars)
+        .collect()
+}
+
+impl Deref for UrlEntry {
+    type Target = RectangleControl;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl DerefMut for UrlEntry {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_visible_text() {
+        assert_eq!("URL: _", visible_text("", 20));
+        assert_eq!("URL: http://a/b.mp4_", visible_text("http://a/b.mp4", 20));
+        // The end of a long text is shown
+        assert_eq!(
+            "URL: /a/b/c.mkv_",
+            visible_text("rtsp://host/a/b/c.mkv", 16)
+        );
+        assert_eq!("URL: ?.mp4_", visible_text("\u{89c6}.mp4", 20));
+        // Too narrow for anything but a part of the prompt
+        assert_eq!("URL", visible_text("http://a", 3));
+    }
+}
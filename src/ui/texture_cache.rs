@@ -0,0 +1,107 @@
+use std::mem;
+
+use sdl2::{
+    render::{Canvas, Texture, TextureCreator},
+    video::{Window, WindowContext},
+};
+
+use crate::util::error::PlayerError;
+
+/// One texture of a canvas, it's reused until a texture of another key is asked for,
+/// e.g. frames of another size.
+///
+/// The cache owns a `TextureCreator` of the canvas, which keeps the renderer alive,
+/// so the texture is always destroyed before its renderer.
+pub struct TextureCache<K> {
+    /// Declared before `creator` since fields are dropped in order
+    texture: Option<(K, Texture<'static>)>,
+    creator: TextureCreator<WindowContext>,
+}
+
+impl<K: PartialEq> TextureCache<K> {
+    pub fn new(canvas: &Canvas<Window>) -> Self {
+        Self {
+            texture: None,
+            creator: canvas.texture_creator(),
+        }
+    }
+
+    /// The texture uploaded last, if any
+    pub fn get(&self) -> Option<&Texture<'_>> {
+        self.texture.as_ref().map(|(_, texture)| texture)
+    }
+
+    /// Upload pixels to the texture of `key`, the texture is made by `create`
+    /// if the cached one is of another key, which is destroyed then
+    pub fn upload<C, U>(&mut self, key: K, create: C, upload: U) -> Result<(), PlayerError>
+    where
+        C: FnOnce(&TextureCreator<WindowContext>) -> Result<Texture<'_>, PlayerError>,
+        U: FnOnce(&mut Texture<'_>) -> Result<(), PlayerError>,
+    {
+        if self.texture.as_ref().map_or(true, |(cached, _)| *cached != key) {
+            self.texture = None;
+            let texture = create(&self.creator)?;
+            // SAFETY: The texture borrows `creator`, which holds the renderer alive. The
+            // borrow is given up only because both are fields of the cache: `creator` is
+            // never replaced, and `texture` is declared before it so it's dropped first,
+            // also when the cache outlives the canvas. The texture is only lent out for
+            // the borrow of the cache, so it's never used after `creator` is gone.
+            let texture = unsafe { mem::transmute::<Texture<'_>, Texture<'static>>(texture) };
+            self.texture = Some((key, texture));
+        }
+
+        match self.texture.as_mut() {
+            Some((_, texture)) => upload(texture),
+            None => Ok(()),
+        }
+    }
+
+    /// Destroy the texture, e.g. the picture is gone
+    pub fn clear(&mut self) {
+        self.texture = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sdl2::{
+        hint::{self, Hint},
+        pixels::PixelFormatEnum,
+    };
+
+    use super::*;
+
+    /// The texture is destroyed by the cache after the window and its canvas are gone.
+    /// SDL is initialized in a test thread with the `dummy` video driver, so run it alone:
+    /// `cargo test test_drop_after_canvas -- --ignored`
+    #[test]
+    #[ignore]
+    fn test_drop_after_canvas() {
+        hint::set_with_priority("SDL_VIDEODRIVER", "dummy", &Hint::Override);
+        let sdl = sdl2::init().unwrap();
+        let video = sdl.video().unwrap();
+        let window = video.window("test", 64, 48).hidden().build().unwrap();
+        let mut canvas = Some(window.into_canvas().software().build().unwrap());
+
+        let mut cache = TextureCache::new(canvas.as_ref().unwrap());
+        for size in [16, 32] {
+            cache
+                .upload(
+                    size,
+                    |creator| {
+                        Ok(creator.create_texture_streaming(PixelFormatEnum::RGB24, size, size)?)
+                    },
+                    |texture| {
+                        texture.with_lock(None, |pixels, _| pixels.fill(128))?;
+                        Ok(())
+                    },
+                )
+                .unwrap();
+            // The next texture is made, and the old one destroyed, on the renderer
+            // kept by the cache
+            drop(canvas.take());
+        }
+        assert_eq!(32, cache.get().unwrap().query().width);
+        drop(cache);
+    }
+}
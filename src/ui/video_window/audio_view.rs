@@ -0,0 +1,226 @@
+use std::{
+    cell::RefCell,
+    ops::{Deref, DerefMut},
+    rc::Rc,
+};
+
+use log::warn;
+use sdl2::{
+    gfx::primitives::DrawRenderer, image::ImageRWops, pixels::Color, rect::Rect, render::Canvas,
+    rwops::RWops, video::Window,
+};
+
+use super::display_mode::{compute_layout, DisplayMode};
+use crate::{
+    global::{AUDIO_SUMMARY, PLAYED_SAMPLES},
+    media::metadata::MediaInfo,
+    sound::visualizer::{self, VisualizerMode},
+    ui::{texture_cache::TextureCache, RectangleControl},
+    util::error::PlayerError,
+};
+
+/// Number of bars of the spectrum
+const SPECTRUM_BANDS: usize = 48;
+/// How much of the previous level remains in a bar, makes the bars fall smoothly
+const BAR_DECAY: f32 = 0.85;
+/// Width and height of one character of the built-in font
+const CHAR_SIZE: i32 = 8;
+const TEXT_COLOR: Color = Color::RGB(220, 220, 220);
+const VISUALIZER_COLOR: Color = Color::RGB(51, 51, 255);
+
+/// Shown instead of video when the media has audio only:
+/// the cover art, tags of the media and a visualizer of the playing audio
+pub struct AudioView {
+    inner: RectangleControl,
+    info: MediaInfo,
+    cover: TextureCache<()>,
+    /// Indicate that the cover texture should be created from `info`
+    cover_dirty: bool,
+    mode: VisualizerMode,
+    /// Levels of the spectrum bars currently shown
+    levels: Vec<f32>,
+}
+
+impl AudioView {
+    pub fn new(
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        canvas: Rc<RefCell<Canvas<Window>>>,
+    ) -> Result<Self, PlayerError> {
+        Ok(Self {
+            cover: TextureCache::new(&canvas.borrow()),
+            inner: RectangleControl::new(x, y, width, height, canvas)?,
+            info: MediaInfo::default(),
+            cover_dirty: false,
+            mode: VisualizerMode::default(),
+            levels: vec![0.0; SPECTRUM_BANDS],
+        })
+    }
+
+    pub fn set_media_info(&mut self, info: MediaInfo) {
+        self.info = info;
+        self.cover_dirty = true;
+        self.levels.fill(0.0);
+    }
+
+    pub fn cycle_visualizer(&mut self) {
+        self.mode = self.mode.next();
+    }
+
+    pub fn render(&mut self) -> Result<bool, PlayerError> {
+        if self.cover_dirty {
+            self.cover_dirty = false;
+            self.cover.clear();
+            // Broken pictures are common in tags, play without cover then
+            if let Err(err) = self.create_cover() {
+                warn!("Failed to load cover art: {}", err);
+            }
+        }
+
+        let (x, y, width, height) = (self.x, self.y, self.width, self.height);
+        let cover_area = Rect::new(
+            x + width as i32 / 4,
+            y + height as i32 / 10,
+            (width / 2).max(1),
+            (height / 2).max(1),
+        );
+        let text_y = cover_area.bottom() + CHAR_SIZE * 2;
+        let visualizer_area = Rect::new(
+            x + width as i32 / 10,
+            text_y + CHAR_SIZE * 4,
+            (width * 4 / 5).max(1),
+            (height / 5).max(1),
+        );
+
+        self.render_cover(cover_area)?;
+        self.render_tags(text_y)?;
+        match self.mode {
+            VisualizerMode::Spectrum => self.render_spectrum(visualizer_area)?,
+            VisualizerMode::Waveform => self.render_waveform(visualizer_area)?,
+        }
+
+        Ok(true)
+    }
+
+    pub fn set_size(&mut self, width: u32, height: u32) {
+        self.inner.set_size(width, height);
+    }
+
+    fn create_cover(&mut self) -> Result<(), PlayerError> {
+        let data = match self.info.cover_art.as_ref() {
+            Some(data) => data,
+            None => return Ok(()),
+        };
+
+        let surface = RWops::from_bytes(data)?.load()?;
+        self.cover.upload(
+            (),
+            |creator| Ok(creator.create_texture_from_surface(&surface)?),
+            |_| Ok(()),
+        )
+    }
+
+    fn render_cover(&self, area: Rect) -> Result<(), PlayerError> {
+        let mut canvas = self.canvas.borrow_mut();
+        match self.cover.get() {
+            Some(cover) => {
+                let query = cover.query();
+                let layout =
+                    compute_layout(query.width, query.height, (1, 1), area, DisplayMode::Fit);
+                if let Some((src, dst)) = layout {
+                    canvas.copy(cover, src, dst)?;
+                }
+            }
+            None => {
+                // Placeholder of the missing cover
+                let side = area.width().min(area.height());
+                let placeholder = Rect::from_center(area.center(), side, side);
+                canvas.set_draw_color(Color::RGB(40, 40, 40));
+                canvas.fill_rect(placeholder)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn render_tags(&self, y: i32) -> Result<(), PlayerError> {
+        let lines = [self.info.display_title(), self.info.album.clone()];
+        let canvas = self.canvas.borrow();
+
+        for (i, line) in lines.iter().flatten().enumerate() {
+            // The built-in font has ASCII characters only
+            let text: String = line
+                .chars()
+                .map(|c| if c.is_ascii() { c } else { '?' })
+                .collect();
+            let text_width = text.len() as i32 * CHAR_SIZE;
+            let x = self.x + (self.width as i32 - text_width) / 2;
+            let line_y = y + i as i32 * CHAR_SIZE * 2;
+            canvas.string(x.max(self.x) as i16, line_y as i16, &text, TEXT_COLOR)?;
+        }
+
+        Ok(())
+    }
+
+    fn render_spectrum(&mut self, area: Rect) -> Result<(), PlayerError> {
+        let sample_rate = match AUDIO_SUMMARY.read().unwrap().as_ref() {
+            Some(summary) => summary.sample_rate,
+            None => return Ok(()),
+        };
+        let samples: Vec<f32> = PLAYED_SAMPLES.lock().unwrap().iter().copied().collect();
+        let bands = visualizer::spectrum(&samples, sample_rate, SPECTRUM_BANDS);
+        for (level, band) in self.levels.iter_mut().zip(bands) {
+            *level = band.max(*level * BAR_DECAY);
+        }
+
+        let mut canvas = self.canvas.borrow_mut();
+        canvas.set_draw_color(VISUALIZER_COLOR);
+        let slot = area.width() / SPECTRUM_BANDS as u32;
+        let bar_width = (slot * 3 / 4).max(1);
+        for (i, level) in self.levels.iter().enumerate() {
+            let bar_height = (area.height() as f32 * level) as u32;
+            if bar_height == 0 {
+                continue;
+            }
+
+            let bar_x = area.x() + (i as u32 * slot) as i32;
+            let bar_y = area.bottom() - bar_height as i32;
+            canvas.fill_rect(Rect::new(bar_x, bar_y, bar_width, bar_height))?;
+        }
+
+        Ok(())
+    }
+
+    fn render_waveform(&self, area: Rect) -> Result<(), PlayerError> {
+        let samples: Vec<f32> = PLAYED_SAMPLES.lock().unwrap().iter().copied().collect();
+        let points = visualizer::waveform(&samples, area.width() as usize);
+        let center_y = area.center().y();
+        let half_height = area.height() as f32 / 2.0;
+
+        let mut canvas = self.canvas.borrow_mut();
+        canvas.set_draw_color(VISUALIZER_COLOR);
+        for (i, point) in points.iter().enumerate() {
+            let x = area.x() + i as i32;
+            let y = center_y - (point * half_height) as i32;
+            canvas.draw_line((x, center_y), (x, y))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Deref for AudioView {
+    type Target = RectangleControl;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl DerefMut for AudioView {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
@@ -0,0 +1,347 @@
+use sdl2::rect::Rect;
+
+/// Minimum zoom factor of `DisplayMode::PanZoom`
+pub const MIN_ZOOM: f32 = 1.0;
+/// Maximum zoom factor of `DisplayMode::PanZoom`
+pub const MAX_ZOOM: f32 = 4.0;
+
+/// How the video frame is placed in the play box
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DisplayMode {
+    /// Show the whole frame with its aspect ratio, letterbox or pillarbox is added
+    #[default]
+    Fit,
+    /// Fill the whole box with its aspect ratio, the frame is cropped
+    Fill,
+    /// Fill the whole box, the aspect ratio is ignored
+    Stretch,
+    /// Show the whole frame with the forced display aspect ratio `width:height`
+    Ratio(u32, u32),
+    /// Zoom in the fitted frame, `pan_x` and `pan_y` are in range `-1.0..=1.0`,
+    /// which move the visible area from left/top to right/bottom
+    PanZoom { zoom: f32, pan_x: f32, pan_y: f32 },
+}
+
+impl DisplayMode {
+    /// The mode after current one when user cycles display modes
+    pub fn next(&self) -> Self {
+        match self {
+            DisplayMode::Fit => DisplayMode::Fill,
+            DisplayMode::Fill => DisplayMode::Stretch,
+            DisplayMode::Stretch => DisplayMode::Ratio(4, 3),
+            DisplayMode::Ratio(4, 3) => DisplayMode::Ratio(16, 9),
+            DisplayMode::Ratio(16, 9) => DisplayMode::Ratio(235, 100),
+            _ => DisplayMode::Fit,
+        }
+    }
+
+    /// Multiply the zoom factor, the mode will be changed to `PanZoom` if it's not
+    pub fn zoom(&self, factor: f32) -> Self {
+        let (zoom, pan_x, pan_y) = match *self {
+            DisplayMode::PanZoom { zoom, pan_x, pan_y } => (zoom, pan_x, pan_y),
+            _ => (MIN_ZOOM, 0.0, 0.0),
+        };
+
+        DisplayMode::PanZoom {
+            zoom: (zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM),
+            pan_x,
+            pan_y,
+        }
+    }
+
+    /// Move the visible area, only works in `PanZoom` mode
+    pub fn pan(&self, dx: f32, dy: f32) -> Self {
+        match *self {
+            DisplayMode::PanZoom { zoom, pan_x, pan_y } => DisplayMode::PanZoom {
+                zoom,
+                pan_x: (pan_x + dx).clamp(-1.0, 1.0),
+                pan_y: (pan_y + dy).clamp(-1.0, 1.0),
+            },
+            mode => mode,
+        }
+    }
+}
+
+/// Compute which part of the frame is shown and where it is drawn.
+/// # Arguments
+/// * `frame_width`, `frame_height`: The size of frame in pixels
+/// * `sample_aspect_ratio`: The aspect ratio of one pixel, `(0, 1)` or any invalid value means square pixel
+/// * `area`: The area of the play box
+/// * `mode`: The display mode
+/// # Returns
+/// * `Rect` - the source rectangle in the frame
+/// * `Rect` - the destination rectangle on the canvas
+/// * `None` if the frame or the area is empty
+pub fn compute_layout(
+    frame_width: u32,
+    frame_height: u32,
+    sample_aspect_ratio: (i32, i32),
+    area: Rect,
+    mode: DisplayMode,
+) -> Option<(Rect, Rect)> {
+    if frame_width == 0 || frame_height == 0 || area.width() == 0 || area.height() == 0 {
+        return None;
+    }
+
+    let (sar_num, sar_den) = sample_aspect_ratio;
+    let sar = if sar_num > 0 && sar_den > 0 {
+        sar_num as f64 / sar_den as f64
+    } else {
+        1.0
+    };
+    let display_ratio = frame_width as f64 * sar / frame_height as f64;
+    let area_ratio = area.width() as f64 / area.height() as f64;
+    let full_frame = Rect::new(0, 0, frame_width, frame_height);
+
+    let layout = match mode {
+        DisplayMode::Fit => (full_frame, fit(area, display_ratio)),
+        DisplayMode::Ratio(width, height) if width > 0 && height > 0 => {
+            (full_frame, fit(area, width as f64 / height as f64))
+        }
+        DisplayMode::Ratio(_, _) => (full_frame, fit(area, display_ratio)),
+        DisplayMode::Stretch => (full_frame, area),
+        DisplayMode::Fill => {
+            // Crop the frame to the aspect ratio of area
+            let (src_width, src_height) = if area_ratio > display_ratio {
+                let height = frame_height as f64 * display_ratio / area_ratio;
+                (frame_width as f64, height)
+            } else {
+                let width = frame_width as f64 * area_ratio / display_ratio;
+                (width, frame_height as f64)
+            };
+
+            (
+                centered(frame_width, frame_height, src_width, src_height, 0.0, 0.0),
+                area,
+            )
+        }
+        DisplayMode::PanZoom { zoom, pan_x, pan_y } => {
+            let fitted = fit(area, display_ratio);
+            let zoom = (zoom as f64).max(1.0);
+            let zoomed_width = fitted.width() as f64 * zoom;
+            let zoomed_height = fitted.height() as f64 * zoom;
+
+            // The part out of area is cropped from source
+            let dst_width = zoomed_width.min(area.width() as f64);
+            let dst_height = zoomed_height.min(area.height() as f64);
+            let src_width = frame_width as f64 * dst_width / zoomed_width;
+            let src_height = frame_height as f64 * dst_height / zoomed_height;
+
+            (
+                centered(
+                    frame_width,
+                    frame_height,
+                    src_width,
+                    src_height,
+                    pan_x as f64,
+                    pan_y as f64,
+                ),
+                centered_in(area, dst_width, dst_height),
+            )
+        }
+    };
+
+    Some(layout)
+}
+
+/// Same as `compute_layout`, but the frame is turned clockwise by `rotation` when drawn.
+/// # Returns
+/// * `Rect` - the source rectangle in the frame
+/// * `Rect` - the destination rectangle before rotation, it's turned around its center
+/// * `None` if the frame or the area is empty
+pub fn compute_rotated_layout(
+    frame_width: u32,
+    frame_height: u32,
+    sample_aspect_ratio: (i32, i32),
+    area: Rect,
+    mode: DisplayMode,
+    rotation: i32,
+) -> Option<(Rect, Rect)> {
+    let sideways = rotation == 90 || rotation == 270;
+    let (width, height, sar) = if sideways {
+        let (sar_num, sar_den) = sample_aspect_ratio;
+        (frame_height, frame_width, (sar_den, sar_num))
+    } else {
+        (frame_width, frame_height, sample_aspect_ratio)
+    };
+    let (src, dst) = compute_layout(width, height, sar, area, mode)?;
+
+    // Map the source rectangle from the turned picture back to the frame
+    let (x, y, w, h) = (src.x(), src.y(), src.width(), src.height());
+    let (frame_width, frame_height) = (frame_width as i32, frame_height as i32);
+    let src = match rotation {
+        90 => Rect::new(y, frame_height - x - w as i32, h, w),
+        180 => Rect::new(frame_width - x - w as i32, frame_height - y - h as i32, w, h),
+        270 => Rect::new(frame_width - y - h as i32, x, h, w),
+        _ => src,
+    };
+    let dst = match sideways {
+        true => Rect::from_center(dst.center(), dst.height(), dst.width()),
+        false => dst,
+    };
+
+    Some((src, dst))
+}
+
+/// The largest rectangle with specified aspect ratio in the center of area
+fn fit(area: Rect, ratio: f64) -> Rect {
+    let area_ratio = area.width() as f64 / area.height() as f64;
+    let (width, height) = if area_ratio > ratio {
+        // Pillarbox
+        (area.height() as f64 * ratio, area.height() as f64)
+    } else {
+        // Letterbox
+        (area.width() as f64, area.width() as f64 / ratio)
+    };
+
+    centered_in(area, width, height)
+}
+
+fn centered_in(area: Rect, width: f64, height: f64) -> Rect {
+    let width = width.round().max(1.0);
+    let height = height.round().max(1.0);
+    let x = area.x() as f64 + (area.width() as f64 - width) / 2.0;
+    let y = area.y() as f64 + (area.height() as f64 - height) / 2.0;
+
+    Rect::new(x.round() as i32, y.round() as i32, width as u32, height as u32)
+}
+
+/// A rectangle of specified size in the frame, `pan_x` and `pan_y` offset it from the center
+fn centered(
+    frame_width: u32,
+    frame_height: u32,
+    width: f64,
+    height: f64,
+    pan_x: f64,
+    pan_y: f64,
+) -> Rect {
+    let width = width.round().clamp(1.0, frame_width as f64);
+    let height = height.round().clamp(1.0, frame_height as f64);
+    let x = (frame_width as f64 - width) * (pan_x.clamp(-1.0, 1.0) + 1.0) / 2.0;
+    let y = (frame_height as f64 - height) * (pan_y.clamp(-1.0, 1.0) + 1.0) / 2.0;
+
+    Rect::new(x.round() as i32, y.round() as i32, width as u32, height as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sdl2::rect::Point;
+
+    const SQUARE: (i32, i32) = (1, 1);
+
+    #[test]
+    fn test_rotated_fit() {
+        // Portrait phone video stored as landscape
+        let area = Rect::new(0, 0, 1000, 1000);
+        let (src, dst) =
+            compute_rotated_layout(1920, 1080, SQUARE, area, DisplayMode::Fit, 90).unwrap();
+        assert_eq!(Rect::new(0, 0, 1920, 1080), src);
+        // 563x1000 on screen after turned
+        assert_eq!(Rect::new(0, 219, 1000, 563), dst);
+        assert_eq!(Point::new(500, 500), dst.center());
+
+        let layout = compute_rotated_layout(1920, 1080, SQUARE, area, DisplayMode::Fit, 0);
+        assert_eq!(compute_layout(1920, 1080, SQUARE, area, DisplayMode::Fit), layout);
+    }
+
+    #[test]
+    fn test_rotated_fill() {
+        // The visible part of turned picture is the middle of the frame's width
+        let area = Rect::new(0, 0, 1000, 1000);
+        for rotation in [90, 270] {
+            let (src, dst) =
+                compute_rotated_layout(2000, 1000, SQUARE, area, DisplayMode::Fill, rotation)
+                    .unwrap();
+            assert_eq!(Rect::new(500, 0, 1000, 1000), src);
+            assert_eq!(area, dst);
+        }
+
+        let (src, _) =
+            compute_rotated_layout(2000, 1000, SQUARE, area, DisplayMode::Fill, 180).unwrap();
+        assert_eq!(Rect::new(500, 0, 1000, 1000), src);
+    }
+
+    #[test]
+    fn test_fit_letterbox() {
+        let area = Rect::new(0, 0, 1024, 768);
+        let (src, dst) = compute_layout(1920, 1080, SQUARE, area, DisplayMode::Fit).unwrap();
+        assert_eq!(Rect::new(0, 0, 1920, 1080), src);
+        assert_eq!(Rect::new(0, 96, 1024, 576), dst);
+    }
+
+    #[test]
+    fn test_fit_pillarbox() {
+        let area = Rect::new(0, 0, 1920, 1080);
+        let (_, dst) = compute_layout(640, 480, SQUARE, area, DisplayMode::Fit).unwrap();
+        assert_eq!(Rect::new(240, 0, 1440, 1080), dst);
+    }
+
+    #[test]
+    fn test_fit_anamorphic() {
+        // NTSC DVD 16:9, 720x480 with sample aspect ratio 32:27
+        let area = Rect::new(0, 0, 1920, 1080);
+        let (_, dst) = compute_layout(720, 480, (32, 27), area, DisplayMode::Fit).unwrap();
+        assert_eq!(Rect::new(0, 0, 1920, 1080), dst);
+
+        // Invalid sample aspect ratio is treated as square pixel
+        let (_, dst) = compute_layout(720, 480, (0, 1), area, DisplayMode::Fit).unwrap();
+        assert_eq!(Rect::new(150, 0, 1620, 1080), dst);
+    }
+
+    #[test]
+    fn test_fill() {
+        let area = Rect::new(0, 0, 1920, 1080);
+        let (src, dst) = compute_layout(640, 480, SQUARE, area, DisplayMode::Fill).unwrap();
+        assert_eq!(Rect::new(0, 60, 640, 360), src);
+        assert_eq!(area, dst);
+    }
+
+    #[test]
+    fn test_stretch() {
+        let area = Rect::new(0, 0, 800, 800);
+        let (src, dst) = compute_layout(1920, 1080, SQUARE, area, DisplayMode::Stretch).unwrap();
+        assert_eq!(Rect::new(0, 0, 1920, 1080), src);
+        assert_eq!(area, dst);
+    }
+
+    #[test]
+    fn test_forced_ratio() {
+        let area = Rect::new(0, 0, 1920, 1080);
+        let mode = DisplayMode::Ratio(235, 100);
+        let (_, dst) = compute_layout(1920, 1080, SQUARE, area, mode).unwrap();
+        assert_eq!(Rect::new(0, 132, 1920, 817), dst);
+
+        let (_, dst) = compute_layout(1920, 1080, SQUARE, area, DisplayMode::Ratio(4, 3)).unwrap();
+        assert_eq!(Rect::new(240, 0, 1440, 1080), dst);
+    }
+
+    #[test]
+    fn test_pan_zoom() {
+        let area = Rect::new(0, 0, 1920, 1080);
+        let mode = DisplayMode::Fit.zoom(2.0);
+        let (src, dst) = compute_layout(1920, 1080, SQUARE, area, mode).unwrap();
+        assert_eq!(Rect::new(480, 270, 960, 540), src);
+        assert_eq!(area, dst);
+
+        let mode = mode.pan(-1.0, 1.0);
+        let (src, _) = compute_layout(1920, 1080, SQUARE, area, mode).unwrap();
+        assert_eq!(Rect::new(0, 540, 960, 540), src);
+    }
+
+    #[test]
+    fn test_pan_zoom_partially_cropped() {
+        // The zoomed frame is still narrower than the area, only the height is cropped
+        let area = Rect::new(0, 0, 1920, 1080);
+        let mode = DisplayMode::Fit.zoom(1.2);
+        let (src, dst) = compute_layout(640, 480, SQUARE, area, mode).unwrap();
+        assert_eq!(Rect::new(0, 40, 640, 400), src);
+        assert_eq!(Rect::new(96, 0, 1728, 1080), dst);
+    }
+
+    #[test]
+    fn test_empty() {
+        let area = Rect::new(0, 0, 1920, 1080);
+        assert!(compute_layout(0, 1080, SQUARE, area, DisplayMode::Fit).is_none());
+    }
+}
@@ -1,11 +1,15 @@
+mod audio_view;
+mod display_mode;
 mod playbar;
 mod playbox;
 mod progressbar;
 mod titlebar;
+mod volume_indicator;
 
 use std::{
     cell::RefCell,
     ops::{Deref, DerefMut},
+    path::{Path, PathBuf},
     rc::Rc,
 };
 
@@ -19,21 +23,33 @@ use sdl2::{
     VideoSubsystem,
 };
 
-use crate::media::decoder::VideoFrame;
-use crate::util::error::SuperError;
+use crate::media::{
+    decoder::VideoFrame, metadata::MediaInfo, screenshot, source::MediaSource, thumbnail::Thumbnail,
+};
+use crate::util::error::PlayerError;
 use crate::{
-    global::{APP_NAME, INIT_HEIGHT, INIT_WIDTH, LOGO_PATH},
+    global::{
+        APP_NAME, AUDIO_SUMMARY, INIT_HEIGHT, INIT_WIDTH, LOGO_PATH, SCREENSHOT_DIR, VIDEO_SUMMARY,
+    },
     util::error::handle_result,
 };
 
+use self::audio_view::AudioView;
+use self::display_mode::DisplayMode;
 use self::playbar::PlayBar;
 use self::playbox::PlayBox;
-use self::progressbar::ProgressBar;
+use self::progressbar::{ProgressBar, PROGRESSBAR_HEIGHT};
 use self::titlebar::TitleBar;
+use self::volume_indicator::{VolumeIndicator, INDICATOR_WIDTH};
 
-use super::{MouseDownParam, MouseMotionParam, MouseUpParam, MouseWheelParam, RectangleControl};
+use super::{
+    renderer, MouseDownParam, MouseMotionParam, MouseUpParam, MouseWheelParam, RectangleControl,
+};
 
 pub const BACKGROUND_COLOR: Color = Color::RGB(0, 0, 0);
+/// Space between the volume indicator and the top right corner, below the title bar
+const INDICATOR_MARGIN: i32 = 20;
+const INDICATOR_TOP: i32 = 50;
 
 pub struct VideoWindow {
     pub id: u32,
@@ -42,10 +58,17 @@ pub struct VideoWindow {
     playbar: PlayBar,
     progressbar: ProgressBar,
     playbox: PlayBox,
+    /// Shown instead of `playbox` if the media has no video
+    audio_view: AudioView,
+    volume_indicator: VolumeIndicator,
+    /// The media which is playing
+    media: Option<MediaSource>,
+    /// Indicate that the content has been changed and the window should be rendered again
+    needs_render: bool,
 }
 
 impl VideoWindow {
-    pub fn new(sys: &VideoSubsystem) -> Result<Self, SuperError> {
+    pub fn new(sys: &VideoSubsystem) -> Result<Self, PlayerError> {
         let wind = Self::prepare_window(sys)?;
         let window_id = wind.id();
         let (x, y) = wind.position();
@@ -54,18 +77,36 @@ impl VideoWindow {
         let canvas = Self::prepare_canvas(wind)?;
         let canvas = Rc::new(RefCell::new(canvas));
         let play_box = PlayBox::new(0, 0, INIT_WIDTH, INIT_HEIGHT, canvas.clone())?;
+        let audio_view = AudioView::new(0, 0, INIT_WIDTH, INIT_HEIGHT, canvas.clone())?;
+        let progress_bar = ProgressBar::new(
+            0,
+            (INIT_HEIGHT - PROGRESSBAR_HEIGHT) as i32,
+            INIT_WIDTH,
+            PROGRESSBAR_HEIGHT,
+            canvas.clone(),
+        )?;
+        let volume_indicator = VolumeIndicator::new(
+            Self::indicator_x(INIT_WIDTH),
+            INDICATOR_TOP,
+            canvas.clone(),
+        )?;
 
         Ok(Self {
             titlebar: TitleBar::new(canvas.clone(), None, None, None, None)?,
             playbar: PlayBar,
-            progressbar: ProgressBar,
+            progressbar: progress_bar,
             playbox: play_box,
+            audio_view,
+            volume_indicator,
+            media: None,
+            needs_render: true,
             id: window_id,
             inner: RectangleControl::new(x, y, width, height, canvas.clone())?,
         })
     }
 
     pub fn show(&mut self) {
+        self.needs_render = true;
         self.canvas.borrow_mut().window_mut().show();
     }
 
@@ -73,7 +114,7 @@ impl VideoWindow {
         self.canvas.borrow_mut().window_mut().hide();
     }
 
-    pub fn set_logo(&mut self, path: &str) -> Result<(), SuperError> {
+    pub fn set_logo(&mut self, path: &str) -> Result<(), PlayerError> {
         let logo = Surface::from_file(path)?;
         self.canvas.borrow_mut().window_mut().set_icon(logo);
 
@@ -81,18 +122,103 @@ impl VideoWindow {
     }
 
     pub fn update_video_frame(&mut self, frame: VideoFrame) {
+        self.needs_render = true;
         self.playbox.update_frame(frame);
     }
 
-    fn prepare_window(sys: &VideoSubsystem) -> Result<Window, SuperError> {
-        let mut wind = sys
-            .window("NT Player", INIT_WIDTH, INIT_HEIGHT)
-            .borderless()
-            .allow_highdpi()
-            .position_centered()
-            .resizable()
-            .opengl()
-            .build()?;
+    /// Switch to the next display mode, e.g. fit, fill, stretch and forced aspect ratios
+    pub fn cycle_display_mode(&mut self) {
+        self.needs_render = true;
+        let mode = self.playbox.display_mode().next();
+        self.playbox.set_display_mode(mode);
+    }
+
+    /// Multiply the zoom factor of picture
+    pub fn zoom(&mut self, factor: f32) {
+        self.needs_render = true;
+        let mode = self.playbox.display_mode().zoom(factor);
+        self.playbox.set_display_mode(mode);
+    }
+
+    /// Move the visible area of zoomed picture
+    pub fn pan(&mut self, dx: f32, dy: f32) {
+        self.needs_render = true;
+        let mode = self.playbox.display_mode().pan(dx, dy);
+        self.playbox.set_display_mode(mode);
+    }
+
+    /// Prepare the window for the new media
+    pub fn load_media(&mut self, source: &MediaSource) {
+        self.media = Some(source.clone());
+        self.progressbar.load_media(source);
+    }
+
+    /// Save the displayed frame as PNG with its source resolution
+    pub fn screenshot(&self) -> Result<PathBuf, PlayerError> {
+        let frame = self
+            .playbox
+            .frame()
+            .ok_or(PlayerError::NotPlaying("No frame is displayed"))?;
+        let media = self
+            .media
+            .as_ref()
+            .ok_or(PlayerError::NotPlaying("No media is playing"))?;
+
+        let name = media.name();
+        let path = screenshot::screenshot_path(
+            Path::new(SCREENSHOT_DIR),
+            Path::new(&name),
+            frame.pts_millis,
+        );
+        screenshot::save_video_frame(frame, &path)?;
+
+        Ok(path)
+    }
+
+    /// Show tags and cover art of the media, they're visible in audio-only mode
+    pub fn set_media_info(&mut self, info: MediaInfo) {
+        self.needs_render = true;
+        let title = match info.display_title() {
+            Some(title) => format!("{} - {}", title, APP_NAME),
+            None => APP_NAME.to_string(),
+        };
+        if let Err(err) = self.canvas.borrow_mut().window_mut().set_title(&title) {
+            error!("Failed to set window title: {}", err);
+        }
+
+        self.audio_view.set_media_info(info);
+    }
+
+    /// Switch between the visualizers of audio-only mode
+    pub fn cycle_visualizer(&mut self) {
+        self.needs_render = true;
+        self.audio_view.cycle_visualizer();
+    }
+
+    /// The media has audio but no video, e.g. music files
+    fn is_audio_only() -> bool {
+        VIDEO_SUMMARY.read().unwrap().is_none() && AUDIO_SUMMARY.read().unwrap().is_some()
+    }
+
+    /// Show the volume indicator for a while, call it after volume or mute changed
+    pub fn show_volume(&mut self) {
+        self.needs_render = true;
+        self.volume_indicator.show();
+    }
+
+    pub fn update_thumbnail(&mut self, thumbnail: Thumbnail) {
+        self.needs_render = true;
+        self.progressbar.update_thumbnail(thumbnail);
+    }
+
+    fn prepare_window(sys: &VideoSubsystem) -> Result<Window, PlayerError> {
+        let mut wind = renderer::build_window(
+            sys.window("NT Player", INIT_WIDTH, INIT_HEIGHT)
+                .borderless()
+                .allow_highdpi()
+                .position_centered()
+                .resizable(),
+        )?;
 
         wind.set_title(APP_NAME)?;
 
@@ -102,40 +228,45 @@ impl VideoWindow {
         Ok(wind)
     }
 
-    fn prepare_canvas(wind: Window) -> Result<Canvas<Window>, SuperError> {
-        let mut canvas = wind.into_canvas().build()?;
+    fn prepare_canvas(wind: Window) -> Result<Canvas<Window>, PlayerError> {
+        let mut canvas = renderer::build_canvas(wind)?;
         canvas.set_draw_color(BACKGROUND_COLOR);
 
         Ok(canvas)
     }
 
-    pub fn on_mouse_down(&mut self, params: &MouseDownParam) -> Result<bool, SuperError> {
+    pub fn on_mouse_down(&mut self, params: &MouseDownParam) -> Result<bool, PlayerError> {
         if params.window_id != self.id {
             return Ok(false);
         }
+
+        self.progressbar.on_mouse_down(params)?;
         Ok(true)
     }
 
-    pub fn on_mouse_up(&mut self, params: &MouseUpParam) -> Result<bool, SuperError> {
+    pub fn on_mouse_up(&mut self, params: &MouseUpParam) -> Result<bool, PlayerError> {
         if params.window_id != self.id {
             return Ok(false);
         }
 
         self.titlebar.on_mouse_up(params)?;
+        self.needs_render = true;
 
         Ok(true)
     }
 
-    pub fn on_mouse_motion(&mut self, params: &MouseMotionParam) -> Result<bool, SuperError> {
+    pub fn on_mouse_motion(&mut self, params: &MouseMotionParam) -> Result<bool, PlayerError> {
         if params.window_id != self.id {
             return Ok(false);
         }
 
         self.titlebar.on_mouse_motion(params)?;
+        self.progressbar.on_mouse_motion(params)?;
+        self.needs_render = true;
         Ok(true)
     }
 
-    pub fn on_mouse_wheel(&mut self, params: &MouseWheelParam) -> Result<bool, SuperError> {
+    pub fn on_mouse_wheel(&mut self, params: &MouseWheelParam) -> Result<bool, PlayerError> {
         if params.window_id != self.id {
             return Ok(false);
         }
@@ -158,33 +289,69 @@ impl VideoWindow {
     }
 
     pub fn on_resized(&mut self, width: u32, height: u32) {
+        self.needs_render = true;
         // Adjust playbox size
         self.playbox.set_size(width, height);
+        self.audio_view.set_size(width, height);
 
         // Adjuist titlebar size
         let tb_height = self.titlebar.height;
         self.titlebar.set_size(width, tb_height);
+
+        // Keep progressbar at the bottom of window
+        let pb_height = self.progressbar.height;
+        self.progressbar.set_size(width, pb_height);
+        self.progressbar.set_position(0, height as i32 - pb_height as i32);
+
+        // Keep volume indicator at the top right corner
+        self.volume_indicator
+            .set_position(Self::indicator_x(width), INDICATOR_TOP);
+    }
+
+    fn indicator_x(window_width: u32) -> i32 {
+        window_width as i32 - INDICATOR_WIDTH as i32 - INDICATOR_MARGIN
     }
 
     pub fn set_position(&mut self, x: WindowPos, y: WindowPos) {
         self.canvas.borrow_mut().window_mut().set_position(x, y);
     }
 
-    pub fn render(&mut self) -> Result<bool, SuperError> {
+    /// Mark the window to be rendered again in next `render` call
+    pub fn invalidate(&mut self) {
+        self.needs_render = true;
+    }
+
+    /// Render the window if anything has been changed since last rendering
+    pub fn render(&mut self) -> Result<bool, PlayerError> {
+        // The visualizer of audio-only mode is animated, render it every time
+        let audio_only = Self::is_audio_only();
+        // The window should be rendered again to hide the expired indicator
+        if !self.volume_indicator.take_expired() && !self.needs_render && !audio_only {
+            return Ok(false);
+        }
+
         self.canvas.borrow_mut().set_draw_color(Color::BLACK);
         self.canvas.borrow_mut().clear();
 
         // Render content
-        self.playbox.render()?;
+        if audio_only {
+            self.audio_view.render()?;
+        } else {
+            self.playbox.render()?;
+        }
+        self.progressbar.render()?;
+        self.volume_indicator.render()?;
         self.titlebar.render()?;
 
         // Display on screen
         self.canvas.borrow_mut().present();
+        self.needs_render = false;
 
         Ok(true)
     }
 
     pub fn set_fullscreen(&mut self, fs_type: FullscreenType) {
+        self.needs_render = true;
         if let Err(err) = self
             .canvas
             .borrow_mut()
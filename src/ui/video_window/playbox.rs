@@ -5,25 +5,39 @@ use std::{
 };
 
 use log::warn;
-use rsmpeg::ffi::AVPixelFormat_AV_PIX_FMT_YUV420P as AVPIXELFORMAT_AV_PIX_FMT_YUV420P;
+use rsmpeg::ffi::{
+    AVPixelFormat_AV_PIX_FMT_RGB24 as AVPIXELFORMAT_AV_PIX_FMT_RGB24,
+    AVPixelFormat_AV_PIX_FMT_YUV420P as AVPIXELFORMAT_AV_PIX_FMT_YUV420P,
+};
 use sdl2::{
     pixels::PixelFormatEnum,
     rect::Rect,
     render::Canvas,
+    sys::{SDL_SetYUVConversionMode, SDL_YUV_CONVERSION_MODE},
     video::{Window, WindowPos},
 };
 
+use super::display_mode::{compute_rotated_layout, DisplayMode};
 use crate::{
     entity::EventMessage,
     global::EVENT_CHANNEL,
     media::decoder::VideoFrame,
-    ui::{RectangleControl, TControl},
-    util::error::{safe_send, SuperError},
+    ui::{texture_cache::TextureCache, RectangleControl, TControl},
+    util::{
+        colorspace::sdl_conversion_mode,
+        error::{safe_send, PlayerError},
+    },
 };
 
 pub struct PlayBox {
     inner: RectangleControl,
     frame: Option<VideoFrame>,
+    display_mode: DisplayMode,
+    /// The texture which the frames are uploaded to, reused between frames
+    /// of the same width, height and pixel format
+    texture: TextureCache<(u32, u32, i32)>,
+    /// Indicate that the frame has not been uploaded to texture yet
+    dirty: bool,
 }
 
 impl PlayBox {
@@ -33,15 +47,32 @@ impl PlayBox {
         width: u32,
         height: u32,
         canvas: Rc<RefCell<Canvas<Window>>>,
-    ) -> Result<Self, SuperError> {
+    ) -> Result<Self, PlayerError> {
         Ok(Self {
-            inner: RectangleControl::new(x, y, width, height, canvas.clone())?,
+            texture: TextureCache::new(&canvas.borrow()),
+            inner: RectangleControl::new(x, y, width, height, canvas)?,
             frame: None,
+            display_mode: DisplayMode::default(),
+            dirty: false,
         })
     }
 
     pub fn update_frame(&mut self, frame: VideoFrame) {
         self.frame = Some(frame);
+        self.dirty = true;
+    }
+
+    pub fn display_mode(&self) -> DisplayMode {
+        self.display_mode
+    }
+
+    pub fn set_display_mode(&mut self, mode: DisplayMode) {
+        self.display_mode = mode;
+    }
+
+    /// The frame which is currently displayed
+    pub fn frame(&self) -> Option<&VideoFrame> {
+        self.frame.as_ref()
     }
 
     pub fn set_size(&mut self, width: u32, height: u32) {
@@ -55,60 +86,103 @@ impl PlayBox {
         safe_send(result);
     }
 
-    pub fn render(&mut self) -> Result<bool, SuperError> {
-        if self.frame.is_none() {
-            return Ok(false);
+    pub fn render(&mut self) -> Result<bool, PlayerError> {
+        let (frame_width, frame_height, format) = match self.frame.as_ref() {
+            Some(frame) => (frame.width as u32, frame.height as u32, frame.format),
+            None => return Ok(false),
+        };
+
+        // The mode is global, renderers read it when uploading or drawing YUV textures
+        if format == AVPIXELFORMAT_AV_PIX_FMT_YUV420P {
+            let colorimetry = self.frame.as_ref().unwrap().colorimetry;
+            let mode = sdl_conversion_mode(
+                colorimetry.matrix(frame_height as usize),
+                colorimetry.range(),
+            )
+            .unwrap_or(SDL_YUV_CONVERSION_MODE::SDL_YUV_CONVERSION_AUTOMATIC);
+            unsafe { SDL_SetYUVConversionMode(mode) };
         }
 
-        let mut canvas_mut = self.canvas.borrow_mut();
-        let texture_creator = canvas_mut.texture_creator();
-        let wind = canvas_mut.window_mut();
+        // Only upload the frame once, the cached texture is reused until a new frame arrived
+        if self.dirty {
+            let frame = self.frame.as_ref().unwrap();
+            let key = (frame_width, frame_height, format);
+            match frame.format {
+                AVPIXELFORMAT_AV_PIX_FMT_YUV420P => {
+                    // Rows may be padded beyond the width, the pitches tell the real ones
+                    let planes = &frame.planes;
+                    let (Some((y, ypitch)), Some((u, upitch)), Some((v, vpitch))) =
+                        (planes.get(0), planes.get(1), planes.get(2))
+                    else {
+                        warn!("video frame without pixels");
+                        return Ok(false);
+                    };
+
+                    self.texture.upload(
+                        key,
+                        |creator| {
+                            Ok(creator.create_texture_streaming(
+                                PixelFormatEnum::IYUV,
+                                frame_width,
+                                frame_height,
+                            )?)
+                        },
+                        |texture| Ok(texture.update_yuv(None, y, ypitch, u, upitch, v, vpitch)?),
+                    )?;
+                }
+                // Converted by the decoder since SDL has no mode for its colors
+                AVPIXELFORMAT_AV_PIX_FMT_RGB24 => {
+                    let Some((rgb, pitch)) = frame.planes.get(0) else {
+                        warn!("video frame without pixels");
+                        return Ok(false);
+                    };
+
+                    self.texture.upload(
+                        key,
+                        |creator| {
+                            Ok(creator.create_texture_streaming(
+                                PixelFormatEnum::RGB24,
+                                frame_width,
+                                frame_height,
+                            )?)
+                        },
+                        |texture| Ok(texture.update(None, rgb, pitch)?),
+                    )?;
+                }
+                _ => {
+                    warn!("unknown pixel format: {}", frame.format);
+                    return Ok(false);
+                }
+            }
 
-        let frame = self.frame.as_ref().unwrap();
-        let frame_width = frame.width as u32;
-        let frame_height = frame.height as u32;
+            self.dirty = false;
+        }
 
-        let mut texture = texture_creator.create_texture_streaming(
-            PixelFormatEnum::IYUV,
+        let texture = match self.texture.get() {
+            Some(texture) => texture,
+            None => return Ok(false),
+        };
+
+        let frame = self.frame.as_ref().unwrap();
+        let area = Rect::new(self.x, self.y, self.width, self.height);
+        let layout = compute_rotated_layout(
             frame_width,
             frame_height,
-        )?;
-        match frame.format {
-            AVPIXELFORMAT_AV_PIX_FMT_YUV420P => {
-                let data = &frame.data;
-                let ypitch = frame.width;
-                let upitch = ypitch / 2;
-                let vpitch = ypitch / 2;
-
-                texture.update_yuv(None, &data[0], ypitch, &data[1], upitch, &data[2], vpitch)?;
-            }
-            _ => {
-                warn!("unknown pixel format: {}", frame.format);
-                return Ok(false);
-            }
+            frame.sample_aspect_ratio,
+            area,
+            self.display_mode,
+            frame.rotation,
+        );
+        if let Some((src, dst)) = layout {
+            // Frames are rotated here only if video filters didn't do it
+            let angle = frame.rotation as f64;
+            self.canvas
+                .borrow_mut()
+                .copy_ex(texture, src, dst, angle, None, false, false)?;
         }
 
-        let (width, height) = self.compute_render_size(frame_width, frame_height)?;
-        let (x, y) = self.compute_render_position(width, height);
-        canvas_mut.copy(&texture, None, Rect::new(x, y, width, height))?;
         Ok(true)
     }
-
-    fn compute_render_size(
-        &self,
-        frame_width: u32,
-        frame_height: u32,
-    ) -> Result<(u32, u32), SuperError> {
-        let ratio = frame_width / frame_height;
-        let height = self.width / ratio;
-
-        Ok((self.width, height))
-    }
-
-    fn compute_render_position(&self, width: u32, height: u32) -> (i32, i32) {
-        let (center_x, center_y) = self.center;
-        (center_x - width as i32 / 2, center_y - height as i32 / 2)
-    }
 }
 
 impl Deref for PlayBox {
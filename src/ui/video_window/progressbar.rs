@@ -1 +1,207 @@
-pub struct ProgressBar;
\ No newline at end of file
+use std::{
+    cell::RefCell,
+    ops::{Deref, DerefMut},
+    rc::Rc,
+    sync::atomic::Ordering,
+    time::Duration,
+};
+
+use log::warn;
+use sdl2::{
+    mouse::MouseButton,
+    pixels::{Color, PixelFormatEnum},
+    rect::Rect,
+    render::Canvas,
+    video::{Window, WindowPos},
+};
+
+use crate::{
+    entity::EventMessage,
+    global::{AUDIO_SUMMARY, EVENT_CHANNEL, GLOBAL_PTS_MILLIS, VIDEO_SUMMARY},
+    media::{
+        source::MediaSource,
+        thumbnail::{Thumbnail, Thumbnailer},
+    },
+    ui::{
+        texture_cache::TextureCache, MouseDownParam, MouseMotionParam, RectangleControl, TControl,
+    },
+    util::error::{safe_send, PlayerError},
+};
+
+/// Height of the progress bar
+pub const PROGRESSBAR_HEIGHT: u32 = 8;
+/// Space between the thumbnail and the progress bar
+const THUMBNAIL_MARGIN: i32 = 10;
+
+pub struct ProgressBar {
+    inner: RectangleControl,
+    /// Provide thumbnails of the playing media
+    thumbnailer: Option<Thumbnailer>,
+    /// The latest thumbnail received
+    thumbnail: Option<Thumbnail>,
+    /// `thumbnail` uploaded, it's reused by thumbnails of the same size
+    thumbnail_texture: TextureCache<(u32, u32)>,
+    /// Indicate that the thumbnail has not been uploaded to texture yet
+    thumbnail_dirty: bool,
+    /// The x coordinate of cursor if the cursor is hovering on the progress bar
+    hover_x: Option<i32>,
+}
+
+impl ProgressBar {
+    pub fn new(
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        canvas: Rc<RefCell<Canvas<Window>>>,
+    ) -> Result<Self, PlayerError> {
+        Ok(Self {
+            thumbnail_texture: TextureCache::new(&canvas.borrow()),
+            inner: RectangleControl::new(x, y, width, height, canvas)?,
+            thumbnailer: None,
+            thumbnail: None,
+            thumbnail_dirty: false,
+            hover_x: None,
+        })
+    }
+
+    /// Prepare thumbnails for the new media, the old ones will be dropped.
+    /// Only local files have thumbnails, streams can't be read twice
+    pub fn load_media(&mut self, source: &MediaSource) {
+        self.thumbnail = None;
+        self.thumbnailer = source.path().and_then(|path| match Thumbnailer::new(path) {
+            Ok(thumbnailer) => Some(thumbnailer),
+            Err(err) => {
+                warn!("Failed to create thumbnailer: {}", err);
+                None
+            }
+        });
+    }
+
+    pub fn update_thumbnail(&mut self, thumbnail: Thumbnail) {
+        // Ignore the thumbnail if the cursor has left the progress bar
+        if self.hover_x.is_some() {
+            self.thumbnail = Some(thumbnail);
+            self.thumbnail_dirty = true;
+        }
+    }
+
+    pub fn on_mouse_motion(&mut self, params: &MouseMotionParam) -> Result<bool, PlayerError> {
+        if !self.inner.on_mouse_motion(params)? {
+            self.hover_x = None;
+            self.thumbnail = None;
+            return Ok(false);
+        }
+
+        self.hover_x = Some(params.x);
+        if let Some(thumbnailer) = self.thumbnailer.as_ref() {
+            thumbnailer.request(self.position_at(params.x));
+        }
+
+        Ok(true)
+    }
+
+    /// Seek to the clicked position
+    pub fn on_mouse_down(&mut self, params: &MouseDownParam) -> Result<bool, PlayerError> {
+        if params.mouse_btn != MouseButton::Left || !self.inner.on_mouse_down(params)? {
+            return Ok(false);
+        }
+
+        let position = Duration::from_millis(self.position_at(params.x) as u64);
+        safe_send(EVENT_CHANNEL.0.send(EventMessage::Seek(position)));
+
+        Ok(true)
+    }
+
+    pub fn render(&mut self) -> Result<bool, PlayerError> {
+        let duration = Self::duration_millis();
+        if duration <= 0 {
+            return Ok(false);
+        }
+
+        // Cloned since the fields of thumbnail change while the canvas is borrowed
+        let canvas = self.canvas.clone();
+        let mut canvas = canvas.borrow_mut();
+
+        // Draw background
+        canvas.set_draw_color(Color::RGB(80, 80, 80));
+        canvas.fill_rect(Rect::new(self.x, self.y, self.width, self.height))?;
+
+        // Draw played part
+        let pts = GLOBAL_PTS_MILLIS.load(Ordering::Acquire).clamp(0, duration);
+        let played_width = (self.width as i64 * pts / duration) as u32;
+        if played_width > 0 {
+            canvas.set_draw_color(Color::RGB(51, 51, 255));
+            canvas.fill_rect(Rect::new(self.x, self.y, played_width, self.height))?;
+        }
+
+        // Draw thumbnail above the cursor
+        if let (Some(hover_x), Some(thumbnail)) = (self.hover_x, self.thumbnail.as_ref()) {
+            let image = &thumbnail.image;
+            // Only upload the thumbnail once, it's drawn again while the cursor moves
+            if self.thumbnail_dirty {
+                self.thumbnail_dirty = false;
+                self.thumbnail_texture.upload(
+                    (image.width, image.height),
+                    |creator| {
+                        Ok(creator.create_texture_static(
+                            PixelFormatEnum::RGB24,
+                            image.width,
+                            image.height,
+                        )?)
+                    },
+                    |texture| Ok(texture.update(None, &image.data, image.pitch)?),
+                )?;
+            }
+
+            if let Some(texture) = self.thumbnail_texture.get() {
+                let max_x = self.x + self.width as i32 - image.width as i32;
+                let x = (hover_x - image.width as i32 / 2).clamp(self.x, max_x.max(self.x));
+                let y = self.y - image.height as i32 - THUMBNAIL_MARGIN;
+                canvas.copy(texture, None, Rect::new(x, y, image.width, image.height))?;
+            }
+        }
+
+        Ok(true)
+    }
+
+    pub fn set_size(&mut self, width: u32, height: u32) {
+        self.inner.set_size(width, height);
+    }
+
+    pub fn set_position(&mut self, x: i32, y: i32) {
+        self.inner
+            .set_position(WindowPos::Positioned(x), WindowPos::Positioned(y));
+    }
+
+    /// Compute the media position of specified x coordinate, unit: milliseconds
+    fn position_at(&self, x: i32) -> i64 {
+        let offset = (x - self.x).clamp(0, self.width as i32) as i64;
+        Self::duration_millis() * offset / self.width.max(1) as i64
+    }
+
+    fn duration_millis() -> i64 {
+        if let Some(summary) = VIDEO_SUMMARY.read().unwrap().as_ref() {
+            return summary.duration_millis as i64;
+        }
+        if let Some(summary) = AUDIO_SUMMARY.read().unwrap().as_ref() {
+            return summary.duration_millis as i64;
+        }
+
+        0
+    }
+}
+
+impl Deref for ProgressBar {
+    type Target = RectangleControl;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl DerefMut for ProgressBar {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
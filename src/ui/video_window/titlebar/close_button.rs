@@ -14,7 +14,7 @@ use sdl2::{
 
 use crate::{
     ui::{Button, MouseMotionParam, TButton, TControl, MouseUpParam},
-    util::error::{SuperError, safe_send}, global::EVENT_CHANNEL, entity::EventMessage,
+    util::error::{PlayerError, safe_send}, global::EVENT_CHANNEL, entity::EventMessage,
 };
 
 pub struct CloseButton {
@@ -29,7 +29,7 @@ impl CloseButton {
         width: u32,
         height: u32,
         canvas: Rc<RefCell<Canvas<Window>>>,
-    ) -> Result<Self, SuperError> {
+    ) -> Result<Self, PlayerError> {
         let inner = Button::new(x, y, width, height, canvas)?;
 
         Ok(Self {
@@ -38,7 +38,7 @@ impl CloseButton {
         })
     }
 
-    pub fn render(&mut self) -> Result<bool, SuperError> {
+    pub fn render(&mut self) -> Result<bool, PlayerError> {
         let mut canvas = self.canvas.borrow_mut();
         let button_rect = Rect::new(self.x, self.y, self.width, self.height);
 
@@ -65,13 +65,13 @@ impl CloseButton {
         Ok(true)
     }
 
-    pub fn on_mouse_motion(&mut self, params: &MouseMotionParam) -> Result<bool, SuperError> {
+    pub fn on_mouse_motion(&mut self, params: &MouseMotionParam) -> Result<bool, PlayerError> {
         self.selected = self.inner.on_mouse_motion(params)?;
 
         Ok(true)
     }
 
-    pub fn on_mouse_up(&mut self, params: &MouseUpParam) -> Result<bool, SuperError>{
+    pub fn on_mouse_up(&mut self, params: &MouseUpParam) -> Result<bool, PlayerError>{
         if ! self.inner.on_mouse_up(params)?{
             return Ok(false);
         }
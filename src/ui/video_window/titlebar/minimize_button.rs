@@ -16,7 +16,7 @@ use crate::{
     entity::EventMessage,
     global::EVENT_CHANNEL,
     ui::{Button, MouseMotionParam, MouseUpParam, TButton, TControl},
-    util::error::{safe_send, SuperError},
+    util::error::{safe_send, PlayerError},
 };
 
 pub struct MinimizeButton {
@@ -31,7 +31,7 @@ impl MinimizeButton {
         width: u32,
         height: u32,
         canvas: Rc<RefCell<Canvas<Window>>>,
-    ) -> Result<Self, SuperError> {
+    ) -> Result<Self, PlayerError> {
         let inner = Button::new(x, y, width, height, canvas)?;
 
         Ok(Self {
@@ -40,7 +40,7 @@ impl MinimizeButton {
         })
     }
 
-    pub fn render(&mut self) -> Result<bool, SuperError> {
+    pub fn render(&mut self) -> Result<bool, PlayerError> {
         let mut canvas = self.canvas.borrow_mut();
         let button_rect = Rect::new(self.x, self.y, self.width, self.height);
 
@@ -63,13 +63,13 @@ impl MinimizeButton {
         Ok(true)
     }
 
-    pub fn on_mouse_motion(&mut self, params: &MouseMotionParam) -> Result<bool, SuperError> {
+    pub fn on_mouse_motion(&mut self, params: &MouseMotionParam) -> Result<bool, PlayerError> {
         self.selected = self.inner.on_mouse_motion(params)?;
 
         Ok(true)
     }
 
-    pub fn on_mouse_up(&mut self, params: &MouseUpParam) -> Result<bool, SuperError> {
+    pub fn on_mouse_up(&mut self, params: &MouseUpParam) -> Result<bool, PlayerError> {
         if !self.inner.on_mouse_up(params)? {
             return Ok(false);
         }
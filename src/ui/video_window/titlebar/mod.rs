@@ -22,7 +22,7 @@ use self::{
 };
 use crate::{
     ui::{MouseDownParam, MouseMotionParam, MouseUpParam, RectangleControl, TControl},
-    util::error::SuperError,
+    util::error::PlayerError,
 };
 
 pub struct TitleBar {
@@ -41,7 +41,7 @@ impl TitleBar {
         y: I,
         width: U,
         height: U,
-    ) -> Result<Self, SuperError>
+    ) -> Result<Self, PlayerError>
     where
         I: Into<Option<i32>>,
         U: Into<Option<u32>>,
@@ -100,7 +100,7 @@ impl TitleBar {
             .set_position(WindowPos::Positioned(mibtn_x), WindowPos::Positioned(0));
     }
 
-    pub fn render(&mut self) -> Result<bool, SuperError> {
+    pub fn render(&mut self) -> Result<bool, PlayerError> {
         // If user is currently operating on canvas, show sub components
         if self.op_flag.load(Ordering::Acquire) {
             self.close_button.render()?;
@@ -111,7 +111,7 @@ impl TitleBar {
         Ok(true)
     }
 
-    pub fn on_mouse_motion(&mut self, params: &MouseMotionParam) -> Result<bool, SuperError> {
+    pub fn on_mouse_motion(&mut self, params: &MouseMotionParam) -> Result<bool, PlayerError> {
         if !self.inner.on_mouse_motion(params)? {
             self.op_flag.store(false, Ordering::Release);
             return Ok(false);
@@ -126,7 +126,7 @@ impl TitleBar {
         Ok(true)
     }
 
-    pub fn on_mouse_up(&mut self, params: &MouseUpParam) -> Result<bool, SuperError> {
+    pub fn on_mouse_up(&mut self, params: &MouseUpParam) -> Result<bool, PlayerError> {
         if !self.inner.on_mouse_up(params)? {
             self.op_flag.store(false, Ordering::Release);
             return Ok(false);
@@ -141,7 +141,7 @@ impl TitleBar {
         Ok(true)
     }
 
-    pub fn on_mouse_down(&mut self, params: &MouseDownParam) -> Result<bool, SuperError> {
+    pub fn on_mouse_down(&mut self, params: &MouseDownParam) -> Result<bool, PlayerError> {
         if !self.inner.on_mouse_down(params)? {
             self.op_flag.store(false, Ordering::Release);
             return Ok(false);
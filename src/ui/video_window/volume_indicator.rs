@@ -0,0 +1,103 @@
+use std::{
+    cell::RefCell,
+    ops::{Deref, DerefMut},
+    rc::Rc,
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+use sdl2::{pixels::Color, rect::Rect, render::Canvas, video::Window, video::WindowPos};
+
+use crate::{
+    global::{MAX_VOLUME, MUTED, VOLUME},
+    ui::RectangleControl,
+    util::error::PlayerError,
+};
+
+pub const INDICATOR_WIDTH: u32 = 160;
+pub const INDICATOR_HEIGHT: u32 = 12;
+/// How long the indicator stays on screen after the volume changed
+const DISPLAY_DURATION: Duration = Duration::from_millis(1500);
+
+/// A bar showing the current volume for a while after it's changed
+pub struct VolumeIndicator {
+    inner: RectangleControl,
+    /// The indicator is hidden after this moment
+    visible_until: Option<Instant>,
+}
+
+impl VolumeIndicator {
+    pub fn new(x: i32, y: i32, canvas: Rc<RefCell<Canvas<Window>>>) -> Result<Self, PlayerError> {
+        Ok(Self {
+            inner: RectangleControl::new(x, y, INDICATOR_WIDTH, INDICATOR_HEIGHT, canvas)?,
+            visible_until: None,
+        })
+    }
+
+    pub fn show(&mut self) {
+        self.visible_until = Some(Instant::now() + DISPLAY_DURATION);
+    }
+
+    /// Returns true once the indicator has just been hidden, so the window should be rendered again
+    pub fn take_expired(&mut self) -> bool {
+        match self.visible_until {
+            Some(until) if Instant::now() >= until => {
+                self.visible_until = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn render(&mut self) -> Result<bool, PlayerError> {
+        if self.visible_until.is_none() {
+            return Ok(false);
+        }
+
+        let volume = VOLUME.load(Ordering::Acquire).clamp(0, MAX_VOLUME);
+        let muted = MUTED.load(Ordering::Acquire);
+        let mut canvas = self.canvas.borrow_mut();
+
+        // Draw background
+        canvas.set_draw_color(Color::RGB(80, 80, 80));
+        canvas.fill_rect(Rect::new(self.x, self.y, self.width, self.height))?;
+
+        // Draw current volume, grey out if muted
+        let level_width = self.width * volume as u32 / MAX_VOLUME as u32;
+        if level_width > 0 {
+            let color = if muted {
+                Color::RGB(140, 140, 140)
+            } else {
+                Color::RGB(51, 51, 255)
+            };
+            canvas.set_draw_color(color);
+            canvas.fill_rect(Rect::new(self.x, self.y, level_width, self.height))?;
+        }
+
+        // Mark the original loudness, the part on the right is amplified
+        let unity_x = self.x + (self.width * 100 / MAX_VOLUME as u32) as i32;
+        canvas.set_draw_color(Color::WHITE);
+        canvas.fill_rect(Rect::new(unity_x, self.y, 2, self.height))?;
+
+        Ok(true)
+    }
+
+    pub fn set_position(&mut self, x: i32, y: i32) {
+        self.inner
+            .set_position(WindowPos::Positioned(x), WindowPos::Positioned(y));
+    }
+}
+
+impl Deref for VolumeIndicator {
+    type Target = RectangleControl;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl DerefMut for VolumeIndicator {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
@@ -0,0 +1,151 @@
+use std::{
+    fmt,
+    ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use crossbeam::queue::ArrayQueue;
+
+/// Keeps the buffers of converted frames for reuse, so that playing doesn't allocate for
+/// every frame. A buffer comes back to the pool when its `PooledBuffer` is dropped
+pub struct BufferPool<T> {
+    shared: Arc<Shared<T>>,
+}
+
+struct Shared<T> {
+    /// Idle buffers, the ones beyond the capacity are freed
+    free: ArrayQueue<Vec<T>>,
+    /// Number of buffers created because no idle one was left
+    allocations: AtomicUsize,
+}
+
+impl<T> BufferPool<T> {
+    /// `capacity` is the number of idle buffers kept
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                free: ArrayQueue::new(capacity),
+                allocations: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// An empty buffer, it keeps the capacity it had if it's reused
+    pub fn take(&self) -> PooledBuffer<T> {
+        let buffer = self.shared.free.pop().unwrap_or_else(|| {
+            self.shared.allocations.fetch_add(1, Ordering::Relaxed);
+            Vec::new()
+        });
+
+        PooledBuffer {
+            buffer,
+            pool: Some(self.shared.clone()),
+        }
+    }
+
+    /// Number of buffers created since the pool is created
+    pub fn allocations(&self) -> usize {
+        self.shared.allocations.load(Ordering::Relaxed)
+    }
+}
+
+/// A buffer taken from `BufferPool`, it's used like `Vec`
+pub struct PooledBuffer<T> {
+    buffer: Vec<T>,
+    /// `None` if the buffer doesn't come from a pool
+    pool: Option<Arc<Shared<T>>>,
+}
+
+impl<T> Deref for PooledBuffer<T> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.buffer
+    }
+}
+
+impl<T> DerefMut for PooledBuffer<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.buffer
+    }
+}
+
+impl<T> Drop for PooledBuffer<T> {
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool.take() {
+            let mut buffer = std::mem::take(&mut self.buffer);
+            buffer.clear();
+            // The buffer is freed if the pool is full
+            let _ = pool.free.push(buffer);
+        }
+    }
+}
+
+impl<T> From<Vec<T>> for PooledBuffer<T> {
+    fn from(buffer: Vec<T>) -> Self {
+        Self { buffer, pool: None }
+    }
+}
+
+impl<T: Clone> Clone for PooledBuffer<T> {
+    fn clone(&self) -> Self {
+        Self {
+            buffer: self.buffer.clone(),
+            pool: self.pool.clone(),
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for PooledBuffer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.buffer.fmt(f)
+    }
+}
+
+impl<T: PartialEq> PartialEq for PooledBuffer<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.buffer == other.buffer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A frame worth of samples converted again and again
+    #[test]
+    fn test_reuse() {
+        let pool = BufferPool::new(4);
+        for _ in 0..1000 {
+            let mut samples = pool.take();
+            assert!(samples.is_empty());
+            samples.extend(std::iter::repeat(0.5f32).take(2048));
+        }
+        assert_eq!(1, pool.allocations());
+
+        // The buffers in use can't be reused, idle buffers beyond the capacity are freed
+        let taken: Vec<_> = (0..6).map(|_| pool.take()).collect();
+        assert_eq!(6, pool.allocations());
+        drop(taken);
+        let taken: Vec<_> = (0..6).map(|_| pool.take()).collect();
+        assert_eq!(8, pool.allocations());
+        // Capacity is kept for the next frame
+        assert!(
+            taken
+                .iter()
+                .filter(|buffer| buffer.capacity() >= 2048)
+                .count()
+                >= 1
+        );
+    }
+
+    #[test]
+    fn test_unpooled() {
+        let buffer = PooledBuffer::from(vec![1u8, 2, 3]);
+        assert_eq!(buffer.clone(), buffer);
+        assert_eq!(&[1, 2, 3], &buffer[..]);
+    }
+}
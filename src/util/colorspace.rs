@@ -0,0 +1,388 @@
+use std::ptr;
+
+use rsmpeg::{
+    avutil::AVFrame,
+    ffi::{
+        sws_getCoefficients, sws_setColorspaceDetails,
+        AVColorPrimaries_AVCOL_PRI_BT2020 as AVCOLORPRIMARIES_AVCOL_PRI_BT2020,
+        AVColorPrimaries_AVCOL_PRI_BT470BG as AVCOLORPRIMARIES_AVCOL_PRI_BT470BG,
+        AVColorPrimaries_AVCOL_PRI_BT709 as AVCOLORPRIMARIES_AVCOL_PRI_BT709,
+        AVColorPrimaries_AVCOL_PRI_SMPTE170M as AVCOLORPRIMARIES_AVCOL_PRI_SMPTE170M,
+        AVColorRange_AVCOL_RANGE_JPEG as AVCOLORRANGE_AVCOL_RANGE_JPEG,
+        AVColorSpace_AVCOL_SPC_BT2020_CL as AVCOLORSPACE_AVCOL_SPC_BT2020_CL,
+        AVColorSpace_AVCOL_SPC_BT2020_NCL as AVCOLORSPACE_AVCOL_SPC_BT2020_NCL,
+        AVColorSpace_AVCOL_SPC_BT470BG as AVCOLORSPACE_AVCOL_SPC_BT470BG,
+        AVColorSpace_AVCOL_SPC_BT709 as AVCOLORSPACE_AVCOL_SPC_BT709,
+        AVColorSpace_AVCOL_SPC_FCC as AVCOLORSPACE_AVCOL_SPC_FCC,
+        AVColorSpace_AVCOL_SPC_SMPTE170M as AVCOLORSPACE_AVCOL_SPC_SMPTE170M,
+        AVColorSpace_AVCOL_SPC_SMPTE240M as AVCOLORSPACE_AVCOL_SPC_SMPTE240M,
+        AVPixelFormat_AV_PIX_FMT_RGB24 as AVPIXELFORMAT_AV_PIX_FMT_RGB24, SWS_BICUBIC,
+        SWS_CS_BT2020, SWS_CS_ITU601, SWS_CS_ITU709,
+    },
+    swscale::SwsContext,
+};
+use sdl2::sys::SDL_YUV_CONVERSION_MODE;
+
+use crate::{
+    global::VIDEO_PLANE_POOL,
+    util::{buffer_pool::PooledBuffer, error::PlayerError},
+};
+
+/// Untagged videos taller than this are supposed to be HD, which SDL does as well
+const SD_MAX_HEIGHT: usize = 576;
+
+/// Coefficients of the conversion between YUV and RGB
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum YuvMatrix {
+    Bt601,
+    Bt709,
+    Bt2020,
+}
+
+impl YuvMatrix {
+    fn sws_colorspace(&self) -> i32 {
+        let colorspace = match self {
+            Self::Bt601 => SWS_CS_ITU601,
+            Self::Bt709 => SWS_CS_ITU709,
+            Self::Bt2020 => SWS_CS_BT2020,
+        };
+        colorspace as i32
+    }
+}
+
+/// Range of the YUV values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum YuvRange {
+    /// Luma in 16-235, also known as TV or MPEG range
+    Limited,
+    /// Luma in 0-255, also known as PC or JPEG range
+    Full,
+}
+
+/// Colors of a video frame as tagged by FFmpeg, any of them may be unspecified
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Colorimetry {
+    /// `AVColorSpace`, the matrix of the YUV values
+    pub color_space: u32,
+    /// `AVColorRange`
+    pub color_range: u32,
+    /// `AVColorPrimaries`
+    pub color_primaries: u32,
+    /// `AVColorTransferCharacteristic`, HDR videos are told by it
+    pub color_trc: u32,
+}
+
+impl Colorimetry {
+    pub fn of_frame(frame: &AVFrame) -> Self {
+        Self {
+            color_space: frame.colorspace,
+            color_range: frame.color_range,
+            color_primaries: frame.color_primaries,
+            color_trc: frame.color_trc,
+        }
+    }
+
+    /// The matrix of the frame, untagged frames are guessed from the primaries or the height
+    pub fn matrix(&self, height: usize) -> YuvMatrix {
+        match self.color_space {
+            AVCOLORSPACE_AVCOL_SPC_BT709 | AVCOLORSPACE_AVCOL_SPC_SMPTE240M => YuvMatrix::Bt709,
+            AVCOLORSPACE_AVCOL_SPC_BT470BG
+            | AVCOLORSPACE_AVCOL_SPC_SMPTE170M
+            | AVCOLORSPACE_AVCOL_SPC_FCC => YuvMatrix::Bt601,
+            AVCOLORSPACE_AVCOL_SPC_BT2020_NCL | AVCOLORSPACE_AVCOL_SPC_BT2020_CL => {
+                YuvMatrix::Bt2020
+            }
+            _ => match self.color_primaries {
+                AVCOLORPRIMARIES_AVCOL_PRI_BT709 => YuvMatrix::Bt709,
+                AVCOLORPRIMARIES_AVCOL_PRI_BT470BG | AVCOLORPRIMARIES_AVCOL_PRI_SMPTE170M => {
+                    YuvMatrix::Bt601
+                }
+                AVCOLORPRIMARIES_AVCOL_PRI_BT2020 => YuvMatrix::Bt2020,
+                _ if height > SD_MAX_HEIGHT => YuvMatrix::Bt709,
+                _ => YuvMatrix::Bt601,
+            },
+        }
+    }
+
+    /// Untagged frames are in limited range, as almost all videos are
+    pub fn range(&self) -> YuvRange {
+        if self.color_range == AVCOLORRANGE_AVCOL_RANGE_JPEG {
+            YuvRange::Full
+        } else {
+            YuvRange::Limited
+        }
+    }
+}
+
+/// The conversion mode for SDL to show the YUV texture, `None` if SDL has no such mode
+pub fn sdl_conversion_mode(matrix: YuvMatrix, range: YuvRange) -> Option<SDL_YUV_CONVERSION_MODE> {
+    match (matrix, range) {
+        (YuvMatrix::Bt601, YuvRange::Full) => {
+            Some(SDL_YUV_CONVERSION_MODE::SDL_YUV_CONVERSION_JPEG)
+        }
+        (YuvMatrix::Bt601, YuvRange::Limited) => {
+            Some(SDL_YUV_CONVERSION_MODE::SDL_YUV_CONVERSION_BT601)
+        }
+        (YuvMatrix::Bt709, YuvRange::Limited) => {
+            Some(SDL_YUV_CONVERSION_MODE::SDL_YUV_CONVERSION_BT709)
+        }
+        _ => None,
+    }
+}
+
+/// Tell swscale the coefficients and the range of the YUV source, the output is full range RGB
+pub fn set_yuv_source(sws_ctx: &mut SwsContext, matrix: YuvMatrix, range: YuvRange) {
+    unsafe {
+        let table = sws_getCoefficients(matrix.sws_colorspace());
+        // Brightness, contrast and saturation are the defaults in 16.16 fixed point
+        sws_setColorspaceDetails(
+            sws_ctx.as_mut_ptr(),
+            table,
+            (range == YuvRange::Full) as i32,
+            table,
+            1,
+            0,
+            1 << 16,
+            1 << 16,
+        );
+    }
+}
+
+/// Scale context converting YUV frames to an RGB format with the right coefficients,
+/// it's recreated once the frames change
+pub struct RgbScaler {
+    dst_format: i32,
+    /// The scale context and the width, height, format, matrix and range it's created for
+    sws: Option<((i32, i32, i32, YuvMatrix, YuvRange), SwsContext)>,
+}
+
+impl RgbScaler {
+    pub fn new(dst_format: i32) -> Self {
+        Self {
+            dst_format,
+            sws: None,
+        }
+    }
+
+    /// Convert the whole frame, negative linesizes of flipped frames are fine for swscale
+    /// # Safety
+    /// Each plane of `dst` must hold `dst_stride * frame.height` bytes for `dst_format`
+    pub unsafe fn scale(
+        &mut self,
+        frame: &AVFrame,
+        matrix: YuvMatrix,
+        range: YuvRange,
+        dst: &[*mut u8; 4],
+        dst_stride: &[i32; 4],
+    ) -> Result<(), PlayerError> {
+        let key = (frame.width, frame.height, frame.format, matrix, range);
+        if self
+            .sws
+            .as_ref()
+            .map_or(true, |(sws_key, _)| *sws_key != key)
+        {
+            let mut sws_ctx = SwsContext::get_context(
+                frame.width,
+                frame.height,
+                frame.format,
+                frame.width,
+                frame.height,
+                self.dst_format,
+                SWS_BICUBIC,
+            )
+            .ok_or(PlayerError::ScaleContext)?;
+            set_yuv_source(&mut sws_ctx, matrix, range);
+            self.sws = Some((key, sws_ctx));
+        }
+
+        let (_, sws_ctx) = self.sws.as_mut().unwrap();
+        sws_ctx.scale(
+            frame.data.as_ptr() as *const *const u8,
+            frame.linesize.as_ptr(),
+            0,
+            frame.height,
+            dst.as_ptr(),
+            dst_stride.as_ptr(),
+        )?;
+
+        Ok(())
+    }
+}
+
+/// Converts the YUV frames that SDL can't show correctly to RGB24 in the decoding thread
+pub struct RgbConverter {
+    scaler: RgbScaler,
+}
+
+impl Default for RgbConverter {
+    fn default() -> Self {
+        Self {
+            scaler: RgbScaler::new(AVPIXELFORMAT_AV_PIX_FMT_RGB24),
+        }
+    }
+}
+
+impl RgbConverter {
+    /// Returns the pooled RGB24 pixels and the pitch of them
+    pub fn convert(
+        &mut self,
+        frame: &AVFrame,
+        matrix: YuvMatrix,
+        range: YuvRange,
+    ) -> Result<(PooledBuffer<u8>, usize), PlayerError> {
+        let pitch = frame.width.max(0) as usize * 3;
+        let mut buffer = VIDEO_PLANE_POOL.take();
+        buffer.resize(pitch * frame.height.max(0) as usize, 0);
+
+        let dst: [*mut u8; 4] = [
+            buffer.as_mut_ptr(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+        ];
+        let dst_stride: [i32; 4] = [pitch as i32, 0, 0, 0];
+        unsafe { self.scaler.scale(frame, matrix, range, &dst, &dst_stride)? };
+
+        Ok((buffer, pitch))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rsmpeg::ffi::{
+        AVColorPrimaries_AVCOL_PRI_UNSPECIFIED as AVCOLORPRIMARIES_AVCOL_PRI_UNSPECIFIED,
+        AVColorRange_AVCOL_RANGE_MPEG as AVCOLORRANGE_AVCOL_RANGE_MPEG,
+        AVColorRange_AVCOL_RANGE_UNSPECIFIED as AVCOLORRANGE_AVCOL_RANGE_UNSPECIFIED,
+        AVColorSpace_AVCOL_SPC_UNSPECIFIED as AVCOLORSPACE_AVCOL_SPC_UNSPECIFIED,
+        AVPixelFormat_AV_PIX_FMT_YUV420P as AVPIXELFORMAT_AV_PIX_FMT_YUV420P,
+    };
+
+    use super::*;
+
+    fn colorimetry(color_space: u32, color_range: u32, color_primaries: u32) -> Colorimetry {
+        Colorimetry {
+            color_space,
+            color_range,
+            color_primaries,
+            ..Colorimetry::default()
+        }
+    }
+
+    #[test]
+    fn test_matrix() {
+        let tagged = colorimetry(
+            AVCOLORSPACE_AVCOL_SPC_SMPTE170M,
+            AVCOLORRANGE_AVCOL_RANGE_MPEG,
+            AVCOLORPRIMARIES_AVCOL_PRI_UNSPECIFIED,
+        );
+        assert_eq!(YuvMatrix::Bt601, tagged.matrix(1080));
+        let tagged = colorimetry(
+            AVCOLORSPACE_AVCOL_SPC_BT2020_NCL,
+            AVCOLORRANGE_AVCOL_RANGE_MPEG,
+            AVCOLORPRIMARIES_AVCOL_PRI_BT2020,
+        );
+        assert_eq!(YuvMatrix::Bt2020, tagged.matrix(2160));
+
+        // Guessed from the primaries
+        let primaries = colorimetry(
+            AVCOLORSPACE_AVCOL_SPC_UNSPECIFIED,
+            AVCOLORRANGE_AVCOL_RANGE_UNSPECIFIED,
+            AVCOLORPRIMARIES_AVCOL_PRI_BT709,
+        );
+        assert_eq!(YuvMatrix::Bt709, primaries.matrix(480));
+
+        // Guessed from the height
+        let untagged = Colorimetry::default();
+        assert_eq!(YuvMatrix::Bt601, untagged.matrix(576));
+        assert_eq!(YuvMatrix::Bt709, untagged.matrix(720));
+    }
+
+    #[test]
+    fn test_range() {
+        assert_eq!(YuvRange::Limited, Colorimetry::default().range());
+        let full = colorimetry(
+            AVCOLORSPACE_AVCOL_SPC_BT709,
+            AVCOLORRANGE_AVCOL_RANGE_JPEG,
+            AVCOLORPRIMARIES_AVCOL_PRI_BT709,
+        );
+        assert_eq!(YuvRange::Full, full.range());
+    }
+
+    #[test]
+    fn test_sdl_conversion_mode() {
+        assert_eq!(
+            Some(SDL_YUV_CONVERSION_MODE::SDL_YUV_CONVERSION_JPEG),
+            sdl_conversion_mode(YuvMatrix::Bt601, YuvRange::Full)
+        );
+        assert_eq!(
+            Some(SDL_YUV_CONVERSION_MODE::SDL_YUV_CONVERSION_BT709),
+            sdl_conversion_mode(YuvMatrix::Bt709, YuvRange::Limited)
+        );
+        assert_eq!(None, sdl_conversion_mode(YuvMatrix::Bt709, YuvRange::Full));
+        assert_eq!(
+            None,
+            sdl_conversion_mode(YuvMatrix::Bt2020, YuvRange::Limited)
+        );
+    }
+
+    /// A YUV420P frame of one color
+    fn yuv_frame(y: u8, u: u8, v: u8) -> AVFrame {
+        let mut frame = AVFrame::new();
+        frame.set_format(AVPIXELFORMAT_AV_PIX_FMT_YUV420P);
+        frame.set_width(16);
+        frame.set_height(16);
+        frame.alloc_buffer().unwrap();
+        for (plane, value, rows) in [(0, y, 16), (1, u, 8), (2, v, 8)] {
+            let size = frame.linesize[plane] as usize * rows;
+            unsafe { ptr::write_bytes(frame.data[plane], value, size) };
+        }
+
+        frame
+    }
+
+    /// RGB of the limited range YUV, computed from the luma coefficients of red and blue
+    fn expected_rgb(yuv: (u8, u8, u8), kr: f64, kb: f64) -> [f64; 3] {
+        let y = (yuv.0 as f64 - 16.0) / 219.0;
+        let pb = (yuv.1 as f64 - 128.0) / 224.0;
+        let pr = (yuv.2 as f64 - 128.0) / 224.0;
+        let r = y + 2.0 * (1.0 - kr) * pr;
+        let b = y + 2.0 * (1.0 - kb) * pb;
+        let g = (y - kr * r - kb * b) / (1.0 - kr - kb);
+        [r * 255.0, g * 255.0, b * 255.0]
+    }
+
+    /// Every pixel is converted with the coefficients of the matrix
+    #[test]
+    fn test_convert_pixels() {
+        let mut converter = RgbConverter::default();
+        // Orange, cyan and gray, the colors differ by about 10 between the matrices
+        for yuv in [(88, 96, 216), (164, 160, 40), (126, 128, 128)] {
+            let frame = yuv_frame(yuv.0, yuv.1, yuv.2);
+            for (matrix, kr, kb) in [
+                (YuvMatrix::Bt709, 0.2126, 0.0722),
+                (YuvMatrix::Bt2020, 0.2627, 0.0593),
+            ] {
+                let (rgb, pitch) = converter
+                    .convert(&frame, matrix, YuvRange::Limited)
+                    .unwrap();
+                assert_eq!(16 * 3, pitch);
+                assert_eq!(16 * pitch, rgb.len());
+
+                let expected = expected_rgb(yuv, kr, kb);
+                for pixel in rgb.chunks_exact(3) {
+                    for (value, expected) in pixel.iter().zip(expected) {
+                        // swscale computes in fixed point
+                        assert!(
+                            (*value as f64 - expected).abs() <= 3.0,
+                            "{:?} {:?}: {:?} != {:?}",
+                            matrix,
+                            yuv,
+                            pixel,
+                            expected
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,14 +1,90 @@
-use crossbeam::channel::{SendError};
+use crossbeam::channel::SendError;
 // use tracing::error;
 use log::error;
-use std::{error::Error, fmt::Display};
+use rsmpeg::error::RsmpegError;
+use sdl2::{
+    render::{TextureValueError, UpdateTextureError},
+    video::WindowBuildError,
+    IntegerOrSdlError,
+};
+use std::{ffi::NulError, io};
+use thiserror::Error;
 
-use crate::{entity::EventMessage, global::EVENT_CHANNEL};
+use crate::{entity::EventMessage, global::EVENT_CHANNEL, media::player::state::PlaybackState};
 
-pub type SuperError = Box<dyn Error>;
+/// Errors of the player
+#[derive(Debug, Error)]
+pub enum PlayerError {
+    /// Most SDL functions report errors as text
+    #[error("SDL error: {0}")]
+    Sdl(String),
+    #[error("FFmpeg error: {0}")]
+    Ffmpeg(#[from] RsmpegError),
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    /// Text passed to C contains a zero byte
+    #[error("Invalid text: {0}")]
+    Nul(#[from] NulError),
+    /// Command line arguments can't be parsed, the message includes the usage
+    #[error("{0}")]
+    InvalidArgument(String),
+    /// The media can't be played, e.g. it has no stream which can be decoded
+    #[error("Invalid media: {0}")]
+    InvalidMedia(String),
+    #[error("Not supported: {0}")]
+    Unsupported(String),
+    #[error("Failed to create scale context")]
+    ScaleContext,
+    #[error("No audio device available: {0}")]
+    NoAudioDevice(String),
+    /// The operation needs a playing media
+    #[error("{0}")]
+    NotPlaying(&'static str),
+    #[error("Playback state can't change from {0:?} to {1:?}")]
+    IllegalTransition(PlaybackState, PlaybackState),
+    /// The receiver of events has gone, the app is quitting
+    #[error("Event channel is closed")]
+    ChannelClosed,
+}
+
+impl From<String> for PlayerError {
+    fn from(err: String) -> Self {
+        Self::Sdl(err)
+    }
+}
+
+impl From<WindowBuildError> for PlayerError {
+    fn from(err: WindowBuildError) -> Self {
+        Self::Sdl(err.to_string())
+    }
+}
+
+impl From<IntegerOrSdlError> for PlayerError {
+    fn from(err: IntegerOrSdlError) -> Self {
+        Self::Sdl(err.to_string())
+    }
+}
+
+impl From<TextureValueError> for PlayerError {
+    fn from(err: TextureValueError) -> Self {
+        Self::Sdl(err.to_string())
+    }
+}
+
+impl From<UpdateTextureError> for PlayerError {
+    fn from(err: UpdateTextureError) -> Self {
+        Self::Sdl(err.to_string())
+    }
+}
+
+impl<T> From<SendError<T>> for PlayerError {
+    fn from(_: SendError<T>) -> Self {
+        Self::ChannelClosed
+    }
+}
 
 /// Handle result function of methods, process exception if any, return content if there's no exception
-pub fn handle_result<T>(result: Result<T, SuperError>) -> Option<T> {
+pub fn handle_result<T>(result: Result<T, PlayerError>) -> Option<T> {
     match result {
         Ok(t) => Some(t),
         Err(e) => {
@@ -25,21 +101,24 @@ pub fn safe_send<T>(result: Result<(), SendError<T>>) {
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct CustomError{
-    message: String,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-impl CustomError {
-    pub fn new<T:Into<String>>(message: T)->Self{
-        Self { message: message.into() }
-    }
-}
+    #[test]
+    fn test_conversion() {
+        let err: PlayerError = String::from("Invalid renderer").into();
+        assert_eq!("SDL error: Invalid renderer", err.to_string());
+
+        let err: PlayerError = io::Error::new(io::ErrorKind::NotFound, "a.mp4").into();
+        assert!(matches!(err, PlayerError::Io(_)));
 
-impl Error for CustomError {}
+        let err: PlayerError = std::ffi::CString::new("a\0b").unwrap_err().into();
+        assert!(matches!(err, PlayerError::Nul(_)));
 
-impl Display for CustomError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.message)
+        let (sender, receiver) = crossbeam::channel::unbounded::<i32>();
+        drop(receiver);
+        let err: PlayerError = sender.send(1).unwrap_err().into();
+        assert!(matches!(err, PlayerError::ChannelClosed));
     }
-}
\ No newline at end of file
+}
@@ -1,5 +1,8 @@
+pub mod buffer_pool;
+pub mod colorspace;
 pub mod log_builder;
 pub mod error;
 pub mod pixel_format;
 pub mod sample_format;
-pub mod subtitle_format;
\ No newline at end of file
+pub mod subtitle_format;
+pub mod worker;
\ No newline at end of file
@@ -15,6 +15,7 @@ use rsmpeg::{ffi::{
     // AVPixelFormat_AV_PIX_FMT_RGB4_BYTE, AVPixelFormat_AV_PIX_FMT_VDPAU,
     // AVPixelFormat_AV_PIX_FMT_X2RGB10LE, AVPixelFormat_AV_PIX_FMT_XYZ12BE,
     // AVPixelFormat_AV_PIX_FMT_YA16BE, 
+    AVPixelFormat_AV_PIX_FMT_RGB24 as AVPIXELFORMAT_AV_PIX_FMT_RGB24,
     AVPixelFormat_AV_PIX_FMT_YUV420P as AVPIXELFORMAT_AV_PIX_FMT_YUV420P,
     // AVPixelFormat_AV_PIX_FMT_YUV420P12, AVPixelFormat_AV_PIX_FMT_YUV420P16,
     // AVPixelFormat_AV_PIX_FMT_YUV420P9, AVPixelFormat_AV_PIX_FMT_YUV422P,
@@ -24,41 +25,151 @@ use rsmpeg::{ffi::{
     // AVPixelFormat_AV_PIX_FMT_YUVA420P9LE, AVPixelFormat_AV_PIX_FMT_YUVA422P16LE,
     // AVPixelFormat_AV_PIX_FMT_YUVA444P12LE, AVPixelFormat_AV_PIX_FMT_YUVA444P16LE,
     // AVPixelFormat_AV_PIX_FMT_YUVJ422P
+    av_pix_fmt_count_planes, av_pix_fmt_desc_get,
 }, avutil::AVFrame};
 
-use crate::{global::VIDEO_SUMMARY, media::decoder::VideoFrame};
+use crate::{
+    global::VIDEO_PLANE_POOL,
+    media::{
+        decoder::{VideoFrame, VideoSummary},
+        tone_map::{HdrTransfer, ToneMapper},
+    },
+    util::{
+        buffer_pool::{BufferPool, PooledBuffer},
+        colorspace::{sdl_conversion_mode, Colorimetry, RgbConverter},
+    },
+};
 
-pub fn parse_video_frame(frame: &AVFrame) -> VideoFrame {
-    let r = VIDEO_SUMMARY.read().unwrap();
-    let summary = r.as_ref().unwrap();
+/// Pixels of a video frame. The ref-counted buffers of the decoded frame are shared,
+/// only frames whose rows go upwards, e.g. flipped by filters, are copied into pooled buffers
+pub enum FramePlanes {
+    Shared {
+        frame: AVFrame,
+        rows: Vec<usize>,
+    },
+    /// Buffer and pitch of each plane
+    Pooled(Vec<(PooledBuffer<u8>, usize)>),
+}
+
+impl FramePlanes {
+    pub fn new(frame: AVFrame) -> Self {
+        Self::with_pool(frame, &VIDEO_PLANE_POOL)
+    }
+
+    /// Share the pixels of the frame, they're copied into buffers of `pool` only
+    /// if the rows go upwards
+    pub fn with_pool(frame: AVFrame, pool: &BufferPool<u8>) -> Self {
+        let rows = plane_rows(frame.format, frame.height.max(0) as usize);
+        if (0..rows.len()).all(|index| frame.linesize[index] > 0) {
+            return Self::Shared { frame, rows };
+        }
+
+        Self::copied(&frame, || pool.take())
+    }
+
+    /// Copy the pixels into the buffers returned by `buffer`, one for each plane,
+    /// the rows of the copy always go downwards
+    pub fn copied(frame: &AVFrame, mut buffer: impl FnMut() -> PooledBuffer<u8>) -> Self {
+        let rows = plane_rows(frame.format, frame.height.max(0) as usize);
+        let planes = rows
+            .iter()
+            .enumerate()
+            .map(|(index, &rows)| {
+                let linesize = frame.linesize[index] as isize;
+                let pitch = linesize.unsigned_abs();
+                let mut buffer = buffer();
+                buffer.reserve(pitch * rows);
+                for row in 0..rows as isize {
+                    let line = unsafe { frame.data[index].offset(row * linesize) };
+                    buffer.extend_from_slice(unsafe { slice::from_raw_parts(line, pitch) });
+                }
+                (buffer, pitch)
+            })
+            .collect();
+
+        Self::Pooled(planes)
+    }
+
+    /// Data and pitch of the plane, rows may be padded beyond the width
+    pub fn get(&self, index: usize) -> Option<(&[u8], usize)> {
+        match self {
+            Self::Shared { frame, rows } => {
+                let rows = *rows.get(index)?;
+                let pitch = frame.linesize[index] as usize;
+                let data = unsafe { slice::from_raw_parts(frame.data[index], pitch * rows) };
+                Some((data, pitch))
+            }
+            Self::Pooled(planes) => planes
+                .get(index)
+                .map(|(buffer, pitch)| (buffer.as_slice(), *pitch)),
+        }
+    }
+
+    /// The pixels are shared with the decoded frame instead of copied
+    pub fn is_shared(&self) -> bool {
+        matches!(self, Self::Shared { .. })
+    }
+
+    /// Bytes of all planes, including the padding of rows
+    pub fn byte_size(&self) -> usize {
+        (0..)
+            .map_while(|index| self.get(index))
+            .map(|(data, _)| data.len())
+            .sum()
+    }
+}
+
+/// Number of rows of each plane of the pixel format, empty if the format is unknown
+fn plane_rows(format: i32, height: usize) -> Vec<usize> {
+    let planes = unsafe { av_pix_fmt_count_planes(format) };
+    let desc = unsafe { av_pix_fmt_desc_get(format) };
+    if planes <= 0 || desc.is_null() {
+        return vec![];
+    }
+
+    let chroma_shift = unsafe { (*desc).log2_chroma_h };
+    rows_of_planes(planes as usize, chroma_shift, height)
+}
+
+/// Chroma planes, the second and the third, have `height` divided by `2^chroma_shift`
+/// rows rounded up, other planes have `height` rows
+fn rows_of_planes(planes: usize, chroma_shift: u8, height: usize) -> Vec<usize> {
+    let chroma_rows = (height + (1 << chroma_shift) - 1) >> chroma_shift;
+    (0..planes)
+        .map(|index| match index {
+            1 | 2 => chroma_rows,
+            _ => height,
+        })
+        .collect()
+}
+
+/// Wrap the decoded frame without copying its pixels, unless it's HDR or SDL can't convert
+/// its colors, then it's tone mapped or converted to RGB24. `summary` is the one of the decoded
+/// media, which may not be playing yet
+pub fn parse_video_frame(
+    frame: AVFrame,
+    summary: &VideoSummary,
+    converter: &mut RgbConverter,
+    tone_mapper: &mut ToneMapper,
+) -> VideoFrame {
     let width = frame.width as usize;
     let height = frame.height as usize;
+    let sample_aspect_ratio = (frame.sample_aspect_ratio.num, frame.sample_aspect_ratio.den);
+    let colorimetry = Colorimetry::of_frame(&frame);
+    let pts = frame.pts;
+
+    // HDR frames are tone mapped whatever the pixel format is, the video filters keep
+    // the bits they're decoded with, e.g. 10 bits of HDR10
+    let tone_mapped = HdrTransfer::of(&colorimetry).and_then(|transfer| {
+        tone_mapper
+            .map(&frame, &colorimetry, transfer)
+            .map_err(|e| warn!("failed to tone map the frame: {}", e))
+            .ok()
+    });
 
     match frame.format {
-        AVPIXELFORMAT_AV_PIX_FMT_YUV420P => {
-            let y_size = width * height;
-            let u_size = y_size / 4; // width/2 * height/2
-            let v_size = y_size / 4; // width/2 * height/2
-
-            let y_ptr = frame.data[0];
-            let y = unsafe { slice::from_raw_parts(y_ptr, y_size) }.to_vec();
-
-            let u_ptr = frame.data[1];
-            let u = unsafe { slice::from_raw_parts(u_ptr, u_size) }.to_vec();
-
-            let v_ptr = frame.data[2];
-            let v = unsafe { slice::from_raw_parts(v_ptr, v_size) }.to_vec();
-
-            VideoFrame {
-                format: frame.format,
-                data: [y, u, v, vec![], vec![], vec![], vec![], vec![]],
-                width,
-                height,
-                pts: frame.pts,
-                pts_millis: 1000 * frame.pts * summary.timebase_num as i64
-                    / summary.timebase_den as i64,
-            }
-        }
+        _ if tone_mapped.is_some() => {}
+        AVPIXELFORMAT_AV_PIX_FMT_YUV420P => {}
         // AVPixelFormat_AV_PIX_FMT_ABGR => {}
         // AVPixelFormat_AV_PIX_FMT_BAYER_BGGR8 => {}
         // AVPixelFormat_AV_PIX_FMT_BAYER_GRBG16 => {}
@@ -104,24 +215,57 @@ pub fn parse_video_frame(frame: &AVFrame) -> VideoFrame {
                 "Un implemented pixel format: {}. It needs some time to finish the work.",
                 frame.format
             );
-            VideoFrame {
-                format: frame.format,
-                data: [
-                    vec![],
-                    vec![],
-                    vec![],
-                    vec![],
-                    vec![],
-                    vec![],
-                    vec![],
-                    vec![],
-                ],
-                width,
-                height,
-                pts: frame.pts,
-                pts_millis: frame.pts * 1000 * frame.time_base.num as i64
-                    / frame.time_base.den as i64,
-            }
         }
     }
+
+    let matrix = colorimetry.matrix(height);
+    let range = colorimetry.range();
+    let converted = if tone_mapped.is_some() {
+        tone_mapped
+    } else if frame.format == AVPIXELFORMAT_AV_PIX_FMT_YUV420P
+        && sdl_conversion_mode(matrix, range).is_none()
+    {
+        converter
+            .convert(&frame, matrix, range)
+            .map_err(|e| warn!("failed to convert the colors of the frame: {}", e))
+            .ok()
+    } else {
+        None
+    };
+    let (format, planes) = match converted {
+        Some(plane) => (
+            AVPIXELFORMAT_AV_PIX_FMT_RGB24,
+            FramePlanes::Pooled(vec![plane]),
+        ),
+        None => (frame.format, FramePlanes::new(frame)),
+    };
+
+    VideoFrame {
+        format,
+        width,
+        height,
+        sample_aspect_ratio,
+        colorimetry,
+        rotation: 0,
+        media: 0,
+        pts,
+        pts_millis: 1000 * pts * summary.timebase_num as i64 / summary.timebase_den as i64,
+        planes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rows_of_planes() {
+        // yuv420p
+        assert_eq!(vec![1080, 540, 540], rows_of_planes(3, 1, 1080));
+        assert_eq!(vec![1081, 541, 541], rows_of_planes(3, 1, 1081));
+        // yuv444p with alpha
+        assert_eq!(vec![720, 720, 720, 720], rows_of_planes(4, 0, 720));
+        // nv12
+        assert_eq!(vec![1080, 540], rows_of_planes(2, 1, 1080));
+    }
 }
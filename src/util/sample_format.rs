@@ -1,27 +1,19 @@
-use std::{slice, sync::atomic::Ordering};
+use std::slice;
 
 use rsmpeg::avutil::AVFrame;
-use crate::{global::{AUDIO_SUMMARY, VOLUME, VOLUME_BENCHMARK}, media::decoder::AudioFrame};
+use crate::{
+    global::AUDIO_SAMPLE_POOL,
+    media::decoder::{AudioFrame, AudioSummary},
+};
 
-/// Parse ffmpeg audio frame to AudioFrame
-pub fn parse_audio_frame(frame: &mut AVFrame) -> AudioFrame {
-    let r = AUDIO_SUMMARY.read().unwrap();
-    let summary = r.as_ref().unwrap();
+/// Parse ffmpeg audio frame to AudioFrame,
+/// `summary` is the one of the decoded media, which may not be playing yet
+pub fn parse_audio_frame(frame: &mut AVFrame, summary: &AudioSummary) -> AudioFrame {
     let pts_millis = 1000 * frame.pts * summary.timebase_num as i64 / summary.timebase_den as i64;
 
-    let left_slice =
-        unsafe { slice::from_raw_parts(frame.data[0] as *const f32, frame.nb_samples as usize) };
-    let right_slice =
-        unsafe { slice::from_raw_parts(frame.data[1] as *const f32, frame.nb_samples as usize) };
-
-    // Convert planar data to mono data
-    // Set volume to current frame
-    let volume = VOLUME.load(Ordering::Acquire) as f32 / VOLUME_BENCHMARK;
-    let mut data = vec![];
-    for (d0, d1) in left_slice.iter().zip(right_slice.iter()) {
-        data.push((*d0) * volume);
-        data.push((*d1) * volume);
-    }
+    // Volume is applied by audio output
+    let mut data = AUDIO_SAMPLE_POOL.take();
+    interleave_samples(frame, &mut data);
 
     let audio_frame = AudioFrame {
         format: frame.format,
@@ -31,7 +23,22 @@ pub fn parse_audio_frame(frame: &mut AVFrame) -> AudioFrame {
         sample_rate: frame.sample_rate,
         channels: frame.channels as u8,
         channel_layout: frame.channel_layout as u8,
+        media: 0,
     };
 
     audio_frame
 }
+
+/// Append the samples of planar stereo frame to `data`, left and right in turn
+pub fn interleave_samples(frame: &AVFrame, data: &mut Vec<f32>) {
+    let left_slice =
+        unsafe { slice::from_raw_parts(frame.data[0] as *const f32, frame.nb_samples as usize) };
+    let right_slice =
+        unsafe { slice::from_raw_parts(frame.data[1] as *const f32, frame.nb_samples as usize) };
+
+    data.reserve(left_slice.len() * 2);
+    for (d0, d1) in left_slice.iter().zip(right_slice.iter()) {
+        data.push(*d0);
+        data.push(*d1);
+    }
+}
@@ -0,0 +1,64 @@
+use std::{
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use log::{error, warn};
+
+/// The interval to check whether a thread has exited
+const JOIN_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Wait for the thread to exit, it's detached if it's still running after `timeout`.
+/// Returns true if the thread has exited
+pub fn join_timeout<T>(handle: JoinHandle<T>, timeout: Duration) -> bool {
+    let name = handle.thread().name().unwrap_or("unnamed").to_string();
+    let deadline = Instant::now() + timeout;
+    while !handle.is_finished() {
+        if Instant::now() >= deadline {
+            warn!("Thread {} doesn't exit in {:?}, detach it", name, timeout);
+            return false;
+        }
+        thread::sleep(JOIN_POLL_INTERVAL);
+    }
+
+    if handle.join().is_err() {
+        error!("Thread {} panicked", name);
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_join_timeout() {
+        let handle = thread::spawn(|| thread::sleep(Duration::from_millis(20)));
+        assert!(join_timeout(handle, Duration::from_secs(1)));
+
+        // A blocked thread is detached
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = thread::spawn({
+            let stop = stop.clone();
+            move || {
+                while !stop.load(Ordering::Acquire) {
+                    thread::sleep(Duration::from_millis(5));
+                }
+            }
+        });
+        let start = Instant::now();
+        assert!(!join_timeout(handle, Duration::from_millis(50)));
+        assert!(start.elapsed() < Duration::from_secs(1));
+        stop.store(true, Ordering::Release);
+
+        // A panicked thread has exited as well
+        let handle = thread::spawn(|| panic!("decoding failed"));
+        assert!(join_timeout(handle, Duration::from_secs(1)));
+    }
+}